@@ -0,0 +1,723 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use async_std::task;
+use log::{info, warn, debug};
+use reqwest::Url;
+use serde_json::{json, Value};
+use crate::error::ProblemDetail;
+use crate::metrics::Metrics;
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::{Storage, StorageError, ObservationQuery};
+pub use crate::storage::parse_timestamp;
+use crate::tabular::{self, Row};
+use crate::wind;
+
+/// Page size when the request has no limit.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest limit a request may ask for.
+const DEFAULT_MAX_PAGE_SIZE: usize = 1000;
+
+/// Most observations a summary may read, about a year of 10 minute observations.
+const DEFAULT_MAX_RANGE_ROWS: usize = 50_000;
+
+/// Fields summarized by /stations/{id}/summary.
+const SUMMARY_FIELDS: [&str; 6] = ["temperature_C", "dewpoint_C", "rel_humidity",
+    "wind_spd_km_h", "wind_gust_km_h", "baro_pres_pa"];
+
+/// Represents an api response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiResponse {
+    pub status:        u16,
+    pub content_type:  String,
+    pub headers:       Vec<(String, String)>,
+    pub body:          String,
+}
+
+/// Implementation for ApiResponse.
+impl ApiResponse {
+
+    ///  Creates a 200 application/json response.
+    ///
+    /// # Arguments
+    ///
+    ///*'value'-the body
+    ///
+    /// # Return
+    ///
+    /// ApiResponse
+    fn json(value: &Value) -> ApiResponse {
+        ApiResponse {
+            status:        200,
+            content_type:  "application/json".to_string(),
+            headers:       Vec::new(),
+            body:          value.to_string(),
+        }
+    }
+
+    ///  Creates a 200 text/csv response.
+    ///
+    /// # Arguments
+    ///
+    ///*'body'-the CSV text
+    ///
+    /// # Return
+    ///
+    /// ApiResponse
+    fn csv(body: String) -> ApiResponse {
+        ApiResponse {
+            status:        200,
+            content_type:  "text/csv; charset=utf-8".to_string(),
+            headers:       Vec::new(),
+            body,
+        }
+    }
+
+    ///  Creates an application/problem+json error response,
+    ///      shaped like the api.weather.gov ones.
+    ///
+    /// # Arguments
+    ///
+    ///*'status'-the HTTP status
+    ///*'title'-the status text, e.g. "Bad Request"
+    ///*'detail'-what went wrong
+    ///
+    /// # Return
+    ///
+    /// ApiResponse
+    fn problem(status: u16, title: &str, detail: &str) -> ApiResponse {
+        let problem = ProblemDetail {
+            title:   Some(title.to_string()),
+            status:  Some(status),
+            detail:  Some(detail.to_string()),
+            ..Default::default()
+        };
+        ApiResponse {
+            status,
+            content_type:  "application/problem+json".to_string(),
+            headers:       Vec::new(),
+            body:          serde_json::to_string(&problem).unwrap_or_default(),
+        }
+    }
+
+    ///  Creates the response for a storage error, 503 if it is transient.
+    ///
+    /// # Arguments
+    ///
+    ///*'err'-the StorageError
+    ///
+    /// # Return
+    ///
+    /// ApiResponse, 503 or 500
+    fn storage_error(err: StorageError) -> ApiResponse {
+        warn!("Api storage error: {}", err);
+        if err.transient {
+            ApiResponse::problem(503, "Service Unavailable", &err.to_string())
+        } else {
+            ApiResponse::problem(500, "Internal Server Error", &err.to_string())
+        }
+    }
+
+    ///  Adds a header.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the response
+    ///*'name'-the header name
+    ///*'value'-the header value
+    ///
+    /// # Return
+    ///
+    /// The response with the header
+    fn with_header(mut self, name: &str, value: &str) -> ApiResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+} // impl ApiResponse
+
+
+/// Response body format, from ?format= or the Accept header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// Represents a parsed api request.
+struct ApiRequest {
+    url:     Url,
+    params:  HashMap<String, String>,
+    format:  Format,
+}
+
+/// Implementation for ApiRequest.
+impl ApiRequest {
+
+    ///  Gets a query parameter.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the request
+    ///*'name'-the parameter
+    ///
+    /// # Return
+    ///
+    /// The value, None if missing or empty
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+
+    ///  Gets a query parameter as a normalized timestamp.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the request
+    ///*'name'-the parameter
+    ///
+    /// # Return
+    ///
+    /// The UTC timestamp, None if missing, or a 400 response
+    fn timestamp(&self, name: &str) -> Result<Option<String>, ApiResponse> {
+        match self.param(name) {
+            None => Ok(None),
+            Some(s) => parse_timestamp(s).map(Some).ok_or_else(|| ApiResponse::problem(400,
+                "Bad Request", &format!("{} {:?} is not an RFC 3339 timestamp or a date", name, s))),
+        }
+    }
+
+    ///  Gets a query parameter as a number.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the request
+    ///*'name'-the parameter
+    ///*'default'-the number when missing
+    ///
+    /// # Return
+    ///
+    /// The number, or a 400 response
+    fn number(&self, name: &str, default: usize) -> Result<usize, ApiResponse> {
+        match self.param(name) {
+            None => Ok(default),
+            Some(s) => s.parse().map_err(|_| ApiResponse::problem(400, "Bad Request",
+                &format!("{} {:?} is not a number", name, s))),
+        }
+    }
+
+    ///  Builds the link to the next page, this request with a new offset.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the request
+    ///*'offset'-the next page's offset
+    ///
+    /// # Return
+    ///
+    /// The path and query
+    fn next_link(&self, offset: usize) -> String {
+        let mut next = self.url.clone();
+        next.query_pairs_mut().clear();
+        for (k, v) in self.url.query_pairs().filter(|(k, _)| k != "offset") {
+            next.query_pairs_mut().append_pair(&k, &v);
+        }
+        next.query_pairs_mut().append_pair("offset", &offset.to_string());
+        format!("{}?{}", next.path(), next.query().unwrap_or(""))
+    }
+
+} // impl ApiRequest
+
+
+/// Represents the embedded http api over a Storage.
+///     Answers GET requests only:
+///
+/// * /stations
+/// * /stations/{id}/latest
+/// * /stations/{id}/observations?from&to&fields&limit&offset
+/// * /stations/{id}/summary?period=hour|day|month&from&to, from is required,
+///   periods are UTC, the local-time climate summaries are in the db's
+///   daily_summary_table and monthly_summary_table
+/// * /stations/{id}/windrose?from&to&bins, from is required, bins are km/h
+///   speed edges
+/// * /metrics, Prometheus text, when given the collector metrics
+///
+/// Lists are paged with limit and offset, and give the next page in a
+/// "next" member and a Link header. Responses are JSON unless
+/// ?format=csv or Accept: text/csv asks for CSV. Missing values are null,
+/// or empty in CSV. Summaries of more than MAX_RANGE_ROWS observations
+/// are refused with a 400, asking for a narrower from and to.
+pub struct Api {
+    storage:         Arc<dyn Storage>,
+    metrics:         Option<Arc<Metrics>>,
+    page_size:       usize,
+    max_page_size:   usize,
+    max_range_rows:  usize,
+}
+
+/// Implementation for the embedded http api.
+impl Api {
+
+    ///  Creates the api.
+    ///
+    /// # Arguments
+    ///
+    ///*'storage'-where the records are read from
+    ///*'cfg'-the api_section, PAGE_SIZE, MAX_PAGE_SIZE and MAX_RANGE_ROWS
+    ///     are optional
+    ///
+    /// # Return
+    ///
+    /// Api instance
+    pub fn new(storage: Arc<dyn Storage>, cfg: &HashMap<String, String>) -> Api {
+        let max_page_size = cfg.get("MAX_PAGE_SIZE").and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_PAGE_SIZE).max(1);
+        let page_size = cfg.get("PAGE_SIZE").and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, max_page_size);
+        let max_range_rows = cfg.get("MAX_RANGE_ROWS").and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_RANGE_ROWS).max(1);
+        Api { storage, metrics: None, page_size, max_page_size, max_range_rows }
+    }
+
+    ///  Serves the collector metrics on /metrics.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the api
+    ///*'metrics'-the collector metrics
+    ///
+    /// # Return
+    ///
+    /// Api instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Api {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    ///  Answers a request.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the api
+    ///*'method'-the http method
+    ///*'url'-the request target, path and query
+    ///*'accept'-the Accept header, if any
+    ///
+    /// # Return
+    ///
+    /// ApiResponse
+    pub async fn handle(&self, method: &str, url: &str, accept: Option<&str>) -> ApiResponse {
+        if method != "GET" {
+            return ApiResponse::problem(405, "Method Not Allowed",
+                                        &format!("{} is not supported", method))
+                .with_header("Allow", "GET");
+        }
+        let req = match parse_request(url, accept) {
+            Ok(r) => r,
+            Err(resp) => return resp,
+        };
+
+        let segments: Vec<String> = req.url.path_segments()
+            .map(|s| s.filter(|p| !p.is_empty()).map(|p| p.to_string()).collect())
+            .unwrap_or_default();
+        let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        let resp = match segments.as_slice() {
+            ["metrics"] if self.metrics.is_some() => Ok(self.metrics()),
+            ["stations"] => self.stations(&req).await,
+            ["stations", id, "latest"] => self.latest(id, &req).await,
+            ["stations", id, "observations"] => self.observations(id, &req).await,
+            ["stations", id, "summary"] => self.summary(id, &req).await,
+            ["stations", id, "windrose"] => self.wind_rose(id, &req).await,
+            _ => Err(ApiResponse::problem(404, "Not Found",
+                                          &format!("{} is not an api path", req.url.path()))),
+        };
+        resp.unwrap_or_else(|e| e)
+    }
+
+    ///  Gets the page limit and offset of a request.
+    fn page(&self, req: &ApiRequest) -> Result<(usize, usize), ApiResponse> {
+        let limit = req.number("limit", self.page_size)?;
+        if limit == 0 || limit > self.max_page_size {
+            return Err(ApiResponse::problem(400, "Bad Request",
+                &format!("limit must be between 1 and {}", self.max_page_size)));
+        }
+        Ok((limit, req.number("offset", 0)?))
+    }
+
+    ///  GET /metrics
+    fn metrics(&self) -> ApiResponse {
+        ApiResponse {
+            status:        200,
+            content_type:  "text/plain; version=0.0.4; charset=utf-8".to_string(),
+            headers:       Vec::new(),
+            body:          self.metrics.as_ref().map(|m| m.render()).unwrap_or_default(),
+        }
+    }
+
+    ///  GET /stations
+    async fn stations(&self, req: &ApiRequest) -> Result<ApiResponse, ApiResponse> {
+        let (limit, offset) = self.page(req)?;
+        let stations = self.storage.list_stations().await.map_err(ApiResponse::storage_error)?;
+        let more = stations.len() > offset + limit;
+        let rows: Vec<Row> = stations.iter().skip(offset).take(limit).map(tabular::to_row).collect();
+        let fields: Vec<String> = tabular::to_row(&StationRecord::default()).keys().cloned().collect();
+        Ok(page_response(req, "stations", json!({}), &fields, rows, offset,
+                         more.then_some(offset + limit)))
+    }
+
+    ///  GET /stations/{id}/latest
+    async fn latest(&self, id: &str, req: &ApiRequest) -> Result<ApiResponse, ApiResponse> {
+        let fields = observation_fields(req)?;
+        let rec = self.storage.latest_observation(id).await.map_err(ApiResponse::storage_error)?
+            .ok_or_else(|| ApiResponse::problem(404, "Not Found",
+                                                &format!("No observations for station {}", id)))?;
+        let row = tabular::select(&tabular::to_row(&rec), &fields);
+        Ok(match req.format {
+            Format::Json => ApiResponse::json(&Value::Object(row)),
+            Format::Csv => ApiResponse::csv(tabular::to_csv(&fields, &[row])),
+        })
+    }
+
+    ///  GET /stations/{id}/observations
+    async fn observations(&self, id: &str, req: &ApiRequest) -> Result<ApiResponse, ApiResponse> {
+        let fields = observation_fields(req)?;
+        let (limit, offset) = self.page(req)?;
+        // One extra row tells whether there is a next page
+        let query = ObservationQuery {
+            station_id:  id.to_string(),
+            from:        req.timestamp("from")?,
+            to:          req.timestamp("to")?,
+            limit:       limit + 1,
+            offset,
+        };
+        let mut recs = self.storage.observations(&query).await
+            .map_err(ApiResponse::storage_error)?;
+        let more = recs.len() > limit;
+        recs.truncate(limit);
+
+        let rows: Vec<Row> = recs.iter()
+            .map(|r| tabular::select(&tabular::to_row(r), &fields))
+            .collect();
+        Ok(page_response(req, "observations", json!({"station_id": id}), &fields, rows,
+                         offset, more.then_some(offset + limit)))
+    }
+
+    ///  GET /stations/{id}/summary
+    async fn summary(&self, id: &str, req: &ApiRequest) -> Result<ApiResponse, ApiResponse> {
+        let period = match req.param("period").unwrap_or("day") {
+            "hour" => Period::Hour,
+            "day" => Period::Day,
+            "month" => Period::Month,
+            p => return Err(ApiResponse::problem(400, "Bad Request",
+                &format!("period {:?} is not hour, day or month", p))),
+        };
+        let recs = self.read_range(id, req).await?;
+        let rows = summarize(&recs, period);
+        Ok(match req.format {
+            Format::Json => ApiResponse::json(&json!({
+                "station_id": id,
+                "period": period.name(),
+                "time_zone": "UTC",
+                "summaries": rows,
+            })),
+            Format::Csv => {
+                let rows: Vec<Row> = rows.into_iter().map(tabular::flatten).collect();
+                let fields: Vec<String> = summary_fields();
+                let rows: Vec<Row> = rows.iter().map(|r| tabular::select(r, &fields)).collect();
+                ApiResponse::csv(tabular::to_csv(&fields, &rows))
+            },
+        })
+    }
+
+    ///  GET /stations/{id}/windrose, over the same bounded range as summaries.
+    async fn wind_rose(&self, id: &str, req: &ApiRequest) -> Result<ApiResponse, ApiResponse> {
+        let edges = match req.param("bins") {
+            None => wind::DEFAULT_SPEED_BINS.to_vec(),
+            Some(b) => b.split(',').map(|e| e.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>()
+                .ok().filter(|e| !e.is_empty() && e.windows(2).all(|w| w[0] < w[1]))
+                .ok_or_else(|| ApiResponse::problem(400, "Bad Request",
+                    &format!("bins {:?} are not ascending km/h speeds, e.g. 1,10,20", b)))?,
+        };
+        let recs = self.read_range(id, req).await?;
+        let rose = wind::wind_rose(id, &recs, &edges);
+        Ok(match req.format {
+            Format::Json => ApiResponse::json(&json!(rose)),
+            Format::Csv => {
+                let mut fields = vec!["direction".to_string()];
+                fields.extend(rose.speed_bins.iter().cloned());
+                fields.push("total".to_string());
+                let mut rows: Vec<Row> = rose.directions.iter().map(|d| {
+                    let mut row = Row::new();
+                    row.insert("direction".to_string(), json!(d.direction));
+                    for (bin, count) in rose.speed_bins.iter().zip(&d.counts) {
+                        row.insert(bin.clone(), json!(count));
+                    }
+                    row.insert("total".to_string(), json!(d.counts.iter().sum::<u32>()));
+                    row
+                }).collect();
+                // Calm and variable winds have no speed bin or direction
+                for (name, count) in [(wind::CALM, rose.calm), (wind::VARIABLE, rose.variable)] {
+                    let mut row = Row::new();
+                    row.insert("direction".to_string(), json!(name));
+                    row.insert("total".to_string(), json!(count));
+                    rows.push(row);
+                }
+                let rows: Vec<Row> = rows.iter().map(|r| tabular::select(r, &fields)).collect();
+                ApiResponse::csv(tabular::to_csv(&fields, &rows))
+            },
+        })
+    }
+
+    ///  Reads a station's observations in the request's from and to, a
+    ///      page at a time. from is required, and a range of more than
+    ///      max_range_rows observations is a 400.
+    async fn read_range(&self, id: &str, req: &ApiRequest)
+                                    -> Result<Vec<ObservationRecord>, ApiResponse> {
+        let from = req.timestamp("from")?.ok_or_else(|| ApiResponse::problem(400,
+            "Bad Request", "from is required, an RFC 3339 timestamp or a date"))?;
+        let mut query = ObservationQuery {
+            station_id:  id.to_string(),
+            from:        Some(from),
+            to:          req.timestamp("to")?,
+            limit:       self.max_page_size,
+            offset:      0,
+        };
+        let mut recs = Vec::new();
+        loop {
+            let page = self.storage.observations(&query).await
+                .map_err(ApiResponse::storage_error)?;
+            let done = page.len() < query.limit;
+            recs.extend(page);
+            if recs.len() > self.max_range_rows {
+                return Err(ApiResponse::problem(400, "Bad Request", &format!(
+                    "more than {} observations from {:?} to {:?}, narrow the range",
+                    self.max_range_rows, query.from.unwrap_or_default(),
+                    query.to.unwrap_or_default())));
+            }
+            if done {
+                return Ok(recs);
+            }
+            query.offset += query.limit;
+        }
+    }
+
+} // impl Api
+
+
+/// Summary period, observations are grouped by UTC hour, day or month,
+///     not the station's local day as in climate::DailySummary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Hour,
+    Day,
+    Month,
+}
+
+/// Implementation for Period.
+impl Period {
+
+    ///  Gets the period name, as used in ?period=.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+            Period::Month => "month",
+        }
+    }
+
+    ///  Gets the period a timestamp falls in, a prefix of the timestamp,
+    ///      e.g. "2024-04-01T12", "2024-04-01" or "2024-04".
+    pub fn key<'a>(&self, timestamp_utc: &'a str) -> &'a str {
+        let len = match self {
+            Period::Hour => 13,
+            Period::Day => 10,
+            Period::Month => 7,
+        };
+        timestamp_utc.get(..len).unwrap_or(timestamp_utc)
+    }
+}
+
+
+///  Summarizes observations by UTC period.
+///      For each of temperature_C, dewpoint_C, rel_humidity, wind_spd_km_h,
+///      wind_gust_km_h and baro_pres_pa gives the min, max, mean and count
+///      of the values that are not missing.
+///
+/// # Arguments
+///
+///*'recs'-the observation records, of one station
+///*'period'-the grouping
+///
+/// # Return
+///
+/// A row per period, oldest first, with period, observations and a
+/// {min, max, mean, count} object per field
+pub fn summarize(recs: &[ObservationRecord], period: Period) -> Vec<Row> {
+    let mut groups: BTreeMap<String, Vec<Row>> = BTreeMap::new();
+    for rec in recs {
+        groups.entry(period.key(&rec.timestamp_UTC).to_string()).or_default()
+            .push(tabular::to_row(rec));
+    }
+
+    groups.into_iter().map(|(key, rows)| {
+        let mut out = Row::new();
+        out.insert("period".to_string(), json!(key));
+        out.insert("observations".to_string(), json!(rows.len()));
+        for field in SUMMARY_FIELDS {
+            let values: Vec<f64> = rows.iter()
+                .filter_map(|r| r.get(field).and_then(|v| v.as_f64()))
+                .collect();
+            let stats = if values.is_empty() {
+                json!({"min": null, "max": null, "mean": null, "count": 0})
+            } else {
+                json!({
+                    "min": values.iter().cloned().fold(f64::INFINITY, f64::min),
+                    "max": values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                    "mean": values.iter().sum::<f64>() / values.len() as f64,
+                    "count": values.len(),
+                })
+            };
+            out.insert(field.to_string(), stats);
+        }
+        out
+    }).collect()
+}
+
+///  Gets the CSV columns of a summary.
+fn summary_fields() -> Vec<String> {
+    let mut fields = vec!["period".to_string(), "observations".to_string()];
+    for field in SUMMARY_FIELDS {
+        for stat in ["min", "max", "mean", "count"] {
+            fields.push(format!("{}_{}", field, stat));
+        }
+    }
+    fields
+}
+
+///  Gets the observation fields a request asks for.
+///      station_id and timestamp_UTC are always included, first.
+fn observation_fields(req: &ApiRequest) -> Result<Vec<String>, ApiResponse> {
+    let all: Vec<String> = tabular::fields::<ObservationRecord>().keys().cloned().collect();
+    let wanted = match req.param("fields") {
+        None => return Ok(all),
+        Some(f) => f,
+    };
+
+    let mut fields = vec!["station_id".to_string(), "timestamp_UTC".to_string()];
+    for f in wanted.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        if !all.iter().any(|a| a == f) {
+            return Err(ApiResponse::problem(400, "Bad Request",
+                &format!("unknown field {:?}, fields are {}", f, all.join(", "))));
+        }
+        if !fields.iter().any(|x| x == f) {
+            fields.push(f.to_string());
+        }
+    }
+    Ok(fields)
+}
+
+///  Builds a paged list response.
+fn page_response(req: &ApiRequest, name: &str, envelope: Value, fields: &[String],
+                 rows: Vec<Row>, offset: usize, next_offset: Option<usize>) -> ApiResponse {
+    let next = next_offset.map(|o| req.next_link(o));
+    let resp = match req.format {
+        Format::Json => {
+            let mut body = match envelope {
+                Value::Object(m) => m,
+                _ => Row::new(),
+            };
+            body.insert("count".to_string(), json!(rows.len()));
+            body.insert("offset".to_string(), json!(offset));
+            body.insert("next".to_string(), json!(next));
+            body.insert(name.to_string(), Value::Array(rows.into_iter().map(Value::Object).collect()));
+            ApiResponse::json(&Value::Object(body))
+        },
+        Format::Csv => ApiResponse::csv(tabular::to_csv(fields, &rows)),
+    };
+    match next {
+        Some(n) => resp.with_header("Link", &format!("<{}>; rel=\"next\"", n)),
+        None => resp,
+    }
+}
+
+///  Parses a request target and picks the response format.
+fn parse_request(target: &str, accept: Option<&str>) -> Result<ApiRequest, ApiResponse> {
+    let url = Url::parse("http://localhost").and_then(|base| base.join(target))
+        .map_err(|e| ApiResponse::problem(400, "Bad Request", &e.to_string()))?;
+    let params: HashMap<String, String> = url.query_pairs()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    let format = match params.get("format").map(|f| f.as_str()) {
+        Some("csv") => Format::Csv,
+        Some("json") => Format::Json,
+        Some(f) => return Err(ApiResponse::problem(400, "Bad Request",
+                                                   &format!("format {:?} is not json or csv", f))),
+        None if accept.is_some_and(|a| a.contains("text/csv")) => Format::Csv,
+        None => Format::Json,
+    };
+
+    Ok(ApiRequest { url, params, format })
+}
+
+///  Gets the address to serve the api on.
+///
+/// # Arguments
+///
+///*'cfg'-the api_section
+///
+/// # Return
+///
+/// The BIND address, None if the api is not configured
+pub fn bind_address(cfg: &HashMap<String, String>) -> Option<String> {
+    cfg.get("BIND").map(|b| b.trim().to_string()).filter(|b| !b.is_empty())
+}
+
+///  Serves the api on a background thread, each request is answered
+///      on its own task.
+///
+/// # Arguments
+///
+///*'api'-the api
+///*'bind'-the address, e.g. "127.0.0.1:8080", port 0 picks a free port
+///
+/// # Return
+///
+/// The address the api listens on
+pub fn spawn(api: Arc<Api>, bind: &str) -> io::Result<SocketAddr> {
+    let server = tiny_http::Server::http(bind).map_err(io::Error::other)?;
+    let addr = server.server_addr().to_ip()
+        .ok_or_else(|| io::Error::other("api is not listening on an ip address"))?;
+    info!("Api listening on http://{}", addr);
+
+    thread::Builder::new().name("api".to_string()).spawn(move || {
+        for request in server.incoming_requests() {
+            let api = api.clone();
+            task::spawn(async move {
+                let accept = request.headers().iter()
+                    .find(|h| h.field.equiv("Accept"))
+                    .map(|h| h.value.as_str().to_string());
+                let resp = api.handle(request.method().as_str(), request.url(),
+                                      accept.as_deref()).await;
+                debug!("Api {} {} -> {}", request.method(), request.url(), resp.status);
+
+                let mut out = tiny_http::Response::from_string(resp.body)
+                    .with_status_code(resp.status);
+                let headers = std::iter::once(("Content-Type".to_string(), resp.content_type))
+                    .chain(resp.headers);
+                for (k, v) in headers {
+                    if let Ok(h) = tiny_http::Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+                        out.add_header(h);
+                    }
+                }
+                if let Err(e) = request.respond(out) {
+                    warn!("Api could not send response: {:?}", e);
+                }
+            });
+        }
+    })?;
+
+    Ok(addr)
+}
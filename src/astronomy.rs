@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::station::ObservationRecord;
+
+/// Sun altitude at sunrise and sunset, for refraction and the sun's radius, degrees.
+pub const SUNRISE_ALTITUDE: f64 = -0.833;
+
+/// The sun's radius, the apparent altitude of its centre as its top edge
+///     crosses the horizon, degrees.
+pub const SUN_SEMI_DIAMETER: f64 = 0.267;
+
+/// Sun altitudes at the start of civil, nautical and astronomical twilight, degrees.
+pub const CIVIL_TWILIGHT: f64 = -6.0;
+pub const NAUTICAL_TWILIGHT: f64 = -12.0;
+pub const ASTRONOMICAL_TWILIGHT: f64 = -18.0;
+
+/// Represents where the sun is in the sky.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SolarPosition {
+    /// Above the horizon, corrected for refraction, degrees.
+    pub elevation_deg:  f64,
+    /// Clockwise from north, degrees.
+    pub azimuth_deg:    f64,
+}
+
+/// Represents the sun's day at a place: rise, set, twilights and noon.
+///     Events that don't happen, e.g. sunset in the polar summer, are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SunTimes {
+    pub date:               NaiveDate,
+    pub astronomical_dawn:  Option<DateTime<Utc>>,
+    pub nautical_dawn:      Option<DateTime<Utc>>,
+    pub civil_dawn:         Option<DateTime<Utc>>,
+    pub sunrise:            Option<DateTime<Utc>>,
+    pub solar_noon:         DateTime<Utc>,
+    pub sunset:             Option<DateTime<Utc>>,
+    pub civil_dusk:         Option<DateTime<Utc>>,
+    pub nautical_dusk:      Option<DateTime<Utc>>,
+    pub astronomical_dusk:  Option<DateTime<Utc>>,
+    /// Sunrise to sunset, a whole day or none when the sun doesn't set or rise.
+    pub day_length_secs:    i64,
+    pub noon_elevation_deg: f64,
+}
+
+/// Represents the sun's declination and the equation of time at an instant.
+struct Sun {
+    declination:  f64,
+    /// Apparent minus mean solar time, minutes.
+    equation:     f64,
+}
+
+///  Gets the sun's declination and equation of time, NOAA's formulas
+///      after Meeus.
+fn sun(t: DateTime<Utc>) -> Sun {
+    let jd = t.timestamp_millis() as f64 / 86_400_000.0 + 2_440_587.5;
+    let c = (jd - 2_451_545.0) / 36_525.0;
+
+    let mean_long = (280.46646 + c * (36000.76983 + c * 0.0003032)).rem_euclid(360.0);
+    let mean_anom = 357.52911 + c * (35999.05029 - 0.0001537 * c);
+    let ecc = 0.016708634 - c * (0.000042037 + 0.0000001267 * c);
+    let m = mean_anom.to_radians();
+    let center = m.sin() * (1.914602 - c * (0.004817 + 0.000014 * c))
+        + (2.0 * m).sin() * (0.019993 - 0.000101 * c) + (3.0 * m).sin() * 0.000289;
+    let omega = (125.04 - 1934.136 * c).to_radians();
+    let apparent_long = (mean_long + center - 0.00569 - 0.00478 * omega.sin()).to_radians();
+    let mean_obliq = 23.0 + (26.0 + (21.448 - c * (46.815 + c * (0.00059 - c * 0.001813)))
+        / 60.0) / 60.0;
+    let obliq = (mean_obliq + 0.00256 * omega.cos()).to_radians();
+
+    let y = (obliq / 2.0).tan().powi(2);
+    let l0 = mean_long.to_radians();
+    let equation = 4.0 * (y * (2.0 * l0).sin() - 2.0 * ecc * m.sin()
+        + 4.0 * ecc * y * m.sin() * (2.0 * l0).cos() - 0.5 * y * y * (4.0 * l0).sin()
+        - 1.25 * ecc * ecc * (2.0 * m).sin()).to_degrees();
+    Sun { declination: (obliq.sin() * apparent_long.sin()).asin(), equation }
+}
+
+///  Gets the refraction correction of a geometric elevation, NOAA's
+///      approximation, degrees.
+fn refraction(elevation: f64) -> f64 {
+    let tan = elevation.to_radians().tan();
+    let arcsec = if elevation > 85.0 {
+        0.0
+    } else if elevation > 5.0 {
+        58.1 / tan - 0.07 / tan.powi(3) + 0.000086 / tan.powi(5)
+    } else if elevation > -0.575 {
+        1735.0 + elevation * (-518.2 + elevation * (103.4 + elevation * (-12.79
+            + elevation * 0.711)))
+    } else {
+        -20.772 / tan
+    };
+    arcsec / 3600.0
+}
+
+///  Gets the sun's position seen from a place at a time.
+///
+/// # Arguments
+///
+///*'latitude'-degrees north
+///*'longitude'-degrees east
+///*'t'-the time
+///
+/// # Return
+///
+/// SolarPosition
+pub fn solar_position(latitude: f64, longitude: f64, t: DateTime<Utc>) -> SolarPosition {
+    let s = sun(t);
+    let minutes = t.timestamp_millis().rem_euclid(86_400_000) as f64 / 60_000.0;
+    let true_solar = minutes + s.equation + 4.0 * longitude;
+    let hour_angle = (true_solar / 4.0 - 180.0).to_radians();
+    let lat = latitude.to_radians();
+
+    let cos_zenith = lat.sin() * s.declination.sin()
+        + lat.cos() * s.declination.cos() * hour_angle.cos();
+    let elevation = 90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees();
+    let azimuth = hour_angle.sin()
+        .atan2(hour_angle.cos() * lat.sin() - s.declination.tan() * lat.cos())
+        .to_degrees() + 180.0;
+    SolarPosition {
+        elevation_deg:  elevation + refraction(elevation),
+        azimuth_deg:    azimuth.rem_euclid(360.0),
+    }
+}
+
+///  Gets the sun altitude of sunrise and sunset seen from a height, the
+///      horizon dips below level from above the ground.
+///
+/// # Arguments
+///
+///*'height_m'-the observer's height above the surrounding terrain, m, not
+///     the elevation above sea level
+///
+/// # Return
+///
+/// The altitude, degrees
+pub fn horizon(height_m: f64) -> f64 {
+    SUNRISE_ALTITUDE - 2.076 * height_m.max(0.0).sqrt() / 60.0
+}
+
+///  Gets a UTC date's midnight plus minutes.
+fn at_minutes(date: NaiveDate, minutes: f64) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+        + Duration::milliseconds((minutes * 60_000.0).round() as i64)
+}
+
+///  Finds when the sun crosses an altitude, rising or setting, on the
+///      day of a solar noon. Refined once at the first estimate.
+fn crossing(date: NaiveDate, latitude: f64, longitude: f64, altitude: f64, rising: bool)
+                                                            -> Option<DateTime<Utc>> {
+    let lat = latitude.to_radians();
+    let mut t = solar_noon(date, longitude);
+    for _ in 0..2 {
+        let s = sun(t);
+        let cos_ha = (altitude.to_radians().sin() - lat.sin() * s.declination.sin())
+            / (lat.cos() * s.declination.cos());
+        if !(-1.0..=1.0).contains(&cos_ha) {
+            return None;
+        }
+        let ha = cos_ha.acos().to_degrees();
+        let ha = if rising { ha } else { -ha };
+        t = at_minutes(date, 720.0 - 4.0 * (longitude + ha) - s.equation);
+    }
+    Some(t)
+}
+
+///  Gets the solar noon of a date at a longitude.
+///
+/// # Arguments
+///
+///*'date'-the date
+///*'longitude'-degrees east
+///
+/// # Return
+///
+/// The UTC time
+pub fn solar_noon(date: NaiveDate, longitude: f64) -> DateTime<Utc> {
+    let mut t = at_minutes(date, 720.0 - 4.0 * longitude);
+    for _ in 0..2 {
+        t = at_minutes(date, 720.0 - 4.0 * longitude - sun(t).equation);
+    }
+    t
+}
+
+///  Gets the sun's day at a place: sunrise, sunset, the civil, nautical
+///      and astronomical twilights, solar noon and the day length.
+///
+/// # Arguments
+///
+///*'latitude'-degrees north
+///*'longitude'-degrees east
+///*'height_m'-the observer's height above the surrounding terrain, m,
+///     lowers the horizon, 0 for a station on the ground
+///*'date'-the local date
+///
+/// # Return
+///
+/// SunTimes
+pub fn sun_times(latitude: f64, longitude: f64, height_m: f64, date: NaiveDate) -> SunTimes {
+    let event = |altitude, rising| crossing(date, latitude, longitude, altitude, rising);
+    let solar_noon = solar_noon(date, longitude);
+    let noon_elevation_deg = solar_position(latitude, longitude, solar_noon).elevation_deg;
+    let (sunrise, sunset) = (event(horizon(height_m), true), event(horizon(height_m), false));
+    let day_length_secs = match (sunrise, sunset) {
+        (Some(rise), Some(set)) => (set - rise).num_seconds(),
+        _ if noon_elevation_deg > -SUN_SEMI_DIAMETER => 86_400,
+        _ => 0,
+    };
+    SunTimes {
+        date,
+        astronomical_dawn:  event(ASTRONOMICAL_TWILIGHT, true),
+        nautical_dawn:      event(NAUTICAL_TWILIGHT, true),
+        civil_dawn:         event(CIVIL_TWILIGHT, true),
+        sunrise,
+        solar_noon,
+        sunset,
+        civil_dusk:         event(CIVIL_TWILIGHT, false),
+        nautical_dusk:      event(NAUTICAL_TWILIGHT, false),
+        astronomical_dusk:  event(ASTRONOMICAL_TWILIGHT, false),
+        day_length_secs,
+        noon_elevation_deg,
+    }
+}
+
+///  Tags an observation with the sun's elevation, and day or night: day
+///      while the top of the sun is above the horizon.
+///
+/// # Arguments
+///
+///*'rec'-the observation
+///*'latitude'-the station's degrees north
+///*'longitude'-the station's degrees east
+///
+/// # Return
+///
+/// None, observations without a valid time are left as they are
+pub fn tag(rec: &mut ObservationRecord, latitude: f64, longitude: f64) {
+    let Ok(t) = DateTime::parse_from_rfc3339(&rec.timestamp_UTC) else { return };
+    let position = solar_position(latitude, longitude, t.with_timezone(&Utc));
+    rec.sun_elevation_deg = position.elevation_deg;
+    rec.day_night = if position.elevation_deg > -SUN_SEMI_DIAMETER { "day" }
+                    else { "night" }.to_string();
+}
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use chrono::NaiveDate;
+
+/// Usage, printed for --help and bad arguments.
+pub const USAGE: &str = "\
+Usage: weather_gov [COMMAND] [OPTIONS]
+
+Commands:
+    collect             Poll the configured stations and store observations (default)
+    spool-status        Print the depth of the observation spool and exit
+    export              Export stored observations to files and exit
+    summarize           Compute the daily and monthly climate summaries of stored
+                        observations and exit
+    sun                 Print a station's sunrise, sunset, twilight and solar noon
+                        and exit
+
+Options for collect:
+    --record <dir>      Write every api.weather.gov request/response to <dir>
+    --replay <dir>      Serve responses recorded with --record instead of the network,
+                        in accelerated time, then exit
+
+Options for export:
+    --format <format>   parquet, files partitioned by station and month
+    --from <time>       First observation time, a date or RFC 3339 time
+    --to <time>         Observations before this time
+    --stations <ids>    Comma separated station ids, default every stored station
+    --out <dir>         Directory to write to, default ./export
+
+Options for summarize:
+    --from <date>       First local date, YYYY-MM-DD
+    --to <date>         Local date after the last, default tomorrow (UTC)
+    --stations <ids>    Comma separated station ids, default every stored station
+
+Options for sun:
+    --station <id>      The station id, looked up on api.weather.gov
+    --date <date>       The station's local date, YYYY-MM-DD, default today
+
+    -h, --help          Print this help";
+
+/// Represents the command line.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Collect {
+        record:  Option<PathBuf>,
+        replay:  Option<PathBuf>,
+    },
+    SpoolStatus,
+    Export {
+        format:    String,
+        /// Stored UTC timestamps, see storage::parse_timestamp.
+        from:      Option<String>,
+        to:        Option<String>,
+        stations:  Vec<String>,
+        out:       PathBuf,
+    },
+    Summarize {
+        from:      NaiveDate,
+        /// Exclusive, None for tomorrow.
+        to:        Option<NaiveDate>,
+        stations:  Vec<String>,
+    },
+    Sun {
+        station:   String,
+        /// The station's local date, None for today.
+        date:      Option<NaiveDate>,
+    },
+    Help,
+}
+
+///  Parses the command line arguments, without the program name.
+///
+/// # Arguments
+///
+///*'args'-the arguments
+///
+/// # Return
+///
+/// Command, or a message saying what is wrong
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(|a| a.as_str()) {
+        Some("spool-status") => { args.next(); "spool-status" },
+        Some("collect") => { args.next(); "collect" },
+        Some("export") => { args.next(); "export" },
+        Some("summarize") => { args.next(); "summarize" },
+        Some("sun") => { args.next(); "sun" },
+        _ => "collect",
+    };
+
+    let mut record = None;
+    let mut replay = None;
+    let mut format = None;
+    let mut from = None;
+    let mut to = None;
+    let mut stations = Vec::new();
+    let mut out = PathBuf::from("./export");
+    let mut from_date = None;
+    let mut to_date = None;
+    let mut station = None;
+    let mut sun_date = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--record" if command == "collect" => record = Some(value(&arg, args.next())?),
+            "--replay" if command == "collect" => replay = Some(value(&arg, args.next())?),
+            "--format" if command == "export" => format = Some(text(&arg, args.next())?),
+            "--from" if command == "export" => from = Some(time(&arg, args.next())?),
+            "--to" if command == "export" => to = Some(time(&arg, args.next())?),
+            "--from" if command == "summarize" => from_date = Some(date(&arg, args.next())?),
+            "--to" if command == "summarize" => to_date = Some(date(&arg, args.next())?),
+            "--stations" if command == "export" || command == "summarize" =>
+                stations = text(&arg, args.next())?.split(',').map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty()).collect(),
+            "--out" if command == "export" => out = value(&arg, args.next())?,
+            "--station" if command == "sun" => station = Some(text(&arg, args.next())?),
+            "--date" if command == "sun" => sun_date = Some(date(&arg, args.next())?),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    match command {
+        "spool-status" => Ok(Command::SpoolStatus),
+        "export" => match format.as_deref() {
+            Some("parquet") => Ok(Command::Export {
+                format: "parquet".to_string(), from, to, stations, out,
+            }),
+            Some(f) => Err(format!("export format {:?} is not supported, use parquet", f)),
+            None => Err("export needs --format".to_string()),
+        },
+        "summarize" => match from_date {
+            Some(from) => Ok(Command::Summarize { from, to: to_date, stations }),
+            None => Err("summarize needs --from".to_string()),
+        },
+        "sun" => match station {
+            Some(station) => Ok(Command::Sun { station: station.trim().to_uppercase(),
+                                               date: sun_date }),
+            None => Err("sun needs --station".to_string()),
+        },
+        _ if record.is_some() && replay.is_some() =>
+            Err("--record and --replay can't be used together".to_string()),
+        _ => Ok(Command::Collect { record, replay }),
+    }
+}
+
+///  Gets an option's value as a path.
+fn value(option: &str, v: Option<String>) -> Result<PathBuf, String> {
+    match v {
+        Some(v) if !v.starts_with("--") => Ok(PathBuf::from(v)),
+        _ => Err(format!("{} needs a value", option)),
+    }
+}
+
+///  Gets an option's value as text.
+fn text(option: &str, v: Option<String>) -> Result<String, String> {
+    value(option, v).map(|p| p.to_string_lossy().to_string())
+}
+
+///  Gets an option's value as a stored UTC timestamp.
+fn time(option: &str, v: Option<String>) -> Result<String, String> {
+    let t = text(option, v)?;
+    weather_gov::storage::parse_timestamp(&t)
+        .ok_or_else(|| format!("{} {:?} is not a date or RFC 3339 time", option, t))
+}
+
+///  Gets an option's value as a date.
+fn date(option: &str, v: Option<String>) -> Result<NaiveDate, String> {
+    let d = text(option, v)?;
+    NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("{} {:?} is not a YYYY-MM-DD date", option, d))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse(args(&[])), Ok(Command::Collect { record: None, replay: None }));
+        assert_eq!(parse(args(&["spool-status"])), Ok(Command::SpoolStatus));
+        assert_eq!(parse(args(&["--replay", "rec"])),
+                   Ok(Command::Collect { record: None, replay: Some(PathBuf::from("rec")) }));
+        assert_eq!(parse(args(&["collect", "--record", "rec"])),
+                   Ok(Command::Collect { record: Some(PathBuf::from("rec")), replay: None }));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(args(&["--record"])).is_err());
+        assert!(parse(args(&["--record", "a", "--replay", "b"])).is_err());
+        assert!(parse(args(&["spool-status", "--record", "a"])).is_err());
+        assert!(parse(args(&["--bogus"])).is_err());
+        assert!(parse(args(&["export"])).is_err());
+        assert!(parse(args(&["export", "--format", "xls"])).is_err());
+        assert!(parse(args(&["export", "--format", "parquet", "--from", "soon"])).is_err());
+        assert!(parse(args(&["--from", "2024-04-01"])).is_err());
+        assert!(parse(args(&["summarize"])).is_err());
+        assert!(parse(args(&["summarize", "--from", "2024-04-01T00:00:00Z"])).is_err());
+        assert!(parse(args(&["sun"])).is_err());
+        assert!(parse(args(&["sun", "--station", "KPHX", "--date", "today"])).is_err());
+    }
+
+    #[test]
+    fn parses_export_and_summarize() {
+        assert_eq!(parse(args(&["export", "--format", "parquet", "--from", "2024-04-01",
+                                "--to", "2024-05-01T00:00:00-07:00", "--stations", "KPHX, KTUS",
+                                "--out", "/tmp/wx"])),
+                   Ok(Command::Export {
+                       format:    "parquet".to_string(),
+                       from:      Some("2024-04-01T00:00:00+00:00".to_string()),
+                       to:        Some("2024-05-01T07:00:00+00:00".to_string()),
+                       stations:  vec!["KPHX".to_string(), "KTUS".to_string()],
+                       out:       PathBuf::from("/tmp/wx"),
+                   }));
+        assert_eq!(parse(args(&["summarize", "--from", "2024-04-01", "--stations", "KPHX"])),
+                   Ok(Command::Summarize {
+                       from:      NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+                       to:        None,
+                       stations:  vec!["KPHX".to_string()],
+                   }));
+    }
+
+    #[test]
+    fn parses_sun() {
+        assert_eq!(parse(args(&["sun", "--station", "kphx"])),
+                   Ok(Command::Sun { station: "KPHX".to_string(), date: None }));
+        assert_eq!(parse(args(&["sun", "--station", "KPHX", "--date", "2024-06-21"])),
+                   Ok(Command::Sun {
+                       station:   "KPHX".to_string(),
+                       date:      NaiveDate::from_ymd_opt(2024, 6, 21),
+                   }));
+    }
+}
@@ -0,0 +1,341 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use log::debug;
+use serde::de::DeserializeOwned;
+use crate::error::{WeatherGovError, ProblemDetail};
+use crate::metrics::{Metrics, HTTP_DURATION};
+use crate::replay::{Exchange, Recorder, Replayer};
+use crate::models::{decode, StationFeature, ObservationFeature, ObservationCollection,
+                    PointFeature, ForecastFeature, AlertCollection};
+
+/// api.weather.gov base url.
+pub const DEFAULT_BASE_URL: &str = "https://api.weather.gov";
+
+/// api.weather.gov requires User-Agent be set, but reqwest does not
+/// set one. See weather.gov.
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (X11; Linux i686; rv:124.0)\
+                                      Gecko/20100101 Firefox/124.0";
+
+/// Where the client's responses come from.
+#[derive(Debug, Clone)]
+pub enum HttpMode {
+    /// From api.weather.gov.
+    Live,
+    /// From api.weather.gov, and every exchange is written to disk.
+    Record(Arc<Recorder>),
+    /// From a recording, the network is not used.
+    Replay(Arc<Replayer>),
+}
+
+/// Represents an api.weather.gov client.
+///     Cloning is cheap, clones share the connection pool and recording.
+#[derive(Debug, Clone)]
+pub struct NwsClient {
+    pub base_url:     String,
+    pub user_agent:   String,
+    pub mode:         HttpMode,
+    pub metrics:      Option<Arc<Metrics>>,
+    http:             reqwest::Client,
+}
+
+
+/// Implementation for the api.weather.gov client.
+impl NwsClient {
+
+    ///  Creates a new client.
+    ///
+    /// # Arguments
+    ///
+    ///*'base_url'-the api base url, e.g. https://api.weather.gov
+    ///
+    /// # Return
+    ///
+    /// NwsClient instance
+    pub fn new(base_url: &str) -> NwsClient {
+        Self {
+            base_url:    base_url.trim_end_matches('/').to_string(),
+            user_agent:  DEFAULT_USER_AGENT.to_string(),
+            mode:        HttpMode::Live,
+            metrics:     None,
+            http:        reqwest::Client::new(),
+        }
+    }
+
+    ///  Sets the User-Agent sent with every request.
+    ///      weather.gov asks for an app name and contact.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'user_agent'-the User-Agent
+    ///
+    /// # Return
+    ///
+    /// NwsClient instance
+    pub fn with_user_agent(mut self, user_agent: &str) -> NwsClient {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    ///  Records every exchange, for --record.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'recorder'-the recorder
+    ///
+    /// # Return
+    ///
+    /// NwsClient instance
+    pub fn with_recorder(mut self, recorder: Recorder) -> NwsClient {
+        self.mode = HttpMode::Record(Arc::new(recorder));
+        self
+    }
+
+    ///  Serves responses from a recording instead of the network, for --replay.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'replayer'-the loaded recording
+    ///
+    /// # Return
+    ///
+    /// NwsClient instance
+    pub fn with_replayer(mut self, replayer: Replayer) -> NwsClient {
+        self.mode = HttpMode::Replay(Arc::new(replayer));
+        self
+    }
+
+    ///  Records the latency of every api.weather.gov request.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'metrics'-the collector metrics
+    ///
+    /// # Return
+    ///
+    /// NwsClient instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> NwsClient {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    ///  Gets how many recorded responses are left, when replaying.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///
+    /// # Return
+    ///
+    /// Some(count) when replaying, None otherwise
+    pub fn replay_remaining(&self) -> Option<usize> {
+        match &self.mode {
+            HttpMode::Replay(r) => Some(r.remaining()),
+            _ => None,
+        }
+    }
+
+    ///  Gets the stations url, e.g. https://api.weather.gov/stations/
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///
+    /// # Return
+    ///
+    /// The stations url, with a trailing slash
+    pub fn stations_url(&self) -> String {
+        format!("{}/stations/", self.base_url)
+    }
+
+    ///  Gets a json document from api.weather.gov.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'url'-the url to get
+    ///
+    /// # Return
+    ///
+    /// The response body and its decoded model, or WeatherGovError
+    pub async fn get<T: DeserializeOwned>(&self, url: &str)
+                                -> Result<(String, T), WeatherGovError> {
+        let resp = self.fetch(url).await?;
+
+        let status = resp.status;
+        if status == 429 {
+            let retry_after = resp.header(reqwest::header::RETRY_AFTER.as_str())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(WeatherGovError::RateLimited { url: url.to_string(), retry_after });
+        }
+
+        let rtext = resp.body;
+        if !(200..300).contains(&status) {
+            // Error bodies are application/problem+json, but don't count on it
+            let problem = serde_json::from_str::<ProblemDetail>(&rtext).ok().map(Box::new);
+            return Err(WeatherGovError::Status {
+                url: url.to_string(),
+                status,
+                problem,
+            });
+        }
+
+        let model = decode::<T>(url, &rtext)?;
+        Ok((rtext, model))
+    }
+
+    ///  Gets a response, from the network or the recording.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'url'-the url to get
+    ///
+    /// # Return
+    ///
+    /// The response as an Exchange, or WeatherGovError
+    async fn fetch(&self, url: &str) -> Result<Exchange, WeatherGovError> {
+        if let HttpMode::Replay(replayer) = &self.mode {
+            debug!("REPLAY {}", url);
+            return replayer.next(url)
+                .ok_or_else(|| WeatherGovError::ReplayExhausted { url: url.to_string() });
+        }
+
+        debug!("GET {}", url);
+        let started = Instant::now();
+        let result = self.http.get(url)
+            .header("Accept", "application/geo+json")
+            .header("User-Agent", self.user_agent.as_str())
+            .send().await;
+        if let Some(metrics) = &self.metrics {
+            let status = match &result {
+                Ok(resp) => resp.status().as_u16().to_string(),
+                Err(_) => "error".to_string(),
+            };
+            metrics.observe_duration(HTTP_DURATION, &[("status", &status)], started.elapsed());
+        }
+        let resp = result?;
+        let status = resp.status().as_u16();
+        let headers: Vec<(String, String)> = resp.headers().iter()
+            .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).to_string()))
+            .collect();
+        let body = resp.text().await?;
+
+        if let HttpMode::Record(recorder) = &self.mode {
+            recorder.record(url, status, &headers, &body);
+        }
+
+        Ok(Exchange {
+            url: url.to_string(),
+            status,
+            headers,
+            body,
+            timestamp: Utc::now().to_rfc3339(),
+        })
+    }
+
+    ///  Gets a station's meta data.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'station_id'-the station id, e.g. KPHX
+    ///
+    /// # Return
+    ///
+    /// StationFeature
+    pub async fn get_station(&self, station_id: &str) -> Result<StationFeature, WeatherGovError> {
+        let url = format!("{}{}", self.stations_url(), station_id);
+        Ok(self.get(&url).await?.1)
+    }
+
+    ///  Gets a station's latest observation.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'station_id'-the station id, e.g. KPHX
+    ///
+    /// # Return
+    ///
+    /// ObservationFeature
+    pub async fn get_latest_observation(&self, station_id: &str)
+                                -> Result<ObservationFeature, WeatherGovError> {
+        let url = format!("{}{}/observations/latest", self.stations_url(), station_id);
+        Ok(self.get(&url).await?.1)
+    }
+
+    ///  Gets a station's observations between two times.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'station_id'-the station id, e.g. KPHX
+    ///*'start'-ISO 8601 start time
+    ///*'end'-ISO 8601 end time
+    ///
+    /// # Return
+    ///
+    /// ObservationCollection, newest first
+    pub async fn get_observations(&self, station_id: &str, start: &str, end: &str)
+                                -> Result<ObservationCollection, WeatherGovError> {
+        let url = format!("{}{}/observations?start={}&end={}", self.stations_url(),
+                          station_id, start, end);
+        Ok(self.get(&url).await?.1)
+    }
+
+    ///  Gets the forecast office and grid for a point.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'latitude'-degrees north
+    ///*'longitude'-degrees east
+    ///
+    /// # Return
+    ///
+    /// PointFeature
+    pub async fn get_point(&self, latitude: f64, longitude: f64)
+                                -> Result<PointFeature, WeatherGovError> {
+        let url = format!("{}/points/{:.4},{:.4}", self.base_url, latitude, longitude);
+        Ok(self.get(&url).await?.1)
+    }
+
+    ///  Gets a forecast, from a PointProperties forecast or forecast_hourly url.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'url'-the forecast url
+    ///
+    /// # Return
+    ///
+    /// ForecastFeature
+    pub async fn get_forecast(&self, url: &str) -> Result<ForecastFeature, WeatherGovError> {
+        Ok(self.get(url).await?.1)
+    }
+
+    ///  Gets the active alerts for a point.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the client
+    ///*'latitude'-degrees north
+    ///*'longitude'-degrees east
+    ///
+    /// # Return
+    ///
+    /// AlertCollection
+    pub async fn get_active_alerts(&self, latitude: f64, longitude: f64)
+                                -> Result<AlertCollection, WeatherGovError> {
+        let url = format!("{}/alerts/active?point={:.4},{:.4}", self.base_url,
+                          latitude, longitude);
+        Ok(self.get(&url).await?.1)
+    }
+
+} // impl NwsClient
@@ -0,0 +1,598 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{info, warn, debug};
+use serde::{Deserialize, Serialize};
+use crate::station::{StationRecord, ObservationRecord, MISSING};
+use crate::storage::{Storage, StorageError, ObservationQuery};
+
+/// Observations read per query while summarizing a day.
+const PAGE_SIZE: usize = 1000;
+
+/// Represents a station's climate summary of one local day.
+///     Values are MISSING when no observation had them; the counts are
+///     the coverage, how many observations or hours contributed.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailySummary {
+    pub station_id:           String,
+    /// The local date, YYYY-MM-DD.
+    pub date:                 String,
+    pub time_zone:            String,
+    pub observations:         u32,
+    /// Clock hours with at least one observation, 24 for a full day.
+    pub hours:                u32,
+    pub temperature_C_max:    f64,
+    pub temperature_C_min:    f64,
+    pub temperature_C_mean:   f64,
+    pub temperature_count:    u32,
+    pub dewpoint_C_mean:      f64,
+    pub rel_humidity_mean:    f64,
+    pub wind_spd_km_h_mean:   f64,
+    pub wind_gust_km_h_max:   f64,
+    /// Total of each hour's precipitation.
+    pub precip_mm:            f64,
+    /// Hours with a precipitation report.
+    pub precip_hours:         u32,
+    pub first_UTC:            String,
+    pub last_UTC:             String,
+    pub computed_UTC:         String,
+}
+
+/// Represents a station's climate summary of one local month,
+///     built from its daily summaries.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonthlySummary {
+    pub station_id:              String,
+    /// The local month, YYYY-MM.
+    pub month:                   String,
+    pub time_zone:               String,
+    /// Days with a daily summary.
+    pub days:                    u32,
+    pub observations:            u32,
+    pub temperature_C_max:       f64,
+    pub temperature_C_min:       f64,
+    /// Mean of the daily means.
+    pub temperature_C_mean:      f64,
+    /// Mean of the daily highs.
+    pub temperature_C_mean_max:  f64,
+    /// Mean of the daily lows.
+    pub temperature_C_mean_min:  f64,
+    pub wind_gust_km_h_max:      f64,
+    pub precip_mm:               f64,
+    /// Days with a precipitation report.
+    pub precip_days:             u32,
+    pub computed_UTC:            String,
+}
+
+/// Represents a station's summary of one UTC hour, what raw observations
+///     are rolled up into before retention deletes them.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HourlySummary {
+    pub station_id:           String,
+    /// The start of the hour, e.g. "2024-04-12T21:00:00+00:00".
+    pub hour_UTC:             String,
+    pub observations:         u32,
+    pub temperature_C_max:    f64,
+    pub temperature_C_min:    f64,
+    pub temperature_C_mean:   f64,
+    pub dewpoint_C_mean:      f64,
+    pub rel_humidity_mean:    f64,
+    pub wind_spd_km_h_mean:   f64,
+    pub wind_gust_km_h_max:   f64,
+    pub baro_pres_pa_mean:    f64,
+    /// The hour's largest last hour precipitation report.
+    pub precip_mm:            f64,
+    pub first_UTC:            String,
+    pub last_UTC:             String,
+}
+
+/// Implementation for HourlySummary.
+impl HourlySummary {
+
+    ///  Adds another summary of the same hour, e.g. of observations that
+    ///      arrived after the hour was rolled up. Means are weighted by
+    ///      the observation counts.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the summary
+    ///*'other'-the other summary
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn merge(&mut self, other: &HourlySummary) {
+        let (n, m) = (self.observations as f64, other.observations as f64);
+        let mean = |a: f64, b: f64| match (a == MISSING, b == MISSING) {
+            (true, _) => b,
+            (_, true) => a,
+            _ => (a * n + b * m) / (n + m),
+        };
+        let pick = |a: f64, b: f64, f: fn(f64, f64) -> f64| match (a == MISSING, b == MISSING) {
+            (true, _) => b,
+            (_, true) => a,
+            _ => f(a, b),
+        };
+        let max = |a, b| pick(a, b, f64::max);
+        let min = |a, b| pick(a, b, f64::min);
+
+        self.temperature_C_max = max(self.temperature_C_max, other.temperature_C_max);
+        self.temperature_C_min = min(self.temperature_C_min, other.temperature_C_min);
+        self.temperature_C_mean = mean(self.temperature_C_mean, other.temperature_C_mean);
+        self.dewpoint_C_mean = mean(self.dewpoint_C_mean, other.dewpoint_C_mean);
+        self.rel_humidity_mean = mean(self.rel_humidity_mean, other.rel_humidity_mean);
+        self.wind_spd_km_h_mean = mean(self.wind_spd_km_h_mean, other.wind_spd_km_h_mean);
+        self.wind_gust_km_h_max = max(self.wind_gust_km_h_max, other.wind_gust_km_h_max);
+        self.baro_pres_pa_mean = mean(self.baro_pres_pa_mean, other.baro_pres_pa_mean);
+        self.precip_mm = max(self.precip_mm, other.precip_mm);
+        self.first_UTC = self.first_UTC.clone().min(other.first_UTC.clone());
+        self.last_UTC = self.last_UTC.clone().max(other.last_UTC.clone());
+        self.observations += other.observations;
+    }
+
+} // impl HourlySummary
+
+
+///  Gets a station's time zone, UTC if it is empty or unknown.
+///
+/// # Arguments
+///
+///*'name'-the IANA name, e.g. "America/Phoenix"
+///
+/// # Return
+///
+/// Tz
+pub fn time_zone(name: &str) -> Tz {
+    if name.is_empty() {
+        return Tz::UTC;
+    }
+    name.parse().unwrap_or_else(|_| {
+        warn!("Unknown time zone {:?}, using UTC", name);
+        Tz::UTC
+    })
+}
+
+///  Gets the local date of an observation time.
+///
+/// # Arguments
+///
+///*'tz'-the station's time zone
+///*'timestamp_utc'-the observation time, RFC 3339
+///
+/// # Return
+///
+/// The date, None if the time can't be parsed
+pub fn local_date(tz: Tz, timestamp_utc: &str) -> Option<NaiveDate> {
+    Some(DateTime::parse_from_rfc3339(timestamp_utc).ok()?.with_timezone(&tz).date_naive())
+}
+
+///  Gets the UTC range of a local day, in the stored timestamp form.
+///      A day that starts in a daylight saving gap starts at its first
+///      valid time.
+///
+/// # Arguments
+///
+///*'tz'-the station's time zone
+///*'date'-the local date
+///
+/// # Return
+///
+/// (from, to), to is exclusive
+pub fn day_bounds(tz: Tz, date: NaiveDate) -> (String, String) {
+    let start = |d: NaiveDate| -> String {
+        let utc = (0..24).filter_map(|h| tz.from_local_datetime(&d.and_hms_opt(h, 0, 0)?)
+                                          .earliest())
+            .next()
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| d.and_hms_opt(0, 0, 0).unwrap().and_utc());
+        utc.format("%Y-%m-%dT%H:%M:%S+00:00").to_string()
+    };
+    (start(date), start(date.succ_opt().unwrap_or(date)))
+}
+
+///  Gets the first day of the month after a date's month.
+fn next_month(date: NaiveDate) -> NaiveDate {
+    match date.month() {
+        12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+        m => NaiveDate::from_ymd_opt(date.year(), m + 1, 1),
+    }.unwrap_or(date)
+}
+
+///  Gets the min, max, mean and count of values, skipping MISSING.
+fn stats(values: impl Iterator<Item = f64>) -> (f64, f64, f64, u32) {
+    let values: Vec<f64> = values.filter(|v| *v != MISSING).collect();
+    if values.is_empty() {
+        return (MISSING, MISSING, MISSING, 0);
+    }
+    (values.iter().cloned().fold(f64::INFINITY, f64::min),
+     values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+     values.iter().sum::<f64>() / values.len() as f64,
+     values.len() as u32)
+}
+
+
+///  Summarizes a station's observations of one local day.
+///      Precipitation is the last hour's amount, which every report in
+///      an hour repeats or updates, so each clock hour's largest report
+///      is added up.
+///
+/// # Arguments
+///
+///*'station_id'-the station
+///*'time_zone'-the station's time zone name
+///*'date'-the local date
+///*'recs'-the day's observations
+///
+/// # Return
+///
+/// DailySummary
+pub fn daily_summary(station_id: &str, time_zone: &str, date: NaiveDate,
+                     recs: &[ObservationRecord]) -> DailySummary {
+    let (t_min, t_max, t_mean, t_count) = stats(recs.iter().map(|r| r.temperature_C));
+    let (_, gust_max, _, _) = stats(recs.iter().map(|r| r.wind_gust_km_h));
+
+    // UTC hour -> largest precipitation report, hours are the same in any zone
+    let mut hours: BTreeMap<&str, f64> = BTreeMap::new();
+    for rec in recs {
+        let hour = rec.timestamp_UTC.get(..13).unwrap_or(&rec.timestamp_UTC);
+        let precip = hours.entry(hour).or_insert(MISSING);
+        if rec.precip_last_hour_mm != MISSING {
+            *precip = precip.max(rec.precip_last_hour_mm);
+        }
+    }
+    let precip: Vec<f64> = hours.values().cloned().filter(|v| *v != MISSING).collect();
+
+    DailySummary {
+        station_id:           station_id.to_string(),
+        date:                 date.format("%Y-%m-%d").to_string(),
+        time_zone:            time_zone.to_string(),
+        observations:         recs.len() as u32,
+        hours:                hours.len() as u32,
+        temperature_C_max:    t_max,
+        temperature_C_min:    t_min,
+        temperature_C_mean:   t_mean,
+        temperature_count:    t_count,
+        dewpoint_C_mean:      stats(recs.iter().map(|r| r.dewpoint_C)).2,
+        rel_humidity_mean:    stats(recs.iter().map(|r| r.rel_humidity)).2,
+        wind_spd_km_h_mean:   stats(recs.iter().map(|r| r.wind_spd_km_h)).2,
+        wind_gust_km_h_max:   gust_max,
+        precip_mm:            if precip.is_empty() { MISSING } else { precip.iter().sum() },
+        precip_hours:         precip.len() as u32,
+        first_UTC:            recs.iter().map(|r| r.timestamp_UTC.clone()).min()
+                                  .unwrap_or_default(),
+        last_UTC:             recs.iter().map(|r| r.timestamp_UTC.clone()).max()
+                                  .unwrap_or_default(),
+        computed_UTC:         Utc::now().format("%Y-%m-%dT%H:%M:%S+00:00").to_string(),
+    }
+}
+
+///  Summarizes a station's local month from its daily summaries.
+///
+/// # Arguments
+///
+///*'station_id'-the station
+///*'time_zone'-the station's time zone name
+///*'month'-the month, YYYY-MM
+///*'days'-the month's daily summaries
+///
+/// # Return
+///
+/// MonthlySummary
+pub fn monthly_summary(station_id: &str, time_zone: &str, month: &str,
+                       days: &[DailySummary]) -> MonthlySummary {
+    let precip: Vec<f64> = days.iter().map(|d| d.precip_mm).filter(|v| *v != MISSING).collect();
+    MonthlySummary {
+        station_id:              station_id.to_string(),
+        month:                   month.to_string(),
+        time_zone:               time_zone.to_string(),
+        days:                    days.len() as u32,
+        observations:            days.iter().map(|d| d.observations).sum(),
+        temperature_C_max:       stats(days.iter().map(|d| d.temperature_C_max)).1,
+        temperature_C_min:       stats(days.iter().map(|d| d.temperature_C_min)).0,
+        temperature_C_mean:      stats(days.iter().map(|d| d.temperature_C_mean)).2,
+        temperature_C_mean_max:  stats(days.iter().map(|d| d.temperature_C_max)).2,
+        temperature_C_mean_min:  stats(days.iter().map(|d| d.temperature_C_min)).2,
+        wind_gust_km_h_max:      stats(days.iter().map(|d| d.wind_gust_km_h_max)).1,
+        precip_mm:               if precip.is_empty() { MISSING } else { precip.iter().sum() },
+        precip_days:             precip.len() as u32,
+        computed_UTC:            Utc::now().format("%Y-%m-%dT%H:%M:%S+00:00").to_string(),
+    }
+}
+
+///  Summarizes a station's observations by UTC hour.
+///
+/// # Arguments
+///
+///*'station_id'-the station
+///*'recs'-the observations
+///
+/// # Return
+///
+/// A HourlySummary per hour with observations, oldest first
+pub fn hourly_summaries(station_id: &str, recs: &[ObservationRecord]) -> Vec<HourlySummary> {
+    let mut hours: BTreeMap<&str, Vec<&ObservationRecord>> = BTreeMap::new();
+    for rec in recs {
+        hours.entry(rec.timestamp_UTC.get(..13).unwrap_or(&rec.timestamp_UTC))
+            .or_default().push(rec);
+    }
+    hours.into_iter().map(|(hour, recs)| {
+        let (t_min, t_max, t_mean, _) = stats(recs.iter().map(|r| r.temperature_C));
+        HourlySummary {
+            station_id:           station_id.to_string(),
+            hour_UTC:             format!("{}:00:00+00:00", hour),
+            observations:         recs.len() as u32,
+            temperature_C_max:    t_max,
+            temperature_C_min:    t_min,
+            temperature_C_mean:   t_mean,
+            dewpoint_C_mean:      stats(recs.iter().map(|r| r.dewpoint_C)).2,
+            rel_humidity_mean:    stats(recs.iter().map(|r| r.rel_humidity)).2,
+            wind_spd_km_h_mean:   stats(recs.iter().map(|r| r.wind_spd_km_h)).2,
+            wind_gust_km_h_max:   stats(recs.iter().map(|r| r.wind_gust_km_h)).1,
+            baro_pres_pa_mean:    stats(recs.iter().map(|r| r.baro_pres_pa)).2,
+            precip_mm:            stats(recs.iter().map(|r| r.precip_last_hour_mm)).1,
+            first_UTC:            recs.iter().map(|r| r.timestamp_UTC.clone()).min()
+                                      .unwrap_or_default(),
+            last_UTC:             recs.iter().map(|r| r.timestamp_UTC.clone()).max()
+                                      .unwrap_or_default(),
+        }
+    }).collect()
+}
+
+///  Reads a station's observations of one local day and summarizes them.
+///
+/// # Arguments
+///
+///*'storage'-the storage
+///*'station_id'-the station
+///*'time_zone'-the station's time zone name
+///*'date'-the local date
+///
+/// # Return
+///
+/// DailySummary, with no observations if the day has none
+pub async fn summarize_day(storage: &dyn Storage, station_id: &str, time_zone: &str,
+                           date: NaiveDate) -> Result<DailySummary, StorageError> {
+    let (from, to) = day_bounds(self::time_zone(time_zone), date);
+    let mut query = ObservationQuery {
+        station_id:  station_id.to_string(),
+        from:        Some(from),
+        to:          Some(to),
+        limit:       PAGE_SIZE,
+        offset:      0,
+    };
+    let mut recs = Vec::new();
+    loop {
+        let page = storage.observations(&query).await?;
+        let done = page.len() < query.limit;
+        recs.extend(page);
+        if done {
+            break;
+        }
+        query.offset += query.limit;
+    }
+    Ok(daily_summary(station_id, time_zone, date, &recs))
+}
+
+///  Summarizes stations' stored observations over a range of local days,
+///      e.g. to build the summaries of observations stored before the job
+///      existed.
+///
+/// # Arguments
+///
+///*'storage'-the storage
+///*'stations'-the stations, with their time zones
+///*'from'-the first local date
+///*'to'-the local date after the last
+///
+/// # Return
+///
+/// (days, months) stored, or the first storage error
+pub async fn summarize(storage: &dyn Storage, stations: &[StationRecord], from: NaiveDate,
+                       to: NaiveDate) -> Result<(usize, usize), StorageError> {
+    let mut job = Summarizer::new(Duration::ZERO);
+    let mut zones = HashMap::new();
+    for station in stations {
+        let tz = time_zone(&station.time_zone);
+        for date in from.iter_days().take_while(|d| *d < to) {
+            job.mark(&station.call_id, &day_bounds(tz, date).0);
+        }
+        zones.insert(station.call_id.clone(), station.time_zone.clone());
+    }
+    job.run(storage, &zones, Instant::now()).await
+}
+
+
+/// Represents the summary job: the stations and times of observations
+///     stored since it last ran. Running it recomputes their local days,
+///     then those days' months, so an observation that arrives late, e.g.
+///     from the spool, updates the summaries it belongs to.
+#[derive(Debug)]
+pub struct Summarizer {
+    pub enabled:   bool,
+    /// How often the collector runs the job.
+    pub interval:  Duration,
+    /// Days before this many days ago may have had their observations
+    ///     rolled up by retention, they are not summarized again. 0 for none.
+    pub raw_days:  u64,
+    // (station_id, timestamp_UTC) of observations stored since the last run
+    pending:       BTreeSet<(String, String)>,
+    last_run:      Option<Instant>,
+}
+
+/// Implementation for the summary job.
+impl Summarizer {
+
+    ///  Creates the job.
+    ///
+    /// # Arguments
+    ///
+    ///*'interval'-how often it runs
+    ///
+    /// # Return
+    ///
+    /// Summarizer instance
+    pub fn new(interval: Duration) -> Summarizer {
+        Summarizer { enabled: true, interval, raw_days: 0, pending: BTreeSet::new(),
+                     last_run: None }
+    }
+
+    ///  Reads the job settings from summary_section, ENABLED and
+    ///      INTERVAL_SECS (default 300).
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the summary_section
+    ///*'stored'-whether observations are stored, without storage there
+    ///     is nothing to summarize
+    ///
+    /// # Return
+    ///
+    /// Summarizer instance
+    pub fn from_config(cfg: &HashMap<String, String>, stored: bool) -> Summarizer {
+        let interval = cfg.get("INTERVAL_SECS").and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(300);
+        let mut job = Summarizer::new(Duration::from_secs(interval));
+        job.enabled = stored && !matches!(cfg.get("ENABLED").map(|v| v.trim().to_lowercase())
+                                              .as_deref(), Some("false" | "no" | "0"));
+        job
+    }
+
+    ///  Notes a stored observation, its day is summarized on the next run.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the job
+    ///*'station_id'-the station
+    ///*'timestamp_utc'-the observation time
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn mark(&mut self, station_id: &str, timestamp_utc: &str) {
+        if self.enabled {
+            self.pending.insert((station_id.to_string(), timestamp_utc.to_string()));
+        }
+    }
+
+    ///  Gets how many stored observations are waiting to be summarized.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    ///  Checks whether the job has work and its interval has passed.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the job
+    ///*'now'-the current time
+    ///
+    /// # Return
+    ///
+    /// true if it should run
+    pub fn due(&self, now: Instant) -> bool {
+        self.enabled && !self.pending.is_empty()
+            && self.last_run.is_none_or(|last| now >= last + self.interval)
+    }
+
+    ///  Recomputes the daily summaries of the marked days, then the
+    ///      monthly summaries of their months. Days without observations
+    ///      are not stored. On an error what is left stays marked.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the job
+    ///*'storage'-the storage
+    ///*'zones'-station id -> time zone name, UTC for stations not in it
+    ///*'now'-the current time
+    ///
+    /// # Return
+    ///
+    /// (days, months) stored, or the first storage error
+    pub async fn run(&mut self, storage: &dyn Storage, zones: &HashMap<String, String>,
+                     now: Instant) -> Result<(usize, usize), StorageError> {
+        self.last_run = Some(now);
+        let zone = |station: &str| zones.get(station).cloned().unwrap_or_default();
+
+        let rolled_up = (self.raw_days > 0).then(|| Utc::now() - Days::new(self.raw_days))
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S+00:00").to_string());
+        let mut days = BTreeSet::new();
+        for (station, timestamp) in std::mem::take(&mut self.pending) {
+            match local_date(time_zone(&zone(&station)), &timestamp) {
+                Some(date) if rolled_up.as_ref().is_some_and(|cutoff|
+                        day_bounds(time_zone(&zone(&station)), date).0 < *cutoff) =>
+                    debug!("Not summarizing {:?} on {}, its observations may be rolled up",
+                           station, date),
+                Some(date) => { days.insert((station, date)); },
+                None => warn!("Not summarizing {:?} at {:?}, bad timestamp", station, timestamp),
+            }
+        }
+
+        let (mut stored_days, mut stored_months) = (0, 0);
+        let mut months = BTreeSet::new();
+        let mut remaining: Vec<(String, NaiveDate)> = days.into_iter().collect();
+        while let Some((station, date)) = remaining.first().cloned() {
+            let result = async {
+                let summary = summarize_day(storage, &station, &zone(&station), date).await?;
+                if summary.observations > 0 {
+                    storage.put_daily_summary(&summary).await?;
+                }
+                Ok::<_, StorageError>(summary.observations > 0)
+            }.await;
+            match result {
+                Ok(stored) => {
+                    stored_days += stored as usize;
+                    months.insert((station, date.with_day(1).unwrap_or(date)));
+                    remaining.remove(0);
+                },
+                Err(e) => {
+                    self.remark(&zone, remaining.iter().chain(months.iter()));
+                    return Err(e);
+                },
+            }
+        }
+
+        let mut remaining: Vec<(String, NaiveDate)> = months.into_iter().collect();
+        while let Some((station, first)) = remaining.first().cloned() {
+            let month = first.format("%Y-%m").to_string();
+            let result = async {
+                let days = storage.daily_summaries(&station, &first.format("%Y-%m-%d").to_string(),
+                    &next_month(first).format("%Y-%m-%d").to_string()).await?;
+                if !days.is_empty() {
+                    let summary = monthly_summary(&station, &zone(&station), &month, &days);
+                    storage.put_monthly_summary(&summary).await?;
+                }
+                Ok::<_, StorageError>(!days.is_empty())
+            }.await;
+            match result {
+                Ok(stored) => {
+                    stored_months += stored as usize;
+                    remaining.remove(0);
+                },
+                Err(e) => {
+                    self.remark(&zone, remaining.iter());
+                    return Err(e);
+                },
+            }
+        }
+
+        if stored_days > 0 {
+            info!("Summarized {} day(s) and {} month(s)", stored_days, stored_months);
+        }
+        Ok((stored_days, stored_months))
+    }
+
+    ///  Marks days again, by the UTC start of each, after a failed run.
+    fn remark<'a>(&mut self, zone: &dyn Fn(&str) -> String,
+                  days: impl Iterator<Item = &'a (String, NaiveDate)>) {
+        for (station, date) in days {
+            let (start, _) = day_bounds(time_zone(&zone(station)), *date);
+            debug!("Summary of {:?} on {} left for the next run", station, date);
+            self.pending.insert((station.clone(), start));
+        }
+    }
+
+} // impl Summarizer
@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use async_std::task;
+use chrono::prelude::{DateTime, Utc};
+use log::{error, warn, info, debug};
+use crate::client::{NwsClient, HttpMode, DEFAULT_BASE_URL};
+use crate::climate::Summarizer;
+use crate::config::Config;
+use crate::error::ErrorAction;
+use crate::metrics::{self, Metrics};
+use crate::qc::QualityControl;
+use crate::retention::RetentionPolicy;
+use crate::rules::{RulesEngine, RuleEvent, RuleState};
+use crate::sink::{self, SinkRegistry};
+use crate::spool::Spool;
+use crate::station::{Station, ObservationRecord};
+use crate::storage::{Storage, InsertOutcome, ObservationQuery};
+use crate::tendency;
+
+/// How many times to try getting a station's meta data at startup.
+const STATION_META_ATTEMPTS: u32 = 3;
+
+/// Most stored observations read for a station's tendencies and quality
+///     checks, a day or more of observations.
+const HISTORY_LIMIT: usize = 1000;
+
+/// Represents the observation collector: the poll loop over the
+///     configured stations, storing each poll cycle.
+pub struct Collector {
+    pub client:     NwsClient,
+    pub stations:   Vec<Station>,
+    pub storage:    Arc<dyn Storage>,
+    pub spool:      Arc<Spool>,
+    pub metrics:    Arc<Metrics>,
+    pub sinks:      SinkRegistry,
+    pub rules:      RulesEngine,
+    pub qc:         QualityControl,
+    pub summaries:  Summarizer,
+    pub interval:   Duration,
+    // Timestamp of each station's newest stored observation
+    last_stored:    HashMap<String, String>,
+    // Timestamp of each station's newest spooled observation
+    last_spooled:   HashMap<String, String>,
+    // Stations backing off after rate limiting, and when they may poll again
+    backoff_until:  HashMap<String, Instant>,
+    iteration:      u64,
+    // Time skipped instead of slept while replaying, see now() and sleep()
+    skipped:        Duration,
+}
+
+///  Converts a SystemTime to a iso 8601 string
+///
+/// # Arguments
+///
+///*'st' - SystemTime
+///
+/// # Return
+///
+/// ISO 8601 string
+pub fn iso8601(st: &SystemTime) -> String {
+    let dt: DateTime<Utc> = (*st).into();
+    format!("{}", dt.format("%+"))
+    // formats like "2001-07-08T00:34:60.026490+09:30"
+}
+
+
+/// Implementation for the collector.
+impl Collector {
+
+    ///  Creates a new collector from the config.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the weather_gov config
+    ///*'storage'-where observations are stored
+    ///*'spool'-where observations go while storage is unavailable
+    ///
+    /// # Return
+    ///
+    /// Collector instance
+    pub fn new(config: &Config, storage: Arc<dyn Storage>, spool: Arc<Spool>) -> Collector {
+        // Get the stations url for the stations later on
+        let host = &config.host_section;
+        debug!("Host config: {:?}", host);
+        let base_url = match host.get("BASE_URL") {
+            Some(url) => url.as_str(),
+            None => DEFAULT_BASE_URL,
+        };
+        let metrics = Arc::new(Metrics::new()
+            .with_spool(spool.clone())
+            .with_storage(storage.clone()));
+        let client = NwsClient::new(base_url).with_metrics(metrics.clone());
+        let stations_url = match host.get("STATIONS_URL") {
+            Some(url) => url.clone(),
+            None => client.stations_url(),
+        };
+
+        // Get the polling interval - how often to get station
+        //                            observations
+        let obs_interval = match config.parameters_section.get("OBS_INTERVAL_SECS") {
+            Some(inter) => inter.parse::<u64>().unwrap_or(300),
+            None => 300,
+        };
+        info!("obs_interval: {:?}", obs_interval);
+
+        // Create station objects and add to station list
+        info!("Stations config: {:?}", config.stations_section);
+        let mut stations = Vec::<Station>::new();
+        for (key, value) in &config.stations_section {
+            debug!("{} / {}", key, value);
+            stations.push(Station::new(value.clone(), stations_url.clone()));
+        }
+
+        let mut summaries = Summarizer::from_config(&config.summary_section,
+            sink::sink_names(config).iter().any(|s| s == sink::DB_SINK));
+        summaries.raw_days = RetentionPolicy::from_config(&config.retention_section)
+            .map_or(0, |p| p.raw_days);
+
+        Self {
+            client,
+            stations,
+            storage,
+            spool,
+            sinks: SinkRegistry::from_config(config, metrics.clone()),
+            metrics,
+            rules: RulesEngine::from_config(&config.rules_section),
+            qc: QualityControl::from_config(&config.qc_section),
+            summaries,
+            interval: Duration::from_secs(obs_interval),
+            last_stored: HashMap::new(),
+            last_spooled: HashMap::new(),
+            backoff_until: HashMap::new(),
+            iteration: 0,
+            skipped: Duration::ZERO,
+        }
+    }
+
+    ///  Gets the collector's current time.
+    ///      When replaying, time skipped by sleep() is added, so back offs
+    ///      expire in replayed time rather than wall clock time.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// Instant
+    pub fn now(&self) -> Instant {
+        Instant::now() + self.skipped
+    }
+
+    ///  Waits, or when replaying skips the time without waiting so a
+    ///      recording replays in accelerated time.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'d'-how long
+    ///
+    /// # Return
+    ///
+    /// None
+    pub async fn sleep(&mut self, d: Duration) {
+        match self.client.mode {
+            HttpMode::Replay(_) => self.skipped += d,
+            _ => task::sleep(d).await,
+        }
+    }
+
+    ///  Gets each station's meta data and stores its station record.
+    ///      Stations that can't be found are dropped, the rest keep polling.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// None
+    pub async fn init_stations(&mut self) {
+        let stations = std::mem::take(&mut self.stations);
+        let mut kept = Vec::<Station>::with_capacity(stations.len());
+        for mut station in stations {
+            let mut attempt: u32 = 0;
+            let json = loop {
+                attempt += 1;
+                let err = match station.get_station_json(&self.client).await {
+                    Ok(r) => break Some(r),
+                    Err(err) => err,
+                };
+                let action = err.action();
+                if action == ErrorAction::Disable || attempt >= STATION_META_ATTEMPTS {
+                    error!("Could not get station json for {:?}, not polling it: {}",
+                           station.station_identifier, err);
+                    break None;
+                }
+                warn!("Could not get station json for {:?} (attempt {}): {}",
+                      station.station_identifier, attempt, err);
+                match action {
+                    ErrorAction::BackOff(d) => self.sleep(d).await,
+                    _ => self.sleep(Duration::from_secs(5)).await,
+                }
+            };
+            let json = match json {
+                Some(j) => j,
+                None => continue,
+            };
+            debug!("Returned Station json: {}", json);
+
+            // Get the station record and add to storage if not already there
+            let station_record = station.get_station_record();
+            info!("Station record: {:?}", station_record);
+            if let Err(e) = self.storage.put_station_record(&station_record).await {
+                error!("Could not put station record for {:?}: {}",
+                       station.station_identifier, e);
+            }
+            self.sinks.on_station(&station_record);
+
+            // So a restart does not publish what is already stored as new
+            match self.storage.latest_observation(&station.station_identifier).await {
+                Ok(Some(rec)) => {
+                    self.last_stored.insert(rec.station_id, rec.timestamp_UTC);
+                },
+                Ok(None) => {},
+                Err(e) => debug!("No stored observation for {:?}: {}",
+                                 station.station_identifier, e),
+            }
+            kept.push(station);
+        }
+        self.stations = kept;
+        self.restore_rules().await;
+    }
+
+    ///  Picks the rules up where they were before a restart: replays each
+    ///      station's stored observations over the longest rule window, and
+    ///      restores which rules are fired from the stored rule events.
+    ///      Without them, e.g. a storage that can't read, rules start afresh.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// None
+    async fn restore_rules(&mut self) {
+        if self.rules.rules.is_empty() {
+            return;
+        }
+        let lookback = chrono::Duration::from_std(self.rules.lookback()).unwrap_or_default();
+        let latest: Vec<(String, String)> = self.last_stored.iter()
+            .map(|(id, t)| (id.clone(), t.clone())).collect();
+        for (station_id, timestamp) in latest {
+            let Ok(at) = DateTime::parse_from_rfc3339(&timestamp) else { continue };
+            let query = ObservationQuery {
+                station_id:  station_id.clone(),
+                from:        Some((at - lookback).with_timezone(&Utc)
+                                  .format("%Y-%m-%dT%H:%M:%S+00:00").to_string()),
+                to:          None,
+                limit:       HISTORY_LIMIT,
+                offset:      0,
+            };
+            match self.storage.observations(&query).await {
+                Ok(history) => self.rules.replay(&history),
+                Err(e) => debug!("No history to replay rules for {:?}: {}", station_id, e),
+            }
+        }
+        match self.storage.latest_rule_events().await {
+            Ok(events) => self.rules.restore(&events),
+            Err(e) => debug!("No rule events to restore: {}", e),
+        }
+    }
+
+    ///  Gets the latest observation from every station that is not
+    ///      backing off, and stores them as one batch.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// The observations collected this cycle
+    pub async fn poll_once(&mut self) -> Vec<ObservationRecord> {
+        let now = SystemTime::now();
+        info!("\n\nLOOP ITERATION: {}  TIME: {}", self.iteration, iso8601(&now));
+        self.iteration += 1;
+
+        let mut cycle = Vec::<ObservationRecord>::with_capacity(self.stations.len());
+        let mut disabled = Vec::<String>::new();
+        let now = self.now();
+        for station in self.stations.iter_mut() {
+            if let Some(until) = self.backoff_until.get(&station.station_identifier) {
+                if now < *until {
+                    info!("Station {:?} is backing off, skipping", station.station_identifier);
+                    continue;
+                }
+                self.backoff_until.remove(&station.station_identifier);
+            }
+            info!("\nGETTING STATION OBSERVATION FOR {:?}, {:?}, latitude {:?}  \
+                  longitude {:?}, elevation {:?} meters", station.station_identifier,
+                  station.station_name, station.latitude, station.longitude,
+                  station.elevation_meters);
+            let id = station.station_identifier.clone();
+            let labels = [("station", id.as_str())];
+            self.metrics.inc(metrics::POLL_ATTEMPTS, &labels);
+            let obs = match station.get_latest_observation_data(&self.client).await {
+                Ok(r) => r,
+                Err(e) => {
+                    let action = e.observation_action();
+                    let failure = match action {
+                        ErrorAction::Retry => "retry",
+                        ErrorAction::BackOff(_) => "back_off",
+                        ErrorAction::Disable => "disable",
+                    };
+                    self.metrics.inc(metrics::POLL_FAILURES,
+                                     &[labels[0], ("action", failure)]);
+                    match action {
+                        ErrorAction::Retry => {
+                            warn!("Failed getting latest observation for station \
+                                  {:?}, {:?}: {}", station.station_identifier,
+                                  station.station_name, e);
+                        },
+                        ErrorAction::BackOff(d) => {
+                            warn!("Backing off station {:?}, {:?} for {:?}: {}",
+                                  station.station_identifier, station.station_name, d, e);
+                            self.backoff_until.insert(station.station_identifier.clone(),
+                                                      now + d);
+                        },
+                        ErrorAction::Disable => {
+                            error!("Disabling station {:?}, {:?}: {}",
+                                   station.station_identifier, station.station_name, e);
+                            disabled.push(station.station_identifier.clone());
+                        },
+                    }
+                    continue;
+                }
+            };
+
+            info!("Returned observation json for station: {:?}, {:?}: {:?}",
+                      station.station_identifier, station.station_name, obs);
+            self.metrics.inc(metrics::POLL_SUCCESSES, &labels);
+            cycle.push(obs);
+        }
+        self.stations.retain(|st| !disabled.contains(&st.station_identifier));
+
+        // Tendencies and quality checks need the stored history, so are added
+        //     here rather than in preprocess_observation; repeats of the last
+        //     observation are skipped
+        for obs in cycle.iter_mut() {
+            if self.is_new(obs) {
+                self.check_history(obs).await;
+            }
+        }
+
+        let stored = self.store_cycle(&cycle).await;
+        let (inserted, duplicate, failed) = self.storage.counters().totals();
+        info!("Observation totals: inserted {}, duplicate {}, failed {}",
+              inserted, duplicate, failed);
+        for obs in &stored {
+            self.last_stored.insert(obs.station_id.clone(), obs.timestamp_UTC.clone());
+            self.sinks.on_observation(obs);
+            self.summaries.mark(&obs.station_id, &obs.timestamp_UTC);
+        }
+        // Stored since by the spool drainer, they are new to the sinks too
+        for obs in self.spool.take_drained() {
+            self.sinks.on_observation(&obs);
+            self.summaries.mark(&obs.station_id, &obs.timestamp_UTC);
+        }
+        self.summarize().await;
+        // The whole cycle, so rules still fire while storage is down;
+        //     the engine skips observations it has already seen.
+        for obs in &cycle {
+            for event in self.rules.evaluate(obs) {
+                self.rule_event(&event).await;
+            }
+        }
+        self.sinks.flush().await;
+        cycle
+    }
+
+    ///  Runs the summary job when it is due, over the observations stored
+    ///      since it last ran, including those drained from the spool, as
+    ///      marked by poll_once.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// None
+    pub async fn summarize(&mut self) {
+        if !self.summaries.due(self.now()) {
+            return;
+        }
+        let zones: HashMap<String, String> = self.stations.iter()
+            .map(|s| (s.station_identifier.clone(), s.time_zone.clone()))
+            .collect();
+        let now = self.now();
+        if let Err(e) = self.summaries.run(self.storage.as_ref(), &zones, now).await {
+            warn!("Could not update the climate summaries, {} observation(s) left for \
+                  the next run: {}", self.summaries.pending(), e);
+        }
+    }
+
+    ///  Polls every interval, forever, or when replaying until
+    ///      the recording is used up.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///
+    /// # Return
+    ///
+    /// None
+    pub async fn run(&mut self) {
+        loop {
+            self.poll_once().await;
+            if let Some(remaining) = self.client.replay_remaining() {
+                if remaining == 0 || self.stations.is_empty() {
+                    info!("Replay finished after {} poll cycle(s)", self.iteration);
+                    return;
+                }
+            }
+            self.sleep(self.interval).await;
+        }
+    }
+
+    ///  Checks whether an observation is newer than its station's
+    ///      newest stored observation.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'obs'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// true if it is new
+    fn is_new(&self, obs: &ObservationRecord) -> bool {
+        self.last_stored.get(&obs.station_id)
+            .is_none_or(|last| obs.timestamp_UTC.as_str() > last.as_str())
+    }
+
+    ///  Fills in an observation's three-hour tendencies and runs the
+    ///      quality checks, from the station's stored observations, counting
+    ///      failed checks in the metrics. Without the history, e.g. while
+    ///      storage is down, tendencies stay MISSING and only the range and
+    ///      consistency checks run.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'obs'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    async fn check_history(&self, obs: &mut ObservationRecord) {
+        let mut history = Vec::new();
+        if let Ok(at) = DateTime::parse_from_rfc3339(&obs.timestamp_UTC) {
+            let lookback = tendency::lookback().max(self.qc.lookback());
+            let query = ObservationQuery {
+                station_id:  obs.station_id.clone(),
+                from:        Some((at - lookback).with_timezone(&Utc)
+                                  .format("%Y-%m-%dT%H:%M:%S+00:00").to_string()),
+                to:          Some(obs.timestamp_UTC.clone()),
+                limit:       HISTORY_LIMIT,
+                offset:      0,
+            };
+            match self.storage.observations(&query).await {
+                Ok(h) => {
+                    tendency::apply(obs, &h);
+                    history = h;
+                },
+                Err(e) => debug!("No history for {:?} tendencies and qc: {}",
+                                 obs.station_id, e),
+            }
+        }
+
+        for flag in self.qc.apply(obs, &history) {
+            warn!("Observation {:?} {:?} failed the {} check of {}", obs.station_id,
+                  obs.timestamp_UTC, flag.check, flag.field);
+            self.metrics.inc(metrics::QC_FLAGS, &[("station", obs.station_id.as_str()),
+                             ("field", flag.field.as_str()), ("check", flag.check)]);
+        }
+    }
+
+    ///  Stores the whole poll cycle in one transaction.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'cycle'-the observations collected this cycle
+    ///
+    /// # Return
+    ///
+    /// The observations that were stored and are not duplicates.
+    ///     Spooled observations are not included.
+    async fn store_cycle(&mut self, cycle: &[ObservationRecord]) -> Vec<ObservationRecord> {
+        let started = Instant::now();
+        let result = self.storage.put_observation_batch(cycle).await;
+        self.metrics.observe_duration(metrics::INSERT_DURATION, &[("op", "batch")],
+                                      started.elapsed());
+        match result {
+            Ok(outcome) => {
+                info!("Stored poll cycle of {} observation(s): inserted {}, duplicate {}",
+                      cycle.len(), outcome.inserted, outcome.duplicate);
+                // Only what the batch inserted, not what was already stored,
+                // e.g. before a restart; stores that keep nothing insert every
+                // repeat of the latest observation, so it must be new as well
+                cycle.iter()
+                    .filter(|obs| outcome.inserted_keys.iter()
+                        .any(|(id, t)| *id == obs.station_id && *t == obs.timestamp_UTC))
+                    .filter(|obs| self.is_new(obs))
+                    .cloned().collect()
+            },
+            Err(err) if err.transient => {
+                error!("Storage unavailable storing {} observation(s): {}",
+                       cycle.len(), err);
+                for obs in cycle {
+                    self.spool_observation(obs);
+                }
+                Vec::new()
+            },
+            Err(err) => {
+                // Not a connection problem, one bad record rolled back the batch.
+                // Store one at a time so only the bad record is lost.
+                warn!("Batch of {} observation(s) failed, storing individually: {}",
+                      cycle.len(), err);
+                let mut stored = Vec::new();
+                for obs in cycle {
+                    if self.put_observation(obs).await {
+                        stored.push(obs.clone());
+                    }
+                }
+                stored
+            },
+        }
+    }
+
+    ///  Stores a single observation and handles the outcome.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'obs'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// true if it was inserted
+    async fn put_observation(&mut self, obs: &ObservationRecord) -> bool {
+        let started = Instant::now();
+        let outcome = self.storage.put_observation_record(obs).await;
+        self.metrics.observe_duration(metrics::INSERT_DURATION, &[("op", "single")],
+                                      started.elapsed());
+        match outcome {
+            InsertOutcome::Inserted => {
+                info!("Inserted observation record for station {:?}", obs.station_id);
+                return true;
+            },
+            InsertOutcome::Duplicate => {
+                info!("Ignoring Duplicate Observation Record for station {:?}", obs.station_id);
+            },
+            InsertOutcome::Failed(err) if err.transient => {
+                error!("Storage unavailable putting latest observation from station \
+                       {:?}: {}", obs.station_id, err);
+                self.spool_observation(obs);
+            },
+            InsertOutcome::Failed(err) => {
+                // Not a connection problem, retrying later would fail the same way
+                error!("Error putting latest observation from station \
+                       {:?}: {}", obs.station_id, err);
+            },
+        }
+        false
+    }
+
+    ///  Appends an observation to the spool when storage is unavailable.
+    ///      Only new observations are spooled, the latest endpoint repeats
+    ///      an observation every cycle until there is a newer one.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'obs'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    fn spool_observation(&mut self, obs: &ObservationRecord) {
+        let spooled = self.last_spooled.get(&obs.station_id)
+            .is_some_and(|last| obs.timestamp_UTC.as_str() <= last.as_str());
+        if spooled || !self.is_new(obs) {
+            debug!("Observation for station {:?} is already spooled or stored",
+                   obs.station_id);
+            return;
+        }
+        match self.spool.push(obs) {
+            Ok(true) => {
+                warn!("Spooled observation for station {:?}", obs.station_id);
+                self.last_spooled.insert(obs.station_id.clone(), obs.timestamp_UTC.clone());
+            },
+            Ok(false) => error!("Spool {:?} is full, dropping observation for \
+                                station {:?}", self.spool.path, obs.station_id),
+            Err(e) => error!("Could not spool observation for station {:?}: {:?}",
+                             obs.station_id, e),
+        }
+    }
+
+    ///  Sends a rule firing or clearing to the log, the storage and
+    ///      the sinks.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the collector
+    ///*'event'-the RuleEvent
+    ///
+    /// # Return
+    ///
+    /// None
+    async fn rule_event(&self, event: &RuleEvent) {
+        match event.state {
+            RuleState::Fired => warn!("Rule {:?} fired for station {:?} at {}: {} is {}",
+                                      event.rule, event.station_id, event.timestamp_UTC,
+                                      event.field, event.value),
+            RuleState::Cleared => info!("Rule {:?} cleared for station {:?} at {}: {} is {}",
+                                        event.rule, event.station_id, event.timestamp_UTC,
+                                        event.field, event.value),
+        }
+        if let Err(e) = self.storage.put_rule_event(event).await {
+            warn!("Could not store rule event {:?} for station {:?}: {}",
+                  event.rule, event.station_id, e);
+        }
+        self.sinks.on_rule_event(event);
+    }
+
+} // impl Collector
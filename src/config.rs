@@ -5,13 +5,39 @@ use std::collections::HashMap;
 
 
 /// Implmentation of a weather_gov config.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
    pub log_section:        HashMap<String, String>,
    pub host_section:       HashMap<String, String>,
+   #[serde(default)]
    pub db_section:         HashMap<String, String>,
    pub stations_section:   HashMap<String, String>,
    pub parameters_section: HashMap<String, String>,
+   #[serde(default)]
+   pub spool_section:      HashMap<String, String>,
+   #[serde(default)]
+   pub api_section:        HashMap<String, String>,
+   #[serde(default)]
+   pub mqtt_section:       HashMap<String, String>,
+   #[serde(default)]
+   pub webhook_section:    HashMap<String, String>,
+   #[serde(default)]
+   pub rules_section:      HashMap<String, String>,
+   #[serde(default)]
+   pub file_section:       HashMap<String, String>,
+   #[serde(default)]
+   pub parquet_section:    HashMap<String, String>,
+   #[serde(default)]
+   pub influx_section:     HashMap<String, String>,
+   #[serde(default)]
+   pub summary_section:    HashMap<String, String>,
+   #[serde(default)]
+   pub retention_section:  HashMap<String, String>,
+   #[serde(default)]
+   pub qc_section:         HashMap<String, String>,
+   /// Outputs for new observations, see sink::DEFAULT_SINKS.
+   #[serde(default)]
+   pub sinks:              Vec<String>,
 }
 
 
@@ -38,6 +64,18 @@ impl Config {
               db_section: _c.db_section,
               stations_section: _c.stations_section,
               parameters_section: _c.parameters_section,
+              spool_section: _c.spool_section,
+              api_section: _c.api_section,
+              mqtt_section: _c.mqtt_section,
+              webhook_section: _c.webhook_section,
+              rules_section: _c.rules_section,
+              file_section: _c.file_section,
+              parquet_section: _c.parquet_section,
+              influx_section: _c.influx_section,
+              summary_section: _c.summary_section,
+              retention_section: _c.retention_section,
+              qc_section: _c.qc_section,
+              sinks: _c.sinks,
         }
     }
 
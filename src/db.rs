@@ -1,21 +1,223 @@
-
-use std::collections::HashMap;
-use sqlx::{Pool, MySql, Error, MySqlPool};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use sqlx::{Pool, MySql, Error, Row};
+use sqlx::mysql::{MySqlPoolOptions, MySqlDatabaseError, MySqlArguments, MySqlQueryResult,
+                  MySqlRow};
+use sqlx::query::Query;
 use async_std::task;
+use async_trait::async_trait;
+use crate::climate::{DailySummary, HourlySummary, MonthlySummary};
+use crate::rules::{RuleEvent, RuleState};
 use crate::station::StationRecord;
-use crate::station::ObservationRecord;
-use log::{info};
+use crate::station::{ObservationRecord, MISSING};
+use crate::storage::{Storage, StorageError, InsertOutcome, BatchOutcome, InsertCounters,
+                     ObservationQuery};
+use log::{info, warn, error};
+
+/// MySQL server error numbers that indicate a transient condition.
+///     1040 too many connections, 1053 server shutdown,
+///     1205 lock wait timeout, 1213 deadlock,
+///     2002/2003 can't connect, 2006 server gone away, 2013 lost connection.
+const TRANSIENT_MYSQL_ERRORS: [u16; 8] = [1040, 1053, 1205, 1213, 2002, 2003, 2006, 2013];
+
+/// MySQL ER_DUP_ENTRY, a row with the same primary key exists.
+const ER_DUP_ENTRY: u16 = 1062;
+
+/// Observation table columns, in bind_observation order.
+const OBSERVATION_COLUMNS: [&str; 35] = ["station_id", "timestamp_UTC", "temperature_C",
+    "temperature_F", "dewpoint_C", "dewpoint_F", "description", "wind_dir", "wind_spd_km_h",
+    "wind_spd_mi_h", "wind_gust_km_h", "wind_gust_mi_h", "baro_pres_pa", "baro_pres_inHg",
+    "rel_humidity", "precip_last_hour_mm", "heat_index_C", "wind_chill_C",
+    "apparent_temperature_C", "humidex", "wet_bulb_C", "abs_humidity_g_m3",
+    "mixing_ratio_g_kg", "derived", "wind_compass", "wind_beaufort", "wind_beaufort_desc",
+    "wind_saffir_simpson", "pres_tendency_3h_pa", "pres_tendency_code",
+    "temperature_change_3h_C", "dewpoint_change_3h_C", "sun_elevation_deg", "day_night",
+    "qc_flags"];
+
+/// Columns added to the station table since it was first created, with their types.
+const STATION_ADDED_COLUMNS: [(&str, &str); 1] = [("time_zone", "VARCHAR(40)")];
+
+/// Columns added to the observation table since it was first created, with their types.
+const OBSERVATION_ADDED_COLUMNS: [(&str, &str); 20] = [("precip_last_hour_mm", "FLOAT"),
+    ("heat_index_C", "FLOAT"), ("wind_chill_C", "FLOAT"), ("apparent_temperature_C", "FLOAT"),
+    ("humidex", "FLOAT"), ("wet_bulb_C", "FLOAT"), ("abs_humidity_g_m3", "FLOAT"),
+    ("mixing_ratio_g_kg", "FLOAT"), ("derived", "VARCHAR(160)"), ("wind_compass", "VARCHAR(4)"),
+    ("wind_beaufort", "FLOAT"), ("wind_beaufort_desc", "VARCHAR(20)"),
+    ("wind_saffir_simpson", "FLOAT"), ("pres_tendency_3h_pa", "FLOAT"),
+    ("pres_tendency_code", "FLOAT"), ("temperature_change_3h_C", "FLOAT"),
+    ("dewpoint_change_3h_C", "FLOAT"), ("sun_elevation_deg", "FLOAT"),
+    ("day_night", "VARCHAR(5)"), ("qc_flags", "VARCHAR(255)")];
+
+/// Hourly summary table columns, in bind_hourly_summary order.
+const HOURLY_SUMMARY_COLUMNS: [&str; 14] = ["station_id", "hour_UTC", "observations",
+    "temperature_C_max", "temperature_C_min", "temperature_C_mean", "dewpoint_C_mean",
+    "rel_humidity_mean", "wind_spd_km_h_mean", "wind_gust_km_h_max", "baro_pres_pa_mean",
+    "precip_mm", "first_UTC", "last_UTC"];
+
+/// Daily summary table columns, in bind_daily_summary order.
+const DAILY_SUMMARY_COLUMNS: [&str; 18] = ["station_id", "date", "time_zone", "observations",
+    "hours", "temperature_C_max", "temperature_C_min", "temperature_C_mean",
+    "temperature_count", "dewpoint_C_mean", "rel_humidity_mean", "wind_spd_km_h_mean",
+    "wind_gust_km_h_max", "precip_mm", "precip_hours", "first_UTC", "last_UTC",
+    "computed_UTC"];
+
+/// Monthly summary table columns, in bind_monthly_summary order.
+const MONTHLY_SUMMARY_COLUMNS: [&str; 14] = ["station_id", "month", "time_zone", "days",
+    "observations", "temperature_C_max", "temperature_C_min", "temperature_C_mean",
+    "temperature_C_mean_max", "temperature_C_mean_min", "wind_gust_km_h_max", "precip_mm",
+    "precip_days", "computed_UTC"];
 
-/// Represents a db instance.
+/// Rows per multi-row INSERT, keeps well under the 65535 placeholder limit.
+const BATCH_ROWS: usize = 500;
+
+/// Represents a MySQL db instance, the Storage used by the collector.
+///     Cloning is cheap, clones share the connection pool and counters.
+#[derive(Clone)]
 pub struct Db {
-    pub host:              String,
-    pub port:              String,
-    pub user:              String,
-    pub password:          String,
-    pub database:          String,
-    pub station_table:     String,
-    pub observation_table: String,
-    db_pool:           Pool<MySql>,
+    pub host:                   String,
+    pub port:                   String,
+    pub user:                   String,
+    pub password:               String,
+    pub database:               String,
+    pub station_table:          String,
+    pub observation_table:      String,
+    pub rule_event_table:       String,
+    pub hourly_summary_table:   String,
+    pub daily_summary_table:    String,
+    pub monthly_summary_table:  String,
+    pub pool_options:           DbPoolOptions,
+    pub counters:               Arc<InsertCounters>,
+    db_pool:                    Pool<MySql>,
+}
+
+/// Connection pool and retry settings, read from db_section.
+///     All keys are optional.
+#[derive(Debug, Clone)]
+pub struct DbPoolOptions {
+    pub max_connections:      u32,
+    pub min_connections:      u32,
+    pub acquire_timeout:      Duration,
+    pub idle_timeout:         Option<Duration>,
+    /// 0 means retry forever.
+    pub connect_retries:      u32,
+    pub connect_retry_delay:  Duration,
+    pub insert_retries:       u32,
+    pub insert_retry_delay:   Duration,
+}
+
+/// Implementation for db pool options.
+impl DbPoolOptions {
+
+    ///  Reads the pool options from the Db config,
+    ///      using defaults for missing or bad values.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the Db config
+    ///
+    /// # Return
+    ///
+    /// DbPoolOptions instance
+    pub fn from_config(cfg: &HashMap<String, String>) -> DbPoolOptions {
+        let get = |key: &str, default: u64| -> u64 {
+            match cfg.get(key) {
+                Some(v) => v.parse::<u64>().unwrap_or_else(|_| {
+                    warn!("Bad db_section value for {}: {:?}, using {}", key, v, default);
+                    default
+                }),
+                None => default,
+            }
+        };
+        let idle_secs = get("idle_timeout_secs", 600);
+
+        DbPoolOptions {
+            max_connections:      get("max_connections", 10) as u32,
+            min_connections:      get("min_connections", 0) as u32,
+            acquire_timeout:      Duration::from_secs(get("acquire_timeout_secs", 30)),
+            idle_timeout:         if idle_secs == 0 { None } else { Some(Duration::from_secs(idle_secs)) },
+            connect_retries:      get("connect_retries", 0) as u32,
+            connect_retry_delay:  Duration::from_secs(get("connect_retry_secs", 5)),
+            insert_retries:       get("insert_retries", 3) as u32,
+            insert_retry_delay:   Duration::from_millis(get("insert_retry_ms", 500)),
+        }
+    }
+}
+
+///  Decides whether a database error is worth retrying.
+///
+/// # Arguments
+///
+///*'err'-the sqlx error
+///
+/// # Return
+///
+/// true if the error is transient (connection loss, pool timeout, deadlock...)
+pub fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Io(_) | Error::PoolTimedOut | Error::PoolClosed | Error::WorkerCrashed => true,
+        Error::Database(db_err) => match db_err.try_downcast_ref::<MySqlDatabaseError>() {
+            Some(e) => TRANSIENT_MYSQL_ERRORS.contains(&e.number()),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+
+///  Decides whether a database error is a duplicate primary key.
+///
+/// # Arguments
+///
+///*'err'-the sqlx error
+///
+/// # Return
+///
+/// true if the row already exists
+pub fn is_duplicate(err: &Error) -> bool {
+    match err {
+        Error::Database(db_err) => match db_err.try_downcast_ref::<MySqlDatabaseError>() {
+            Some(e) => e.number() == ER_DUP_ENTRY,
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+
+///  Converts a database error to a StorageError.
+///
+/// # Arguments
+///
+///*'err'-the sqlx error
+///
+/// # Return
+///
+/// StorageError, transient if is_transient
+pub fn storage_error(err: Error) -> StorageError {
+    if is_transient(&err) {
+        StorageError::transient(err)
+    } else {
+        StorageError::permanent(err)
+    }
+}
+
+///  Classifies the result of an observation insert.
+///
+/// # Arguments
+///
+///*'result'-the sqlx execute result
+///
+/// # Return
+///
+/// InsertOutcome
+pub fn insert_outcome(result: Result<MySqlQueryResult, Error>) -> InsertOutcome {
+    match result {
+        Ok(_) => InsertOutcome::Inserted,
+        Err(e) if is_duplicate(&e) => InsertOutcome::Duplicate,
+        Err(e) => InsertOutcome::Failed(storage_error(e)),
+    }
 }
 
 
@@ -23,6 +225,8 @@ pub struct Db {
 impl Db {
 
     ///  Creates a new Db instance.
+    ///      Retries the connection until the database is available,
+    ///      or until connect_retries attempts have been made.
     ///
     /// # Arguments
     ///
@@ -30,10 +234,16 @@ impl Db {
     ///
     /// # Return
     ///
-    /// Db instance
-    pub fn new(cfg: HashMap<String, String>) -> Db  {
+    /// Db instance, or the last connection error once the retries run out
+    pub fn new(cfg: HashMap<String, String>) -> Result<Db, Error> {
+        let pool_options = DbPoolOptions::from_config(&cfg);
+        info!("Db pool options: {:?}", pool_options);
+        let url = format!("mysql://{}:{}@{}:{}/{}", cfg["user"], cfg["password"],
+                          cfg["host"], cfg["port"], cfg["database"]);
+
+        let db_pool = task::block_on(Db::connect(url.as_str(), &pool_options))?;
 
-        Self {
+        Ok(Self {
             host:               cfg["host"].clone(),
             port:               cfg["port"].clone(),
             user:               cfg["user"].clone(),
@@ -41,28 +251,223 @@ impl Db {
             database:           cfg["database"].clone(),
             station_table:      cfg["station_table"].clone(),
             observation_table:  cfg["observation_table"].clone(),
-
-            // Following will crash if we can't connect, but without a db connection there's
-            //      not much can be done anyway.
-            db_pool:            task::block_on(Db::connect(format!("mysql://{}:{}@{}:{}/{}",
-                                cfg["user"], cfg["password"], cfg["host"], cfg["port"],
-                                cfg["database"]).as_str())).expect("Could not connect to database"),
-        }
+            rule_event_table:   cfg.get("rule_event_table").cloned()
+                                    .unwrap_or_else(|| "rule_event_rust".to_string()),
+            hourly_summary_table:   cfg.get("hourly_summary_table").cloned()
+                                        .unwrap_or_else(|| "hourly_summary_rust".to_string()),
+            daily_summary_table:    cfg.get("daily_summary_table").cloned()
+                                        .unwrap_or_else(|| "daily_summary_rust".to_string()),
+            monthly_summary_table:  cfg.get("monthly_summary_table").cloned()
+                                        .unwrap_or_else(|| "monthly_summary_rust".to_string()),
+            pool_options,
+            counters:           Arc::new(InsertCounters::default()),
+            db_pool,
+        })
     }
 
-    ///  Creates a new Db connection.
+    ///  Creates a new Db connection pool, retrying
+    ///      while the database is unreachable.
     ///
     /// # Arguments
     ///
     ///*'cpath'-the Db url
+    ///*'opts'-the pool options
     ///
     /// # Return
     ///
     /// Db Pool
-    async fn connect(cpath: &str) -> Result<Pool<MySql>, Error> {
-        return MySqlPool::connect(cpath).await;
+    async fn connect(cpath: &str, opts: &DbPoolOptions) -> Result<Pool<MySql>, Error> {
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let res = MySqlPoolOptions::new()
+                .max_connections(opts.max_connections)
+                .min_connections(opts.min_connections)
+                .acquire_timeout(opts.acquire_timeout)
+                .idle_timeout(opts.idle_timeout)
+                .connect(cpath).await;
+            match res {
+                Ok(pool) => {
+                    info!("Connected to database after {} attempt(s)", attempt);
+                    return Ok(pool);
+                },
+                Err(e) => {
+                    if opts.connect_retries != 0 && attempt >= opts.connect_retries {
+                        error!("Giving up connecting to database after {} attempts: {:?}",
+                               attempt, e);
+                        return Err(e);
+                    }
+                    warn!("Database not available (attempt {}), retrying in {:?}: {:?}",
+                          attempt, opts.connect_retry_delay, e);
+                    task::sleep(opts.connect_retry_delay).await;
+                },
+            }
+        }
+    }
+
+    ///  Executes a query, retrying transient errors.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'what'-description of the query, for logging
+    ///*'make_query'-builds the query; called once per attempt
+    ///
+    /// # Return
+    ///
+    /// Result
+    async fn execute_with_retry<'q, F>(&self, what: &str, make_query: F)
+                                        -> Result<MySqlQueryResult, Error>
+        where F: Fn() -> Query<'q, MySql, MySqlArguments> {
+        let mut attempt: u32 = 0;
+        loop {
+            match make_query().execute(&self.db_pool).await {
+                Err(e) if is_transient(&e) && attempt < self.pool_options.insert_retries => {
+                    attempt += 1;
+                    warn!("Transient error on {} (retry {} of {}): {:?}", what, attempt,
+                          self.pool_options.insert_retries, e);
+                    task::sleep(self.pool_options.insert_retry_delay * attempt).await;
+                },
+                res => return res,
+            }
+        }
+    }
+
+    ///  Adds columns a table created by an older version is missing.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'table'-the table
+    ///*'columns'-the columns that may be missing, with their types
+    ///
+    /// # Return
+    ///
+    /// Result
+    async fn add_missing_columns(&self, table: &str, columns: &[(&str, &str)])
+                                                        -> Result<(), StorageError> {
+        let rows = sqlx::query("SELECT COLUMN_NAME FROM information_schema.COLUMNS
+            WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?")
+            .bind(table)
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        let existing: Vec<String> = rows.iter()
+            .map(|r| r.try_get::<String, _>(0).unwrap_or_default().to_lowercase())
+            .collect();
+        for (column, kind) in columns {
+            if existing.contains(&column.to_lowercase()) {
+                continue;
+            }
+            let query_str = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, kind);
+            sqlx::query(query_str.as_str())
+                .execute(&self.db_pool).await.map_err(storage_error)?;
+            info!("Added column {} to table {}", column, table);
+        }
+        Ok(())
+    }
+
+    ///  One attempt at put_observation_batch.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'recs'-the ObservationRecords
+    ///
+    /// # Return
+    ///
+    /// BatchOutcome
+    async fn try_observation_batch(&self, recs: &[ObservationRecord])
+                                                 -> Result<BatchOutcome, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        let mut seen = HashSet::<(&str, &str)>::new();
+        let mut inserted_keys = Vec::new();
+        for chunk in recs.chunks(BATCH_ROWS) {
+            // sqlx connects with CLIENT_FOUND_ROWS, so rows_affected counts a
+            // duplicate left unchanged as well; existing keys are read first
+            let keys = vec!["(?, ?)"; chunk.len()].join(", ");
+            let query_str = format!("SELECT station_id, timestamp_UTC FROM {} \
+                WHERE (station_id, timestamp_UTC) IN ({}) FOR UPDATE",
+                self.observation_table, keys);
+            let mut query = sqlx::query(query_str.as_str());
+            for rec in chunk {
+                query = query.bind(&rec.station_id).bind(&rec.timestamp_UTC);
+            }
+            let existing: HashSet<(String, String)> = query.fetch_all(&mut *tx).await?
+                .iter()
+                .map(|row| Ok((row.try_get("station_id")?, row.try_get("timestamp_UTC")?)))
+                .collect::<Result<_, sqlx::Error>>()?;
+
+            let new: Vec<&ObservationRecord> = chunk.iter()
+                .filter(|r| !existing.contains(&(r.station_id.clone(), r.timestamp_UTC.clone())))
+                .filter(|r| seen.insert((r.station_id.as_str(), r.timestamp_UTC.as_str())))
+                .collect();
+            if new.is_empty() {
+                continue;
+            }
+            // Only a duplicate key is absorbed, by a no-op update, e.g. a row
+            // a concurrent writer just added; any other row error fails the batch
+            let query_str = format!("INSERT INTO {} ({}) VALUES {} \
+                ON DUPLICATE KEY UPDATE station_id = station_id",
+                self.observation_table, OBSERVATION_COLUMNS.join(", "),
+                observation_placeholders(new.len()));
+            let mut query = sqlx::query(query_str.as_str());
+            for rec in &new {
+                query = bind_observation(query, rec);
+            }
+            query.execute(&mut *tx).await?;
+            inserted_keys.extend(new.iter()
+                .map(|r| (r.station_id.clone(), r.timestamp_UTC.clone())));
+        }
+        tx.commit().await?;
+
+        let inserted = inserted_keys.len() as u64;
+        Ok(BatchOutcome {
+            inserted,
+            duplicate: recs.len() as u64 - inserted,
+            inserted_keys,
+        })
+    }
+
+    ///  One attempt at roll_up.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'summaries'-the HourlySummaries
+    ///*'station_id'-the station call id
+    ///*'from'-the first UTC time
+    ///*'to'-the UTC time after the last
+    ///
+    /// # Return
+    ///
+    /// The number of observations deleted
+    async fn try_roll_up(&self, summaries: &[HourlySummary], station_id: &str, from: &str,
+                         to: &str) -> Result<u64, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        let query_str = format!("REPLACE INTO {} ({}) VALUES ({})", self.hourly_summary_table,
+            HOURLY_SUMMARY_COLUMNS.join(", "),
+            vec!["?"; HOURLY_SUMMARY_COLUMNS.len()].join(", "));
+        for summary in summaries {
+            bind_hourly_summary(sqlx::query(query_str.as_str()), summary)
+                .execute(&mut *tx).await?;
+        }
+        let query_str = format!("DELETE FROM {} WHERE station_id = ? AND timestamp_UTC >= ?
+            AND timestamp_UTC < ?", self.observation_table);
+        let deleted = sqlx::query(query_str.as_str())
+            .bind(station_id)
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx).await?.rows_affected();
+        tx.commit().await?;
+        Ok(deleted)
     }
 
+} // impl Db
+
+
+/// Implementation of Storage for the MySQL db.
+#[async_trait]
+impl Storage for Db {
+
     ///  Creates the weather_gov Db tables.
     ///
     /// # Arguments
@@ -71,15 +476,15 @@ impl Db {
     ///
     /// # Return
     ///
-    /// Db Pool
-    pub async fn create_tables(&mut self) -> Result<(), sqlx::Error>  {
+    /// Result
+    async fn create_tables(&self) -> Result<(), StorageError>  {
 
-        // Fatal if we cannot create tables
+        // The caller treats failing to create tables as fatal
         let query_str_st =  format!("CREATE TABLE IF NOT EXISTS {} (call_id VARCHAR(5)
             PRIMARY KEY, name VARCHAR(80), latitude_deg FLOAT, longitude_deg FLOAT,
-            elevation_m FLOAT, url VARCHAR(80))", self.station_table);
+            elevation_m FLOAT, url VARCHAR(80), time_zone VARCHAR(40))", self.station_table);
         let query_st = sqlx::query(query_str_st.as_str())
-            .execute(&self.db_pool).await.expect("Fatal: could not create station metadata table");
+            .execute(&self.db_pool).await.map_err(storage_error)?;
         info!("Query result create station table: {:?}", query_st);
 
         let query_str_obs = format!("CREATE TABLE IF NOT EXISTS {} (station_id
@@ -87,13 +492,66 @@ impl Db {
         dewpoint_C FLOAT, dewpoint_F FLOAT, description VARCHAR(40), wind_dir FLOAT,
         wind_spd_km_h FLOAT, wind_spd_mi_h FLOAT, wind_gust_km_h FLOAT,
         wind_gust_mi_h FLOAT, baro_pres_pa FLOAT, baro_pres_inHg FLOAT,
-        rel_humidity FLOAT, PRIMARY KEY (station_id, timestamp_UTC))",
+        rel_humidity FLOAT, precip_last_hour_mm FLOAT, heat_index_C FLOAT,
+        wind_chill_C FLOAT, apparent_temperature_C FLOAT, humidex FLOAT, wet_bulb_C FLOAT,
+        abs_humidity_g_m3 FLOAT, mixing_ratio_g_kg FLOAT, derived VARCHAR(160),
+        wind_compass VARCHAR(4), wind_beaufort FLOAT, wind_beaufort_desc VARCHAR(20),
+        wind_saffir_simpson FLOAT, pres_tendency_3h_pa FLOAT, pres_tendency_code FLOAT,
+        temperature_change_3h_C FLOAT, dewpoint_change_3h_C FLOAT, sun_elevation_deg FLOAT,
+        day_night VARCHAR(5), qc_flags VARCHAR(255), PRIMARY KEY (station_id, timestamp_UTC))",
         self.observation_table);
         let query_st_obs = sqlx::query(query_str_obs.as_str())
-           .execute(&self.db_pool).await.expect("Fatal: could not create station \
-                                                observation table");
+           .execute(&self.db_pool).await.map_err(storage_error)?;
         info!("Query result create observation table: {:?}", query_st_obs);
 
+        let query_str_rule = format!("CREATE TABLE IF NOT EXISTS {} (id BIGINT
+        AUTO_INCREMENT PRIMARY KEY, rule VARCHAR(80), station_id VARCHAR(20),
+        timestamp_UTC VARCHAR(40), state VARCHAR(10), field VARCHAR(40), value DOUBLE,
+        description VARCHAR(255), INDEX (station_id, timestamp_UTC))",
+        self.rule_event_table);
+        let query_st_rule = sqlx::query(query_str_rule.as_str())
+           .execute(&self.db_pool).await.map_err(storage_error)?;
+        info!("Query result create rule event table: {:?}", query_st_rule);
+
+        let query_str_hourly = format!("CREATE TABLE IF NOT EXISTS {} (station_id
+        VARCHAR(20), hour_UTC VARCHAR(40), observations INT UNSIGNED,
+        temperature_C_max FLOAT, temperature_C_min FLOAT, temperature_C_mean FLOAT,
+        dewpoint_C_mean FLOAT, rel_humidity_mean FLOAT, wind_spd_km_h_mean FLOAT,
+        wind_gust_km_h_max FLOAT, baro_pres_pa_mean FLOAT, precip_mm FLOAT,
+        first_UTC VARCHAR(40), last_UTC VARCHAR(40), PRIMARY KEY (station_id, hour_UTC),
+        INDEX (hour_UTC))",
+        self.hourly_summary_table);
+        let query_st_hourly = sqlx::query(query_str_hourly.as_str())
+           .execute(&self.db_pool).await.map_err(storage_error)?;
+        info!("Query result create hourly summary table: {:?}", query_st_hourly);
+
+        let query_str_daily = format!("CREATE TABLE IF NOT EXISTS {} (station_id
+        VARCHAR(20), date VARCHAR(10), time_zone VARCHAR(40), observations INT UNSIGNED,
+        hours INT UNSIGNED, temperature_C_max FLOAT, temperature_C_min FLOAT,
+        temperature_C_mean FLOAT, temperature_count INT UNSIGNED, dewpoint_C_mean FLOAT,
+        rel_humidity_mean FLOAT, wind_spd_km_h_mean FLOAT, wind_gust_km_h_max FLOAT,
+        precip_mm FLOAT, precip_hours INT UNSIGNED, first_UTC VARCHAR(40),
+        last_UTC VARCHAR(40), computed_UTC VARCHAR(40), PRIMARY KEY (station_id, date))",
+        self.daily_summary_table);
+        let query_st_daily = sqlx::query(query_str_daily.as_str())
+           .execute(&self.db_pool).await.map_err(storage_error)?;
+        info!("Query result create daily summary table: {:?}", query_st_daily);
+
+        let query_str_monthly = format!("CREATE TABLE IF NOT EXISTS {} (station_id
+        VARCHAR(20), month VARCHAR(7), time_zone VARCHAR(40), days INT UNSIGNED,
+        observations INT UNSIGNED, temperature_C_max FLOAT, temperature_C_min FLOAT,
+        temperature_C_mean FLOAT, temperature_C_mean_max FLOAT, temperature_C_mean_min FLOAT,
+        wind_gust_km_h_max FLOAT, precip_mm FLOAT, precip_days INT UNSIGNED,
+        computed_UTC VARCHAR(40), PRIMARY KEY (station_id, month))",
+        self.monthly_summary_table);
+        let query_st_monthly = sqlx::query(query_str_monthly.as_str())
+           .execute(&self.db_pool).await.map_err(storage_error)?;
+        info!("Query result create monthly summary table: {:?}", query_st_monthly);
+
+        // Tables from older versions get the columns added since
+        self.add_missing_columns(&self.station_table, &STATION_ADDED_COLUMNS).await?;
+        self.add_missing_columns(&self.observation_table, &OBSERVATION_ADDED_COLUMNS).await?;
+
         Ok(())
     }
 
@@ -108,28 +566,26 @@ impl Db {
     /// # Return
     ///
     /// Result
-    pub async fn put_station_record(&mut self, rec: StationRecord)
-                                                 -> Result<String, sqlx::Error> {
+    async fn put_station_record(&self, rec: &StationRecord) -> Result<(), StorageError> {
 
-        // Fatal if we cannot put station records
+        // Transient errors are retried, anything else goes back to the caller
 
         let query_str = format!("REPLACE INTO {} (call_id, name, latitude_deg,
-            longitude_deg, elevation_m, url) VALUES (?, ?, ?, ?, ?, ?)",
+            longitude_deg, elevation_m, url, time_zone) VALUES (?, ?, ?, ?, ?, ?, ?)",
             self.station_table);
-        let result = sqlx::query(query_str.as_str())
-        .bind(rec.call_id)
-        .bind(rec.name)
-        .bind(rec.latitude_deg)
-        .bind(rec.longitude_deg)
-        .bind(rec.elevation_m)
-        .bind(rec.url)
-        .execute(&self.db_pool)
-        .await
-        .unwrap();
-
-        let rstring = format!("Success: result: {:?}", result);
-        Ok(rstring)
+        let result = self.execute_with_retry("put station record", || {
+            sqlx::query(query_str.as_str())
+            .bind(&rec.call_id)
+            .bind(&rec.name)
+            .bind(rec.latitude_deg)
+            .bind(rec.longitude_deg)
+            .bind(rec.elevation_m)
+            .bind(&rec.url)
+            .bind(&rec.time_zone)
+        }).await.map_err(storage_error)?;
+        info!("Put station record result: {:?}", result);
 
+        Ok(())
     }
 
     ///  Adds a station observation record to
@@ -142,47 +598,789 @@ impl Db {
     ///
     /// # Return
     ///
-    /// Result
-    pub async fn put_observation_record(&mut self, rec: ObservationRecord)
-                                                 -> Result<String, sqlx::Error> {
+    /// InsertOutcome
+    async fn put_observation_record(&self, rec: &ObservationRecord) -> InsertOutcome {
 
         // Not fatal if we can't put an observation record
 
-        let query_str = format!("INSERT INTO {} (station_id,
-             timestamp_UTC, temperature_C, temperature_F, dewpoint_C,
-             dewpoint_F, description, wind_dir, wind_spd_km_h, wind_spd_mi_h,
-             wind_gust_km_h, wind_gust_mi_h, baro_pres_pa, baro_pres_inHg,
-             rel_humidity) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-             self.observation_table);
-         let result = sqlx::query(query_str.as_str())
-        .bind(rec.station_id)
-        .bind(rec.timestamp_UTC)
-        .bind(rec.temperature_C)
-        .bind(rec.temperature_F)
-        .bind(rec.dewpoint_C)
-        .bind(rec.dewpoint_F)
-        .bind(rec.description)
-        .bind(rec.wind_dir)
-        .bind(rec.wind_spd_km_h)
-        .bind(rec.wind_spd_mi_h)
-        .bind(rec.wind_gust_km_h)
-        .bind(rec.wind_gust_mi_h)
-        .bind(rec.baro_pres_pa)
-        .bind(rec.baro_pres_inHg)
-        .bind(rec.rel_humidity)
-        .execute(&self.db_pool)
-        .await;
-        //.unwrap();
+        let query_str = format!("INSERT INTO {} ({}) VALUES {}", self.observation_table,
+                                OBSERVATION_COLUMNS.join(", "), observation_placeholders(1));
+        let result = self.execute_with_retry("put observation record", || {
+            bind_observation(sqlx::query(query_str.as_str()), rec)
+        }).await;
 
         // Don't unwrap the result above, it will cause a crash on error.
+        // Transient errors have already been retried.
         // The most common error is Duplicate record, which is not fatal.
-        // All errors should be passed back to the caller and let caller
-        // decide what to do.
-        let rstring = format!("Success: result: {:?}", result);
-        Ok(rstring)
+        // All errors are passed back to the caller to decide what to do.
+        let outcome = insert_outcome(result);
+        self.counters.count(&outcome);
+        outcome
+    }
+
+    ///  Adds many station observation records to the weather_gov db
+    ///      in one transaction, using multi-row INSERT statements. Rows
+    ///      whose primary key already exists are skipped and counted as
+    ///      duplicates; any other row error, e.g. a value out of range,
+    ///      rolls the whole batch back so the caller can store the rows one
+    ///      at a time. The whole batch is retried on transient errors. The
+    ///      outcome has the keys of the rows inserted, so only those are
+    ///      published as new.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'recs'-the ObservationRecords
+    ///
+    /// # Return
+    ///
+    /// BatchOutcome, or the error that rolled the batch back
+    async fn put_observation_batch(&self, recs: &[ObservationRecord])
+                                                 -> Result<BatchOutcome, StorageError> {
+        if recs.is_empty() {
+            return Ok(BatchOutcome::default());
+        }
 
+        let mut attempt: u32 = 0;
+        loop {
+            match self.try_observation_batch(recs).await {
+                Err(e) if is_transient(&e) && attempt < self.pool_options.insert_retries => {
+                    attempt += 1;
+                    warn!("Transient error on observation batch of {} (retry {} of {}): {:?}",
+                          recs.len(), attempt, self.pool_options.insert_retries, e);
+                    task::sleep(self.pool_options.insert_retry_delay * attempt).await;
+                },
+                Ok(outcome) => {
+                    self.counters.add(outcome.inserted, outcome.duplicate);
+                    return Ok(outcome);
+                },
+                // Not counted as failed here, the caller decides whether the
+                // rows are spooled or retried one at a time.
+                Err(e) => return Err(storage_error(e)),
+            }
+        }
     }
 
-} // impl Db
+    ///  Gets all station records, ordered by call id.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///
+    /// # Return
+    ///
+    /// The StationRecords
+    async fn list_stations(&self) -> Result<Vec<StationRecord>, StorageError> {
+        let query_str = format!("SELECT call_id, name, latitude_deg, longitude_deg,
+            elevation_m, url, time_zone FROM {} ORDER BY call_id", self.station_table);
+        let rows = sqlx::query(query_str.as_str())
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(station_from_row).collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    ///  Gets the newest observation record of a station.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'station_id'-the station call id
+    ///
+    /// # Return
+    ///
+    /// The ObservationRecord, None if the station has none
+    async fn latest_observation(&self, station_id: &str)
+                                        -> Result<Option<ObservationRecord>, StorageError> {
+        let query_str = format!("SELECT {} FROM {} WHERE station_id = ?
+            ORDER BY timestamp_UTC DESC LIMIT 1",
+            OBSERVATION_COLUMNS.join(", "), self.observation_table);
+        let row = sqlx::query(query_str.as_str())
+            .bind(station_id)
+            .fetch_optional(&self.db_pool).await.map_err(storage_error)?;
+        row.as_ref().map(observation_from_row).transpose().map_err(storage_error)
+    }
+
+    ///  Gets the observation records of a station in a time range,
+    ///      a page at a time. Uses the (station_id, timestamp_UTC) primary key.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'query'-the station, range and page
+    ///
+    /// # Return
+    ///
+    /// The ObservationRecords, oldest first
+    async fn observations(&self, query: &ObservationQuery)
+                                        -> Result<Vec<ObservationRecord>, StorageError> {
+        let query_str = format!("SELECT {} FROM {} WHERE station_id = ?
+            AND timestamp_UTC >= ? AND timestamp_UTC < ?
+            ORDER BY timestamp_UTC LIMIT ? OFFSET ?",
+            OBSERVATION_COLUMNS.join(", "), self.observation_table);
+        // Timestamps all start with a digit, so "" and "~" bound everything
+        let rows = sqlx::query(query_str.as_str())
+            .bind(&query.station_id)
+            .bind(query.from.as_deref().unwrap_or(""))
+            .bind(query.to.as_deref().unwrap_or("~"))
+            .bind(query.limit as u64)
+            .bind(query.offset as u64)
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(observation_from_row).collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    ///  Adds a rule firing or clearing to the rule event table.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'event'-the RuleEvent
+    ///
+    /// # Return
+    ///
+    /// Result
+    async fn put_rule_event(&self, event: &RuleEvent) -> Result<(), StorageError> {
+        let query_str = format!("INSERT INTO {} (rule, station_id, timestamp_UTC, state,
+            field, value, description) VALUES (?, ?, ?, ?, ?, ?, ?)", self.rule_event_table);
+        self.execute_with_retry("put rule event", || {
+            sqlx::query(query_str.as_str())
+            .bind(&event.rule)
+            .bind(&event.station_id)
+            .bind(&event.timestamp_UTC)
+            .bind(event.state.to_string())
+            .bind(&event.field)
+            .bind(event.value)
+            .bind(&event.description)
+        }).await.map_err(storage_error)?;
+        Ok(())
+    }
+
+    ///  Gets the newest rule event of each rule and station, so a restart
+    ///      knows which rules are still fired.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///
+    /// # Return
+    ///
+    /// The RuleEvents
+    async fn latest_rule_events(&self) -> Result<Vec<RuleEvent>, StorageError> {
+        let query_str = format!("SELECT e.rule, e.station_id, e.timestamp_UTC, e.state,
+            e.field, e.value, e.description FROM {0} e JOIN (SELECT MAX(id) AS id FROM {0}
+            GROUP BY rule, station_id) latest ON e.id = latest.id", self.rule_event_table);
+        let rows = sqlx::query(query_str.as_str())
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(rule_event_from_row).collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    ///  Adds or replaces a station's daily summary.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'summary'-the DailySummary
+    ///
+    /// # Return
+    ///
+    /// Result
+    async fn put_daily_summary(&self, summary: &DailySummary) -> Result<(), StorageError> {
+        let query_str = format!("REPLACE INTO {} ({}) VALUES ({})", self.daily_summary_table,
+            DAILY_SUMMARY_COLUMNS.join(", "), vec!["?"; DAILY_SUMMARY_COLUMNS.len()].join(", "));
+        self.execute_with_retry("put daily summary", || {
+            bind_daily_summary(sqlx::query(query_str.as_str()), summary)
+        }).await.map_err(storage_error)?;
+        Ok(())
+    }
+
+    ///  Adds or replaces a station's monthly summary.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'summary'-the MonthlySummary
+    ///
+    /// # Return
+    ///
+    /// Result
+    async fn put_monthly_summary(&self, summary: &MonthlySummary) -> Result<(), StorageError> {
+        let query_str = format!("REPLACE INTO {} ({}) VALUES ({})", self.monthly_summary_table,
+            MONTHLY_SUMMARY_COLUMNS.join(", "),
+            vec!["?"; MONTHLY_SUMMARY_COLUMNS.len()].join(", "));
+        self.execute_with_retry("put monthly summary", || {
+            bind_monthly_summary(sqlx::query(query_str.as_str()), summary)
+        }).await.map_err(storage_error)?;
+        Ok(())
+    }
+
+    ///  Gets a station's daily summaries of local dates in [from, to).
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'station_id'-the station call id
+    ///*'from'-the first date, YYYY-MM-DD
+    ///*'to'-the date after the last
+    ///
+    /// # Return
+    ///
+    /// The DailySummaries, oldest first
+    async fn daily_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<DailySummary>, StorageError> {
+        let query_str = format!("SELECT {} FROM {} WHERE station_id = ? AND date >= ?
+            AND date < ? ORDER BY date", DAILY_SUMMARY_COLUMNS.join(", "),
+            self.daily_summary_table);
+        let rows = sqlx::query(query_str.as_str())
+            .bind(station_id)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(daily_summary_from_row).collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    ///  Gets a station's monthly summaries of local months in [from, to).
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'station_id'-the station call id
+    ///*'from'-the first month, YYYY-MM
+    ///*'to'-the month after the last
+    ///
+    /// # Return
+    ///
+    /// The MonthlySummaries, oldest first
+    async fn monthly_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<MonthlySummary>, StorageError> {
+        let query_str = format!("SELECT {} FROM {} WHERE station_id = ? AND month >= ?
+            AND month < ? ORDER BY month", MONTHLY_SUMMARY_COLUMNS.join(", "),
+            self.monthly_summary_table);
+        let rows = sqlx::query(query_str.as_str())
+            .bind(station_id)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(monthly_summary_from_row).collect::<Result<_, _>>()
+            .map_err(storage_error)
+    }
+
+    ///  Rolls a station's raw observations in [from, to) up into hourly
+    ///      summaries, in one transaction.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'summaries'-the HourlySummaries of the observations
+    ///*'station_id'-the station call id
+    ///*'from'-the first UTC time
+    ///*'to'-the UTC time after the last
+    ///
+    /// # Return
+    ///
+    /// The number of observations deleted
+    async fn roll_up(&self, summaries: &[HourlySummary], station_id: &str, from: &str, to: &str)
+                                        -> Result<u64, StorageError> {
+        let mut attempt: u32 = 0;
+        loop {
+            match self.try_roll_up(summaries, station_id, from, to).await {
+                Err(e) if is_transient(&e) && attempt < self.pool_options.insert_retries => {
+                    attempt += 1;
+                    warn!("Transient error on roll up (retry {} of {}): {:?}", attempt,
+                          self.pool_options.insert_retries, e);
+                    task::sleep(self.pool_options.insert_retry_delay * attempt).await;
+                },
+                res => return res.map_err(storage_error),
+            }
+        }
+    }
+
+    ///  Gets a station's hourly summaries of hours in [from, to).
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'station_id'-the station call id
+    ///*'from'-the first UTC hour
+    ///*'to'-the UTC time after the last
+    ///
+    /// # Return
+    ///
+    /// The HourlySummaries, oldest first
+    async fn hourly_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<HourlySummary>, StorageError> {
+        let query_str = format!("SELECT {} FROM {} WHERE station_id = ? AND hour_UTC >= ?
+            AND hour_UTC < ? ORDER BY hour_UTC", HOURLY_SUMMARY_COLUMNS.join(", "),
+            self.hourly_summary_table);
+        let rows = sqlx::query(query_str.as_str())
+            .bind(station_id)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.db_pool).await.map_err(storage_error)?;
+        rows.iter().map(hourly_summary_from_row).collect::<Result<_, _>>()
+            .map_err(storage_error)
+    }
+
+    ///  Deletes up to limit hourly summaries of hours before a UTC time.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'before'-the UTC time
+    ///*'limit'-the most rows to delete
+    ///
+    /// # Return
+    ///
+    /// The number of summaries deleted
+    async fn purge_hourly_summaries(&self, before: &str, limit: usize)
+                                        -> Result<u64, StorageError> {
+        let query_str = format!("DELETE FROM {} WHERE hour_UTC < ? LIMIT ?",
+                                self.hourly_summary_table);
+        let result = self.execute_with_retry("purge hourly summaries", || {
+            sqlx::query(query_str.as_str()).bind(before).bind(limit as u64)
+        }).await.map_err(storage_error)?;
+        Ok(result.rows_affected())
+    }
+
+    ///  Deletes up to limit daily summaries of local dates before a date.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///*'before'-the date, YYYY-MM-DD
+    ///*'limit'-the most rows to delete
+    ///
+    /// # Return
+    ///
+    /// The number of summaries deleted
+    async fn purge_daily_summaries(&self, before: &str, limit: usize)
+                                        -> Result<u64, StorageError> {
+        let query_str = format!("DELETE FROM {} WHERE date < ? LIMIT ?",
+                                self.daily_summary_table);
+        let result = self.execute_with_retry("purge daily summaries", || {
+            sqlx::query(query_str.as_str()).bind(before).bind(limit as u64)
+        }).await.map_err(storage_error)?;
+        Ok(result.rows_affected())
+    }
+
+    ///  Gets the running insert totals.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the Db instance
+    ///
+    /// # Return
+    ///
+    /// InsertCounters
+    fn counters(&self) -> &InsertCounters {
+        &self.counters
+    }
+
+} // impl Storage for Db
+
+
+///  Builds the VALUES placeholders for a multi-row observation insert.
+///
+/// # Arguments
+///
+///*'rows'-the number of rows
+///
+/// # Return
+///
+/// "(?, ..., ?), (?, ..., ?)"
+fn observation_placeholders(rows: usize) -> String {
+    let row = format!("({})", vec!["?"; OBSERVATION_COLUMNS.len()].join(", "));
+    vec![row; rows].join(", ")
+}
+
+///  Binds an observation record's values, in OBSERVATION_COLUMNS order.
+///
+/// # Arguments
+///
+///*'query'-the query being built
+///*'rec'-the ObservationRecord
+///
+/// # Return
+///
+/// The query with the record bound
+fn bind_observation<'q>(query: Query<'q, MySql, MySqlArguments>, rec: &'q ObservationRecord)
+                                                 -> Query<'q, MySql, MySqlArguments> {
+    query
+    .bind(&rec.station_id)
+    .bind(&rec.timestamp_UTC)
+    .bind(rec.temperature_C)
+    .bind(rec.temperature_F)
+    .bind(rec.dewpoint_C)
+    .bind(rec.dewpoint_F)
+    .bind(&rec.description)
+    .bind(rec.wind_dir)
+    .bind(rec.wind_spd_km_h)
+    .bind(rec.wind_spd_mi_h)
+    .bind(rec.wind_gust_km_h)
+    .bind(rec.wind_gust_mi_h)
+    .bind(rec.baro_pres_pa)
+    .bind(rec.baro_pres_inHg)
+    .bind(rec.rel_humidity)
+    .bind(rec.precip_last_hour_mm)
+    .bind(rec.heat_index_C)
+    .bind(rec.wind_chill_C)
+    .bind(rec.apparent_temperature_C)
+    .bind(rec.humidex)
+    .bind(rec.wet_bulb_C)
+    .bind(rec.abs_humidity_g_m3)
+    .bind(rec.mixing_ratio_g_kg)
+    .bind(&rec.derived)
+    .bind(&rec.wind_compass)
+    .bind(rec.wind_beaufort)
+    .bind(&rec.wind_beaufort_desc)
+    .bind(rec.wind_saffir_simpson)
+    .bind(rec.pres_tendency_3h_pa)
+    .bind(rec.pres_tendency_code)
+    .bind(rec.temperature_change_3h_C)
+    .bind(rec.dewpoint_change_3h_C)
+    .bind(rec.sun_elevation_deg)
+    .bind(&rec.day_night)
+    .bind(&rec.qc_flags)
+}
+
+///  Binds an hourly summary's values, in HOURLY_SUMMARY_COLUMNS order.
+///
+/// # Arguments
+///
+///*'query'-the query being built
+///*'s'-the HourlySummary
+///
+/// # Return
+///
+/// The query with the summary bound
+fn bind_hourly_summary<'q>(query: Query<'q, MySql, MySqlArguments>, s: &'q HourlySummary)
+                                                 -> Query<'q, MySql, MySqlArguments> {
+    query
+    .bind(&s.station_id)
+    .bind(&s.hour_UTC)
+    .bind(s.observations)
+    .bind(s.temperature_C_max)
+    .bind(s.temperature_C_min)
+    .bind(s.temperature_C_mean)
+    .bind(s.dewpoint_C_mean)
+    .bind(s.rel_humidity_mean)
+    .bind(s.wind_spd_km_h_mean)
+    .bind(s.wind_gust_km_h_max)
+    .bind(s.baro_pres_pa_mean)
+    .bind(s.precip_mm)
+    .bind(&s.first_UTC)
+    .bind(&s.last_UTC)
+}
+
+///  Binds a daily summary's values, in DAILY_SUMMARY_COLUMNS order.
+///
+/// # Arguments
+///
+///*'query'-the query being built
+///*'s'-the DailySummary
+///
+/// # Return
+///
+/// The query with the summary bound
+fn bind_daily_summary<'q>(query: Query<'q, MySql, MySqlArguments>, s: &'q DailySummary)
+                                                 -> Query<'q, MySql, MySqlArguments> {
+    query
+    .bind(&s.station_id)
+    .bind(&s.date)
+    .bind(&s.time_zone)
+    .bind(s.observations)
+    .bind(s.hours)
+    .bind(s.temperature_C_max)
+    .bind(s.temperature_C_min)
+    .bind(s.temperature_C_mean)
+    .bind(s.temperature_count)
+    .bind(s.dewpoint_C_mean)
+    .bind(s.rel_humidity_mean)
+    .bind(s.wind_spd_km_h_mean)
+    .bind(s.wind_gust_km_h_max)
+    .bind(s.precip_mm)
+    .bind(s.precip_hours)
+    .bind(&s.first_UTC)
+    .bind(&s.last_UTC)
+    .bind(&s.computed_UTC)
+}
+
+///  Binds a monthly summary's values, in MONTHLY_SUMMARY_COLUMNS order.
+///
+/// # Arguments
+///
+///*'query'-the query being built
+///*'s'-the MonthlySummary
+///
+/// # Return
+///
+/// The query with the summary bound
+fn bind_monthly_summary<'q>(query: Query<'q, MySql, MySqlArguments>, s: &'q MonthlySummary)
+                                                 -> Query<'q, MySql, MySqlArguments> {
+    query
+    .bind(&s.station_id)
+    .bind(&s.month)
+    .bind(&s.time_zone)
+    .bind(s.days)
+    .bind(s.observations)
+    .bind(s.temperature_C_max)
+    .bind(s.temperature_C_min)
+    .bind(s.temperature_C_mean)
+    .bind(s.temperature_C_mean_max)
+    .bind(s.temperature_C_mean_min)
+    .bind(s.wind_gust_km_h_max)
+    .bind(s.precip_mm)
+    .bind(s.precip_days)
+    .bind(&s.computed_UTC)
+}
+
+///  Reads a FLOAT column.
+///      FLOAT is 4 bytes, so it is read as f32 and widened through its
+///      shortest decimal form; -999.99 comes back as MISSING, not -999.989990234375.
+///      NULL is read as MISSING.
+///
+/// # Arguments
+///
+///*'row'-the result row
+///*'column'-the column name
+///
+/// # Return
+///
+/// The value
+fn float_column(row: &MySqlRow, column: &str) -> Result<f64, Error> {
+    let value: Option<f32> = row.try_get(column)?;
+    Ok(value.map(|v| v.to_string().parse().unwrap_or(MISSING)).unwrap_or(MISSING))
+}
+
+///  Builds a StationRecord from a station table row.
+///
+/// # Arguments
+///
+///*'row'-the result row
+///
+/// # Return
+///
+/// StationRecord
+fn station_from_row(row: &MySqlRow) -> Result<StationRecord, Error> {
+    Ok(StationRecord {
+        call_id:         row.try_get("call_id")?,
+        name:            row.try_get::<Option<String>, _>("name")?.unwrap_or_default(),
+        latitude_deg:    float_column(row, "latitude_deg")?,
+        longitude_deg:   float_column(row, "longitude_deg")?,
+        elevation_m:     float_column(row, "elevation_m")?,
+        url:             row.try_get::<Option<String>, _>("url")?.unwrap_or_default(),
+        time_zone:       row.try_get::<Option<String>, _>("time_zone")?.unwrap_or_default(),
+    })
+}
+
+///  Builds an ObservationRecord from an observation table row,
+///      the reverse of bind_observation.
+///
+/// # Arguments
+///
+///*'row'-the result row, with the OBSERVATION_COLUMNS
+///
+/// # Return
+///
+/// ObservationRecord
+fn observation_from_row(row: &MySqlRow) -> Result<ObservationRecord, Error> {
+    Ok(ObservationRecord {
+        station_id:       row.try_get("station_id")?,
+        timestamp_UTC:    row.try_get("timestamp_UTC")?,
+        temperature_C:    float_column(row, "temperature_C")?,
+        temperature_F:    float_column(row, "temperature_F")?,
+        dewpoint_C:       float_column(row, "dewpoint_C")?,
+        dewpoint_F:       float_column(row, "dewpoint_F")?,
+        description:      row.try_get::<Option<String>, _>("description")?.unwrap_or_default(),
+        wind_dir:         float_column(row, "wind_dir")?,
+        wind_spd_km_h:    float_column(row, "wind_spd_km_h")?,
+        wind_spd_mi_h:    float_column(row, "wind_spd_mi_h")?,
+        wind_gust_km_h:   float_column(row, "wind_gust_km_h")?,
+        wind_gust_mi_h:   float_column(row, "wind_gust_mi_h")?,
+        baro_pres_pa:     float_column(row, "baro_pres_pa")?,
+        baro_pres_inHg:   float_column(row, "baro_pres_inHg")?,
+        rel_humidity:     float_column(row, "rel_humidity")?,
+        precip_last_hour_mm: float_column(row, "precip_last_hour_mm")?,
+        heat_index_C:     float_column(row, "heat_index_C")?,
+        wind_chill_C:     float_column(row, "wind_chill_C")?,
+        apparent_temperature_C: float_column(row, "apparent_temperature_C")?,
+        humidex:          float_column(row, "humidex")?,
+        wet_bulb_C:       float_column(row, "wet_bulb_C")?,
+        abs_humidity_g_m3: float_column(row, "abs_humidity_g_m3")?,
+        mixing_ratio_g_kg: float_column(row, "mixing_ratio_g_kg")?,
+        derived:          text_column(row, "derived")?,
+        wind_compass:     text_column(row, "wind_compass")?,
+        wind_beaufort:    float_column(row, "wind_beaufort")?,
+        wind_beaufort_desc: text_column(row, "wind_beaufort_desc")?,
+        wind_saffir_simpson: float_column(row, "wind_saffir_simpson")?,
+        pres_tendency_3h_pa: float_column(row, "pres_tendency_3h_pa")?,
+        pres_tendency_code: float_column(row, "pres_tendency_code")?,
+        temperature_change_3h_C: float_column(row, "temperature_change_3h_C")?,
+        dewpoint_change_3h_C: float_column(row, "dewpoint_change_3h_C")?,
+        sun_elevation_deg: float_column(row, "sun_elevation_deg")?,
+        day_night:        text_column(row, "day_night")?,
+        qc_flags:         text_column(row, "qc_flags")?,
+    })
+}
+
+///  Builds a RuleEvent from a rule event table row.
+///
+/// # Arguments
+///
+///*'row'-the result row
+///
+/// # Return
+///
+/// RuleEvent, or the error reading a column
+fn rule_event_from_row(row: &MySqlRow) -> Result<RuleEvent, Error> {
+    let state = match text_column(row, "state")?.as_str() {
+        "fired" => RuleState::Fired,
+        _ => RuleState::Cleared,
+    };
+    Ok(RuleEvent {
+        rule:           text_column(row, "rule")?,
+        station_id:     text_column(row, "station_id")?,
+        timestamp_UTC:  text_column(row, "timestamp_UTC")?,
+        state,
+        field:          text_column(row, "field")?,
+        value:          row.try_get::<Option<f64>, _>("value")?.unwrap_or(MISSING),
+        description:    text_column(row, "description")?,
+    })
+}
+
+///  Reads a nullable text column, NULL as empty.
+///
+/// # Arguments
+///
+///*'row'-the result row
+///*'column'-the column name
+///
+/// # Return
+///
+/// The text, or the error reading the column
+fn text_column(row: &MySqlRow, column: &str) -> Result<String, Error> {
+    Ok(row.try_get::<Option<String>, _>(column)?.unwrap_or_default())
+}
+
+///  Reads a nullable INT UNSIGNED column, NULL as 0.
+///
+/// # Arguments
+///
+///*'row'-the result row
+///*'column'-the column name
+///
+/// # Return
+///
+/// The count, or the error reading the column
+fn count_column(row: &MySqlRow, column: &str) -> Result<u32, Error> {
+    Ok(row.try_get::<Option<u32>, _>(column)?.unwrap_or_default())
+}
+
+///  Builds an HourlySummary from an hourly summary table row.
+///
+/// # Arguments
+///
+///*'row'-the result row, with the HOURLY_SUMMARY_COLUMNS
+///
+/// # Return
+///
+/// HourlySummary
+fn hourly_summary_from_row(row: &MySqlRow) -> Result<HourlySummary, Error> {
+    Ok(HourlySummary {
+        station_id:           row.try_get("station_id")?,
+        hour_UTC:             row.try_get("hour_UTC")?,
+        observations:         count_column(row, "observations")?,
+        temperature_C_max:    float_column(row, "temperature_C_max")?,
+        temperature_C_min:    float_column(row, "temperature_C_min")?,
+        temperature_C_mean:   float_column(row, "temperature_C_mean")?,
+        dewpoint_C_mean:      float_column(row, "dewpoint_C_mean")?,
+        rel_humidity_mean:    float_column(row, "rel_humidity_mean")?,
+        wind_spd_km_h_mean:   float_column(row, "wind_spd_km_h_mean")?,
+        wind_gust_km_h_max:   float_column(row, "wind_gust_km_h_max")?,
+        baro_pres_pa_mean:    float_column(row, "baro_pres_pa_mean")?,
+        precip_mm:            float_column(row, "precip_mm")?,
+        first_UTC:            text_column(row, "first_UTC")?,
+        last_UTC:             text_column(row, "last_UTC")?,
+    })
+}
+
+///  Builds a DailySummary from a daily summary table row.
+///
+/// # Arguments
+///
+///*'row'-the result row, with the DAILY_SUMMARY_COLUMNS
+///
+/// # Return
+///
+/// DailySummary
+fn daily_summary_from_row(row: &MySqlRow) -> Result<DailySummary, Error> {
+    Ok(DailySummary {
+        station_id:           row.try_get("station_id")?,
+        date:                 row.try_get("date")?,
+        time_zone:            text_column(row, "time_zone")?,
+        observations:         count_column(row, "observations")?,
+        hours:                count_column(row, "hours")?,
+        temperature_C_max:    float_column(row, "temperature_C_max")?,
+        temperature_C_min:    float_column(row, "temperature_C_min")?,
+        temperature_C_mean:   float_column(row, "temperature_C_mean")?,
+        temperature_count:    count_column(row, "temperature_count")?,
+        dewpoint_C_mean:      float_column(row, "dewpoint_C_mean")?,
+        rel_humidity_mean:    float_column(row, "rel_humidity_mean")?,
+        wind_spd_km_h_mean:   float_column(row, "wind_spd_km_h_mean")?,
+        wind_gust_km_h_max:   float_column(row, "wind_gust_km_h_max")?,
+        precip_mm:            float_column(row, "precip_mm")?,
+        precip_hours:         count_column(row, "precip_hours")?,
+        first_UTC:            text_column(row, "first_UTC")?,
+        last_UTC:             text_column(row, "last_UTC")?,
+        computed_UTC:         text_column(row, "computed_UTC")?,
+    })
+}
+
+///  Builds a MonthlySummary from a monthly summary table row.
+///
+/// # Arguments
+///
+///*'row'-the result row, with the MONTHLY_SUMMARY_COLUMNS
+///
+/// # Return
+///
+/// MonthlySummary
+fn monthly_summary_from_row(row: &MySqlRow) -> Result<MonthlySummary, Error> {
+    Ok(MonthlySummary {
+        station_id:              row.try_get("station_id")?,
+        month:                   row.try_get("month")?,
+        time_zone:               text_column(row, "time_zone")?,
+        days:                    count_column(row, "days")?,
+        observations:            count_column(row, "observations")?,
+        temperature_C_max:       float_column(row, "temperature_C_max")?,
+        temperature_C_min:       float_column(row, "temperature_C_min")?,
+        temperature_C_mean:      float_column(row, "temperature_C_mean")?,
+        temperature_C_mean_max:  float_column(row, "temperature_C_mean_max")?,
+        temperature_C_mean_min:  float_column(row, "temperature_C_mean_min")?,
+        wind_gust_km_h_max:      float_column(row, "wind_gust_km_h_max")?,
+        precip_mm:               float_column(row, "precip_mm")?,
+        precip_days:             count_column(row, "precip_days")?,
+        computed_UTC:            text_column(row, "computed_UTC")?,
+    })
+}
+
+
+/// Enables debugging a db instance without showing the password.
+impl fmt::Debug for Db {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Db")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("user", &self.user)
+            .field("password", &"*".repeat(self.password.len()))
+            .field("database", &self.database)
+            .field("station_table", &self.station_table)
+            .field("observation_table", &self.observation_table)
+            .field("rule_event_table", &self.rule_event_table)
+            .field("hourly_summary_table", &self.hourly_summary_table)
+            .field("daily_summary_table", &self.daily_summary_table)
+            .field("monthly_summary_table", &self.monthly_summary_table)
+            .field("pool_options", &self.pool_options)
+            .field("counters", &self.counters)
+            .finish()
+    }
+}
 
 
@@ -0,0 +1,192 @@
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Default back off when api.weather.gov rate limits without a Retry-After.
+const DEFAULT_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Back off when a station has no recent observation (404 on observations/latest).
+const NO_OBSERVATION_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Represents an NWS problem detail (application/problem+json) error body.
+///     The embedded api answers errors with the same shape.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemDetail {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub problem_type:     Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title:            Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status:           Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail:           Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance:         Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id:   Option<String>,
+}
+
+/// Displays a ProblemDetail, e.g.
+///     Not Found (Station KXYZ not found) [status 404, type ..., instance ..., correlationId ...]
+impl fmt::Display for ProblemDetail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title.as_deref().unwrap_or("problem"))?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        let mut extra = Vec::new();
+        if let Some(status) = self.status {
+            extra.push(format!("status {}", status));
+        }
+        if let Some(t) = &self.problem_type {
+            extra.push(format!("type {}", t));
+        }
+        if let Some(i) = &self.instance {
+            extra.push(format!("instance {}", i));
+        }
+        if let Some(c) = &self.correlation_id {
+            extra.push(format!("correlationId {}", c));
+        }
+        if !extra.is_empty() {
+            write!(f, " [{}]", extra.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors from talking to api.weather.gov.
+#[derive(Debug)]
+pub enum WeatherGovError {
+    /// The request could not be sent or the body could not be read.
+    Transport(reqwest::Error),
+    /// The server answered with a non-success status.
+    Status {
+        url:      String,
+        status:   u16,
+        problem:  Option<Box<ProblemDetail>>,
+    },
+    /// The body was not the json we expected.
+    ///     path names the member that failed, e.g. properties.windGust.value
+    Json {
+        url:      String,
+        path:     String,
+        source:   serde_json::Error,
+    },
+    /// A field we cannot do without was missing or null.
+    MissingField {
+        url:      String,
+        field:    String,
+    },
+    /// The server asked us to slow down (HTTP 429).
+    RateLimited {
+        url:          String,
+        retry_after:  Option<Duration>,
+    },
+    /// Replaying a recording, and it has no more responses for the url.
+    ReplayExhausted {
+        url:      String,
+    },
+}
+
+/// What the poll scheduler should do about a failed request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorAction {
+    /// Try again on the next poll.
+    Retry,
+    /// Leave the station alone for a while.
+    BackOff(Duration),
+    /// The station will never work, stop polling it.
+    Disable,
+}
+
+
+/// Implementation for WeatherGovError.
+impl WeatherGovError {
+
+    ///  Decides how the scheduler should react to the error of a station
+    ///      meta data request, where 400, 404 and 410 mean the station
+    ///      doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the error
+    ///
+    /// # Return
+    ///
+    /// ErrorAction
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            WeatherGovError::Transport(_) => ErrorAction::Retry,
+            WeatherGovError::RateLimited { retry_after, .. } =>
+                ErrorAction::BackOff(retry_after.unwrap_or(DEFAULT_RATE_LIMIT_BACKOFF)),
+            // Unknown or retired station ids
+            WeatherGovError::Status { status: 400 | 404 | 410, .. } => ErrorAction::Disable,
+            WeatherGovError::Status { status, .. } if *status >= 500 => ErrorAction::Retry,
+            WeatherGovError::Status { .. } => ErrorAction::BackOff(DEFAULT_RATE_LIMIT_BACKOFF),
+            // Usually a truncated body or a station that has not reported yet
+            WeatherGovError::Json { .. } => ErrorAction::Retry,
+            WeatherGovError::MissingField { .. } => ErrorAction::Retry,
+            WeatherGovError::ReplayExhausted { .. } => ErrorAction::Disable,
+        }
+    }
+
+    ///  Decides how the scheduler should react to the error of a latest
+    ///      observation request. The station is known to exist, so 400, 404
+    ///      and 410 mean it has no recent observation, and it backs off
+    ///      rather than being disabled.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the error
+    ///
+    /// # Return
+    ///
+    /// ErrorAction
+    pub fn observation_action(&self) -> ErrorAction {
+        match self {
+            WeatherGovError::Status { status: 400 | 404 | 410, .. } =>
+                ErrorAction::BackOff(NO_OBSERVATION_BACKOFF),
+            _ => self.action(),
+        }
+    }
+}
+
+/// Displays a WeatherGovError.
+impl fmt::Display for WeatherGovError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeatherGovError::Transport(e) => write!(f, "transport error: {}", e),
+            WeatherGovError::Status { url, status, problem } => match problem {
+                Some(p) => write!(f, "HTTP {} from {}: {}", status, url, p),
+                None => write!(f, "HTTP {} from {}", status, url),
+            },
+            WeatherGovError::Json { url, path, source } =>
+                write!(f, "could not decode json from {} at {}: {}", url, path, source),
+            WeatherGovError::MissingField { url, field } =>
+                write!(f, "missing field {} in response from {}", field, url),
+            WeatherGovError::RateLimited { url, retry_after } =>
+                write!(f, "rate limited by {}, retry after {:?}", url, retry_after),
+            WeatherGovError::ReplayExhausted { url } =>
+                write!(f, "no more recorded responses for {}", url),
+        }
+    }
+}
+
+/// Enables ? and source() on a WeatherGovError.
+impl std::error::Error for WeatherGovError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WeatherGovError::Transport(e) => Some(e),
+            WeatherGovError::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a reqwest error to a WeatherGovError.
+impl From<reqwest::Error> for WeatherGovError {
+    fn from(e: reqwest::Error) -> Self {
+        WeatherGovError::Transport(e)
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use chrono::DateTime;
+use log::{info, debug};
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde_json::Value;
+use crate::sink::ObservationSink;
+use crate::station::ObservationRecord;
+use crate::storage::{BoxError, ObservationQuery, Storage};
+use crate::tabular::{self, Row};
+
+/// Observations read from storage per page, and written per row group.
+pub const PAGE_SIZE: usize = 10_000;
+
+/// Represents a Parquet column type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnKind {
+    /// INT64 microseconds since the epoch, UTC.
+    Timestamp,
+    /// UTF-8 BYTE_ARRAY.
+    Text,
+    Float,
+    Integer,
+    Boolean,
+}
+
+/// Represents a Parquet column of the observation schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name:      String,
+    pub kind:      ColumnKind,
+    /// Never null; everything but station_id and timestamp_UTC is nullable.
+    pub required:  bool,
+}
+
+///  Gets the observation columns, in ObservationRecord field order.
+///      Types follow the fields, so new fields are exported as they are added.
+///
+/// # Arguments
+/// None
+///
+/// # Return
+///
+/// The columns
+pub fn observation_columns() -> Vec<Column> {
+    tabular::fields::<ObservationRecord>().into_iter().map(|(name, value)| {
+        let kind = match (name.as_str(), &value) {
+            ("timestamp_UTC", _) => ColumnKind::Timestamp,
+            (_, Value::Number(n)) if n.is_f64() => ColumnKind::Float,
+            (_, Value::Number(_)) => ColumnKind::Integer,
+            (_, Value::Bool(_)) => ColumnKind::Boolean,
+            _ => ColumnKind::Text,
+        };
+        let required = name == "station_id" || name == "timestamp_UTC";
+        Column { name, kind, required }
+    }).collect()
+}
+
+///  Builds the Parquet message type for columns.
+///
+/// # Arguments
+///
+///*'columns'-the columns
+///
+/// # Return
+///
+/// The schema, in the Parquet message type syntax
+pub fn schema(columns: &[Column]) -> String {
+    let fields: Vec<String> = columns.iter().map(|c| {
+        let repetition = if c.required { "REQUIRED" } else { "OPTIONAL" };
+        let kind = match c.kind {
+            ColumnKind::Timestamp => "INT64",
+            ColumnKind::Text => "BYTE_ARRAY",
+            ColumnKind::Float => "DOUBLE",
+            ColumnKind::Integer => "INT64",
+            ColumnKind::Boolean => "BOOLEAN",
+        };
+        let logical = match c.kind {
+            ColumnKind::Timestamp => " (TIMESTAMP(MICROS,true))",
+            ColumnKind::Text => " (STRING)",
+            _ => "",
+        };
+        format!("  {} {} {}{};", repetition, kind, c.name, logical)
+    }).collect();
+    format!("message observation {{\n{}\n}}", fields.join("\n"))
+}
+
+///  Gets the partition directory of an observation:
+///      <dir>/station=<station_id>/month=<YYYY-MM>
+///
+/// # Arguments
+///
+///*'dir'-the export directory
+///*'rec'-the ObservationRecord
+///
+/// # Return
+///
+/// The directory
+pub fn partition_dir(dir: &Path, rec: &ObservationRecord) -> PathBuf {
+    dir.join(format!("station={}", rec.station_id))
+        .join(format!("month={}", rec.timestamp_UTC.get(..7).unwrap_or("unknown")))
+}
+
+
+/// Represents a Parquet file being written.
+///     Rows go to a temporary file, renamed when finished, so a reader
+///     never sees a file without its footer.
+pub struct ParquetFile {
+    pub path:  PathBuf,
+    pub rows:  u64,
+    tmp:       PathBuf,
+    columns:   Vec<Column>,
+    writer:    SerializedFileWriter<File>,
+}
+
+/// Implementation for a Parquet file.
+impl ParquetFile {
+
+    ///  Creates the file, and its directory.
+    ///
+    /// # Arguments
+    ///
+    ///*'path'-the file path
+    ///
+    /// # Return
+    ///
+    /// ParquetFile, or the error creating it
+    pub fn create(path: &Path) -> Result<ParquetFile, BoxError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let columns = observation_columns();
+        let schema = Arc::new(parse_message_type(&schema(&columns))?);
+        let props = Arc::new(WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_created_by(format!("weather_gov {}", env!("CARGO_PKG_VERSION")))
+            .build());
+        let tmp = path.with_extension("parquet.tmp");
+        let writer = SerializedFileWriter::new(File::create(&tmp)?, schema, props)?;
+        Ok(ParquetFile { path: path.to_path_buf(), rows: 0, tmp, columns, writer })
+    }
+
+    ///  Writes observations as one row group.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the file
+    ///*'recs'-the ObservationRecords
+    ///
+    /// # Return
+    ///
+    /// Result
+    pub fn write(&mut self, recs: &[ObservationRecord]) -> Result<(), BoxError> {
+        if recs.is_empty() {
+            return Ok(());
+        }
+        let rows: Vec<Row> = recs.iter().map(tabular::to_row).collect();
+        let mut group = self.writer.next_row_group()?;
+        for column in &self.columns {
+            let mut writer = group.next_column()?.ok_or("fewer columns than the schema")?;
+            let cells: Vec<&Value> = rows.iter()
+                .map(|r| r.get(&column.name).unwrap_or(&Value::Null)).collect();
+            let levels: Vec<i16> = cells.iter().map(|v| if v.is_null() { 0 } else { 1 }).collect();
+            let levels = if column.required { None } else { Some(levels.as_slice()) };
+            let present = cells.iter().filter(|v| !v.is_null());
+            match column.kind {
+                ColumnKind::Timestamp => {
+                    let values = present.map(|v| timestamp_micros(v.as_str().unwrap_or("")))
+                        .collect::<Result<Vec<i64>, BoxError>>()?;
+                    writer.typed::<Int64Type>().write_batch(&values, levels, None)?;
+                },
+                ColumnKind::Text => {
+                    let values: Vec<ByteArray> = present
+                        .map(|v| ByteArray::from(v.as_str().unwrap_or(""))).collect();
+                    writer.typed::<ByteArrayType>().write_batch(&values, levels, None)?;
+                },
+                ColumnKind::Float => {
+                    let values: Vec<f64> = present.map(|v| v.as_f64().unwrap_or(f64::NAN))
+                        .collect();
+                    writer.typed::<DoubleType>().write_batch(&values, levels, None)?;
+                },
+                ColumnKind::Integer => {
+                    let values: Vec<i64> = present.map(|v| v.as_i64().unwrap_or(0)).collect();
+                    writer.typed::<Int64Type>().write_batch(&values, levels, None)?;
+                },
+                ColumnKind::Boolean => {
+                    let values: Vec<bool> = present.map(|v| v.as_bool().unwrap_or(false))
+                        .collect();
+                    writer.typed::<BoolType>().write_batch(&values, levels, None)?;
+                },
+            }
+            writer.close()?;
+        }
+        group.close()?;
+        self.rows += recs.len() as u64;
+        Ok(())
+    }
+
+    ///  Writes the footer and moves the file into place.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the file
+    ///
+    /// # Return
+    ///
+    /// The path and the number of rows
+    pub fn finish(self) -> Result<(PathBuf, u64), BoxError> {
+        self.writer.close()?;
+        fs::rename(&self.tmp, &self.path)?;
+        debug!("Wrote {} row(s) to {:?}", self.rows, self.path);
+        Ok((self.path, self.rows))
+    }
+
+} // impl ParquetFile
+
+///  Converts a stored timestamp to microseconds since the epoch.
+fn timestamp_micros(s: &str) -> Result<i64, BoxError> {
+    Ok(DateTime::parse_from_rfc3339(s)
+        .map_err(|e| format!("timestamp {:?}: {}", s, e))?
+        .timestamp_micros())
+}
+
+
+/// Represents what to export.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportOptions {
+    pub dir:       PathBuf,
+    /// Inclusive, a stored UTC timestamp, see storage::parse_timestamp.
+    pub from:      Option<String>,
+    /// Exclusive.
+    pub to:        Option<String>,
+    /// Station ids, every stored station if empty.
+    pub stations:  Vec<String>,
+}
+
+///  Exports observations from storage to Parquet files, one per station
+///      and month, <dir>/station=<id>/month=<YYYY-MM>/observations.parquet.
+///      Observations are read a page at a time, each page is a row group,
+///      and a rerun replaces the files it writes.
+///
+/// # Arguments
+///
+///*'storage'-the storage, which must be able to read observations
+///*'opts'-what to export
+///
+/// # Return
+///
+/// The files written and their row counts
+pub async fn export(storage: &dyn Storage, opts: &ExportOptions)
+                                            -> Result<Vec<(PathBuf, u64)>, BoxError> {
+    let stations = if opts.stations.is_empty() {
+        storage.list_stations().await?.into_iter().map(|s| s.call_id).collect()
+    } else {
+        opts.stations.clone()
+    };
+
+    let mut written = Vec::new();
+    for station_id in stations {
+        let mut query = ObservationQuery {
+            station_id:  station_id.clone(),
+            from:        opts.from.clone(),
+            to:          opts.to.clone(),
+            limit:       PAGE_SIZE,
+            offset:      0,
+        };
+        let mut file: Option<ParquetFile> = None;
+        loop {
+            let page = storage.observations(&query).await?;
+            // Pages are in timestamp order, so a month's rows are contiguous
+            for month in page.chunk_by(|a, b| a.timestamp_UTC.get(..7) == b.timestamp_UTC.get(..7)) {
+                let path = partition_dir(&opts.dir, &month[0]).join("observations.parquet");
+                if file.as_ref().is_some_and(|f| f.path != path) {
+                    written.push(file.take().unwrap().finish()?);
+                }
+                if file.is_none() {
+                    file = Some(ParquetFile::create(&path)?);
+                }
+                file.as_mut().unwrap().write(month)?;
+            }
+            if page.len() < query.limit {
+                break;
+            }
+            query.offset += page.len();
+        }
+        if let Some(f) = file {
+            written.push(f.finish()?);
+        }
+        info!("Exported station {:?}", station_id);
+    }
+    Ok(written)
+}
+
+
+/// Parquet sink settings, read from parquet_section.
+///     Only DIR is required.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParquetConfig {
+    pub dir:       PathBuf,
+    /// How often buffered observations are written out.
+    pub interval:  Duration,
+}
+
+/// Implementation for ParquetConfig.
+impl ParquetConfig {
+
+    ///  Reads the Parquet sink settings.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the parquet_section
+    ///
+    /// # Return
+    ///
+    /// ParquetConfig, None if DIR is not set
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<ParquetConfig> {
+        let dir = cfg.get("DIR").map(|d| d.trim()).filter(|d| !d.is_empty())?;
+        let secs = cfg.get("INTERVAL_SECS").and_then(|s| s.trim().parse().ok()).unwrap_or(3600);
+        Some(ParquetConfig { dir: PathBuf::from(dir), interval: Duration::from_secs(secs) })
+    }
+}
+
+/// Represents the Parquet output: new observations are buffered, and every
+///     interval written to a new part file in the export's partitions,
+///     <dir>/station=<id>/month=<YYYY-MM>/part-<first timestamp>.parquet.
+///     Observations still buffered when the collector stops are lost.
+pub struct ParquetSink {
+    pub config:  ParquetConfig,
+    buffer:      Mutex<Vec<ObservationRecord>>,
+    written:     Mutex<Instant>,
+}
+
+/// Implementation for the Parquet output.
+impl ParquetSink {
+
+    ///  Creates the sink.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the Parquet sink settings
+    ///
+    /// # Return
+    ///
+    /// ParquetSink instance
+    pub fn new(config: ParquetConfig) -> ParquetSink {
+        info!("Writing Parquet files to {:?} every {:?}", config.dir, config.interval);
+        ParquetSink { config, buffer: Mutex::new(Vec::new()), written: Mutex::new(Instant::now()) }
+    }
+
+    ///  Writes the buffered observations, a part file per station and month.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///
+    /// # Return
+    ///
+    /// The files written and their row counts
+    pub fn write_buffered(&self) -> Result<Vec<(PathBuf, u64)>, BoxError> {
+        let recs = std::mem::take(&mut *self.buffer.lock().unwrap());
+        *self.written.lock().unwrap() = Instant::now();
+        let mut parts: BTreeMap<PathBuf, Vec<ObservationRecord>> = BTreeMap::new();
+        for rec in &recs {
+            parts.entry(partition_dir(&self.config.dir, rec)).or_default().push(rec.clone());
+        }
+        let result: Result<Vec<_>, BoxError> = parts.into_iter().map(|(dir, mut part)| {
+            part.sort_by(|a, b| a.timestamp_UTC.cmp(&b.timestamp_UTC));
+            let first: String = part[0].timestamp_UTC.chars()
+                .filter(|c| c.is_ascii_digit()).take(14).collect();
+            let mut file = ParquetFile::create(&dir.join(format!("part-{}.parquet", first)))?;
+            file.write(&part)?;
+            file.finish()
+        }).collect();
+        // Kept for the next try, parts already written are then replaced
+        if result.is_err() {
+            self.buffer.lock().unwrap().splice(0..0, recs);
+        }
+        result
+    }
+
+} // impl ParquetSink
+
+
+/// Implementation of ObservationSink for the Parquet output.
+#[async_trait]
+impl ObservationSink for ParquetSink {
+
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.buffer.lock().unwrap().push(rec.clone());
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), BoxError> {
+        if self.written.lock().unwrap().elapsed() >= self.config.interval {
+            self.write_buffered()?;
+        }
+        Ok(())
+    }
+
+} // impl ObservationSink for ParquetSink
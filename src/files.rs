@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use serde_json::Value;
+use crate::sink::ObservationSink;
+use crate::station::ObservationRecord;
+use crate::storage::BoxError;
+use crate::tabular;
+
+/// Represents a file format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// CSV with a header line, RFC 4180.
+    Csv,
+    /// A JSON object per line.
+    Jsonl,
+}
+
+/// Implementation for FileFormat.
+impl FileFormat {
+
+    ///  Gets the file extension, csv or jsonl.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// File output settings, read from file_section.
+///     Only DIR is required.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileConfig {
+    pub dir:          PathBuf,
+    pub format:       FileFormat,
+    /// File name prefix.
+    pub prefix:       String,
+    /// A file per station, otherwise one for all stations.
+    pub per_station:  bool,
+    /// A file per UTC day of the observations.
+    pub daily:        bool,
+    /// Close a file once it is this big, 0 for no limit.
+    pub max_bytes:    u64,
+    /// Compress closed files.
+    pub gzip:         bool,
+}
+
+/// Implementation for FileConfig.
+impl FileConfig {
+
+    ///  Reads the file output settings.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the file_section
+    ///
+    /// # Return
+    ///
+    /// FileConfig, None if DIR is not set
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<FileConfig> {
+        let dir = cfg.get("DIR").map(|d| d.trim()).filter(|d| !d.is_empty())?;
+        let get = |key: &str, default: &str| cfg.get(key).map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty()).unwrap_or_else(|| default.to_string());
+        let flag = |key: &str, default: bool| matches!(
+            get(key, if default { "true" } else { "false" }).as_str(), "true" | "yes" | "1");
+
+        let format = match get("FORMAT", "csv").as_str() {
+            "jsonl" | "json" | "ndjson" => FileFormat::Jsonl,
+            "csv" => FileFormat::Csv,
+            other => {
+                warn!("Unknown file FORMAT {:?}, using csv", other);
+                FileFormat::Csv
+            },
+        };
+        Some(FileConfig {
+            dir:          PathBuf::from(dir),
+            format,
+            prefix:       cfg.get("PREFIX").map(|p| p.trim().to_string())
+                              .filter(|p| !p.is_empty())
+                              .unwrap_or_else(|| "observations".to_string()),
+            per_station:  flag("PER_STATION", true),
+            daily:        get("ROTATE", "daily") == "daily",
+            max_bytes:    get("MAX_BYTES", "0").parse().unwrap_or(0),
+            gzip:         flag("GZIP", false),
+        })
+    }
+
+    ///  Gets the file an observation goes in:
+    ///      <dir>/<prefix>[_<station_id>][_<YYYY-MM-DD>].<extension>
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the settings
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// The path
+    pub fn path_for(&self, rec: &ObservationRecord) -> PathBuf {
+        let mut name = self.prefix.clone();
+        if self.per_station {
+            name = format!("{}_{}", name, rec.station_id);
+        }
+        if self.daily {
+            name = format!("{}_{}", name, rec.timestamp_UTC.get(..10).unwrap_or("unknown"));
+        }
+        self.dir.join(format!("{}.{}", name, self.format.extension()))
+    }
+}
+
+
+/// Represents the open files and what is waiting to be written to them.
+#[derive(Debug, Default)]
+struct FileState {
+    // (station id or "" for combined files, path) -> lines, without the header
+    pending:  BTreeMap<(String, PathBuf), Vec<String>>,
+    // Station id, or "" for combined files -> the file written last
+    current:  HashMap<String, PathBuf>,
+    // Files whose CSV header was checked against ours
+    checked:  HashSet<PathBuf>,
+}
+
+/// Represents the CSV or JSON lines file output.
+///     Lines are buffered until the sink is flushed, after each poll cycle,
+///     then appended to each file in one write. A new file is written in
+///     full to a temporary file and renamed, so a reader never sees it
+///     without its header. Files are closed, and optionally compressed,
+///     when the day changes or they reach max_bytes.
+pub struct FileSink {
+    pub config:  FileConfig,
+    /// Column order, the ObservationRecord field order.
+    pub fields:  Vec<String>,
+    state:       Mutex<FileState>,
+}
+
+/// Implementation for the file output.
+impl FileSink {
+
+    ///  Creates the sink and its directory.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the file output settings
+    ///
+    /// # Return
+    ///
+    /// FileSink, or the error creating the directory
+    pub fn new(config: FileConfig) -> io::Result<FileSink> {
+        fs::create_dir_all(&config.dir)?;
+        info!("Writing {} files to {:?}", config.format.extension(), config.dir);
+        let fields = tabular::fields::<ObservationRecord>().keys().cloned().collect();
+        Ok(FileSink { config, fields, state: Mutex::new(FileState::default()) })
+    }
+
+    ///  Formats an observation as a line of the file format.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// The line, with its line ending
+    pub fn line(&self, rec: &ObservationRecord) -> String {
+        let row = tabular::select(&tabular::to_row(rec), &self.fields);
+        match self.config.format {
+            FileFormat::Csv => tabular::csv_line(&row),
+            FileFormat::Jsonl => format!("{}\n", Value::Object(row)),
+        }
+    }
+
+    ///  Buffers an observation for its file.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn append(&self, rec: &ObservationRecord) {
+        let group = if self.config.per_station { rec.station_id.clone() } else { String::new() };
+        let key = (group, self.config.path_for(rec));
+        self.state.lock().unwrap().pending.entry(key).or_default().push(self.line(rec));
+    }
+
+    ///  Writes the buffered lines, then closes the files that are done.
+    ///      Lines that could not be written stay buffered for the next flush.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///
+    /// # Return
+    ///
+    /// Result, the first error
+    pub fn write_pending(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let pending = std::mem::take(&mut state.pending);
+        let mut first_error = None;
+        for ((group, path), lines) in pending {
+            if let Err(e) = self.write_file(&mut state, &group, &path, &lines) {
+                warn!("Could not write {:?}: {}", path, e);
+                state.pending.insert((group, path), lines);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    ///  Appends lines to a file and closes files the file replaces.
+    fn write_file(&self, state: &mut FileState, group: &str, path: &Path, lines: &[String])
+                                                                        -> io::Result<()> {
+        let header = match self.config.format {
+            FileFormat::Csv => tabular::csv_header(&self.fields),
+            FileFormat::Jsonl => String::new(),
+        };
+
+        // A CSV file from a build with other columns is closed, not mixed
+        if self.config.format == FileFormat::Csv && path.exists()
+            && !state.checked.contains(path) {
+            let mut first = String::new();
+            BufReader::new(File::open(path)?).read_line(&mut first)?;
+            if first != header {
+                info!("{:?} has other columns, starting a new file", path);
+                self.close(path, true)?;
+            }
+        }
+        state.checked.insert(path.to_path_buf());
+
+        let body = lines.concat();
+        if path.exists() {
+            let mut f = OpenOptions::new().append(true).open(path)?;
+            f.write_all(body.as_bytes())?;
+            f.sync_data()?;
+        } else {
+            let tmp = with_suffix(path, ".tmp");
+            let mut f = File::create(&tmp)?;
+            f.write_all(header.as_bytes())?;
+            f.write_all(body.as_bytes())?;
+            f.sync_all()?;
+            fs::rename(&tmp, path)?;
+        }
+
+        if let Some(previous) = state.current.insert(group.to_string(), path.to_path_buf()) {
+            if previous != path && previous.exists() {
+                state.checked.remove(&previous);
+                self.close(&previous, false)?;
+            }
+        }
+        if self.config.max_bytes > 0 && fs::metadata(path)?.len() >= self.config.max_bytes {
+            state.checked.remove(path);
+            self.close(path, true)?;
+        }
+        Ok(())
+    }
+
+    ///  Closes a file: moves it aside with a sequence number if asked, so
+    ///      its name can be used again, then compresses it if gzip is on.
+    fn close(&self, path: &Path, number: bool) -> io::Result<()> {
+        let closed = if number {
+            let ext = self.config.format.extension();
+            let stem = path.with_extension("");
+            let target = (1..).map(|n| with_suffix(&stem, &format!(".{}.{}", n, ext)))
+                .find(|p| !p.exists() && !with_suffix(p, ".gz").exists())
+                .expect("a free sequence number");
+            fs::rename(path, &target)?;
+            target
+        } else {
+            path.to_path_buf()
+        };
+        if self.config.gzip {
+            let gz = with_suffix(&closed, ".gz");
+            let tmp = with_suffix(&gz, ".tmp");
+            let mut encoder = GzEncoder::new(File::create(&tmp)?, Compression::default());
+            io::copy(&mut File::open(&closed)?, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+            fs::rename(&tmp, &gz)?;
+            fs::remove_file(&closed)?;
+            info!("Closed {:?}", gz);
+        } else {
+            info!("Closed {:?}", closed);
+        }
+        Ok(())
+    }
+
+} // impl FileSink
+
+
+///  Appends text to a path's file name, e.g. a.csv and ".gz" give a.csv.gz.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+
+/// Implementation of ObservationSink for the file output.
+#[async_trait]
+impl ObservationSink for FileSink {
+
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.append(rec);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), BoxError> {
+        Ok(self.write_pending()?)
+    }
+
+} // impl ObservationSink for FileSink
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::DateTime;
+use log::{info, warn};
+use serde_json::Value;
+use crate::sink::ObservationSink;
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::BoxError;
+use crate::tabular;
+
+/// The measurement every observation is written to.
+pub const MEASUREMENT: &str = "weather";
+
+/// Lines kept for a later write while the target is failing, the oldest are dropped.
+pub const MAX_PENDING: usize = 100_000;
+
+/// Represents where line protocol is written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfluxTarget {
+    /// An InfluxDB 2 /api/v2/write endpoint.
+    Http {
+        /// The write url.
+        url:     String,
+        org:     String,
+        bucket:  String,
+        /// Sent as "Authorization: Token <token>" when set.
+        token:   String,
+    },
+    /// A file lines are appended to, e.g. for Telegraf's tail input.
+    File(PathBuf),
+    /// Standard output, e.g. for Telegraf's execd input.
+    Stdout,
+}
+
+/// InfluxDB settings, read from influx_section.
+///     URL, or FILE ("-" for stdout), is required; URL wins if both are set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfluxConfig {
+    pub target:      InfluxTarget,
+    /// Lines per write.
+    pub batch_size:  usize,
+    pub timeout:     Duration,
+}
+
+/// Implementation for InfluxConfig.
+impl InfluxConfig {
+
+    ///  Reads the InfluxDB settings.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the influx_section
+    ///
+    /// # Return
+    ///
+    /// InfluxConfig, None if neither URL nor FILE is set
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<InfluxConfig> {
+        let get = |key: &str| cfg.get(key).map(|v| v.trim().to_string()).unwrap_or_default();
+        let number = |key: &str, default: u64| get(key).parse::<u64>().unwrap_or(default);
+
+        let url = get("URL");
+        let file = get("FILE");
+        let target = if !url.is_empty() {
+            let base = url.trim_end_matches('/');
+            InfluxTarget::Http {
+                url:     if base.ends_with("/api/v2/write") { base.to_string() }
+                         else { format!("{}/api/v2/write", base) },
+                org:     get("ORG"),
+                bucket:  get("BUCKET"),
+                token:   get("TOKEN"),
+            }
+        } else if file == "-" {
+            InfluxTarget::Stdout
+        } else if !file.is_empty() {
+            InfluxTarget::File(PathBuf::from(file))
+        } else {
+            return None;
+        };
+        Some(InfluxConfig {
+            target,
+            batch_size:  number("BATCH_SIZE", 5000).max(1) as usize,
+            timeout:     Duration::from_secs(number("TIMEOUT_SECS", 10)),
+        })
+    }
+}
+
+
+///  Renders an observation as a line of InfluxDB line protocol:
+///      measurement weather, tags name and station_id, a field per
+///      measured value, and the observation time in nanoseconds.
+///      Missing values are left out.
+///
+/// # Arguments
+///
+///*'rec'-the ObservationRecord
+///*'name'-the station's name, for the name tag
+///
+/// # Return
+///
+/// The line, without a line ending, None if the record has no
+/// valid timestamp or no values
+pub fn line(rec: &ObservationRecord, name: Option<&str>) -> Option<String> {
+    let nanos = DateTime::parse_from_rfc3339(&rec.timestamp_UTC).ok()?.timestamp_nanos_opt()?;
+
+    let fields: Vec<String> = tabular::to_row(rec).into_iter()
+        .filter(|(k, _)| k != "station_id" && k != "timestamp_UTC")
+        .filter_map(|(k, v)| {
+            let value = match v {
+                Value::Number(n) if n.is_f64() => n.to_string(),
+                Value::Number(n) => format!("{}i", n),
+                Value::Bool(b) => b.to_string(),
+                Value::String(s) => format!("\"{}\"", escape(&s, &['\\', '"'])),
+                _ => return None,
+            };
+            Some(format!("{}={}", escape(&k, &[',', '=', ' ']), value))
+        })
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+
+    // Tags sorted by key, as InfluxDB prefers
+    let mut out = escape(MEASUREMENT, &[',', ' ']);
+    if let Some(name) = name.filter(|n| !n.is_empty()) {
+        out.push_str(&format!(",name={}", escape(name, &[',', '=', ' '])));
+    }
+    out.push_str(&format!(",station_id={} {} {}", escape(&rec.station_id, &[',', '=', ' ']),
+                          fields.join(","), nanos));
+    Some(out)
+}
+
+///  Escapes characters with a backslash, newlines become spaces.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' | '\r' => out.push(' '),
+            c if special.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+
+/// Represents the InfluxDB line protocol output.
+///     Lines are buffered and written in batches of batch_size, and
+///     whatever is left when the sink is flushed, after each poll cycle.
+///     Lines that could not be written are kept for the next write.
+pub struct InfluxSink {
+    pub config:  InfluxConfig,
+    http:        reqwest::Client,
+    // Station id -> name, for the name tag
+    names:       Mutex<HashMap<String, String>>,
+    pending:     Mutex<Vec<String>>,
+}
+
+/// Implementation for the InfluxDB output.
+impl InfluxSink {
+
+    ///  Creates the sink.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the InfluxDB settings
+    ///
+    /// # Return
+    ///
+    /// InfluxSink instance
+    pub fn new(config: InfluxConfig) -> InfluxSink {
+        match &config.target {
+            InfluxTarget::Http { url, bucket, .. } =>
+                info!("Writing line protocol to {} bucket {:?}", url, bucket),
+            InfluxTarget::File(path) => info!("Writing line protocol to {:?}", path),
+            InfluxTarget::Stdout => info!("Writing line protocol to stdout"),
+        }
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .unwrap_or_default();
+        InfluxSink {
+            config,
+            http,
+            names:    Mutex::new(HashMap::new()),
+            pending:  Mutex::new(Vec::new()),
+        }
+    }
+
+    ///  Writes the buffered lines, batch_size at a time.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///
+    /// # Return
+    ///
+    /// Result, the error of the batch that failed
+    pub async fn write_pending(&self) -> Result<(), BoxError> {
+        let lines = std::mem::take(&mut *self.pending.lock().unwrap());
+        for (n, batch) in lines.chunks(self.config.batch_size).enumerate() {
+            if let Err(e) = self.write(batch).await {
+                let mut pending = self.pending.lock().unwrap();
+                let mut kept = lines[n * self.config.batch_size..].to_vec();
+                kept.append(&mut pending);
+                if kept.len() > MAX_PENDING {
+                    warn!("InfluxDB output is behind, dropping {} lines", kept.len() - MAX_PENDING);
+                    kept.drain(..kept.len() - MAX_PENDING);
+                }
+                *pending = kept;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    ///  Writes a batch of lines to the target.
+    async fn write(&self, lines: &[String]) -> Result<(), BoxError> {
+        let mut body = lines.join("\n");
+        body.push('\n');
+        match &self.config.target {
+            InfluxTarget::Http { url, org, bucket, token } => {
+                let mut req = self.http.post(url)
+                    .query(&[("org", org.as_str()), ("bucket", bucket.as_str()),
+                             ("precision", "ns")])
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body(body);
+                if !token.is_empty() {
+                    req = req.header("Authorization", format!("Token {}", token));
+                }
+                let resp = req.send().await?;
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_default();
+                    return Err(format!("{} answered {}: {}", url, status, text.trim()).into());
+                }
+            },
+            InfluxTarget::File(path) => {
+                let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+                f.write_all(body.as_bytes())?;
+            },
+            InfluxTarget::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(body.as_bytes())?;
+                out.flush()?;
+            },
+        }
+        Ok(())
+    }
+
+} // impl InfluxSink
+
+
+/// Implementation of ObservationSink for the InfluxDB output.
+#[async_trait]
+impl ObservationSink for InfluxSink {
+
+    fn name(&self) -> &str {
+        "influx"
+    }
+
+    async fn on_station(&self, station: &StationRecord) -> Result<(), BoxError> {
+        self.names.lock().unwrap().insert(station.call_id.clone(), station.name.clone());
+        Ok(())
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        let name = self.names.lock().unwrap().get(&rec.station_id).cloned();
+        let Some(line) = line(rec, name.as_deref()) else {
+            warn!("No line protocol for {} at {:?}", rec.station_id, rec.timestamp_UTC);
+            return Ok(());
+        };
+        let full = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push(line);
+            pending.len() >= self.config.batch_size
+        };
+        if full {
+            self.write_pending().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), BoxError> {
+        self.write_pending().await
+    }
+
+} // impl ObservationSink for InfluxSink
@@ -0,0 +1,88 @@
+//!
+//! Project:        weather_gov
+//!
+//! Description:    A client and collector for the weather_gov api.
+//!
+//! The library can be used on its own for the api.weather.gov client and
+//! record types, or with the collector that periodically gets the latest
+//! observation for the stations given in a config file and stores them.
+//!
+//!
+//! Modules:
+//!
+//! * client - api.weather.gov http client (NwsClient).
+//! * station - Station, StationRecord and ObservationRecord.
+//! * meteorology - heat index, wind chill, humidex and other derived values.
+//! * error - WeatherGovError.
+//! * models - typed serde models of the api.weather.gov GeoJSON responses.
+//! * storage - the Storage trait and insert outcomes.
+//! * tendency - three-hour pressure tendency and temperature and dewpoint changes.
+//! * replay - recording and replaying api.weather.gov exchanges.
+//! * memory - in-memory Storage for tests, and NullStore for running without a db.
+//! * db - MySQL Storage (feature "mysql", on by default).
+//! * spool - local write-ahead spool used while storage is down.
+//! * collector - the poll loop.
+//! * sink - the ObservationSink trait and the registry new observations fan out through.
+//! * metrics - Prometheus metrics of the collector and the weather.
+//! * mqtt - MQTT output of new observations (feature "mqtt", on by default).
+//! * webhook - signed webhook POSTs of new observations.
+//! * astronomy - sunrise, sunset, twilight and the sun's position at a station.
+//! * wind - compass points, Beaufort and Saffir-Simpson classes, and wind roses.
+//! * files - CSV and JSON lines file output of new observations.
+//! * export - Parquet export of stored observations, and a Parquet sink
+//!   (feature "parquet", on by default).
+//! * influx - InfluxDB line protocol output of new observations.
+//! * climate - daily and monthly climate summaries in each station's local time.
+//! * retention - rolling old observations up into hourly summaries, and purging.
+//! * qc - data-quality checks of observations before they are stored.
+//! * rules - threshold alerting rules over new observations.
+//! * config - the weather_gov.yml config.
+//! * tabular - records as ordered rows, and CSV.
+//! * api - embedded http api over the storage (feature "http-api", on by default).
+//!
+//!
+//! Features:
+//!
+//! * mysql - MySQL storage through sqlx. Consumers that only need the
+//!   api client can use default-features = false.
+//! * http-api - the embedded http api, through tiny_http.
+//! * mqtt - the MQTT output, through rumqttc.
+//! * parquet - the Parquet export and sink, through the parquet crate.
+//!
+#[cfg(feature = "http-api")]
+pub mod api;
+pub mod astronomy;
+pub mod client;
+pub mod climate;
+pub mod collector;
+pub mod config;
+#[cfg(feature = "mysql")]
+pub mod db;
+pub mod error;
+#[cfg(feature = "parquet")]
+pub mod export;
+pub mod files;
+pub mod influx;
+pub mod memory;
+pub mod meteorology;
+pub mod metrics;
+pub mod replay;
+pub mod qc;
+pub mod retention;
+pub mod rules;
+pub mod models;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod sink;
+pub mod spool;
+pub mod station;
+pub mod storage;
+pub mod tabular;
+pub mod tendency;
+pub mod webhook;
+pub mod wind;
+
+pub use client::NwsClient;
+pub use error::WeatherGovError;
+pub use station::{Station, StationRecord, ObservationRecord};
+pub use storage::{Storage, StorageError, InsertOutcome, BatchOutcome, ObservationQuery};
@@ -15,7 +15,8 @@
 //!     1.  Parse yml config, weather_gov.yml.
 //!     2.  Create the local database tables.
 //!     3.  Get station list from config.
-//!     4.  For each station, periodically get observations from weather.gov.
+//!     4.  For each station, periodically get observations from weather.gov,
+//!         adding derived values such as heat index and wind chill.
 //!
 //! Running:
 //!
@@ -24,6 +25,103 @@
 //!     This is because weather_gov.yml needs to be in the current directory with the
 //!     executable
 //!
+//!     ./build.sh run spool-status   prints the depth of the observation spool and exits.
+//!     ./build.sh run -- --record <dir>   also writes every api.weather.gov exchange to <dir>.
+//!     ./build.sh run -- --replay <dir>   replays a recording instead of the network, then exits.
+//!     ./build.sh run -- export --format parquet --from 2024-04-01 --to 2024-05-01
+//!                                 writes stored observations to Parquet files, then exits.
+//!     ./build.sh run -- summarize --from 2024-01-01
+//!                                 computes the climate summaries of stored observations,
+//!                                 then exits.
+//!     ./build.sh run -- sun --station KPHX --date 2024-06-21
+//!                                 prints the station's sunrise, sunset, twilight and
+//!                                 solar noon in its local time, then exits.
+//!
+//! Api:
+//!
+//!     With api_section BIND set, an http api serves the stored stations and
+//!     observations as JSON or CSV, so clients don't need db credentials:
+//!     /stations, /stations/{id}/latest, /stations/{id}/observations,
+//!     /stations/{id}/summary and /stations/{id}/windrose, and Prometheus
+//!     metrics on /metrics. Summaries need a from time, and group by UTC
+//!     hour, day or month; the local-time climate summaries are in the db.
+//!
+//! Sinks:
+//!
+//!     New observations fan out to the outputs in the config's sinks list,
+//!     db, file, influx, metrics, mqtt, parquet and webhook by default, each
+//!     with its own queue so a slow or failing output doesn't hold up the
+//!     others. Leave db out of the list to run without a database, e.g.
+//!     writing only files.
+//!
+//! Files:
+//!
+//!     With file_section DIR set, new observations are appended to CSV or
+//!     JSON lines files, per station or combined, rotated daily or by size,
+//!     and optionally gzipped once closed.
+//!
+//! InfluxDB:
+//!
+//!     With influx_section URL set, new observations are written in batches
+//!     to an InfluxDB 2 /api/v2/write endpoint as line protocol, measurement
+//!     weather tagged by station_id and name. With FILE instead they are
+//!     appended to a file, or stdout for "-", for Telegraf to pick up.
+//!
+//! Mqtt:
+//!
+//!     With mqtt_section HOST set, each new observation is published to
+//!     weather_gov/<station_id>/observation as JSON and to a topic per field,
+//!     optionally with Home Assistant discovery configs.
+//!
+//! Webhooks:
+//!
+//!     Endpoints in webhook_section get a JSON POST per new observation,
+//!     HMAC signed when the endpoint has a secret. Deliveries that keep
+//!     failing are appended to a dead letter file.
+//!
+//! Summaries:
+//!
+//!     Daily and monthly climate summaries, highs, lows, means, max gust and
+//!     total precipitation with how many observations contributed, are kept
+//!     per station in its local time zone. Days that get new observations,
+//!     late ones from the spool too, are recomputed every summary_section
+//!     INTERVAL_SECS.
+//!
+//! Retention:
+//!
+//!     With retention_section RAW_DAYS set, observations older than that are
+//!     rolled up into hourly summaries and deleted, in bounded batches, every
+//!     INTERVAL_SECS. Hourly and daily summaries are purged after HOURLY_DAYS
+//!     and DAILY_DAYS. Days are summarized before their observations go.
+//!     The shipped config sets every limit to 0, so nothing is deleted until
+//!     one is chosen.
+//!
+//! Rules:
+//!
+//!     Rules in rules_section, e.g. "baro_pres_pa drops 300 in 3h", are checked
+//!     against each new observation. Firings and clears are logged, stored in
+//!     the rule event table, and sent to the sinks. At startup the stored
+//!     observations over the longest rule window are replayed, and rules
+//!     still fired in the rule event table stay fired.
+//!
+//!     Each new observation has its three-hour pressure tendency, with the
+//!     WMO characteristic code, and temperature and dewpoint changes from
+//!     the stored history, for rules like "pres_tendency_3h_pa <= -500".
+//!
+//! Quality control:
+//!
+//!     Each new observation is checked before it is stored, per qc_section:
+//!     climatological ranges, steps from the station's previous observation,
+//!     values stuck for too long, and dewpoint <= temperature and gust >= speed.
+//!     Failed checks are kept in its qc_flags and counted in the metrics.
+//!
+//! Spool:
+//!
+//!     Observations that cannot be written because the database is down are
+//!     appended to a local spool file, and drained in order by a background
+//!     task once the database is back. A .offset file next to the spool
+//!     marks how far it has drained.
+//!
 //!
 //! Primary Crates Used:
 //!
@@ -32,34 +130,24 @@
 //!     3. colog.
 //!     4. sqlx
 //!
+//!     The client, records and collector live in the weather_gov library,
+//!     this binary only wires them to the config and the MySQL db.
+//!
 //!
-use std::collections::HashMap;
-use std::{thread, time};
+use std::sync::Arc;
 
 // task allows main to not be an async function
 use async_std::task;
 
+use chrono::Utc;
 use log::{error, warn, info, debug};
-mod config;
-mod station;
-mod db;
-use chrono::prelude::{DateTime, Utc};
-use std::time::SystemTime;
-
-/// main::iso8601.
-///  converts a SystemTime to a iso 8601 string
-///
-/// # Arguments
-///*'st' - SystemTime
-///
-/// # Return
-///
-/// ISO 8601 string
-fn iso8601(st: &std::time::SystemTime) -> String {
-    let dt: DateTime<Utc> = st.clone().into();
-    format!("{}", dt.format("%+"))
-    // formats like "2001-07-08T00:34:60.026490+09:30"
-}
+use weather_gov::{astronomy, config, db, spool, collector};
+use weather_gov::replay::{Recorder, Replayer};
+use weather_gov::retention::RetentionPolicy;
+use weather_gov::storage::Storage;
+
+mod cli;
+use cli::Command;
 
 /// main.
 ///  orchestrates the program flow
@@ -76,121 +164,244 @@ fn main() {
     info!("Starting \
            weather_gov");
 
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Help) => { println!("{}", cli::USAGE); return; },
+        Ok(c) => c,
+        Err(e) => { eprintln!("{}\n\n{}", e, cli::USAGE); std::process::exit(2); },
+    };
+
     // Get the config
     let config = config::Config::get_config();
     info!("YAML config: {:?}", config);
 
-    // Get the stations url for the stations later on
-    let host: HashMap<String, String> = config.host_section;
-    debug!("Host config: {:?}", host);
-    let stations_url = match host.get("STATIONS_URL") {
-        Some(url) => url,
-        _ => panic!("Config is missing STATIONS_URL."),
-    };
+    // The spool is needed both for the status command and the collector
+    let spool = Arc::new(spool::Spool::new(&config.spool_section));
+    if command == Command::SpoolStatus {
+        match spool.status() {
+            Ok(st) => println!("Spool {:?}: {} record(s), {} of {} bytes",
+                               st.path, st.records, st.bytes, st.max_bytes),
+            Err(e) => { error!("Could not read spool {:?}: {:?}", spool.path, e);
+                        std::process::exit(1); }
+        }
+        return;
+    }
+    if let Command::Sun { .. } = command {
+        std::process::exit(sun(&config, &command));
+    }
 
-    // Get the polling interval - how often to get station
-    //                            observations
-    let parms: HashMap<String, String> = config.parameters_section;
-    let interval = match parms.get("OBS_INTERVAL_SECS") {
-        Some(inter) => inter,
-        None  => "300",
+    // Without the db sink nothing is stored, observations only go to the
+    //      other sinks, e.g. files. Export always reads the db.
+    let use_db = matches!(command, Command::Export { .. } | Command::Summarize { .. })
+        || weather_gov::sink::sink_names(&config).iter().any(|s| s == weather_gov::sink::DB_SINK);
+    let storage: Arc<dyn Storage> = if use_db {
+        // Need to crank up our db here
+        debug!("Db config: {:?}", config.db_section);
+        // Without a db connection there's not much can be done
+        let db = match db::Db::new(config.db_section.clone()) {
+            Ok(db) => db,
+            Err(e) => {
+                error!("Fatal: could not connect to database: {}", e);
+                std::process::exit(1);
+            },
+        };
+        info!("Database: {:?}", db);
+        let res = task::block_on(db.create_tables());
+        match res {
+            Ok(r) => r,
+            Err(err) => panic!("Fatal: could not create database tables: {}", err),
+        };
+        Arc::new(db)
+    } else {
+        info!("No db sink, observations are not stored");
+        Arc::new(weather_gov::memory::NullStore::new())
     };
 
-    let obs_interval = match interval.parse::<u64>()  {
-        Ok(o) => o,
-        Err(_) => 300,
-    };
-    info!("obs_interval: {:?}", obs_interval);
-
-    // Get the stations from the config
-    let stations: HashMap<String, String> = config.stations_section;
-    info!("Stations config: {:?}", stations);
-
-    // Create station objects and add to station list
-    let mut station_list = Vec::<station::Station>::new();
-    for (key, value) in stations {
-        debug!("{} / {}", key, value);
-        let station = station::Station::new(String::from(value),
-                                            String::from(stations_url));
-        station_list.push(station);
+    if let Command::Export { .. } = command {
+        std::process::exit(export(storage.as_ref(), &command));
+    }
+    if let Command::Summarize { .. } = command {
+        let policy = RetentionPolicy::from_config(&config.retention_section);
+        std::process::exit(summarize(storage.as_ref(), &command, policy));
     }
 
-    // Need to crank up our db here
-    let db_sect: HashMap<String, String> = config.db_section;
-    debug!("Host config: {:?}", db_sect);
-    let db = &mut db::Db::new(db_sect);
-    let res = task::block_on(db.create_tables());
-    match res {
-        Ok(r) => r,
-        Err(err) => panic!("Fatal: could not create database tables: {:?}", err),
-    };
-
+    // Replay anything left over from a previous outage, then keep draining
+    //      in the background. Without a db the spool is left for a later run.
+    match spool.status() {
+        Ok(st) => info!("Spool {:?} holds {} record(s)", st.path, st.records),
+        Err(e) => warn!("Could not read spool {:?}: {:?}", spool.path, e),
+    }
+    if use_db {
+        spool::spawn_drainer(spool.clone(), storage.clone());
+        if let Some(policy) = RetentionPolicy::from_config(&config.retention_section) {
+            weather_gov::retention::spawn(policy, storage.clone());
+        }
+    }
 
-    // Get the station json meta data
-    let mut station_iter = station_list.iter_mut();
-    for station in &mut station_iter {
-        let res = task::block_on(station.get_station_json());
-        let json = match res {
-            Ok(r) => r,
-            Err(err) => panic!("Could not get station json {:?}", err),
-        };
-        debug!("Returned Station json: {}", json);
+    // Get the station json meta data, then go through our stations,
+    //     get latest observation, and store, forever
+    let mut collector = collector::Collector::new(&config, storage.clone(), spool);
+    info!("Sinks: {:?}", collector.sinks.names());
 
-        // Get the station record and add to database if not already there
-        let station_record = station.get_station_record();
-        info!("Station record: {:?}", station_record);
-        let res = task::block_on(db.put_station_record(station_record));
-        info!("Put station record result: {:?}", res);
+    #[cfg(feature = "http-api")]
+    if let Some(bind) = weather_gov::api::bind_address(&config.api_section) {
+        let api = weather_gov::api::Api::new(storage, &config.api_section)
+            .with_metrics(collector.metrics.clone());
+        if let Err(e) = weather_gov::api::spawn(Arc::new(api), &bind) {
+            error!("Could not start the api on {:?}: {:?}", bind, e);
+        }
+    }
 
+    if let Command::Collect { record, replay } = command {
+        if let Some(dir) = record {
+            let recorder = Recorder::new(&dir)
+                .unwrap_or_else(|e| panic!("Could not record to {:?}: {:?}", dir, e));
+            collector.client = collector.client.clone().with_recorder(recorder);
+        }
+        if let Some(dir) = replay {
+            let replayer = Replayer::load(&dir)
+                .unwrap_or_else(|e| panic!("Could not load recording {:?}: {:?}", dir, e));
+            collector.client = collector.client.clone().with_replayer(replayer);
+        }
     }
+    task::block_on(async {
+        collector.init_stations().await;
+        collector.run().await;
+    });
+}
 
 
-    // We need a new station list iterator here, we consumed the earlier one
-    // We need to go through our stations, get latest observation, and put in db
-    let mut i: u64 = 0;
-    let interval = time::Duration::from_secs(obs_interval);
-    loop {
-        let now = SystemTime::now();
-        let t8601 = iso8601(&now);
-        info!("\n\nLOOP ITERATION: {}  TIME: {}", i, t8601);
-        i+=1;
-        let mut station_iter = station_list.iter_mut();
-        for station in &mut station_iter {
-            info!("\nGETTING STATION OBSERVATION FOR {:?}, {:?}, latitude {:?}  \
-                  longitude {:?}, elevation {:?} meters", station.station_identifier, 
-                  station.station_name, station.latitude, station.longitude,
-                  station.elevation_meters);
-            let res = task::block_on(station.get_latest_observation_data());
-            let obs = match res {
-                Ok(r) => r,
-                Err(e) => { warn!("Failed getting latest observation for station \
-                                  {:?}, {:?}: {:?}", station.station_identifier, 
-                                  station.station_name, e);
-                            continue;
-                          }
-            };
-
-            info!("Returned observation json for station: {:?}, {:?}: {:?}",
-                      station.station_identifier, station.station_name, obs);
-            let res = task::block_on(db.put_observation_record(obs));
-            match res {
-                Ok(r) => {
-                    if r.contains("Duplicate") {
-                        info!("Put observation record result for station {:?}, {:?}: {:?}",
-                              station.station_identifier, station.station_name, &r);
-                        info!("Ignoring Duplicate Observation Record for station {:?}, {:?}",
-                              station.station_identifier, station.station_name);
-                    } else {
-                        info!("Put observation record result for station {:?}, {:?}: {:?}",
-                              station.station_identifier, station.station_name, r);
-                    }
-                },
-                Err(err) => { error!("Error putting latest observation from station \
-                                     {:?}, {:?}: {:?}", station.station_identifier, 
-                                     station.station_name, err); }
-            }
+///  Runs the summarize command.
+///
+/// # Arguments
+///
+///*'storage'-the db
+///*'command'-the summarize command
+///*'policy'-the retention policy, days whose observations were rolled up
+///     are not summarized again
+///
+/// # Return
+///
+/// The exit code
+fn summarize(storage: &dyn Storage, command: &Command, policy: Option<RetentionPolicy>) -> i32 {
+    let Command::Summarize { from, to, stations } = command else { return 2 };
+    let to = to.unwrap_or_else(|| Utc::now().date_naive() + chrono::Days::new(1));
+    let kept = policy.map_or(0, |p| p.raw_days);
+    let first_kept = Utc::now().date_naive() - chrono::Days::new(kept) + chrono::Days::new(1);
+    let from = &if kept > 0 && *from < first_kept {
+        warn!("Observations before {} may be rolled up, summarizing from there", first_kept);
+        first_kept
+    } else {
+        *from
+    };
+    let result = task::block_on(async {
+        let mut known = storage.list_stations().await?;
+        if !stations.is_empty() {
+            known.retain(|s| stations.contains(&s.call_id));
         }
+        weather_gov::climate::summarize(storage, &known, *from, to).await
+    });
+    match result {
+        Ok((days, months)) => {
+            println!("Stored {} daily and {} monthly summaries from {} to {}",
+                     days, months, from, to);
+            0
+        },
+        Err(e) => {
+            error!("Summarize failed: {}", e);
+            1
+        },
+    }
+}
 
-        thread::sleep(interval);
+///  Runs the sun command, looking the station's position up on
+///      api.weather.gov.
+///
+/// # Arguments
+///
+///*'config'-the config, for host_section
+///*'command'-the sun command
+///
+/// # Return
+///
+/// The exit code
+fn sun(config: &config::Config, command: &Command) -> i32 {
+    let Command::Sun { station, date } = command else { return 2 };
+    let base_url = config.host_section.get("BASE_URL").map(String::as_str)
+        .unwrap_or(weather_gov::client::DEFAULT_BASE_URL);
+    let client = weather_gov::client::NwsClient::new(base_url);
+    let stations_url = config.host_section.get("STATIONS_URL").cloned()
+        .unwrap_or_else(|| client.stations_url());
+    let mut st = weather_gov::station::Station::new(station.clone(), stations_url);
+    if let Err(e) = task::block_on(st.get_station_json(&client)) {
+        error!("Could not get station {}: {}", station, e);
+        return 1;
+    }
+    if !st.has_position() {
+        error!("Station {} has no position", station);
+        return 1;
     }
+
+    let tz = weather_gov::climate::time_zone(&st.time_zone);
+    let date = date.unwrap_or_else(|| Utc::now().with_timezone(&tz).date_naive());
+    let times = astronomy::sun_times(st.latitude, st.longitude, 0.0, date);
+    let local = |t: Option<chrono::DateTime<Utc>>| t.map_or("-".to_string(),
+        |t| t.with_timezone(&tz).format("%H:%M:%S").to_string());
+    println!("{} {}, {:.4} {:.4}, {:.0} m, {} ({})", st.station_identifier, st.station_name,
+             st.latitude, st.longitude, st.elevation_meters, date, tz);
+    for (name, t) in [("Astronomical dawn", times.astronomical_dawn),
+                      ("Nautical dawn", times.nautical_dawn),
+                      ("Civil dawn", times.civil_dawn),
+                      ("Sunrise", times.sunrise),
+                      ("Solar noon", Some(times.solar_noon)),
+                      ("Sunset", times.sunset),
+                      ("Civil dusk", times.civil_dusk),
+                      ("Nautical dusk", times.nautical_dusk),
+                      ("Astronomical dusk", times.astronomical_dusk)] {
+        println!("    {:<18} {}", name, local(t));
+    }
+    println!("    {:<18} {}:{:02}", "Day length", times.day_length_secs / 3600,
+             times.day_length_secs % 3600 / 60);
+    println!("    {:<18} {:.1}°", "Noon elevation", times.noon_elevation_deg);
+    0
+}
+
+///  Runs the export command.
+///
+/// # Arguments
+///
+///*'storage'-the db
+///*'command'-the export command
+///
+/// # Return
+///
+/// The exit code
+#[cfg(feature = "parquet")]
+fn export(storage: &dyn Storage, command: &Command) -> i32 {
+    use weather_gov::export::{self, ExportOptions};
+
+    let Command::Export { from, to, stations, out, .. } = command else { return 2 };
+    let opts = ExportOptions {
+        dir:       out.clone(),
+        from:      from.clone(),
+        to:        to.clone(),
+        stations:  stations.clone(),
+    };
+    match task::block_on(export::export(storage, &opts)) {
+        Ok(files) => {
+            let rows: u64 = files.iter().map(|(_, n)| n).sum();
+            println!("Exported {} observation(s) to {} file(s) in {:?}", rows, files.len(), out);
+            0
+        },
+        Err(e) => {
+            error!("Export failed: {}", e);
+            1
+        },
+    }
+}
+
+///  Runs the export command, which needs the parquet feature.
+#[cfg(not(feature = "parquet"))]
+fn export(_storage: &dyn Storage, _command: &Command) -> i32 {
+    error!("This build has no parquet export, build it with the parquet feature");
+    2
 }
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use crate::climate::{DailySummary, HourlySummary, MonthlySummary};
+use crate::rules::RuleEvent;
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::{Storage, StorageError, InsertOutcome, BatchOutcome, InsertCounters,
+                     ObservationQuery};
+
+/// Represents an in-memory Storage, for tests and for running without a db.
+///     Observations are keyed like the db primary key, (station_id, timestamp_UTC).
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    stations:      Mutex<BTreeMap<String, StationRecord>>,
+    observations:  Mutex<BTreeMap<(String, String), ObservationRecord>>,
+    rule_events:   Mutex<Vec<RuleEvent>>,
+    // Keyed like the summary tables, (station_id, hour, date or month)
+    hourly:        Mutex<BTreeMap<(String, String), HourlySummary>>,
+    daily:         Mutex<BTreeMap<(String, String), DailySummary>>,
+    monthly:       Mutex<BTreeMap<(String, String), MonthlySummary>>,
+    counters:      InsertCounters,
+}
+
+
+/// Implementation for the in-memory store.
+impl MemoryStore {
+
+    ///  Creates an empty store.
+    ///
+    /// # Arguments
+    /// None
+    ///
+    /// # Return
+    ///
+    /// MemoryStore instance
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    ///  Gets all station records, ordered by call id.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the store
+    ///
+    /// # Return
+    ///
+    /// The station records
+    pub fn station_records(&self) -> Vec<StationRecord> {
+        self.stations.lock().unwrap().values().cloned().collect()
+    }
+
+    ///  Gets all observation records, ordered by station then timestamp.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the store
+    ///
+    /// # Return
+    ///
+    /// The observation records
+    pub fn observation_records(&self) -> Vec<ObservationRecord> {
+        self.observations.lock().unwrap().values().cloned().collect()
+    }
+
+    ///  Gets all rule events, in the order they were added.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the store
+    ///
+    /// # Return
+    ///
+    /// The rule events
+    pub fn rule_events(&self) -> Vec<RuleEvent> {
+        self.rule_events.lock().unwrap().clone()
+    }
+
+} // impl MemoryStore
+
+
+/// Implementation of Storage for the in-memory store.
+#[async_trait]
+impl Storage for MemoryStore {
+
+    async fn create_tables(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn put_station_record(&self, rec: &StationRecord) -> Result<(), StorageError> {
+        self.stations.lock().unwrap().insert(rec.call_id.clone(), rec.clone());
+        Ok(())
+    }
+
+    async fn put_observation_record(&self, rec: &ObservationRecord) -> InsertOutcome {
+        let key = (rec.station_id.clone(), rec.timestamp_UTC.clone());
+        let mut observations = self.observations.lock().unwrap();
+        let outcome = match observations.entry(key) {
+            Entry::Occupied(_) => InsertOutcome::Duplicate,
+            Entry::Vacant(v) => { v.insert(rec.clone()); InsertOutcome::Inserted },
+        };
+        self.counters.count(&outcome);
+        outcome
+    }
+
+    async fn put_observation_batch(&self, recs: &[ObservationRecord])
+                                                -> Result<BatchOutcome, StorageError> {
+        let mut observations = self.observations.lock().unwrap();
+        let mut outcome = BatchOutcome::default();
+        for rec in recs {
+            let key = (rec.station_id.clone(), rec.timestamp_UTC.clone());
+            match observations.entry(key) {
+                Entry::Occupied(_) => outcome.duplicate += 1,
+                Entry::Vacant(v) => {
+                    outcome.inserted_keys.push(v.key().clone());
+                    v.insert(rec.clone());
+                    outcome.inserted += 1;
+                },
+            }
+        }
+        self.counters.add(outcome.inserted, outcome.duplicate);
+        Ok(outcome)
+    }
+
+    async fn list_stations(&self) -> Result<Vec<StationRecord>, StorageError> {
+        Ok(self.station_records())
+    }
+
+    async fn latest_observation(&self, station_id: &str)
+                                        -> Result<Option<ObservationRecord>, StorageError> {
+        let observations = self.observations.lock().unwrap();
+        Ok(observations.values().rev().find(|r| r.station_id == station_id).cloned())
+    }
+
+    async fn observations(&self, query: &ObservationQuery)
+                                        -> Result<Vec<ObservationRecord>, StorageError> {
+        let observations = self.observations.lock().unwrap();
+        Ok(observations.values()
+            .filter(|r| query.matches(r))
+            .skip(query.offset)
+            .take(query.limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn put_rule_event(&self, event: &RuleEvent) -> Result<(), StorageError> {
+        self.rule_events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+
+    async fn latest_rule_events(&self) -> Result<Vec<RuleEvent>, StorageError> {
+        let mut latest: Vec<RuleEvent> = Vec::new();
+        for event in self.rule_events.lock().unwrap().iter() {
+            latest.retain(|e| e.rule != event.rule || e.station_id != event.station_id);
+            latest.push(event.clone());
+        }
+        Ok(latest)
+    }
+
+    async fn put_daily_summary(&self, summary: &DailySummary) -> Result<(), StorageError> {
+        self.daily.lock().unwrap()
+            .insert((summary.station_id.clone(), summary.date.clone()), summary.clone());
+        Ok(())
+    }
+
+    async fn put_monthly_summary(&self, summary: &MonthlySummary) -> Result<(), StorageError> {
+        self.monthly.lock().unwrap()
+            .insert((summary.station_id.clone(), summary.month.clone()), summary.clone());
+        Ok(())
+    }
+
+    async fn daily_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<DailySummary>, StorageError> {
+        Ok(self.daily.lock().unwrap().values()
+            .filter(|d| d.station_id == station_id && d.date.as_str() >= from
+                        && d.date.as_str() < to)
+            .cloned()
+            .collect())
+    }
+
+    async fn monthly_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<MonthlySummary>, StorageError> {
+        Ok(self.monthly.lock().unwrap().values()
+            .filter(|m| m.station_id == station_id && m.month.as_str() >= from
+                        && m.month.as_str() < to)
+            .cloned()
+            .collect())
+    }
+
+    async fn roll_up(&self, summaries: &[HourlySummary], station_id: &str, from: &str, to: &str)
+                                        -> Result<u64, StorageError> {
+        let mut observations = self.observations.lock().unwrap();
+        let before = observations.len();
+        observations.retain(|(id, ts), _| id != station_id || ts.as_str() < from
+                                          || ts.as_str() >= to);
+        let mut hourly = self.hourly.lock().unwrap();
+        for summary in summaries {
+            hourly.insert((summary.station_id.clone(), summary.hour_UTC.clone()), summary.clone());
+        }
+        Ok((before - observations.len()) as u64)
+    }
+
+    async fn hourly_summaries(&self, station_id: &str, from: &str, to: &str)
+                                        -> Result<Vec<HourlySummary>, StorageError> {
+        Ok(self.hourly.lock().unwrap().values()
+            .filter(|h| h.station_id == station_id && h.hour_UTC.as_str() >= from
+                        && h.hour_UTC.as_str() < to)
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_hourly_summaries(&self, before: &str, limit: usize)
+                                        -> Result<u64, StorageError> {
+        let mut hourly = self.hourly.lock().unwrap();
+        let old: Vec<(String, String)> = hourly.keys()
+            .filter(|(_, hour)| hour.as_str() < before).take(limit).cloned().collect();
+        for key in &old {
+            hourly.remove(key);
+        }
+        Ok(old.len() as u64)
+    }
+
+    async fn purge_daily_summaries(&self, before: &str, limit: usize)
+                                        -> Result<u64, StorageError> {
+        let mut daily = self.daily.lock().unwrap();
+        let old: Vec<(String, String)> = daily.keys()
+            .filter(|(_, date)| date.as_str() < before).take(limit).cloned().collect();
+        for key in &old {
+            daily.remove(key);
+        }
+        Ok(old.len() as u64)
+    }
+
+    fn counters(&self) -> &InsertCounters {
+        &self.counters
+    }
+
+} // impl Storage for MemoryStore
+
+
+/// Represents a Storage that keeps nothing, for running with only file
+///     or other sinks. Every observation counts as inserted.
+#[derive(Debug, Default)]
+pub struct NullStore {
+    counters:  InsertCounters,
+}
+
+/// Implementation for the null store.
+impl NullStore {
+
+    ///  Creates the store.
+    ///
+    /// # Arguments
+    /// None
+    ///
+    /// # Return
+    ///
+    /// NullStore instance
+    pub fn new() -> NullStore {
+        NullStore::default()
+    }
+
+} // impl NullStore
+
+
+/// Implementation of Storage for the null store.
+#[async_trait]
+impl Storage for NullStore {
+
+    async fn create_tables(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn put_station_record(&self, _rec: &StationRecord) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn put_observation_record(&self, _rec: &ObservationRecord) -> InsertOutcome {
+        self.counters.count(&InsertOutcome::Inserted);
+        InsertOutcome::Inserted
+    }
+
+    async fn put_observation_batch(&self, recs: &[ObservationRecord])
+                                                -> Result<BatchOutcome, StorageError> {
+        self.counters.add(recs.len() as u64, 0);
+        Ok(BatchOutcome {
+            inserted:       recs.len() as u64,
+            duplicate:      0,
+            inserted_keys:  recs.iter()
+                                .map(|r| (r.station_id.clone(), r.timestamp_UTC.clone()))
+                                .collect(),
+        })
+    }
+
+    async fn put_rule_event(&self, _event: &RuleEvent) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn counters(&self) -> &InsertCounters {
+        &self.counters
+    }
+
+} // impl Storage for NullStore
@@ -0,0 +1,229 @@
+use crate::station::{ObservationRecord, MISSING};
+
+/// Standard sea level pressure, Pa.
+const STANDARD_PRESSURE_PA: f64 = 101325.0;
+
+///  Gets the saturation vapor pressure over water, Magnus form (WMO).
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///
+/// # Return
+///
+/// The vapor pressure, hPa
+pub fn saturation_vapor_pressure_hpa(t_c: f64) -> f64 {
+    6.112 * (17.62 * t_c / (243.12 + t_c)).exp()
+}
+
+///  Gets the relative humidity from the temperature and dewpoint.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'td_c'-the dewpoint, °C
+///
+/// # Return
+///
+/// The relative humidity, %, at most 100
+pub fn relative_humidity(t_c: f64, td_c: f64) -> f64 {
+    (100.0 * saturation_vapor_pressure_hpa(td_c) / saturation_vapor_pressure_hpa(t_c))
+        .clamp(0.0, 100.0)
+}
+
+///  Gets the heat index, NWS: Steadman's simple formula, or the Rothfusz
+///      regression with its low and high humidity adjustments when the
+///      simple value is 80 °F or more.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'rh'-the relative humidity, %
+///
+/// # Return
+///
+/// The heat index, °C, None below 80 °F where it isn't defined
+pub fn heat_index_c(t_c: f64, rh: f64) -> Option<f64> {
+    let t = t_c * 9.0 / 5.0 + 32.0;
+    if t < 80.0 {
+        return None;
+    }
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.04901523 * t + 10.14333127 * rh - 0.22475541 * t * rh
+            - 0.00683783 * t * t - 0.05481717 * rh * rh + 0.00122874 * t * t * rh
+            + 0.00085282 * t * rh * rh - 0.00000199 * t * t * rh * rh;
+        if rh < 13.0 && t <= 112.0 {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && t <= 87.0 {
+            hi += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+        }
+        hi
+    };
+    Some((hi - 32.0) * 5.0 / 9.0)
+}
+
+///  Gets the wind chill, NWS 2001.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'wind_km_h'-the wind speed, km/h
+///
+/// # Return
+///
+/// The wind chill, °C, None above 10 °C or at 4.8 km/h (3 mph) or less
+pub fn wind_chill_c(t_c: f64, wind_km_h: f64) -> Option<f64> {
+    if t_c > 10.0 || wind_km_h <= 4.8 {
+        return None;
+    }
+    let v = wind_km_h.powf(0.16);
+    Some(13.12 + 0.6215 * t_c - 11.37 * v + 0.3965 * t_c * v)
+}
+
+///  Gets the apparent temperature, Steadman's formula as used by the
+///      Australian Bureau of Meteorology, without solar radiation.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'rh'-the relative humidity, %
+///*'wind_km_h'-the wind speed, km/h
+///
+/// # Return
+///
+/// The apparent temperature, °C
+pub fn apparent_temperature_c(t_c: f64, rh: f64, wind_km_h: f64) -> f64 {
+    let e = rh / 100.0 * saturation_vapor_pressure_hpa(t_c);
+    t_c + 0.33 * e - 0.70 * (wind_km_h / 3.6) - 4.00
+}
+
+///  Gets the humidex, Environment Canada.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'td_c'-the dewpoint, °C
+///
+/// # Return
+///
+/// The humidex, in °C equivalent
+pub fn humidex(t_c: f64, td_c: f64) -> f64 {
+    let e = 6.11 * (5417.7530 * (1.0 / 273.16 - 1.0 / (273.15 + td_c))).exp();
+    t_c + 0.5555 * (e - 10.0)
+}
+
+///  Gets the wet-bulb temperature, Stull (2011). Good to about 1 °C for
+///      5 to 99 % humidity near sea level pressure.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'rh'-the relative humidity, %
+///
+/// # Return
+///
+/// The wet-bulb temperature, °C
+pub fn wet_bulb_c(t_c: f64, rh: f64) -> f64 {
+    t_c * (0.151977 * (rh + 8.313659).sqrt()).atan() + (t_c + rh).atan()
+        - (rh - 1.676331).atan() + 0.00391838 * rh.powf(1.5) * (0.023101 * rh).atan()
+        - 4.686035
+}
+
+///  Gets the absolute humidity, the mass of water vapor in a volume of air.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'rh'-the relative humidity, %
+///
+/// # Return
+///
+/// The absolute humidity, g/m³
+pub fn absolute_humidity_g_m3(t_c: f64, rh: f64) -> f64 {
+    let e_pa = rh / 100.0 * saturation_vapor_pressure_hpa(t_c) * 100.0;
+    e_pa / (461.5 * (t_c + 273.15)) * 1000.0
+}
+
+///  Gets the mixing ratio, the mass of water vapor per mass of dry air.
+///
+/// # Arguments
+///
+///*'t_c'-the temperature, °C
+///*'rh'-the relative humidity, %
+///*'pressure_pa'-the station pressure, Pa
+///
+/// # Return
+///
+/// The mixing ratio, g/kg
+pub fn mixing_ratio_g_kg(t_c: f64, rh: f64, pressure_pa: f64) -> f64 {
+    let e = rh / 100.0 * saturation_vapor_pressure_hpa(t_c);
+    621.97 * e / (pressure_pa / 100.0 - e)
+}
+
+///  Gets the pressure at a station's elevation from its sea level pressure,
+///      standard atmosphere.
+///
+/// # Arguments
+///
+///*'sea_level_pa'-the sea level pressure, Pa, None for the standard 101325
+///*'elevation_m'-the station elevation, m
+///
+/// # Return
+///
+/// The station pressure, Pa
+pub fn station_pressure_pa(sea_level_pa: Option<f64>, elevation_m: f64) -> f64 {
+    sea_level_pa.unwrap_or(STANDARD_PRESSURE_PA) * (1.0 - 2.25577e-5 * elevation_m).powf(5.25588)
+}
+
+///  Fills in an observation's derived values: relative humidity when it
+///      is missing but temperature and dewpoint are not, then heat index,
+///      wind chill, apparent temperature, humidex, wet-bulb temperature,
+///      absolute humidity and mixing ratio, where their inputs are there.
+///      The names of the fields filled in are listed in derived.
+///
+/// # Arguments
+///
+///*'rec'-the observation
+///*'elevation_m'-the station elevation, m
+///
+/// # Return
+///
+/// None
+pub fn derive(rec: &mut ObservationRecord, elevation_m: f64) {
+    let value = |v: f64| (v != MISSING).then_some(v);
+    let t = value(rec.temperature_C);
+    let td = value(rec.dewpoint_C);
+    let wind = value(rec.wind_spd_km_h);
+    let mut derived = Vec::new();
+
+    if let (None, Some(t), Some(td)) = (value(rec.rel_humidity), t, td) {
+        rec.rel_humidity = relative_humidity(t, td);
+        derived.push("rel_humidity");
+    }
+    let rh = value(rec.rel_humidity);
+    let pressure = station_pressure_pa(value(rec.baro_pres_pa), elevation_m);
+
+    let mut set = |name: &'static str, field: &mut f64, v: Option<f64>| {
+        *field = v.unwrap_or(MISSING);
+        if v.is_some() {
+            derived.push(name);
+        }
+    };
+    let with_rh = |f: &dyn Fn(f64, f64) -> f64| t.zip(rh).map(|(t, rh)| f(t, rh));
+    set("heat_index_C", &mut rec.heat_index_C,
+        t.zip(rh).and_then(|(t, rh)| heat_index_c(t, rh)));
+    set("wind_chill_C", &mut rec.wind_chill_C,
+        t.zip(wind).and_then(|(t, wind)| wind_chill_c(t, wind)));
+    set("apparent_temperature_C", &mut rec.apparent_temperature_C,
+        wind.and_then(|wind| with_rh(&|t, rh| apparent_temperature_c(t, rh, wind))));
+    set("humidex", &mut rec.humidex, t.zip(td).map(|(t, td)| humidex(t, td)));
+    set("wet_bulb_C", &mut rec.wet_bulb_C, with_rh(&wet_bulb_c));
+    set("abs_humidity_g_m3", &mut rec.abs_humidity_g_m3, with_rh(&absolute_humidity_g_m3));
+    set("mixing_ratio_g_kg", &mut rec.mixing_ratio_g_kg,
+        with_rh(&|t, rh| mixing_ratio_g_kg(t, rh, pressure)));
+
+    rec.derived = derived.join(",");
+}
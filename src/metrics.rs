@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::sink::ObservationSink;
+use crate::spool::Spool;
+use crate::station::{ObservationRecord, MISSING};
+use crate::storage::{Storage, BoxError};
+
+/// Poll attempts, by station.
+pub const POLL_ATTEMPTS: &str = "weather_gov_poll_attempts_total";
+/// Polls that returned an observation, by station.
+pub const POLL_SUCCESSES: &str = "weather_gov_poll_successes_total";
+/// Polls that failed, by station and action (retry, back_off, disable).
+pub const POLL_FAILURES: &str = "weather_gov_poll_failures_total";
+/// api.weather.gov request latency, by status.
+pub const HTTP_DURATION: &str = "weather_gov_http_request_duration_seconds";
+/// Storage insert latency, by op (batch, single).
+pub const INSERT_DURATION: &str = "weather_gov_storage_insert_duration_seconds";
+
+/// Sink calls that failed, by sink.
+pub const SINK_ERRORS: &str = "weather_gov_sink_errors_total";
+/// Sink calls dropped because the sink's queue was full, by sink.
+pub const SINK_DROPPED: &str = "weather_gov_sink_dropped_total";
+/// Failed quality checks, by station, field and check.
+pub const QC_FLAGS: &str = "weather_gov_qc_flags_total";
+
+/// Latency buckets for api.weather.gov requests, in seconds.
+const HTTP_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Latency buckets for storage inserts, in seconds.
+const INSERT_BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Counter families: name and help.
+const COUNTERS: [(&str, &str); 6] = [
+    (POLL_ATTEMPTS, "Latest observation polls attempted."),
+    (POLL_SUCCESSES, "Latest observation polls that returned an observation."),
+    (POLL_FAILURES, "Latest observation polls that failed, by the action taken."),
+    (SINK_ERRORS, "Output sink calls that failed."),
+    (SINK_DROPPED, "Output sink calls dropped because the sink was behind."),
+    (QC_FLAGS, "Observation fields that failed a quality check."),
+];
+
+/// Histogram families: name, help and buckets.
+const HISTOGRAMS: [(&str, &str, &[f64]); 2] = [
+    (HTTP_DURATION, "api.weather.gov request latency.", &HTTP_BUCKETS),
+    (INSERT_DURATION, "Observation storage insert latency.", &INSERT_BUCKETS),
+];
+
+/// Gets one value of an ObservationRecord.
+type Field = fn(&ObservationRecord) -> f64;
+
+/// Weather gauges: name, help and the ObservationRecord value.
+const WEATHER: [(&str, &str, Field); 7] = [
+    ("weather_gov_temperature_celsius", "Latest temperature.", |r| r.temperature_C),
+    ("weather_gov_dewpoint_celsius", "Latest dewpoint.", |r| r.dewpoint_C),
+    ("weather_gov_relative_humidity_percent", "Latest relative humidity.", |r| r.rel_humidity),
+    ("weather_gov_wind_speed_kmh", "Latest wind speed.", |r| r.wind_spd_km_h),
+    ("weather_gov_wind_gust_kmh", "Latest wind gust.", |r| r.wind_gust_km_h),
+    ("weather_gov_wind_direction_degrees", "Latest wind direction.", |r| r.wind_dir),
+    ("weather_gov_pressure_pascals", "Latest barometric pressure.", |r| r.baro_pres_pa),
+];
+
+/// Represents one histogram series.
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets:  &'static [f64],
+    counts:   Vec<u64>,
+    sum:      f64,
+    count:    u64,
+}
+
+/// Represents the collector's Prometheus metrics.
+///     Counters and histograms are kept here as the collector runs;
+///     storage totals, spool depth and the weather are read when rendered.
+#[derive(Default)]
+pub struct Metrics {
+    // family -> rendered labels -> value
+    counters:    Mutex<BTreeMap<&'static str, BTreeMap<String, u64>>>,
+    histograms:  Mutex<BTreeMap<&'static str, BTreeMap<String, Histogram>>>,
+    // Newest observation of each station
+    latest:      Mutex<BTreeMap<String, ObservationRecord>>,
+    spool:       Option<Arc<Spool>>,
+    storage:     Option<Arc<dyn Storage>>,
+}
+
+
+/// Implementation for Metrics.
+impl Metrics {
+
+    ///  Creates an empty set of metrics.
+    ///
+    /// # Arguments
+    /// None
+    ///
+    /// # Return
+    ///
+    /// Metrics instance
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    ///  Reports the spool depth when rendered.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'spool'-the spool
+    ///
+    /// # Return
+    ///
+    /// Metrics instance
+    pub fn with_spool(mut self, spool: Arc<Spool>) -> Metrics {
+        self.spool = Some(spool);
+        self
+    }
+
+    ///  Reports the storage insert totals when rendered.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'storage'-the storage
+    ///
+    /// # Return
+    ///
+    /// Metrics instance
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Metrics {
+        self.storage = Some(storage);
+        self
+    }
+
+    ///  Adds one to a counter.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'name'-the counter family, e.g. POLL_ATTEMPTS
+    ///*'labels'-the label names and values
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn inc(&self, name: &'static str, labels: &[(&str, &str)]) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.entry(name).or_default().entry(label_set(labels)).or_default() += 1;
+    }
+
+    ///  Gets a counter's value.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'name'-the counter family
+    ///*'labels'-the label names and values
+    ///
+    /// # Return
+    ///
+    /// The value, 0 if never incremented
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters.get(name).and_then(|c| c.get(&label_set(labels))).copied().unwrap_or(0)
+    }
+
+    ///  Records a duration in a histogram.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'name'-the histogram family, e.g. HTTP_DURATION
+    ///*'labels'-the label names and values
+    ///*'d'-the duration
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn observe_duration(&self, name: &'static str, labels: &[(&str, &str)], d: Duration) {
+        let buckets = match HISTOGRAMS.iter().find(|(n, _, _)| *n == name) {
+            Some((_, _, b)) => *b,
+            None => return,
+        };
+        let secs = d.as_secs_f64();
+        let mut histograms = self.histograms.lock().unwrap();
+        let h = histograms.entry(name).or_default().entry(label_set(labels))
+            .or_insert_with(|| Histogram {
+                buckets,
+                counts: vec![0; buckets.len()],
+                sum: 0.0,
+                count: 0,
+            });
+        for (i, le) in h.buckets.iter().enumerate() {
+            if secs <= *le {
+                h.counts[i] += 1;
+            }
+        }
+        h.sum += secs;
+        h.count += 1;
+    }
+
+    ///  Keeps an observation as its station's latest, if it is newer.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn observation(&self, rec: &ObservationRecord) {
+        let mut latest = self.latest.lock().unwrap();
+        match latest.get(&rec.station_id) {
+            Some(old) if old.timestamp_UTC >= rec.timestamp_UTC => {},
+            _ => { latest.insert(rec.station_id.clone(), rec.clone()); },
+        }
+    }
+
+    ///  Renders the metrics in the Prometheus text exposition format.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the metrics
+    ///
+    /// # Return
+    ///
+    /// The metrics text
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let counters = self.counters.lock().unwrap();
+        for (name, help) in COUNTERS {
+            family(&mut out, name, "counter", help);
+            for (labels, v) in counters.get(name).into_iter().flatten() {
+                let _ = writeln!(out, "{}{} {}", name, braces(labels), v);
+            }
+        }
+        drop(counters);
+
+        let histograms = self.histograms.lock().unwrap();
+        for (name, help, _) in HISTOGRAMS {
+            family(&mut out, name, "histogram", help);
+            for (labels, h) in histograms.get(name).into_iter().flatten() {
+                let sep = if labels.is_empty() { "" } else { "," };
+                for (le, c) in h.buckets.iter().zip(&h.counts) {
+                    let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, le, c);
+                }
+                let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, h.count);
+                let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), h.sum);
+                let _ = writeln!(out, "{}_count{} {}", name, braces(labels), h.count);
+            }
+        }
+        drop(histograms);
+
+        if let Some(storage) = &self.storage {
+            let (inserted, duplicate, failed) = storage.counters().totals();
+            let name = "weather_gov_observations_stored_total";
+            family(&mut out, name, "counter", "Observation inserts, by outcome.");
+            for (outcome, v) in [("inserted", inserted), ("duplicate", duplicate),
+                                 ("failed", failed)] {
+                let _ = writeln!(out, "{}{{outcome=\"{}\"}} {}", name, outcome, v);
+            }
+        }
+
+        if let Some(spool) = &self.spool {
+            if let Ok(st) = spool.status() {
+                family(&mut out, "weather_gov_spool_records", "gauge",
+                       "Observations waiting in the spool.");
+                let _ = writeln!(out, "weather_gov_spool_records {}", st.records);
+                family(&mut out, "weather_gov_spool_bytes", "gauge", "Size of the spool file.");
+                let _ = writeln!(out, "weather_gov_spool_bytes {}", st.bytes);
+            }
+        }
+
+        let latest = self.latest.lock().unwrap();
+        let now = Utc::now();
+        family(&mut out, "weather_gov_last_observation_timestamp_seconds", "gauge",
+               "Time of the latest observation, unix seconds.");
+        family(&mut out, "weather_gov_last_observation_age_seconds", "gauge",
+               "Age of the latest observation.");
+        for (station, rec) in latest.iter() {
+            if let Ok(t) = DateTime::parse_from_rfc3339(&rec.timestamp_UTC) {
+                let labels = braces(&label_set(&[("station", station)]));
+                let _ = writeln!(out, "weather_gov_last_observation_timestamp_seconds{} {}",
+                                 labels, t.timestamp());
+                let _ = writeln!(out, "weather_gov_last_observation_age_seconds{} {}",
+                                 labels, (now - t.with_timezone(&Utc)).num_seconds());
+            }
+        }
+        for (name, help, value) in WEATHER {
+            family(&mut out, name, "gauge", help);
+            for (station, rec) in latest.iter() {
+                let v = value(rec);
+                if v != MISSING {
+                    let _ = writeln!(out, "{}{} {}", name,
+                                     braces(&label_set(&[("station", station)])), v);
+                }
+            }
+        }
+
+        out
+    }
+
+} // impl Metrics
+
+
+/// Implementation of ObservationSink for Metrics, the weather gauges.
+#[async_trait]
+impl ObservationSink for Metrics {
+
+    fn name(&self) -> &str {
+        "metrics"
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.observation(rec);
+        Ok(())
+    }
+
+} // impl ObservationSink for Metrics
+
+
+/// Enables debugging metrics, without the series.
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("stations", &self.latest.lock().unwrap().len())
+            .field("spool", &self.spool.as_ref().map(|s| s.path.clone()))
+            .field("storage", &self.storage.is_some())
+            .finish()
+    }
+}
+
+
+///  Writes a family's HELP and TYPE lines.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+///  Renders labels as name="value" pairs, values escaped.
+fn label_set(labels: &[(&str, &str)]) -> String {
+    labels.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k,
+                              v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+///  Wraps a label set in braces, nothing if it is empty.
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
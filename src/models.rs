@@ -0,0 +1,353 @@
+//! Typed models of the api.weather.gov GeoJSON responses.
+//!
+//! Only the members weather_gov uses are modelled, anything else in a
+//! response is ignored. Members that every response of a type carries are
+//! required, so schema drift shows up as a decode error naming the field
+//! instead of a silently defaulted value.
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use crate::error::WeatherGovError;
+
+/// A GeoJSON feature with typed properties.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feature<P> {
+    #[serde(default)]
+    pub id:          Option<String>,
+    #[serde(rename = "type")]
+    pub feature_type: String,
+    #[serde(default)]
+    pub geometry:    Option<Geometry>,
+    pub properties:  P,
+}
+
+/// A GeoJSON feature collection with typed feature properties.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureCollection<P> {
+    #[serde(rename = "type")]
+    pub collection_type: String,
+    pub features:        Vec<Feature<P>>,
+    #[serde(default)]
+    pub pagination:      Option<Pagination>,
+}
+
+/// Link to the next page of a collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pagination {
+    pub next: String,
+}
+
+/// A GeoJSON geometry. Coordinates are kept as json because their
+///     nesting depends on the geometry type (Point, Polygon...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Geometry {
+    #[serde(rename = "type")]
+    pub geometry_type: String,
+    pub coordinates:   serde_json::Value,
+}
+
+/// Implementation for Geometry.
+impl Geometry {
+
+    ///  Gets the coordinates of a Point geometry.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the geometry
+    ///
+    /// # Return
+    ///
+    /// Some((longitude, latitude)) for a Point, None otherwise
+    pub fn point(&self) -> Option<(f64, f64)> {
+        if self.geometry_type != "Point" {
+            return None;
+        }
+        let lon = self.coordinates.get(0)?.as_f64()?;
+        let lat = self.coordinates.get(1)?.as_f64()?;
+        Some((lon, lat))
+    }
+}
+
+/// A WMO/NWS quantitative value, e.g.
+///     {"unitCode": "wmoUnit:degC", "value": 21.1, "qualityControl": "V"}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuantitativeValue {
+    pub value:            Option<f64>,
+    #[serde(default)]
+    pub max_value:        Option<f64>,
+    #[serde(default)]
+    pub min_value:        Option<f64>,
+    pub unit_code:        String,
+    #[serde(default)]
+    pub quality_control:  Option<String>,
+}
+
+/// Implementation for QuantitativeValue.
+///     Conversions return None when the value is null or the unit is not
+///     one we know for the quantity.
+impl QuantitativeValue {
+
+    ///  Gets the unit without the wmoUnit:/nwsUnit: prefix.
+    fn unit(&self) -> &str {
+        match self.unit_code.split_once(':') {
+            Some((_, u)) => u,
+            None => self.unit_code.as_str(),
+        }
+    }
+
+    ///  Gets a temperature in degrees C.
+    pub fn as_celsius(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "degC" => Some(v),
+            "degF" => Some((v - 32.0) * 5.0 / 9.0),
+            "K" => Some(v - 273.15),
+            _ => None,
+        }
+    }
+
+    ///  Gets a speed in km/h.
+    pub fn as_km_h(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "km_h-1" => Some(v),
+            "m_s-1" => Some(v * 3.6),
+            "kn" | "kt" => Some(v * 1.852),
+            "mi_h-1" => Some(v * 1.609344),
+            _ => None,
+        }
+    }
+
+    ///  Gets a pressure in Pa.
+    pub fn as_pa(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "Pa" => Some(v),
+            "hPa" | "mbar" => Some(v * 100.0),
+            "kPa" => Some(v * 1000.0),
+            "in_Hg" | "inHg" => Some(v / 0.00029529983071445),
+            _ => None,
+        }
+    }
+
+    ///  Gets a length in meters.
+    pub fn as_meters(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "m" => Some(v),
+            "mm" => Some(v / 1000.0),
+            "km" => Some(v * 1000.0),
+            "ft" => Some(v * 0.3048),
+            "in" => Some(v * 0.0254),
+            _ => None,
+        }
+    }
+
+    ///  Gets a length in millimeters, for precipitation.
+    pub fn as_mm(&self) -> Option<f64> {
+        self.as_meters().map(|m| m * 1000.0)
+    }
+
+    ///  Gets an angle in degrees.
+    pub fn as_degrees(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "degree_(angle)" => Some(v),
+            _ => None,
+        }
+    }
+
+    ///  Gets a percentage, e.g. relative humidity.
+    pub fn as_percent(&self) -> Option<f64> {
+        let v = self.value?;
+        match self.unit() {
+            "percent" => Some(v),
+            _ => None,
+        }
+    }
+}
+
+
+/// Properties of a /stations/{stationId} feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StationProperties {
+    #[serde(rename = "@id", default)]
+    pub url:                 Option<String>,
+    pub station_identifier:  String,
+    pub name:                String,
+    pub elevation:           QuantitativeValue,
+    #[serde(default)]
+    pub time_zone:           Option<String>,
+    #[serde(default)]
+    pub forecast:            Option<String>,
+    #[serde(default)]
+    pub county:              Option<String>,
+    #[serde(default)]
+    pub fire_weather_zone:   Option<String>,
+}
+
+/// Properties of a /stations/{stationId}/observations feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservationProperties {
+    #[serde(default)]
+    pub station:                      Option<String>,
+    pub timestamp:                    String,
+    #[serde(default)]
+    pub raw_message:                  Option<String>,
+    #[serde(default)]
+    pub text_description:             Option<String>,
+    #[serde(default)]
+    pub icon:                         Option<String>,
+    #[serde(default)]
+    pub elevation:                    Option<QuantitativeValue>,
+    pub temperature:                  QuantitativeValue,
+    pub dewpoint:                     QuantitativeValue,
+    pub wind_direction:               QuantitativeValue,
+    pub wind_speed:                   QuantitativeValue,
+    pub wind_gust:                    QuantitativeValue,
+    pub barometric_pressure:          QuantitativeValue,
+    #[serde(default)]
+    pub sea_level_pressure:           Option<QuantitativeValue>,
+    #[serde(default)]
+    pub visibility:                   Option<QuantitativeValue>,
+    #[serde(default, rename = "maxTemperatureLast24Hours")]
+    pub max_temperature_last_24_hours: Option<QuantitativeValue>,
+    #[serde(default, rename = "minTemperatureLast24Hours")]
+    pub min_temperature_last_24_hours: Option<QuantitativeValue>,
+    #[serde(default)]
+    pub precipitation_last_hour:      Option<QuantitativeValue>,
+    #[serde(default, rename = "precipitationLast3Hours")]
+    pub precipitation_last_3_hours:   Option<QuantitativeValue>,
+    #[serde(default, rename = "precipitationLast6Hours")]
+    pub precipitation_last_6_hours:   Option<QuantitativeValue>,
+    pub relative_humidity:            QuantitativeValue,
+    #[serde(default)]
+    pub wind_chill:                   Option<QuantitativeValue>,
+    #[serde(default)]
+    pub heat_index:                   Option<QuantitativeValue>,
+}
+
+/// Properties of a /points/{lat},{lon} feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointProperties {
+    pub grid_id:                String,
+    pub grid_x:                 i64,
+    pub grid_y:                 i64,
+    pub forecast:               String,
+    pub forecast_hourly:        String,
+    #[serde(default)]
+    pub forecast_grid_data:     Option<String>,
+    pub observation_stations:   String,
+    #[serde(default)]
+    pub time_zone:              Option<String>,
+    #[serde(default)]
+    pub radar_station:          Option<String>,
+}
+
+/// Properties of a /gridpoints/{wfo}/{x},{y}/forecast feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastProperties {
+    #[serde(default)]
+    pub units:        Option<String>,
+    #[serde(default)]
+    pub generated_at: Option<String>,
+    #[serde(default)]
+    pub update_time:  Option<String>,
+    #[serde(default)]
+    pub elevation:    Option<QuantitativeValue>,
+    pub periods:      Vec<ForecastPeriod>,
+}
+
+/// One period of a forecast, e.g. "Tonight".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastPeriod {
+    pub number:                        u32,
+    pub name:                          String,
+    pub start_time:                    String,
+    pub end_time:                      String,
+    pub is_daytime:                    bool,
+    pub temperature:                   Option<f64>,
+    pub temperature_unit:              String,
+    #[serde(default)]
+    pub probability_of_precipitation:  Option<QuantitativeValue>,
+    #[serde(default)]
+    pub wind_speed:                    Option<String>,
+    #[serde(default)]
+    pub wind_direction:                Option<String>,
+    #[serde(default)]
+    pub short_forecast:                Option<String>,
+    #[serde(default)]
+    pub detailed_forecast:             Option<String>,
+}
+
+/// Properties of an /alerts feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertProperties {
+    pub id:            String,
+    pub area_desc:     String,
+    pub sent:          String,
+    #[serde(default)]
+    pub effective:     Option<String>,
+    #[serde(default)]
+    pub onset:         Option<String>,
+    #[serde(default)]
+    pub expires:       Option<String>,
+    #[serde(default)]
+    pub ends:          Option<String>,
+    pub status:        String,
+    pub message_type:  String,
+    pub category:      String,
+    pub severity:      String,
+    pub certainty:     String,
+    pub urgency:       String,
+    pub event:         String,
+    #[serde(default)]
+    pub sender_name:   Option<String>,
+    #[serde(default)]
+    pub headline:      Option<String>,
+    #[serde(default)]
+    pub description:   Option<String>,
+    #[serde(default)]
+    pub instruction:   Option<String>,
+    #[serde(default)]
+    pub response:      Option<String>,
+}
+
+/// A /stations/{stationId} response.
+pub type StationFeature = Feature<StationProperties>;
+/// A /stations/{stationId}/observations/latest response.
+pub type ObservationFeature = Feature<ObservationProperties>;
+/// A /stations/{stationId}/observations response.
+pub type ObservationCollection = FeatureCollection<ObservationProperties>;
+/// A /points/{lat},{lon} response.
+pub type PointFeature = Feature<PointProperties>;
+/// A /gridpoints/{wfo}/{x},{y}/forecast response.
+pub type ForecastFeature = Feature<ForecastProperties>;
+/// An /alerts response.
+pub type AlertCollection = FeatureCollection<AlertProperties>;
+
+
+///  Decodes a response body, naming the field that failed.
+///
+/// # Arguments
+///
+///*'url'-where the body came from, for the error
+///*'body'-the response body
+///
+/// # Return
+///
+/// The decoded model, or WeatherGovError::Json with the path of the bad field,
+/// e.g. properties.windGust.unitCode
+pub fn decode<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, WeatherGovError> {
+    let de = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e.path().to_string();
+        WeatherGovError::Json { url: url.to_string(), path, source: e.into_inner() }
+    })
+}
@@ -0,0 +1,329 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use async_trait::async_trait;
+use log::{info, warn, error, debug};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde_json::{json, Value};
+use crate::rules::RuleEvent;
+use crate::sink::ObservationSink;
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::BoxError;
+use crate::tabular;
+
+/// Messages queued for the broker before try_publish starts dropping them.
+const QUEUE_CAPACITY: usize = 1000;
+
+/// Wait before reconnecting to the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Home Assistant sensors announced by discovery:
+///     field, name, unit and device class.
+const DISCOVERY_SENSORS: [(&str, &str, Option<&str>, Option<&str>); 8] = [
+    ("temperature_C", "Temperature", Some("°C"), Some("temperature")),
+    ("dewpoint_C", "Dewpoint", Some("°C"), Some("temperature")),
+    ("rel_humidity", "Relative humidity", Some("%"), Some("humidity")),
+    ("wind_spd_km_h", "Wind speed", Some("km/h"), Some("wind_speed")),
+    ("wind_gust_km_h", "Wind gust", Some("km/h"), Some("wind_speed")),
+    ("wind_dir", "Wind direction", Some("°"), None),
+    ("baro_pres_pa", "Barometric pressure", Some("Pa"), Some("atmospheric_pressure")),
+    ("description", "Conditions", None, None),
+];
+
+/// MQTT settings, read from mqtt_section.
+///     Only HOST is required.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttConfig {
+    pub host:              String,
+    pub port:              u16,
+    pub client_id:         String,
+    pub username:          Option<String>,
+    pub password:          Option<String>,
+    pub topic_prefix:      String,
+    pub qos:               u8,
+    pub retain:            bool,
+    pub keep_alive:        Duration,
+    /// Send Home Assistant discovery configs.
+    pub discovery:         bool,
+    pub discovery_prefix:  String,
+}
+
+/// Implementation for MqttConfig.
+impl MqttConfig {
+
+    ///  Reads the MQTT settings.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the mqtt_section
+    ///
+    /// # Return
+    ///
+    /// MqttConfig, None if HOST is not set
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<MqttConfig> {
+        let host = cfg.get("HOST").map(|h| h.trim()).filter(|h| !h.is_empty())?;
+        let get = |key: &str, default: &str| cfg.get(key).cloned()
+            .filter(|v| !v.is_empty()).unwrap_or_else(|| default.to_string());
+        let flag = |key: &str, default: bool| cfg.get(key)
+            .map(|v| matches!(v.to_lowercase().as_str(), "true" | "yes" | "1"))
+            .unwrap_or(default);
+
+        Some(MqttConfig {
+            host:              host.to_string(),
+            port:              get("PORT", "1883").parse().unwrap_or(1883),
+            client_id:         get("CLIENT_ID", "weather_gov"),
+            username:          cfg.get("USERNAME").cloned().filter(|v| !v.is_empty()),
+            password:          cfg.get("PASSWORD").cloned().filter(|v| !v.is_empty()),
+            topic_prefix:      get("TOPIC_PREFIX", "weather_gov").trim_end_matches('/').to_string(),
+            qos:               get("QOS", "0").parse().unwrap_or(0).min(2),
+            retain:            flag("RETAIN", true),
+            keep_alive:        Duration::from_secs(get("KEEP_ALIVE_SECS", "30").parse()
+                                                   .unwrap_or(30).max(1)),
+            discovery:         flag("DISCOVERY", false),
+            discovery_prefix:  get("DISCOVERY_PREFIX", "homeassistant")
+                                   .trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+
+/// Represents a message to publish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttMessage {
+    pub topic:    String,
+    pub payload:  String,
+    pub retain:   bool,
+}
+
+///  Builds the messages for an observation: the whole record as JSON on
+///      <prefix>/<station_id>/observation, and each field on
+///      <prefix>/<station_id>/<field>. Missing values are null in the JSON,
+///      and an empty payload on the field topic, which clears a retained value.
+///
+/// # Arguments
+///
+///*'cfg'-the MQTT settings
+///*'rec'-the ObservationRecord
+///
+/// # Return
+///
+/// The messages
+pub fn observation_messages(cfg: &MqttConfig, rec: &ObservationRecord) -> Vec<MqttMessage> {
+    let row = tabular::to_row(rec);
+    let base = format!("{}/{}", cfg.topic_prefix, rec.station_id);
+    let mut messages = vec![MqttMessage {
+        topic:    format!("{}/observation", base),
+        payload:  Value::Object(row.clone()).to_string(),
+        retain:   cfg.retain,
+    }];
+    for (field, value) in row.iter().filter(|(k, _)| k.as_str() != "station_id") {
+        messages.push(MqttMessage {
+            topic:    format!("{}/{}", base, field),
+            payload:  match value {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            },
+            retain:   cfg.retain,
+        });
+    }
+    messages
+}
+
+///  Builds the Home Assistant discovery configs for a station, one sensor
+///      per field on <discovery_prefix>/sensor/<object_id>/config, grouped
+///      as one device per station. Always retained, so Home Assistant finds
+///      them after a restart.
+///
+/// # Arguments
+///
+///*'cfg'-the MQTT settings
+///*'station'-the StationRecord
+///
+/// # Return
+///
+/// The messages
+pub fn discovery_messages(cfg: &MqttConfig, station: &StationRecord) -> Vec<MqttMessage> {
+    let device_id = format!("weather_gov_{}", station.call_id);
+    DISCOVERY_SENSORS.iter().map(|(field, name, unit, class)| {
+        let object_id = format!("{}_{}", device_id, field);
+        let mut config = json!({
+            "name": name,
+            "unique_id": object_id,
+            "object_id": object_id,
+            "state_topic": format!("{}/{}/{}", cfg.topic_prefix, station.call_id, field),
+            "device": {
+                "identifiers": [device_id],
+                "name": station.name,
+                "manufacturer": "National Weather Service",
+                "model": "api.weather.gov station",
+                "configuration_url": station.url,
+            },
+        });
+        if let Some(unit) = unit {
+            config["unit_of_measurement"] = json!(unit);
+            config["state_class"] = json!("measurement");
+        }
+        if let Some(class) = class {
+            config["device_class"] = json!(class);
+        }
+        MqttMessage {
+            topic:    format!("{}/sensor/{}/config", cfg.discovery_prefix, object_id),
+            payload:  config.to_string(),
+            retain:   true,
+        }
+    }).collect()
+}
+
+
+/// Represents the MQTT output.
+///     Publishing only queues the message, a background thread talks to
+///     the broker and reconnects, so a slow or missing broker never holds
+///     up the poll loop. When the queue is full messages are dropped.
+pub struct MqttPublisher {
+    pub config:  MqttConfig,
+    client:      Client,
+    // Stations whose discovery configs were sent
+    announced:   Mutex<HashSet<String>>,
+}
+
+/// Implementation for the MQTT output.
+impl MqttPublisher {
+
+    ///  Creates the publisher and starts the broker connection thread.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the MQTT settings
+    ///
+    /// # Return
+    ///
+    /// MqttPublisher instance
+    pub fn connect(config: MqttConfig) -> MqttPublisher {
+        let mut options = MqttOptions::new(config.client_id.clone(), config.host.clone(),
+                                           config.port);
+        options.set_keep_alive(config.keep_alive);
+        if let (Some(u), Some(p)) = (&config.username, &config.password) {
+            options.set_credentials(u.clone(), p.clone());
+        }
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+
+        let broker = format!("{}:{}", config.host, config.port);
+        info!("MQTT publishing to {}", broker);
+        let spawned = thread::Builder::new().name("mqtt".to_string()).spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(event) => debug!("MQTT {:?}", event),
+                    Err(e) => {
+                        warn!("MQTT connection to {} failed, retrying: {}", broker, e);
+                        thread::sleep(RECONNECT_DELAY);
+                    },
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            error!("Could not start the MQTT connection thread: {:?}", e);
+        }
+
+        MqttPublisher { config, client, announced: Mutex::new(HashSet::new()) }
+    }
+
+    ///  Sends a station's Home Assistant discovery configs, once,
+    ///      if discovery is on.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the publisher
+    ///*'station'-the StationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn announce(&self, station: &StationRecord) {
+        if !self.config.discovery
+            || !self.announced.lock().unwrap().insert(station.call_id.clone()) {
+            return;
+        }
+        for message in discovery_messages(&self.config, station) {
+            self.publish(message);
+        }
+    }
+
+    ///  Publishes a newly stored observation.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the publisher
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn publish_observation(&self, rec: &ObservationRecord) {
+        for message in observation_messages(&self.config, rec) {
+            self.publish(message);
+        }
+    }
+
+    ///  Publishes a rule firing or clearing as JSON on
+    ///      <prefix>/<station_id>/rule/<rule>, always retained, so the topic
+    ///      holds the rule's current state.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the publisher
+    ///*'event'-the RuleEvent
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn publish_rule_event(&self, event: &RuleEvent) {
+        self.publish(MqttMessage {
+            topic:    format!("{}/{}/rule/{}", self.config.topic_prefix, event.station_id,
+                              event.rule),
+            payload:  json!(event).to_string(),
+            retain:   true,
+        });
+    }
+
+    ///  Queues a message for the broker.
+    fn publish(&self, message: MqttMessage) {
+        let qos = match self.config.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+        if let Err(e) = self.client.try_publish(message.topic.as_str(), qos, message.retain,
+                                                message.payload.into_bytes()) {
+            warn!("MQTT dropped message for {}: {}", message.topic, e);
+        }
+    }
+
+} // impl MqttPublisher
+
+
+/// Implementation of ObservationSink for the MQTT output.
+#[async_trait]
+impl ObservationSink for MqttPublisher {
+
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    async fn on_station(&self, station: &StationRecord) -> Result<(), BoxError> {
+        self.announce(station);
+        Ok(())
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.publish_observation(rec);
+        Ok(())
+    }
+
+    async fn on_rule_event(&self, event: &RuleEvent) -> Result<(), BoxError> {
+        self.publish_rule_event(event);
+        Ok(())
+    }
+
+} // impl ObservationSink for MqttPublisher
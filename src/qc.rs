@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use chrono::{DateTime, Duration, FixedOffset};
+use log::{error, info};
+use crate::station::ObservationRecord;
+use crate::tabular::{self, Row};
+
+/// Check names, as stored in qc_flags.
+pub const RANGE: &str = "range";
+pub const STEP: &str = "step";
+pub const PERSISTENCE: &str = "persistence";
+pub const CONSISTENCY: &str = "consistency";
+
+/// Default climatological ranges, field, min and max: about the world
+///     records, so only impossible values are flagged.
+const DEFAULT_RANGES: [(&str, f64, f64); 8] = [
+    ("temperature_C", -90.0, 57.0),
+    ("dewpoint_C", -100.0, 35.0),
+    ("rel_humidity", 0.0, 100.0),
+    ("wind_dir", 0.0, 360.0),
+    ("wind_spd_km_h", 0.0, 410.0),
+    ("wind_gust_km_h", 0.0, 410.0),
+    ("baro_pres_pa", 85000.0, 109000.0),
+    ("precip_last_hour_mm", 0.0, 400.0),
+];
+
+/// Default largest changes from the previous observation, field and amount.
+const DEFAULT_STEPS: [(&str, f64); 3] = [
+    ("temperature_C", 10.0),
+    ("dewpoint_C", 10.0),
+    ("baro_pres_pa", 600.0),
+];
+
+/// Default hours a value may stay exactly the same, field and hours.
+const DEFAULT_PERSISTENCE_HOURS: [(&str, f64); 6] = [
+    ("temperature_C", 12.0),
+    ("dewpoint_C", 12.0),
+    ("rel_humidity", 12.0),
+    ("baro_pres_pa", 12.0),
+    ("wind_spd_km_h", 24.0),
+    ("wind_dir", 24.0),
+];
+
+/// Values closer than this are the same, for the float columns values are
+///     read back from.
+const SAME: f64 = 0.01;
+
+/// Fewest earlier observations a persistence check needs.
+const PERSISTENCE_MIN_OBS: usize = 3;
+
+/// Represents a failed check of one field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QcFlag {
+    pub field:  String,
+    pub check:  &'static str,
+}
+
+/// Enables printing a flag as stored, field:check.
+impl fmt::Display for QcFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.field, self.check)
+    }
+}
+
+/// Represents the data-quality checks run on each observation before it
+///     is stored: climatological ranges, steps from the previous
+///     observation, persistence and internal consistency.
+#[derive(Debug, Clone)]
+pub struct QualityControl {
+    pub enabled:           bool,
+    /// Field to min and max.
+    pub ranges:            BTreeMap<String, (f64, f64)>,
+    /// Field to the largest change from the previous observation.
+    pub steps:             BTreeMap<String, f64>,
+    /// Field to the hours a value may stay the same.
+    pub persistence_hours: BTreeMap<String, f64>,
+    /// Previous observations further back than this aren't step checked.
+    pub step_max_gap:      Duration,
+    /// How far the dewpoint may be above the temperature, for rounding, °C.
+    pub dewpoint_tolerance: f64,
+    pub consistency:       bool,
+}
+
+/// Implementation for QualityControl.
+impl QualityControl {
+
+    ///  Reads the checks from qc_section: ENABLED (default true),
+    ///      STEP_MAX_GAP_MINUTES (default 90), DEWPOINT_TOLERANCE_C
+    ///      (default 0.5), CONSISTENCY (default true), and per field
+    ///      <field>.RANGE "min,max", <field>.STEP and
+    ///      <field>.PERSISTENCE_HOURS, which override the defaults; an
+    ///      empty value turns that check off for the field.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the qc_section
+    ///
+    /// # Return
+    ///
+    /// QualityControl
+    pub fn from_config(cfg: &HashMap<String, String>) -> QualityControl {
+        let flag = |key: &str| cfg.get(key).is_none_or(|v| v.trim() != "false");
+        let number = |key: &str, default: f64| cfg.get(key)
+            .and_then(|v| v.trim().parse::<f64>().ok()).unwrap_or(default);
+        let mut qc = QualityControl {
+            enabled:            flag("ENABLED"),
+            ranges:             DEFAULT_RANGES.iter()
+                                    .map(|(f, min, max)| (f.to_string(), (*min, *max))).collect(),
+            steps:              DEFAULT_STEPS.iter().map(|(f, s)| (f.to_string(), *s)).collect(),
+            persistence_hours:  DEFAULT_PERSISTENCE_HOURS.iter()
+                                    .map(|(f, h)| (f.to_string(), *h)).collect(),
+            step_max_gap:       Duration::minutes(number("STEP_MAX_GAP_MINUTES", 90.0) as i64),
+            dewpoint_tolerance: number("DEWPOINT_TOLERANCE_C", 0.5),
+            consistency:        flag("CONSISTENCY"),
+        };
+
+        let numeric: Vec<String> = tabular::fields::<ObservationRecord>().into_iter()
+            .filter(|(_, v)| v.is_number())
+            .map(|(k, _)| k)
+            .collect();
+        for (key, value) in cfg {
+            let Some((field, setting)) = key.split_once('.') else { continue };
+            if !numeric.iter().any(|f| f == field) {
+                error!("Skipping qc {:?}: {:?} is not a numeric field", key, field);
+                continue;
+            }
+            let value = value.trim();
+            let parsed = match setting {
+                "RANGE" if value.is_empty() => qc.ranges.remove(field).map(|_| ()),
+                "RANGE" => value.split_once(',')
+                    .and_then(|(min, max)| min.trim().parse::<f64>().ok()
+                        .zip(max.trim().parse::<f64>().ok()))
+                    .map(|range| { qc.ranges.insert(field.to_string(), range); }),
+                "STEP" if value.is_empty() => qc.steps.remove(field).map(|_| ()),
+                "STEP" => value.parse::<f64>().ok()
+                    .map(|step| { qc.steps.insert(field.to_string(), step); }),
+                "PERSISTENCE_HOURS" if value.is_empty() =>
+                    qc.persistence_hours.remove(field).map(|_| ()),
+                "PERSISTENCE_HOURS" => value.parse::<f64>().ok()
+                    .map(|hours| { qc.persistence_hours.insert(field.to_string(), hours); }),
+                _ => None,
+            };
+            if parsed.is_none() && !value.is_empty() {
+                error!("Skipping qc {:?}: {:?} is not a valid setting", key, value);
+            }
+        }
+        info!("QC: {:?}", qc);
+        qc
+    }
+
+    ///  Gets how far back the checks look, so callers know what history
+    ///      to read.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the checks
+    ///
+    /// # Return
+    ///
+    /// The Duration
+    pub fn lookback(&self) -> Duration {
+        let hours = self.persistence_hours.values().copied().fold(0.0, f64::max);
+        Duration::minutes((hours * 60.0) as i64).max(self.step_max_gap)
+    }
+
+    ///  Checks an observation against the station's earlier observations.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the checks
+    ///*'rec'-the observation
+    ///*'history'-the station's earlier observations, e.g. the last lookback(),
+    ///     empty runs only the range and consistency checks
+    ///
+    /// # Return
+    ///
+    /// The failed checks, by field, in the order they ran
+    pub fn check(&self, rec: &ObservationRecord, history: &[ObservationRecord]) -> Vec<QcFlag> {
+        let mut flags = Vec::new();
+        if !self.enabled {
+            return flags;
+        }
+        let row = tabular::to_row(rec);
+        let mut flag = |field: &str, check: &'static str| {
+            if !flags.iter().any(|f: &QcFlag| f.field == field && f.check == check) {
+                flags.push(QcFlag { field: field.to_string(), check });
+            }
+        };
+
+        for (field, (min, max)) in &self.ranges {
+            if value(&row, field).is_some_and(|v| v < *min || v > *max) {
+                flag(field, RANGE);
+            }
+        }
+
+        if self.consistency {
+            if let (Some(t), Some(td)) = (value(&row, "temperature_C"), value(&row, "dewpoint_C")) {
+                if td > t + self.dewpoint_tolerance {
+                    flag("dewpoint_C", CONSISTENCY);
+                }
+            }
+            if let (Some(speed), Some(gust)) =
+                    (value(&row, "wind_spd_km_h"), value(&row, "wind_gust_km_h")) {
+                if gust < speed {
+                    flag("wind_gust_km_h", CONSISTENCY);
+                }
+            }
+        }
+
+        let Ok(now) = DateTime::parse_from_rfc3339(&rec.timestamp_UTC) else { return flags };
+        let mut earlier: Vec<(DateTime<FixedOffset>, Row)> = history.iter()
+            .filter_map(|r| DateTime::parse_from_rfc3339(&r.timestamp_UTC).ok()
+                .filter(|t| *t < now)
+                .map(|t| (t, tabular::to_row(r))))
+            .collect();
+        earlier.sort_by_key(|(t, _)| *t);
+
+        if let Some((_, previous)) = earlier.last().filter(|(t, _)| now - *t <= self.step_max_gap) {
+            for (field, step) in &self.steps {
+                let before = value(previous, field);
+                if value(&row, field).zip(before).is_some_and(|(v, b)| (v - b).abs() > *step) {
+                    flag(field, STEP);
+                }
+            }
+        }
+
+        for (field, hours) in &self.persistence_hours {
+            let Some(v) = value(&row, field) else { continue };
+            let start = now - Duration::minutes((hours * 60.0) as i64);
+            let window: Vec<(DateTime<FixedOffset>, f64)> = earlier.iter()
+                .filter(|(t, _)| *t >= start)
+                .filter_map(|(t, r)| value(r, field).map(|v| (*t, v)))
+                .collect();
+            let covered = window.first().is_some_and(|(t, _)| *t - start <= Duration::hours(1));
+            if window.len() >= PERSISTENCE_MIN_OBS && covered
+                    && window.iter().all(|(_, w)| (w - v).abs() <= SAME) {
+                flag(field, PERSISTENCE);
+            }
+        }
+        flags
+    }
+
+    ///  Checks an observation and stores the failed checks in its
+    ///      qc_flags, comma separated field:check, e.g.
+    ///      "temperature_C:range,wind_spd_km_h:persistence".
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the checks
+    ///*'rec'-the observation
+    ///*'history'-the station's earlier observations
+    ///
+    /// # Return
+    ///
+    /// The failed checks
+    pub fn apply(&self, rec: &mut ObservationRecord, history: &[ObservationRecord])
+                                                                    -> Vec<QcFlag> {
+        let flags = self.check(rec, history);
+        rec.qc_flags = flags.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(",");
+        flags
+    }
+
+} // impl QualityControl
+
+///  Gets a numeric field of a row, None when missing.
+fn value(row: &Row, field: &str) -> Option<f64> {
+    row.get(field).and_then(|v| v.as_f64())
+}
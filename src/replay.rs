@@ -0,0 +1,169 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Represents one recorded request/response pair, one json file per exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub url:        String,
+    pub status:     u16,
+    pub headers:    Vec<(String, String)>,
+    pub body:       String,
+    /// RFC 3339 time the response was received.
+    pub timestamp:  String,
+}
+
+/// Implementation for Exchange.
+impl Exchange {
+
+    ///  Gets a response header, case insensitive.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the exchange
+    ///*'name'-the header name
+    ///
+    /// # Return
+    ///
+    /// Option of the header value
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+
+/// Represents the recorder for --record: writes every exchange the client makes.
+#[derive(Debug)]
+pub struct Recorder {
+    pub dir:  PathBuf,
+    seq:      AtomicU64,
+}
+
+/// Implementation for the recorder.
+impl Recorder {
+
+    ///  Creates a recorder, creating the directory if needed.
+    ///
+    /// # Arguments
+    ///
+    ///*'dir'-where exchanges are written
+    ///
+    /// # Return
+    ///
+    /// Recorder, or the error creating the directory
+    pub fn new(dir: &Path) -> io::Result<Recorder> {
+        fs::create_dir_all(dir)?;
+        info!("Recording api.weather.gov exchanges to {:?}", dir);
+        Ok(Recorder { dir: dir.to_path_buf(), seq: AtomicU64::new(0) })
+    }
+
+    ///  Writes an exchange.
+    ///      File names sort in the order the responses were received.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the recorder
+    ///*'url'-the request url
+    ///*'status'-the response status
+    ///*'headers'-the response headers
+    ///*'body'-the response body
+    ///
+    /// # Return
+    ///
+    /// None, failures are logged, recording must not stop collection
+    pub fn record(&self, url: &str, status: u16, headers: &[(String, String)], body: &str) {
+        let now = Utc::now();
+        let exchange = Exchange {
+            url:        url.to_string(),
+            status,
+            headers:    headers.to_vec(),
+            body:       body.to_string(),
+            timestamp:  now.to_rfc3339(),
+        };
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{:013}-{:06}.json", now.timestamp_millis(), seq));
+        let res = serde_json::to_vec_pretty(&exchange)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(&path, json));
+        if let Err(e) = res {
+            warn!("Could not record exchange for {} to {:?}: {:?}", url, path, e);
+        }
+    }
+}
+
+
+/// Represents the replayer for --replay: serves recorded exchanges instead of the network.
+///     Each url gets its recorded responses in the order they were recorded.
+#[derive(Debug)]
+pub struct Replayer {
+    pub dir:    PathBuf,
+    exchanges:  Mutex<HashMap<String, VecDeque<Exchange>>>,
+}
+
+/// Implementation for the replayer.
+impl Replayer {
+
+    ///  Loads every exchange in a recording directory.
+    ///
+    /// # Arguments
+    ///
+    ///*'dir'-a directory written by Recorder
+    ///
+    /// # Return
+    ///
+    /// Replayer, or the error reading the recording
+    pub fn load(dir: &Path) -> io::Result<Replayer> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|x| x == "json"))
+            .collect();
+        paths.sort();
+
+        let mut exchanges: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        for path in &paths {
+            let exchange: Exchange = serde_json::from_slice(&fs::read(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                            format!("{:?}: {}", path, e)))?;
+            exchanges.entry(exchange.url.clone()).or_default().push_back(exchange);
+        }
+        info!("Replaying {} exchange(s) for {} url(s) from {:?}", paths.len(),
+              exchanges.len(), dir);
+
+        Ok(Replayer { dir: dir.to_path_buf(), exchanges: Mutex::new(exchanges) })
+    }
+
+    ///  Takes the next recorded response for a url.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the replayer
+    ///*'url'-the request url
+    ///
+    /// # Return
+    ///
+    /// The exchange, or None once the recording for the url is used up
+    pub fn next(&self, url: &str) -> Option<Exchange> {
+        self.exchanges.lock().unwrap().get_mut(url)?.pop_front()
+    }
+
+    ///  Counts the exchanges not replayed yet.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the replayer
+    ///
+    /// # Return
+    ///
+    /// Number of exchanges left
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().values().map(|q| q.len()).sum()
+    }
+}
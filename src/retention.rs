@@ -0,0 +1,283 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use async_std::task;
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use log::{info, error, debug};
+use crate::climate::{self, HourlySummary};
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::{Storage, StorageError, ObservationQuery};
+
+/// Represents how long observations and their summaries are kept.
+///     Ages are in days, 0 keeps forever. Monthly summaries are always kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Raw observations, rolled up into hourly summaries before they go.
+    pub raw_days:     u64,
+    pub hourly_days:  u64,
+    pub daily_days:   u64,
+    /// Rows read, and at most deleted, per statement.
+    pub batch:        usize,
+    /// Wait between batches, so other writers get the table.
+    pub pause:        Duration,
+    /// How often the maintenance task runs.
+    pub interval:     Duration,
+}
+
+/// Represents what a maintenance run did.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RetentionReport {
+    /// Hourly summaries written.
+    pub hours:           u64,
+    pub raw_deleted:     u64,
+    pub hourly_deleted:  u64,
+    pub daily_deleted:   u64,
+}
+
+/// Implementation for RetentionPolicy.
+impl RetentionPolicy {
+
+    ///  Reads the retention settings from retention_section: RAW_DAYS,
+    ///      HOURLY_DAYS, DAILY_DAYS, BATCH_SIZE (default 1000),
+    ///      BATCH_PAUSE_MS (default 200) and INTERVAL_SECS (default 3600).
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the retention_section
+    ///
+    /// # Return
+    ///
+    /// RetentionPolicy, None if everything is kept forever
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<RetentionPolicy> {
+        let number = |key: &str, default: u64| cfg.get(key)
+            .and_then(|v| v.trim().parse::<u64>().ok()).unwrap_or(default);
+        let policy = RetentionPolicy {
+            raw_days:     number("RAW_DAYS", 0),
+            hourly_days:  number("HOURLY_DAYS", 0),
+            daily_days:   number("DAILY_DAYS", 0),
+            batch:        number("BATCH_SIZE", 1000).max(1) as usize,
+            pause:        Duration::from_millis(number("BATCH_PAUSE_MS", 200)),
+            interval:     Duration::from_secs(number("INTERVAL_SECS", 3600).max(1)),
+        };
+        (policy.raw_days + policy.hourly_days + policy.daily_days > 0).then_some(policy)
+    }
+
+    ///  Gets the time before which raw observations are rolled up, the
+    ///      start of the hour raw_days ago.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the policy
+    ///*'now'-the current time
+    ///
+    /// # Return
+    ///
+    /// The UTC time in the stored form, None if raw observations are kept forever
+    pub fn raw_cutoff(&self, now: DateTime<Utc>) -> Option<String> {
+        if self.raw_days == 0 {
+            return None;
+        }
+        let cutoff = now.checked_sub_days(Days::new(self.raw_days))?;
+        Some(cutoff.format("%Y-%m-%dT%H:00:00+00:00").to_string())
+    }
+}
+
+
+///  Runs the retention policy once: rolls each station's old raw
+///      observations up into hourly summaries an hour at a time, then
+///      purges old hourly and daily summaries, a batch at a time.
+///
+/// # Arguments
+///
+///*'policy'-the retention policy
+///*'storage'-the storage
+///*'now'-the current time
+///
+/// # Return
+///
+/// RetentionReport, or the first storage error
+pub async fn run_once(policy: &RetentionPolicy, storage: &dyn Storage, now: DateTime<Utc>)
+                                                -> Result<RetentionReport, StorageError> {
+    let mut report = RetentionReport::default();
+    if let Some(cutoff) = policy.raw_cutoff(now) {
+        for station in storage.list_stations().await? {
+            roll_up_station(policy, storage, &station, &cutoff, &mut report).await?;
+        }
+    }
+
+    if policy.hourly_days > 0 {
+        let before = now - Days::new(policy.hourly_days);
+        let before = before.format("%Y-%m-%dT%H:00:00+00:00").to_string();
+        report.hourly_deleted = purge(policy, |limit| {
+            storage.purge_hourly_summaries(&before, limit)
+        }).await?;
+    }
+    if policy.daily_days > 0 {
+        let before = (now - Days::new(policy.daily_days)).format("%Y-%m-%d").to_string();
+        report.daily_deleted = purge(policy, |limit| {
+            storage.purge_daily_summaries(&before, limit)
+        }).await?;
+    }
+    Ok(report)
+}
+
+///  Deletes a batch at a time until a batch comes back short.
+async fn purge<'a, F, Fut>(policy: &RetentionPolicy, delete: F) -> Result<u64, StorageError>
+    where F: Fn(usize) -> Fut,
+          Fut: std::future::Future<Output = Result<u64, StorageError>> + 'a {
+    let mut total = 0;
+    loop {
+        let deleted = delete(policy.batch).await?;
+        total += deleted;
+        if deleted < policy.batch as u64 {
+            return Ok(total);
+        }
+        task::sleep(policy.pause).await;
+    }
+}
+
+///  Rolls a station's raw observations before the cutoff up into hourly
+///      summaries, oldest first, a batch of whole hours at a time. Each
+///      local day gets its daily summary, if it has none, before its
+///      observations go.
+async fn roll_up_station(policy: &RetentionPolicy, storage: &dyn Storage,
+                         station: &StationRecord, cutoff: &str,
+                         report: &mut RetentionReport) -> Result<(), StorageError> {
+    let id = station.call_id.as_str();
+    let tz = climate::time_zone(&station.time_zone);
+    let mut summarized = BTreeSet::new();
+    loop {
+        let mut recs = storage.observations(&ObservationQuery {
+            station_id:  id.to_string(),
+            from:        None,
+            to:          Some(cutoff.to_string()),
+            limit:       policy.batch,
+            offset:      0,
+        }).await?;
+        if recs.is_empty() {
+            return Ok(());
+        }
+
+        // The page's last hour may go on past it: leave it for the next
+        //     page, unless it is the only hour, then read all of it
+        if recs.len() == policy.batch {
+            let last_hour = hour_of(&recs[recs.len() - 1]).to_string();
+            if hour_of(&recs[0]) == last_hour {
+                recs = whole_hour(storage, id, &last_hour, cutoff, policy.batch).await?;
+            } else {
+                recs.retain(|r| hour_of(r) != last_hour);
+            }
+        }
+        let from = format!("{}:00:00+00:00", hour_of(&recs[0]));
+        let to = next_hour(hour_of(&recs[recs.len() - 1]));
+
+        for date in recs.iter().filter_map(|r| climate::local_date(tz, &r.timestamp_UTC)) {
+            if summarized.insert(date) {
+                ensure_daily_summary(storage, station, date).await?;
+            }
+        }
+
+        // Hours rolled up before, e.g. by an earlier run before a late
+        //     observation arrived, take in the new observations
+        let existing = storage.hourly_summaries(id, &from, &to).await?;
+        let mut hours = climate::hourly_summaries(id, &recs);
+        for hour in hours.iter_mut() {
+            if let Some(old) = existing.iter().find(|e| e.hour_UTC == hour.hour_UTC) {
+                let mut merged: HourlySummary = old.clone();
+                merged.merge(hour);
+                *hour = merged;
+            }
+        }
+
+        let deleted = storage.roll_up(&hours, id, &from, &to).await?;
+        debug!("Rolled up {} observation(s) of {:?} from {} to {}", deleted, id, from, to);
+        report.hours += hours.len() as u64;
+        report.raw_deleted += deleted;
+        task::sleep(policy.pause).await;
+    }
+}
+
+///  Gets the hour of an observation, e.g. "2024-04-12T21".
+fn hour_of(rec: &ObservationRecord) -> &str {
+    rec.timestamp_UTC.get(..13).unwrap_or(&rec.timestamp_UTC)
+}
+
+///  Gets the start of the hour after an hour, e.g. "2024-04-12T21" gives
+///      "2024-04-12T22:00:00+00:00".
+fn next_hour(hour: &str) -> String {
+    DateTime::parse_from_rfc3339(&format!("{}:00:00+00:00", hour))
+        .map(|t| (t + chrono::Duration::hours(1)).format("%Y-%m-%dT%H:%M:%S+00:00").to_string())
+        .unwrap_or_else(|_| format!("{}~", hour))
+}
+
+///  Reads all of a station's observations in an hour, before the cutoff, a page at a time.
+async fn whole_hour(storage: &dyn Storage, station_id: &str, hour: &str, cutoff: &str,
+                    page_size: usize) -> Result<Vec<ObservationRecord>, StorageError> {
+    let mut query = ObservationQuery {
+        station_id:  station_id.to_string(),
+        from:        Some(format!("{}:00:00+00:00", hour)),
+        to:          Some(next_hour(hour).min(cutoff.to_string())),
+        limit:       page_size,
+        offset:      0,
+    };
+    let mut recs = Vec::new();
+    loop {
+        let page = storage.observations(&query).await?;
+        let done = page.len() < query.limit;
+        recs.extend(page);
+        if done {
+            return Ok(recs);
+        }
+        query.offset += query.limit;
+    }
+}
+
+///  Summarizes a local day, and its month, if the day has no daily summary.
+async fn ensure_daily_summary(storage: &dyn Storage, station: &StationRecord, date: NaiveDate)
+                                                        -> Result<(), StorageError> {
+    let id = station.call_id.as_str();
+    let day = date.format("%Y-%m-%d").to_string();
+    let next = date.succ_opt().unwrap_or(date).format("%Y-%m-%d").to_string();
+    if !storage.daily_summaries(id, &day, &next).await?.is_empty() {
+        return Ok(());
+    }
+    let summary = climate::summarize_day(storage, id, &station.time_zone, date).await?;
+    if summary.observations == 0 {
+        return Ok(());
+    }
+    storage.put_daily_summary(&summary).await?;
+
+    let first = date.with_day(1).unwrap_or(date);
+    let after = first.checked_add_months(chrono::Months::new(1)).unwrap_or(first);
+    let days = storage.daily_summaries(id, &first.format("%Y-%m-%d").to_string(),
+                                       &after.format("%Y-%m-%d").to_string()).await?;
+    let month = first.format("%Y-%m").to_string();
+    storage.put_monthly_summary(&climate::monthly_summary(id, &station.time_zone, &month, &days))
+        .await
+}
+
+
+///  Starts the background task that runs the retention policy every interval.
+///
+/// # Arguments
+///
+///*'policy'-the retention policy
+///*'storage'-the shared storage
+///
+/// # Return
+///
+/// None
+pub fn spawn(policy: RetentionPolicy, storage: Arc<dyn Storage>) {
+    info!("Retention: {:?}", policy);
+    task::spawn(async move {
+        loop {
+            match run_once(&policy, storage.as_ref(), Utc::now()).await {
+                Ok(r) => info!("Retention rolled {} observation(s) into {} hour(s), purged {} \
+                               hourly and {} daily summaries", r.raw_deleted, r.hours,
+                               r.hourly_deleted, r.daily_deleted),
+                Err(e) => error!("Retention run failed, retrying next run: {}", e),
+            }
+            task::sleep(policy.interval).await;
+        }
+    });
+}
@@ -0,0 +1,465 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+use chrono::{DateTime, FixedOffset};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use crate::station::ObservationRecord;
+use crate::tabular;
+
+/// Represents a comparison.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+/// Implementation for Op.
+impl Op {
+
+    ///  Parses >, >=, <, <=, == or !=.
+    fn parse(s: &str) -> Option<Op> {
+        match s {
+            ">" => Some(Op::Gt),
+            ">=" => Some(Op::Ge),
+            "<" => Some(Op::Lt),
+            "<=" => Some(Op::Le),
+            "==" | "=" => Some(Op::Eq),
+            "!=" => Some(Op::Ne),
+            _ => None,
+        }
+    }
+
+    ///  Compares a value to a threshold.
+    pub fn apply(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Eq => value == threshold,
+            Op::Ne => value != threshold,
+        }
+    }
+}
+
+/// What a rule compares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    /// The field's value.
+    Value,
+    /// How much the field rose within the window.
+    Rise(Duration),
+    /// How much the field dropped within the window.
+    Drop(Duration),
+}
+
+/// How long a condition must hold before the rule fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Qualifier {
+    /// Fires on the first observation that meets the condition.
+    Immediate,
+    /// Fires after this many consecutive observations meet it.
+    Consecutive(u32),
+    /// Fires once observations have met it for this long.
+    Lasting(Duration),
+}
+
+/// Represents an alerting rule, parsed from rules_section.
+///     The rule text is
+///
+/// * field op value, e.g. "wind_gust_mi_h > 50", or
+/// * field rises|drops amount in duration, e.g. "baro_pres_pa drops 300 in 3h",
+///
+/// optionally followed, in any order, by
+///
+/// * for N observations, or for duration, e.g. "for 3 observations", "for 2h",
+/// * at station[,station...], all stations if absent,
+/// * clear op value, hysteresis: once fired, the rule clears when the
+///   compared value meets this instead of when the condition stops holding.
+///
+/// Values are in the field's units, durations are a number with s, m, h or d.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name:       String,
+    pub text:       String,
+    pub field:      String,
+    pub measure:    Measure,
+    pub op:         Op,
+    pub threshold:  f64,
+    pub qualifier:  Qualifier,
+    pub stations:   Vec<String>,
+    pub clear:      Option<(Op, f64)>,
+}
+
+/// Implementation for Rule.
+impl Rule {
+
+    ///  Parses a rule.
+    ///
+    /// # Arguments
+    ///
+    ///*'name'-the rule name, its rules_section key
+    ///*'text'-the rule text
+    ///
+    /// # Return
+    ///
+    /// Rule, or a message saying what is wrong
+    pub fn parse(name: &str, text: &str) -> Result<Rule, String> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let token = |i: usize| tokens.get(i).copied()
+            .ok_or_else(|| format!("rule {:?} ends early: {:?}", name, text));
+
+        let field = token(0)?;
+        let numeric: Vec<String> = tabular::fields::<ObservationRecord>().into_iter()
+            .filter(|(_, v)| v.is_number())
+            .map(|(k, _)| k)
+            .collect();
+        if !numeric.iter().any(|f| f == field) {
+            return Err(format!("rule {:?}: {:?} is not a numeric field, fields are {}",
+                               name, field, numeric.join(", ")));
+        }
+
+        let (measure, op, threshold, mut i) = match token(1)? {
+            "rises" | "drops" => {
+                let amount = number(name, token(2)?)?;
+                if token(3)? != "in" {
+                    return Err(format!("rule {:?}: expected \"in\" after {}", name, amount));
+                }
+                let window = duration(name, token(4)?)?;
+                let measure = if tokens[1] == "rises" { Measure::Rise(window) }
+                              else { Measure::Drop(window) };
+                (measure, Op::Ge, amount, 5)
+            },
+            t => {
+                let op = Op::parse(t)
+                    .ok_or_else(|| format!("rule {:?}: {:?} is not a comparison", name, t))?;
+                (Measure::Value, op, number(name, token(2)?)?, 3)
+            },
+        };
+
+        let mut rule = Rule {
+            name:       name.to_string(),
+            text:       text.to_string(),
+            field:      field.to_string(),
+            measure,
+            op,
+            threshold,
+            qualifier:  Qualifier::Immediate,
+            stations:   Vec::new(),
+            clear:      None,
+        };
+        while i < tokens.len() {
+            match tokens[i] {
+                "for" => {
+                    let n = token(i + 1)?;
+                    match tokens.get(i + 2) {
+                        Some(&"observations") | Some(&"observation") | Some(&"obs") => {
+                            let count = n.parse::<u32>().ok().filter(|c| *c > 0)
+                                .ok_or_else(|| format!("rule {:?}: {:?} is not a count", name, n))?;
+                            rule.qualifier = Qualifier::Consecutive(count);
+                            i += 3;
+                        },
+                        _ => {
+                            rule.qualifier = Qualifier::Lasting(duration(name, n)?);
+                            i += 2;
+                        },
+                    }
+                },
+                "at" => {
+                    rule.stations = token(i + 1)?.split(',')
+                        .map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+                    i += 2;
+                },
+                "clear" => {
+                    let op = Op::parse(token(i + 1)?)
+                        .ok_or_else(|| format!("rule {:?}: {:?} is not a comparison",
+                                               name, tokens[i + 1]))?;
+                    rule.clear = Some((op, number(name, token(i + 2)?)?));
+                    i += 3;
+                },
+                t => return Err(format!("rule {:?}: unexpected {:?}", name, t)),
+            }
+        }
+        Ok(rule)
+    }
+
+    ///  Checks whether the rule applies to a station.
+    pub fn applies_to(&self, station_id: &str) -> bool {
+        self.stations.is_empty() || self.stations.iter().any(|s| s == station_id)
+    }
+}
+
+///  Parses a rule number.
+fn number(rule: &str, s: &str) -> Result<f64, String> {
+    s.parse().map_err(|_| format!("rule {:?}: {:?} is not a number", rule, s))
+}
+
+///  Parses a rule duration, e.g. 90s, 30m, 3h or 1d.
+fn duration(rule: &str, s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n.parse().map_err(|_| format!("rule {:?}: {:?} is not a duration", rule, s))?;
+    let secs = match unit {
+        "s" => n,
+        "m" | "min" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86400,
+        _ => return Err(format!("rule {:?}: {:?} is not a duration, use s, m, h or d", rule, s)),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+
+/// Whether a rule started or stopped holding.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleState {
+    Fired,
+    Cleared,
+}
+
+/// Displays a RuleState, fired or cleared.
+impl fmt::Display for RuleState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleState::Fired => write!(f, "fired"),
+            RuleState::Cleared => write!(f, "cleared"),
+        }
+    }
+}
+
+/// Represents a rule firing or clearing for a station.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleEvent {
+    pub rule:           String,
+    pub station_id:     String,
+    /// The observation that fired or cleared the rule.
+    pub timestamp_UTC:  String,
+    pub state:          RuleState,
+    pub field:          String,
+    /// The compared value, the field or its change.
+    pub value:          f64,
+    pub description:    String,
+}
+
+
+/// Represents a rule's state for one station.
+#[derive(Debug, Default)]
+struct Tracker {
+    last:         Option<DateTime<FixedOffset>>,
+    consecutive:  u32,
+    since:        Option<DateTime<FixedOffset>>,
+    active:       bool,
+    // Values within the rate of change window, oldest first
+    history:      VecDeque<(DateTime<FixedOffset>, f64)>,
+}
+
+/// Represents the rules engine, evaluating rules as observations arrive.
+#[derive(Debug, Default)]
+pub struct RulesEngine {
+    pub rules:  Vec<Rule>,
+    // (rule name, station id) -> state
+    trackers:   HashMap<(String, String), Tracker>,
+}
+
+/// Implementation for the rules engine.
+impl RulesEngine {
+
+    ///  Creates the engine from rules.
+    ///
+    /// # Arguments
+    ///
+    ///*'rules'-the rules
+    ///
+    /// # Return
+    ///
+    /// RulesEngine instance
+    pub fn new(rules: Vec<Rule>) -> RulesEngine {
+        RulesEngine { rules, trackers: HashMap::new() }
+    }
+
+    ///  Creates the engine from the rules_section, rule name to rule text.
+    ///      Rules that don't parse are logged and skipped.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the rules_section
+    ///
+    /// # Return
+    ///
+    /// RulesEngine instance
+    pub fn from_config(cfg: &HashMap<String, String>) -> RulesEngine {
+        let mut rules = Vec::new();
+        for (name, text) in cfg {
+            match Rule::parse(name, text) {
+                Ok(rule) => {
+                    info!("Rule {:?}: {}", name, text);
+                    rules.push(rule);
+                },
+                Err(e) => error!("Skipping rule: {}", e),
+            }
+        }
+        rules.sort_by(|a, b| a.name.cmp(&b.name));
+        RulesEngine::new(rules)
+    }
+
+    ///  Gets how far back the rules look, the longest rate of change
+    ///      window or lasting qualifier, so a restart knows what history
+    ///      to replay.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the engine
+    ///
+    /// # Return
+    ///
+    /// The Duration, zero if no rule looks back
+    pub fn lookback(&self) -> Duration {
+        self.rules.iter().flat_map(|r| {
+            let window = match r.measure {
+                Measure::Rise(d) | Measure::Drop(d) => d,
+                Measure::Value => Duration::ZERO,
+            };
+            let lasting = match r.qualifier {
+                Qualifier::Lasting(d) => d,
+                _ => Duration::ZERO,
+            };
+            [window, lasting]
+        }).max().unwrap_or_default()
+    }
+
+    ///  Replays a station's stored observations after a restart, so rate of
+    ///      change windows and qualifiers pick up where they were. The
+    ///      events are dropped, they were sent before the restart.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the engine
+    ///*'history'-the station's stored observations, e.g. the last
+    ///     lookback(), oldest first
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn replay(&mut self, history: &[ObservationRecord]) {
+        for rec in history {
+            self.evaluate(rec);
+        }
+    }
+
+    ///  Restores which rules are fired from their newest stored events,
+    ///      so a restart neither fires a rule again nor misses its clearing.
+    ///      Events of rules no longer configured are ignored.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the engine
+    ///*'latest'-the newest event of each rule and station
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn restore(&mut self, latest: &[RuleEvent]) {
+        for event in latest {
+            if !self.rules.iter().any(|r| r.name == event.rule && r.applies_to(&event.station_id)) {
+                continue;
+            }
+            self.trackers.entry((event.rule.clone(), event.station_id.clone()))
+                .or_default()
+                .active = event.state == RuleState::Fired;
+        }
+    }
+
+    ///  Evaluates the rules against a new observation.
+    ///      Observations with a missing value, or not newer than the last
+    ///      one seen for the station, leave a rule's state unchanged.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the engine
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// The rules that fired or cleared
+    pub fn evaluate(&mut self, rec: &ObservationRecord) -> Vec<RuleEvent> {
+        let time = match DateTime::parse_from_rfc3339(&rec.timestamp_UTC) {
+            Ok(t) => t,
+            Err(_) => return Vec::new(),
+        };
+        let row = tabular::to_row(rec);
+        let mut events = Vec::new();
+
+        for rule in self.rules.iter().filter(|r| r.applies_to(&rec.station_id)) {
+            let value = match row.get(&rule.field).and_then(|v| v.as_f64()) {
+                Some(v) => v,
+                None => continue,
+            };
+            let tracker = self.trackers
+                .entry((rule.name.clone(), rec.station_id.clone()))
+                .or_default();
+            if tracker.last.is_some_and(|last| time <= last) {
+                continue;
+            }
+            tracker.last = Some(time);
+
+            let measured = match rule.measure {
+                Measure::Value => value,
+                Measure::Rise(window) | Measure::Drop(window) => {
+                    tracker.history.push_back((time, value));
+                    let oldest = time - chrono::Duration::from_std(window).unwrap_or_default();
+                    while tracker.history.front().is_some_and(|(t, _)| *t < oldest) {
+                        tracker.history.pop_front();
+                    }
+                    let first = tracker.history.front().map(|(_, v)| *v).unwrap_or(value);
+                    if matches!(rule.measure, Measure::Rise(_)) { value - first }
+                    else { first - value }
+                },
+            };
+
+            let holds = rule.op.apply(measured, rule.threshold);
+            if holds {
+                tracker.consecutive += 1;
+                tracker.since.get_or_insert(time);
+            } else {
+                tracker.consecutive = 0;
+                tracker.since = None;
+            }
+            let qualified = holds && match rule.qualifier {
+                Qualifier::Immediate => true,
+                Qualifier::Consecutive(n) => tracker.consecutive >= n,
+                Qualifier::Lasting(d) => tracker.since
+                    .is_some_and(|since| (time - since).to_std().unwrap_or_default() >= d),
+            };
+
+            let state = if !tracker.active && qualified {
+                RuleState::Fired
+            } else if tracker.active && match rule.clear {
+                Some((op, v)) => op.apply(measured, v),
+                None => !holds,
+            } {
+                RuleState::Cleared
+            } else {
+                continue;
+            };
+            tracker.active = state == RuleState::Fired;
+            events.push(RuleEvent {
+                rule:           rule.name.clone(),
+                station_id:     rec.station_id.clone(),
+                timestamp_UTC:  rec.timestamp_UTC.clone(),
+                state,
+                field:          rule.field.clone(),
+                value:          measured,
+                description:    rule.text.clone(),
+            });
+        }
+        events
+    }
+
+} // impl RulesEngine
@@ -0,0 +1,340 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::{future, task};
+use async_trait::async_trait;
+use log::{info, warn, error, debug};
+use crate::config::Config;
+use crate::metrics::{self, Metrics};
+use crate::rules::RuleEvent;
+use crate::station::{StationRecord, ObservationRecord};
+use crate::storage::BoxError;
+
+/// Sinks used when the config has no sinks list, those not configured are skipped.
+pub const DEFAULT_SINKS: [&str; 7] = ["db", "file", "influx", "metrics", "mqtt", "parquet",
+                                      "webhook"];
+
+/// The sink name for the collector's storage, which is not run as a sink:
+///     the collector stores each cycle itself, so it can spool and tell
+///     new observations from duplicates. Without it the collector runs
+///     with a memory::NullStore.
+pub const DB_SINK: &str = "db";
+
+/// Represents an output for new observations.
+///     Each sink in a SinkRegistry gets its calls in order from its own task,
+///     so a sink may take its time, and its errors only reach the log.
+#[async_trait]
+pub trait ObservationSink: Send + Sync {
+
+    ///  Gets the sink's name, for the log and metrics.
+    fn name(&self) -> &str;
+
+    ///  Takes a polled station's record, once per run.
+    async fn on_station(&self, _station: &StationRecord) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    ///  Takes a new observation.
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError>;
+
+    ///  Takes a rule firing or clearing.
+    async fn on_rule_event(&self, _event: &RuleEvent) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    ///  Writes out anything buffered, called after each poll cycle.
+    async fn flush(&self) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+
+/// Represents a call waiting for a sink.
+///     Observations are boxed, they are much larger than the other calls.
+enum SinkCall {
+    Station(StationRecord),
+    Observation(Box<ObservationRecord>),
+    RuleEvent(RuleEvent),
+    Flush(Sender<()>),
+}
+
+/// Represents a running sink: its queue, drained by its task.
+struct SinkHandle {
+    name:  String,
+    tx:    Sender<SinkCall>,
+}
+
+/// Represents the sinks new observations fan out to.
+pub struct SinkRegistry {
+    sinks:          Vec<SinkHandle>,
+    metrics:        Option<Arc<Metrics>>,
+    /// Calls waiting per sink before new ones are dropped.
+    pub buffer:     usize,
+    /// How long flush waits for a sink.
+    pub flush_wait: Duration,
+}
+
+/// Implementation for the sink registry.
+impl SinkRegistry {
+
+    ///  Creates a registry with no sinks.
+    ///
+    /// # Arguments
+    /// None
+    ///
+    /// # Return
+    ///
+    /// SinkRegistry instance
+    pub fn new() -> SinkRegistry {
+        SinkRegistry {
+            sinks:       Vec::new(),
+            metrics:     None,
+            buffer:      1000,
+            flush_wait:  Duration::from_secs(10),
+        }
+    }
+
+    ///  Counts sink errors and dropped calls in the metrics.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///*'metrics'-the metrics
+    ///
+    /// # Return
+    ///
+    /// SinkRegistry instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> SinkRegistry {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    ///  Creates the registry from the config's sinks list, DEFAULT_SINKS
+    ///      if it is empty. parameters_section SINK_BUFFER and
+    ///      SINK_FLUSH_SECS set the buffer and flush wait.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the weather_gov config
+    ///*'metrics'-the collector's metrics, also the metrics sink
+    ///
+    /// # Return
+    ///
+    /// SinkRegistry instance
+    pub fn from_config(config: &Config, metrics: Arc<Metrics>) -> SinkRegistry {
+        let mut registry = SinkRegistry::new().with_metrics(metrics.clone());
+        let number = |key: &str| config.parameters_section.get(key)
+            .and_then(|v| v.parse::<u64>().ok());
+        if let Some(n) = number("SINK_BUFFER") {
+            registry.buffer = n.max(1) as usize;
+        }
+        if let Some(n) = number("SINK_FLUSH_SECS") {
+            registry.flush_wait = Duration::from_secs(n);
+        }
+
+        let explicit = !config.sinks.is_empty();
+        for name in sink_names(config) {
+            let missing = |section: &str| if explicit {
+                warn!("Sink {:?} is listed but {} is not configured", name, section);
+            };
+            match name.as_str() {
+                DB_SINK => {},
+                "file" => match crate::files::FileConfig::from_config(&config.file_section) {
+                    Some(c) => match crate::files::FileSink::new(c) {
+                        Ok(sink) => registry.add(Arc::new(sink)),
+                        Err(e) => error!("Could not start the file sink: {}", e),
+                    },
+                    None => missing("file_section DIR"),
+                },
+                "influx" => match crate::influx::InfluxConfig::from_config(&config.influx_section) {
+                    Some(c) => registry.add(Arc::new(crate::influx::InfluxSink::new(c))),
+                    None => missing("influx_section URL or FILE"),
+                },
+                "metrics" => registry.add(metrics.clone()),
+                #[cfg(feature = "mqtt")]
+                "mqtt" => match crate::mqtt::MqttConfig::from_config(&config.mqtt_section) {
+                    Some(c) => registry.add(Arc::new(crate::mqtt::MqttPublisher::connect(c))),
+                    None => missing("mqtt_section HOST"),
+                },
+                #[cfg(feature = "parquet")]
+                "parquet" => match crate::export::ParquetConfig::from_config(&config.parquet_section) {
+                    Some(c) => registry.add(Arc::new(crate::export::ParquetSink::new(c))),
+                    None => missing("parquet_section DIR"),
+                },
+                "webhook" => match crate::webhook::WebhookConfig::from_config(&config.webhook_section) {
+                    Some(c) => registry.add(Arc::new(crate::webhook::WebhookSink::start(c))),
+                    None => missing("a webhook_section endpoint"),
+                },
+                other => error!("Unknown sink {:?}, not used", other),
+            }
+        }
+        registry
+    }
+
+    ///  Adds a sink and starts its task.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///*'sink'-the sink
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn add(&mut self, sink: Arc<dyn ObservationSink>) {
+        let name = sink.name().to_string();
+        info!("Sink {:?} enabled", name);
+        let (tx, rx) = channel::bounded(self.buffer);
+        task::spawn(run_sink(sink, rx, self.metrics.clone()));
+        self.sinks.push(SinkHandle { name, tx });
+    }
+
+    ///  Gets the names of the sinks, in the order they were added.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///
+    /// # Return
+    ///
+    /// The names
+    pub fn names(&self) -> Vec<String> {
+        self.sinks.iter().map(|s| s.name.clone()).collect()
+    }
+
+    ///  Queues a station record for every sink.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///*'station'-the StationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn on_station(&self, station: &StationRecord) {
+        self.send(|| SinkCall::Station(station.clone()));
+    }
+
+    ///  Queues a new observation for every sink.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn on_observation(&self, rec: &ObservationRecord) {
+        self.send(|| SinkCall::Observation(Box::new(rec.clone())));
+    }
+
+    ///  Queues a rule firing or clearing for every sink.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///*'event'-the RuleEvent
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn on_rule_event(&self, event: &RuleEvent) {
+        self.send(|| SinkCall::RuleEvent(event.clone()));
+    }
+
+    ///  Waits until every sink has handled what was queued before, and
+    ///      flushed. The sinks flush at the same time, so this waits at most
+    ///      flush_wait; a sink that takes longer is left to finish on its own.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the registry
+    ///
+    /// # Return
+    ///
+    /// None
+    pub async fn flush(&self) {
+        let waits = self.sinks.iter().map(|sink| {
+            let (done_tx, done_rx) = channel::bounded(1);
+            let wait = async move {
+                sink.tx.send(SinkCall::Flush(done_tx)).await.ok()?;
+                done_rx.recv().await.ok()
+            };
+            async move {
+                if future::timeout(self.flush_wait, wait).await.is_err() {
+                    warn!("Sink {:?} did not flush within {:?}", sink.name, self.flush_wait);
+                }
+            }
+        });
+        futures::future::join_all(waits).await;
+    }
+
+    ///  Queues a call for every sink, dropping it for sinks whose queue is full.
+    fn send(&self, call: impl Fn() -> SinkCall) {
+        for sink in &self.sinks {
+            match sink.tx.try_send(call()) {
+                Ok(()) => {},
+                Err(TrySendError::Full(_)) | Err(TrySendError::Closed(_)) => {
+                    warn!("Sink {:?} is behind, dropping", sink.name);
+                    if let Some(m) = &self.metrics {
+                        m.inc(metrics::SINK_DROPPED, &[("sink", sink.name.as_str())]);
+                    }
+                },
+            }
+        }
+    }
+
+} // impl SinkRegistry
+
+/// Creates an empty SinkRegistry.
+impl Default for SinkRegistry {
+    fn default() -> Self {
+        SinkRegistry::new()
+    }
+}
+
+
+///  Gets the configured sink names, DEFAULT_SINKS if none are listed.
+///
+/// # Arguments
+///
+///*'config'-the weather_gov config
+///
+/// # Return
+///
+/// The sink names
+pub fn sink_names(config: &Config) -> Vec<String> {
+    if config.sinks.is_empty() {
+        DEFAULT_SINKS.iter().map(|s| s.to_string()).collect()
+    } else {
+        config.sinks.iter().map(|s| s.trim().to_lowercase()).collect()
+    }
+}
+
+///  Hands a sink its calls, in order, until the registry is dropped.
+async fn run_sink(sink: Arc<dyn ObservationSink>, rx: Receiver<SinkCall>,
+                  metrics: Option<Arc<Metrics>>) {
+    while let Ok(call) = rx.recv().await {
+        let (what, result) = match call {
+            SinkCall::Station(s) => ("station", sink.on_station(&s).await),
+            SinkCall::Observation(r) => ("observation", sink.on_observation(&r).await),
+            SinkCall::RuleEvent(e) => ("rule event", sink.on_rule_event(&e).await),
+            SinkCall::Flush(done) => {
+                let result = sink.flush().await;
+                let _ = done.try_send(());
+                ("flush", result)
+            },
+        };
+        match result {
+            Ok(()) => debug!("Sink {:?} took {}", sink.name(), what),
+            Err(e) => {
+                warn!("Sink {:?} failed on {}: {}", sink.name(), what, e);
+                if let Some(m) = &metrics {
+                    m.inc(metrics::SINK_ERRORS, &[("sink", sink.name())]);
+                }
+            },
+        }
+    }
+}
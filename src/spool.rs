@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_std::task;
+use log::{info, warn, error, debug};
+use crate::storage::{Storage, InsertOutcome};
+use crate::station::ObservationRecord;
+
+/// Number of spooled records replayed per drain pass.
+const DRAIN_BATCH: usize = 100;
+
+/// Drained bytes at the head of the spool before it is compacted.
+const COMPACT_BYTES: u64 = 1_000_000;
+
+/// Represents the write-ahead spool of observation records
+///     that could not be written to storage.
+///     The spool is an append-only JSON lines file, oldest record first.
+///     Drained records stay in the file, a cursor file next to it, e.g.
+///     weather_gov.spool.offset, holds the byte offset of the first record
+///     still to drain. The file is removed once drained, or compacted when
+///     the drained head passes COMPACT_BYTES.
+pub struct Spool {
+    pub path:            PathBuf,
+    pub max_bytes:       u64,
+    pub drain_interval:  Duration,
+    // The cursor file, the path with .offset appended
+    cursor:              PathBuf,
+    // Serializes appends against the cursor moves and compaction after a drain,
+    //      and holds the number of records still to drain, once counted
+    lock:                Mutex<Option<usize>>,
+    // Records drained into storage, see take_drained
+    drained:             Mutex<Vec<ObservationRecord>>,
+}
+
+/// Spool depth and size, for the status command.
+#[derive(Debug)]
+pub struct SpoolStatus {
+    pub path:       PathBuf,
+    pub records:    usize,
+    /// Size of the records still to drain.
+    pub bytes:      u64,
+    pub max_bytes:  u64,
+}
+
+
+/// Implementation for the spool.
+impl Spool {
+
+    ///  Creates a new spool instance from the spool config.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the spool config, all keys optional
+    ///
+    /// # Return
+    ///
+    /// Spool instance
+    pub fn new(cfg: &HashMap<String, String>) -> Spool {
+        let path = match cfg.get("SPOOL_FILE") {
+            Some(p) => PathBuf::from(p),
+            None => PathBuf::from("./weather_gov.spool"),
+        };
+        let max_bytes = match cfg.get("MAX_BYTES") {
+            Some(m) => m.parse::<u64>().unwrap_or(50_000_000),
+            None => 50_000_000,
+        };
+        let drain_secs = match cfg.get("DRAIN_INTERVAL_SECS") {
+            Some(d) => d.parse::<u64>().unwrap_or(30),
+            None => 30,
+        };
+
+        let mut cursor = path.clone().into_os_string();
+        cursor.push(".offset");
+        Self {
+            cursor: PathBuf::from(cursor),
+            path,
+            max_bytes,
+            drain_interval: Duration::from_secs(drain_secs),
+            lock: Mutex::new(None),
+            drained: Mutex::new(Vec::new()),
+        }
+    }
+
+    ///  Appends a record to the end of the spool.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///*'rec'-the ObservationRecord that could not be stored
+    ///
+    /// # Return
+    ///
+    /// Ok(true) if spooled, Ok(false) if the spool is full and the record was dropped
+    pub fn push(&self, rec: &ObservationRecord) -> io::Result<bool> {
+        let mut line = serde_json::to_string(rec)?;
+        line.push('\n');
+
+        let mut records = self.lock.lock().unwrap();
+        let size = match fs::metadata(&self.path) {
+            Ok(m) => m.len(),
+            Err(_) => {
+                // A cursor left without its spool doesn't belong to a new one
+                remove_if_exists(&self.cursor)?;
+                *records = Some(0);
+                0
+            },
+        };
+        if size.saturating_sub(self.offset(size)?) + line.len() as u64 > self.max_bytes {
+            return Ok(false);
+        }
+
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        f.write_all(line.as_bytes())?;
+        f.sync_data()?;
+        *records = records.map(|n| n + 1);
+        Ok(true)
+    }
+
+    ///  Reads up to n records from the head of the spool.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///*'n'-maximum number of records
+    ///
+    /// # Return
+    ///
+    /// The oldest records, in order, one entry per line.
+    /// Unreadable lines (e.g. torn by a crash) are None.
+    pub fn peek(&self, n: usize) -> io::Result<Vec<Option<ObservationRecord>>> {
+        let _guard = self.lock.lock().unwrap();
+        let Some((f, _)) = self.open_at_cursor()? else { return Ok(Vec::new()) };
+
+        let mut recs = Vec::new();
+        for line in f.lines().take(n) {
+            let line = line?;
+            match serde_json::from_str::<ObservationRecord>(&line) {
+                Ok(r) => recs.push(Some(r)),
+                Err(e) => {
+                    warn!("Unreadable spool line {:?}: {:?}", line, e);
+                    recs.push(None);
+                },
+            }
+        }
+        Ok(recs)
+    }
+
+    ///  Removes the first n records from the spool by moving the cursor
+    ///      past them. The spool is removed once it is all drained, and the
+    ///      remainder is rewritten to a temporary file and renamed over it
+    ///      once the drained head passes COMPACT_BYTES.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///*'n'-number of records to remove
+    ///
+    /// # Return
+    ///
+    /// Result
+    pub fn pop_front(&self, n: usize) -> io::Result<()> {
+        let mut records = self.lock.lock().unwrap();
+        let Some((mut f, size)) = self.open_at_cursor()? else { return Ok(()) };
+
+        let mut line = Vec::new();
+        let mut popped = 0;
+        while popped < n {
+            line.clear();
+            if f.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            popped += 1;
+        }
+        let offset = f.stream_position()?;
+        *records = records.map(|r| r.saturating_sub(popped));
+
+        // The cursor goes first, a crash in between replays drained records
+        // as duplicates rather than skipping undrained ones.
+        if offset >= size {
+            remove_if_exists(&self.cursor)?;
+            remove_if_exists(&self.path)?;
+            *records = Some(0);
+            Ok(())
+        } else if offset > COMPACT_BYTES {
+            let tmp_path = self.path.with_extension("tmp");
+            let mut tmp = File::create(&tmp_path)?;
+            io::copy(&mut f, &mut tmp)?;
+            tmp.sync_all()?;
+            remove_if_exists(&self.cursor)?;
+            fs::rename(&tmp_path, &self.path)
+        } else {
+            let tmp_path = self.cursor.with_extension("tmp");
+            fs::write(&tmp_path, offset.to_string())?;
+            fs::rename(&tmp_path, &self.cursor)
+        }
+    }
+
+    ///  Gets the spool depth and size. The records are counted from the
+    ///      file once, then kept as records are pushed and popped.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///
+    /// # Return
+    ///
+    /// SpoolStatus
+    pub fn status(&self) -> io::Result<SpoolStatus> {
+        let mut count = self.lock.lock().unwrap();
+        let (records, bytes) = match self.open_at_cursor()? {
+            Some((mut f, size)) => {
+                let offset = f.stream_position()?;
+                let records = match *count {
+                    Some(n) => n,
+                    None => f.lines().count(),
+                };
+                (records, size - offset)
+            },
+            None => (0, 0),
+        };
+        *count = Some(records);
+
+        Ok(SpoolStatus {
+            path: self.path.clone(),
+            records,
+            bytes,
+            max_bytes: self.max_bytes,
+        })
+    }
+
+    ///  Replays spooled records into storage, oldest first.
+    ///      Stops at the first failure so ordering is kept.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///*'storage'-where the records belong
+    ///
+    /// # Return
+    ///
+    /// Number of records removed from the spool
+    pub async fn drain(&self, storage: &dyn Storage) -> usize {
+        let mut drained = 0;
+        loop {
+            let recs = match self.peek(DRAIN_BATCH) {
+                Ok(r) => r,
+                Err(e) => { error!("Could not read spool {:?}: {:?}", self.path, e); break; }
+            };
+            if recs.is_empty() {
+                break;
+            }
+
+            // Unreadable lines can never be stored, they are dropped in place.
+            let mut done = 0;
+            for rec in recs.iter() {
+                let rec = match rec {
+                    Some(r) => r,
+                    None => { done += 1; continue; },
+                };
+                match storage.put_observation_record(rec).await {
+                    InsertOutcome::Inserted => {
+                        debug!("Drained spooled record: {:?} {:?}", rec.station_id,
+                               rec.timestamp_UTC);
+                        self.drained.lock().unwrap().push(rec.clone());
+                        done += 1;
+                    },
+                    InsertOutcome::Duplicate => {
+                        debug!("Drained spooled record: {:?} {:?}", rec.station_id,
+                               rec.timestamp_UTC);
+                        done += 1;
+                    },
+                    InsertOutcome::Failed(e) if e.transient => {
+                        warn!("Storage still unavailable, {} spooled record(s) drained: {:?}",
+                              drained + done, e);
+                        break;
+                    },
+                    // Retrying will never succeed, don't let it block the spool.
+                    InsertOutcome::Failed(e) => {
+                        error!("Dropping spooled record {:?} {:?}: {:?}", rec.station_id,
+                               rec.timestamp_UTC, e);
+                        done += 1;
+                    },
+                }
+            }
+
+            if done > 0 {
+                if let Err(e) = self.pop_front(done) {
+                    error!("Could not remove drained records from spool {:?}: {:?}",
+                           self.path, e);
+                    break;
+                }
+            }
+            drained += done;
+            if done < recs.len() {
+                break;
+            }
+        }
+
+        if drained > 0 {
+            info!("Drained {} record(s) from spool {:?}", drained, self.path);
+        }
+        drained
+    }
+
+    ///  Takes the records drained into storage since the last call, so the
+    ///      sinks and what is derived from stored observations, e.g. the
+    ///      climate summaries, can catch up on them. Duplicates, already
+    ///      stored before, are not included.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the spool instance
+    ///
+    /// # Return
+    ///
+    /// The records, in drain order
+    pub fn take_drained(&self) -> Vec<ObservationRecord> {
+        std::mem::take(&mut *self.drained.lock().unwrap())
+    }
+
+    ///  Reads the cursor, 0 without one or when it is past the end of a
+    ///      spool of size bytes.
+    fn offset(&self, size: u64) -> io::Result<u64> {
+        match fs::read_to_string(&self.cursor) {
+            Ok(s) => Ok(s.trim().parse::<u64>().ok().filter(|o| *o <= size).unwrap_or(0)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///  Opens the spool at the cursor, with the spool's size, None without a spool.
+    fn open_at_cursor(&self) -> io::Result<Option<(BufReader<File>, u64)>> {
+        let mut f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let size = f.metadata()?.len();
+        f.seek(SeekFrom::Start(self.offset(size)?))?;
+        Ok(Some((BufReader::new(f), size)))
+    }
+
+} // impl Spool
+
+
+///  Removes a file, Ok if it is already gone.
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+
+///  Starts the background task that drains the spool
+///      whenever storage is healthy.
+///
+/// # Arguments
+///
+///*'spool'-the shared spool
+///*'storage'-the shared storage
+///
+/// # Return
+///
+/// None
+pub fn spawn_drainer(spool: Arc<Spool>, storage: Arc<dyn Storage>) {
+    task::spawn(async move {
+        loop {
+            task::sleep(spool.drain_interval).await;
+            spool.drain(storage.as_ref()).await;
+        }
+    });
+}
@@ -1,12 +1,18 @@
-use reqwest;
 use log::{error, warn, debug};
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::io;
+use crate::client::NwsClient;
+use crate::error::WeatherGovError;
+use crate::astronomy;
+use crate::meteorology;
+use crate::wind;
+use crate::models::{StationFeature, ObservationFeature, ObservationProperties};
 
-type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
-type GenericResult<T> = Result<T, GenericError>;
+/// Stored for a value that was null, or in a unit we can't convert.
+pub const MISSING: f64 = -999.99;
 
 /// Represents a database station record.
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct StationRecord {
     pub call_id:         String,
     pub name:            String,
@@ -14,6 +20,9 @@ pub struct StationRecord {
     pub longitude_deg:   f64,
     pub elevation_m:     f64,
     pub url:             String,
+    /// IANA time zone, e.g. "America/Phoenix", empty if unknown.
+    #[serde(default)]
+    pub time_zone:       String,
 }
 
 /// Enables debugging a database station record.
@@ -26,6 +35,7 @@ impl fmt::Debug for StationRecord {
             .field("\n        longitude_deg", &self.longitude_deg)
             .field("\n        elevation_m", &self.elevation_m)
             .field("\n        url", &self.url)
+            .field("\n        time_zone", &self.time_zone)
             .finish()
     }
 }
@@ -33,6 +43,7 @@ impl fmt::Debug for StationRecord {
 
 /// Represents database station observation record.
 #[allow(non_snake_case)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ObservationRecord {
     pub station_id:       String,
     pub timestamp_UTC:    String,
@@ -49,6 +60,109 @@ pub struct ObservationRecord {
     pub baro_pres_pa:     f64,
     pub baro_pres_inHg:   f64,
     pub rel_humidity:     f64,
+    /// Spooled before this field existed, read as MISSING.
+    #[serde(default = "missing")]
+    pub precip_last_hour_mm: f64,
+    // Derived from the observed values, see meteorology::derive
+    #[serde(default = "missing")]
+    pub heat_index_C:     f64,
+    #[serde(default = "missing")]
+    pub wind_chill_C:     f64,
+    #[serde(default = "missing")]
+    pub apparent_temperature_C: f64,
+    #[serde(default = "missing")]
+    pub humidex:          f64,
+    #[serde(default = "missing")]
+    pub wet_bulb_C:       f64,
+    #[serde(default = "missing")]
+    pub abs_humidity_g_m3: f64,
+    #[serde(default = "missing")]
+    pub mixing_ratio_g_kg: f64,
+    /// Comma separated names of the fields computed rather than observed,
+    ///     e.g. "rel_humidity,wet_bulb_C".
+    #[serde(default)]
+    pub derived:          String,
+    // Classified from wind_dir and wind_spd_km_h, see wind::classify
+    /// 16-point compass direction, CALM or VRB, empty without a speed.
+    #[serde(default)]
+    pub wind_compass:     String,
+    #[serde(default = "missing")]
+    pub wind_beaufort:    f64,
+    #[serde(default)]
+    pub wind_beaufort_desc: String,
+    /// Hurricane category, MISSING below hurricane force.
+    #[serde(default = "missing")]
+    pub wind_saffir_simpson: f64,
+    // Changes since 3 hours earlier, from the stored history, see tendency::apply
+    #[serde(default = "missing")]
+    pub pres_tendency_3h_pa: f64,
+    /// WMO code table 0200 pressure characteristic, 0 to 8.
+    #[serde(default = "missing")]
+    pub pres_tendency_code: f64,
+    #[serde(default = "missing")]
+    pub temperature_change_3h_C: f64,
+    #[serde(default = "missing")]
+    pub dewpoint_change_3h_C: f64,
+    // From the station's position, see astronomy::tag
+    /// Sun elevation, corrected for refraction, degrees.
+    #[serde(default = "missing")]
+    pub sun_elevation_deg: f64,
+    /// "day" or "night", empty when the station position is unknown.
+    #[serde(default)]
+    pub day_night:        String,
+    /// Comma separated failed quality checks, field:check, e.g.
+    ///     "temperature_C:range", see qc::QualityControl. Empty if all passed.
+    #[serde(default)]
+    pub qc_flags:         String,
+}
+
+///  The serde default of fields added to ObservationRecord.
+fn missing() -> f64 {
+    MISSING
+}
+
+/// Enables building an observation record field by field: values are
+///     MISSING until set, so a field left out is stored as NULL, not 0.
+impl Default for ObservationRecord {
+    fn default() -> Self {
+        ObservationRecord {
+            station_id:       String::new(),
+            timestamp_UTC:    String::new(),
+            temperature_C:    MISSING,
+            temperature_F:    MISSING,
+            dewpoint_C:       MISSING,
+            dewpoint_F:       MISSING,
+            description:      String::new(),
+            wind_dir:         MISSING,
+            wind_spd_km_h:    MISSING,
+            wind_spd_mi_h:    MISSING,
+            wind_gust_km_h:   MISSING,
+            wind_gust_mi_h:   MISSING,
+            baro_pres_pa:     MISSING,
+            baro_pres_inHg:   MISSING,
+            rel_humidity:     MISSING,
+            precip_last_hour_mm: MISSING,
+            heat_index_C:     MISSING,
+            wind_chill_C:     MISSING,
+            apparent_temperature_C: MISSING,
+            humidex:          MISSING,
+            wet_bulb_C:       MISSING,
+            abs_humidity_g_m3: MISSING,
+            mixing_ratio_g_kg: MISSING,
+            derived:          String::new(),
+            wind_compass:     String::new(),
+            wind_beaufort:    MISSING,
+            wind_beaufort_desc: String::new(),
+            wind_saffir_simpson: MISSING,
+            pres_tendency_3h_pa: MISSING,
+            pres_tendency_code: MISSING,
+            temperature_change_3h_C: MISSING,
+            dewpoint_change_3h_C: MISSING,
+            sun_elevation_deg: MISSING,
+            day_night:        String::new(),
+            qc_flags:         String::new(),
+        }
+    }
 }
 
 /// Enables debugging a database observation record.
@@ -68,6 +182,26 @@ impl fmt::Debug for ObservationRecord {
             .field("\n        baro_pres_pa", &self.baro_pres_pa)
             .field("\n        baro_pres_inHg", &self.baro_pres_inHg)
             .field("\n        rel_humidity", &self.rel_humidity)
+            .field("\n        precip_last_hour_mm", &self.precip_last_hour_mm)
+            .field("\n        heat_index_C", &self.heat_index_C)
+            .field("\n        wind_chill_C", &self.wind_chill_C)
+            .field("\n        apparent_temperature_C", &self.apparent_temperature_C)
+            .field("\n        humidex", &self.humidex)
+            .field("\n        wet_bulb_C", &self.wet_bulb_C)
+            .field("\n        abs_humidity_g_m3", &self.abs_humidity_g_m3)
+            .field("\n        mixing_ratio_g_kg", &self.mixing_ratio_g_kg)
+            .field("\n        derived", &self.derived)
+            .field("\n        wind_compass", &self.wind_compass)
+            .field("\n        wind_beaufort", &self.wind_beaufort)
+            .field("\n        wind_beaufort_desc", &self.wind_beaufort_desc)
+            .field("\n        wind_saffir_simpson", &self.wind_saffir_simpson)
+            .field("\n        pres_tendency_3h_pa", &self.pres_tendency_3h_pa)
+            .field("\n        pres_tendency_code", &self.pres_tendency_code)
+            .field("\n        temperature_change_3h_C", &self.temperature_change_3h_C)
+            .field("\n        dewpoint_change_3h_C", &self.dewpoint_change_3h_C)
+            .field("\n        sun_elevation_deg", &self.sun_elevation_deg)
+            .field("\n        day_night", &self.day_night)
+            .field("\n        qc_flags", &self.qc_flags)
             .finish()
     }
 }
@@ -78,14 +212,15 @@ pub struct Station {
     pub station_name:                String,
     pub station_url:                 String,
     pub json_station_data:           String,
-    pub json_station_serde_val:      serde_json::Value,
+    pub station_feature:             Option<StationFeature>,
     pub observation_url:             String,
     pub latest_observation_data:     String,
-    pub json_observation_serde_val:  serde_json::Value,
+    pub latest_observation:          Option<ObservationFeature>,
     pub longitude:                   f64,
     pub latitude:                    f64,
     pub elevation_meters:            f64,
     pub elevation_feet:              f64,
+    pub time_zone:                   String,
 
 }
 
@@ -111,14 +246,15 @@ impl Station {
             station_name: "".to_string(),
             station_url: format!("{}{}", stations_url, sid),
             json_station_data: "".to_string(),
-            json_station_serde_val: serde_json::Value::Null ,
+            station_feature: None,
             observation_url: format!("{}{}/observations/latest", surl, sid),
             latest_observation_data: "".to_string(),
-            json_observation_serde_val:  serde_json::Value::Null,
+            latest_observation: None,
             longitude: 0.0,
             latitude: 0.0,
             elevation_meters: 0.0,
             elevation_feet: 0.0,
+            time_zone: "".to_string(),
         }
     }
 
@@ -128,31 +264,17 @@ impl Station {
     /// # Arguments
     ///
     ///*'self'-the object instance
+    ///*'client'-the api.weather.gov client
     ///
     /// # Return
     ///
     /// Result
-    pub async fn get_station_json(&mut self) -> Result<String, reqwest::Error> {
-        // api.weather.gov requires User-Agent be set, but reqwest does not
-        // set one. See weather.gov.
-        let client = reqwest::Client::new();
-        let resp = client.get(&self.station_url)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", "Mozilla/5.0 (X11; Linux i686; rv:124.0)\
-                                            Gecko/20100101 Firefox/124.0")
-            .send().await?;
-
-        let rtext = resp.text().await?;
+    pub async fn get_station_json(&mut self, client: &NwsClient)
+                                        -> Result<String, WeatherGovError> {
+        let (rtext, feature) = client.get::<StationFeature>(&self.station_url).await?;
         self.json_station_data = rtext.clone();
-        // Need to match here, not use ?, because the error type is not reqwest::Error
-        // If we cannot get the station meta json, we won't be able to create a db record,
-        //   so we panic. Another reason we need to match.
-        self.json_station_serde_val = match serde_json::from_str(&self.json_station_data) {
-            Ok(v) => v,
-            Err(e) => panic!("Could not parse json station data: {:?}", e),
-        };
-
-        self.set_station_data();
+        self.set_station_data(&feature);
+        self.station_feature = Some(feature);
 
         Ok(rtext)
     }
@@ -164,135 +286,54 @@ impl Station {
     /// # Arguments
     ///
     ///*'self'-the station instance
+    ///*'feature'-the decoded station json
     ///
     /// # Return
     ///
     /// None
-    pub fn set_station_data(&mut self) {
-        self.parse_json_station_name();
-        self.parse_json_longitude();
-        self.parse_json_latitude();
-        self.parse_json_elevation();
-    }
-
-    ///  Get station name from station json.
-    ///
-    /// # Arguments
-    ///
-    ///*'self'-the station instance
-    ///
-    /// # Return
-    ///
-    /// None
-    fn parse_json_station_name(&mut self) {
-
-        debug!("parse json station name called");
-
-        //Returns an Option, the value if successful, None otherwise
-        let n = self.json_station_serde_val["properties"]["name"].as_str();
-
-        let nm: String = match n {
-            Some(f) => String::from(f),
-            None => self.station_identifier.clone(),
-        };
-
-        if nm == self.station_identifier {
-            warn!("Could not get name from json, \
+    pub fn set_station_data(&mut self, feature: &StationFeature) {
+        let props = &feature.properties;
+        if props.name.is_empty() {
+            warn!("Station json has an empty name, \
                   setting name to station_identifier: {:?}", self.station_identifier);
+            self.station_name = self.station_identifier.clone();
         } else {
-            self.station_name = nm;
+            self.station_name = props.name.clone();
         }
-    }
 
-    ///  Get station longitude from station json.
-    ///
-    /// # Arguments
-    ///
-    ///*'self'-the station instance
-    ///
-    /// # Return
-    ///
-    /// None
-    fn parse_json_longitude(&mut self) {
-
-        debug!("parse json longitude called");
-
-        //Returns an Option, the value if successful, None otherwise
-        let l = self.json_station_serde_val["geometry"]["coordinates"][0].as_f64();
-
-        let long = match l {
-            Some(f) => f,
-            None  => 0.0
-        };
-
-        self.longitude = long;
-        if self.longitude == 0.0 {
-            warn!("WARNING: Parsing error: station {:?} longitude \
-                  set to zero.", self.station_identifier);
-        } else {
-            debug!("Station longitude: {:?}", self.longitude);
+        match feature.geometry.as_ref().and_then(|g| g.point()) {
+            Some((lon, lat)) => {
+                self.longitude = lon;
+                self.latitude = lat;
+                debug!("Station longitude: {:?} latitude: {:?}", self.longitude, self.latitude);
+            },
+            None => {
+                warn!("WARNING: station {:?} json has no point geometry, longitude and \
+                      latitude set to zero.", self.station_identifier);
+                self.longitude = 0.0;
+                self.latitude = 0.0;
+            },
         }
-    }
 
-    ///  Get station latitude from station json.
-    ///
-    /// # Arguments
-    ///
-    ///*'self'-the station instance
-    ///
-    /// # Return
-    ///
-    /// None
-    fn parse_json_latitude(&mut self) {
-
-        debug!("parse json latitude called");
-
-        //Returns an Option, the value if successful, None otherwise
-        let l = self.json_station_serde_val["geometry"]["coordinates"][1].as_f64();
-
-        let lat = match l {
-            Some(f) => f,
-            None  => 0.0
-        };
-
-        self.latitude = lat;
-        if self.latitude == 0.0 {
-            warn!("WARNING: Parsing error: station {:?} latitude \
-                  set to zero.", self.station_identifier);
-        } else {
-            debug!("Station latitude: {:?}", self.latitude);
+        self.time_zone = props.time_zone.clone().unwrap_or_default();
+        if self.time_zone.is_empty() {
+            warn!("Station {:?} json has no time zone, summarizing in UTC",
+                  self.station_identifier);
         }
-    }
 
-    ///  Get station elevation from station json.
-    ///
-    /// # Arguments
-    ///
-    ///*'self'-the station instance
-    ///
-    /// # Return
-    ///
-    /// None
-    fn parse_json_elevation(&mut self) {
-
-        debug!("parse json elevation called");
-
-        //Returns an Option, the value if successful, None otherwise
-        let e = self.json_station_serde_val["properties"]["elevation"]["value"].as_f64();
-
-        let ele = match e {
-            Some(f) => f,
-            None  => 0.0
-        };
-
-        self.elevation_meters = ele;
-        self.elevation_feet = self.elevation_meters * 3.28084;
-        if self.elevation_meters == 0.0 {
-            warn!("WARNING: Parsing error: station {:?} latitude \
-                  set to zero.", self.station_identifier);
-        } else {
-            debug!("Station elevation_meters: {:?}", self.elevation_meters);
-            debug!("Station elevation_feet: {:?}", self.elevation_feet);
+        match props.elevation.as_meters() {
+            Some(m) => {
+                self.elevation_meters = m;
+                self.elevation_feet = self.elevation_meters * 3.28084;
+                debug!("Station elevation_meters: {:?}", self.elevation_meters);
+                debug!("Station elevation_feet: {:?}", self.elevation_feet);
+            },
+            None => {
+                warn!("WARNING: station {:?} elevation {:?} is missing or in an unknown \
+                      unit, set to zero.", self.station_identifier, props.elevation);
+                self.elevation_meters = 0.0;
+                self.elevation_feet = 0.0;
+            },
         }
     }
 
@@ -314,6 +355,7 @@ impl Station {
             longitude_deg:   self.longitude,
             elevation_m:     self.elevation_meters,
             url:             self.station_url.clone(),
+            time_zone:       self.time_zone.clone(),
         }
     }
 
@@ -323,158 +365,101 @@ impl Station {
     /// # Arguments
     ///
     ///*'self'-the station instance
+    ///*'client'-the api.weather.gov client
     ///
     /// # Return
     ///
-    /// ObservationRecord or Error
-    ///    generic error is used as there are multiple error types and dont want to panic.
-    ///    This should just return to caller.
-    ///    Failing to get an observation is not fatal.
-    ///    GenericResult accomplishes what is wanted, but may lose info about what
-    ///    exacty failed. However, looks like I can wrap the actual error in the Generic Error
-    ///    using format!.
-    ///    On the bright side, this call rarely, if ever, fails.
-    pub async fn get_latest_observation_data(&mut self)  -> GenericResult<ObservationRecord> {
-        // api.weather.gov requires User-Agent be set, but reqwest does not
-        // set one. See weather.gov.
-        let client = reqwest::Client::new();
-        let resp = match client.get(&self.observation_url)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", "Mozilla/5.0 (X11; Linux i686; rv:124.0)\
-                                            Gecko/20100101 Firefox/124.0")
-            .send().await {
-             Ok(resp) => resp,
-             Err(e) =>  {
-                    error!("Error getting latest observation: {:?}", e);
-                    let io_error = io::Error::new(io::ErrorKind::Other,
-                                   format!("get observation response failed: {:?}", e));
-                    return Err(GenericError::from(io_error));
-                    },
-
-            };
-
-        let rtext = match resp.text().await {
-
-             Ok(rtext) => rtext,
-             Err(e) =>  {
-                    error!("Error getting observation response: {:?}", e);
-                    let io_error = io::Error::new(io::ErrorKind::Other,
-                                   format!("get observation response text failed: {:?}", e));
-                    return Err(GenericError::from(io_error));
-                    },
-
-        };
-        self.latest_observation_data = rtext.clone();
-
-        // serde_json::from_str does not emit a reqwest::Error on failure.
-        // By default, cannot use ? operator, but using a GenericResult allows this.
-        //self.json_observation_serde_val
-        //    serde_json::from_str(&self.latest_observation_data)?;
-        let json_obs_serde = match  serde_json::from_str(&self.latest_observation_data) {
-            Ok(obs) => obs,
-
-            Err(e) =>  {
-                    error!("Error getting observation response: {:?}", e);
-                    let io_error = io::Error::new(io::ErrorKind::Other,
-                                   format!("get observation json failed: {:?}", e));
-                    return Err(GenericError::from(io_error));
-                    },
+    /// ObservationRecord or WeatherGovError.
+    ///    Failing to get an observation is not fatal, the caller can use
+    ///    WeatherGovError::action to decide whether to retry, back off or
+    ///    stop polling the station.
+    pub async fn get_latest_observation_data(&mut self, client: &NwsClient)
+                                        -> Result<ObservationRecord, WeatherGovError> {
+        let (rtext, feature) = match client.get::<ObservationFeature>(&self.observation_url).await {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Error getting latest observation: {}", e);
+                return Err(e);
+            },
         };
+        self.latest_observation_data = rtext;
+
+        // The timestamp is part of the db primary key, we can't store without it
+        if feature.properties.timestamp.is_empty() {
+            return Err(WeatherGovError::MissingField {
+                url: self.observation_url.clone(),
+                field: "properties.timestamp".to_string(),
+            });
+        }
 
-        self.json_observation_serde_val = json_obs_serde;
-        let obs = self.preprocess_observation();
+        let obs = self.preprocess_observation(&feature.properties);
+        self.latest_observation = Some(feature);
         Ok(obs)
     }
 
+    ///  Whether the station's position is known, set_station_data leaves
+    ///      latitude and longitude zero without a point geometry.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the station instance
+    ///
+    /// # Return
+    ///
+    /// bool
+    pub fn has_position(&self) -> bool {
+        self.latitude != 0.0 || self.longitude != 0.0
+    }
+
     ///   Helper for get_latest_observation_data,
-    ///      adds items that are not natively in the json
-    ///      and handles null values.
+    ///      adds items that are not natively in the json,
+    ///      the derived values of meteorology::derive and the
+    ///      wind classification of wind::classify, the day or night
+    ///      tag of astronomy::tag, and handles null values.
     ///
     /// # Arguments
     ///
     ///*'self'-the station instance
+    ///*'props'-the decoded observation properties
     ///
     /// # Return
     ///
-    /// ObservationRecord
-    fn preprocess_observation(&mut self) -> ObservationRecord {
-        let mut obs = ObservationRecord {
+    /// ObservationRecord, null values and unknown units are set to MISSING
+    pub fn preprocess_observation(&self, props: &ObservationProperties) -> ObservationRecord {
+        let temperature_c = props.temperature.as_celsius();
+        let dewpoint_c = props.dewpoint.as_celsius();
+        let wind_spd = props.wind_speed.as_km_h();
+        let wind_gust = props.wind_gust.as_km_h();
+        let pressure = props.barometric_pressure.as_pa();
+
+        let mut rec = ObservationRecord {
             station_id:       self.station_identifier.clone(),
-            timestamp_UTC:    "".to_string(),
-            temperature_C:    0.0,
-            temperature_F:    0.0,
-            dewpoint_C:       0.0,
-            dewpoint_F:       0.0,
-            description:      "".to_string().clone(),
-            wind_dir:         0.0,
-            wind_spd_km_h:    0.0,
-            wind_spd_mi_h:    0.0,
-            wind_gust_km_h:   0.0,
-            wind_gust_mi_h:   0.0,
-            baro_pres_pa:     0.0,
-            baro_pres_inHg:   0.0,
-            rel_humidity:     0.0,
+            timestamp_UTC:    props.timestamp.clone(),
+            temperature_C:    temperature_c.unwrap_or(MISSING),
+            temperature_F:    temperature_c.map(|v| v * (9.0/5.0) + 32.0).unwrap_or(MISSING),
+            dewpoint_C:       dewpoint_c.unwrap_or(MISSING),
+            dewpoint_F:       dewpoint_c.map(|v| v * (9.0/5.0) + 32.0).unwrap_or(MISSING),
+            description:      props.text_description.clone().unwrap_or_default(),
+            wind_dir:         props.wind_direction.as_degrees().unwrap_or(MISSING),
+            wind_spd_km_h:    wind_spd.unwrap_or(MISSING),
+            wind_spd_mi_h:    wind_spd.map(|v| v * 0.6213712).unwrap_or(MISSING),
+            wind_gust_km_h:   wind_gust.unwrap_or(MISSING),
+            wind_gust_mi_h:   wind_gust.map(|v| v * 0.6213712).unwrap_or(MISSING),
+            baro_pres_pa:     pressure.unwrap_or(MISSING),
+            baro_pres_inHg:   pressure.map(|v| v * 0.00029529983071445).unwrap_or(MISSING),
+            rel_humidity:     props.relative_humidity.as_percent().unwrap_or(MISSING),
+            precip_last_hour_mm: props.precipitation_last_hour.as_ref()
+                                  .and_then(|p| p.as_mm()).unwrap_or(MISSING),
+            // The rest are derived below, or need the stored history and are
+            // filled in by the collector
+            ..Default::default()
         };
-        let res =  self.json_observation_serde_val["properties"]["timestamp"].as_str();
-        match res {
-            Some(v) => { obs.timestamp_UTC = v.to_string(); },
-            None => { obs.timestamp_UTC = "".to_string(); },
-        }
-
-        let res =  self.json_observation_serde_val["properties"]["temperature"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.temperature_C = v; obs.temperature_F = v * (9.0/5.0) + 32.0; },
-            None => { obs.temperature_C = -999.99; obs.temperature_F = -999.99; },
+        meteorology::derive(&mut rec, self.elevation_meters);
+        wind::classify(&mut rec);
+        if self.has_position() {
+            astronomy::tag(&mut rec, self.latitude, self.longitude);
         }
-
-        let res =  self.json_observation_serde_val["properties"]["dewpoint"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.dewpoint_C = v; obs.dewpoint_F = v * (9.0/5.0) + 32.0; }
-            None => { obs.dewpoint_C = -999.99; obs.dewpoint_F = -999.99; },
-        }
-
-
-        let res =  self.json_observation_serde_val["properties"]["textDescription"].as_str();
-        match res {
-            Some(v) => { obs.description = v.to_string(); },
-            None => { obs.description = "".to_string(); },
-        }
-
-
-        let res =  self.json_observation_serde_val["properties"]["windDirection"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.wind_dir = v; },
-            None => { obs.wind_dir = -999.99; },
-        }
-
-        let res =  self.json_observation_serde_val["properties"]["windSpeed"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.wind_spd_km_h = v; obs.wind_spd_mi_h = v * 0.6213712 },
-            None => { obs.wind_spd_km_h = -999.99; obs.wind_spd_mi_h = -999.99},
-        }
-
-        let res =  self.json_observation_serde_val["properties"]["windGust"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.wind_gust_km_h = v; obs.wind_gust_mi_h = v * 0.6213712 },
-            None => { obs.wind_gust_km_h = -999.99; obs.wind_gust_mi_h = -999.99},
-        }
-
-        let res =  self.json_observation_serde_val["properties"]["barometricPressure"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.baro_pres_pa = v; obs.baro_pres_inHg = v * 0.00029529983071445; },
-            None => { obs.baro_pres_pa = -999.99; obs.baro_pres_inHg = -999.99},
-        }
-
-        let res =  self.json_observation_serde_val["properties"]["relativeHumidity"]["value"].as_f64();
-        match res {
-            Some(v) => { obs.rel_humidity = v; },
-            None => { obs.rel_humidity = -999.99 ; },
-        }
-
-        obs
+        rec
     }
 
 } // impl Station
-
-
-
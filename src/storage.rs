@@ -0,0 +1,305 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::climate::{DailySummary, HourlySummary, MonthlySummary};
+use crate::rules::RuleEvent;
+use crate::station::{StationRecord, ObservationRecord};
+
+/// Boxed source error of a StorageError.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Represents a storage backend error.
+#[derive(Debug)]
+pub struct StorageError {
+    /// The backend is unavailable (connection lost, timeout...), retrying later may work.
+    pub transient:  bool,
+    pub source:     BoxError,
+}
+
+/// Implementation for StorageError.
+impl StorageError {
+
+    ///  Creates a transient error, worth retrying later.
+    ///
+    /// # Arguments
+    ///
+    ///*'source'-the backend error
+    ///
+    /// # Return
+    ///
+    /// StorageError
+    pub fn transient<E: Into<BoxError>>(source: E) -> StorageError {
+        StorageError { transient: true, source: source.into() }
+    }
+
+    ///  Creates a permanent error, retrying will fail the same way.
+    ///
+    /// # Arguments
+    ///
+    ///*'source'-the backend error
+    ///
+    /// # Return
+    ///
+    /// StorageError
+    pub fn permanent<E: Into<BoxError>>(source: E) -> StorageError {
+        StorageError { transient: false, source: source.into() }
+    }
+}
+
+/// Displays a StorageError.
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.transient { "transient" } else { "permanent" };
+        write!(f, "{} storage error: {}", kind, self.source)
+    }
+}
+
+/// Enables ? and source() on a StorageError.
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+
+/// Outcome of inserting an observation record.
+#[derive(Debug)]
+pub enum InsertOutcome {
+    Inserted,
+    Duplicate,
+    Failed(StorageError),
+}
+
+/// Outcome of a batch insert of observation records.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BatchOutcome {
+    pub inserted:       u64,
+    pub duplicate:      u64,
+    /// (station_id, timestamp_UTC) of the inserted records, in batch order.
+    pub inserted_keys:  Vec<(String, String)>,
+}
+
+/// Selects observation records of one station, ordered by timestamp_UTC.
+///     from and to are UTC timestamps in the api.weather.gov form,
+///     e.g. "2024-04-01T12:51:00+00:00", compared as strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObservationQuery {
+    pub station_id:   String,
+    /// Inclusive.
+    pub from:         Option<String>,
+    /// Exclusive.
+    pub to:           Option<String>,
+    pub limit:        usize,
+    pub offset:       usize,
+}
+
+/// Implementation for ObservationQuery.
+impl ObservationQuery {
+
+    ///  Checks whether a record is selected, ignoring limit and offset.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the query
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// true if the record is from the station and in [from, to)
+    pub fn matches(&self, rec: &ObservationRecord) -> bool {
+        rec.station_id == self.station_id
+            && self.from.as_ref().is_none_or(|f| rec.timestamp_UTC.as_str() >= f.as_str())
+            && self.to.as_ref().is_none_or(|t| rec.timestamp_UTC.as_str() < t.as_str())
+    }
+}
+
+
+///  Normalizes a query bound, e.g. an api or command line parameter, to
+///      the stored timestamp form, "2024-04-01T12:51:00+00:00". Takes an
+///      RFC 3339 timestamp in any offset, or a date meaning its UTC midnight.
+///      An unescaped '+' in a query string arrives as a space, so a space
+///      is read as '+'.
+///
+/// # Arguments
+///
+///*'s'-the bound
+///
+/// # Return
+///
+/// The UTC timestamp, None if it can't be parsed
+pub fn parse_timestamp(s: &str) -> Option<String> {
+    let s = s.trim().replace(' ', "+");
+    let utc = match DateTime::parse_from_rfc3339(&s) {
+        Ok(t) => t.with_timezone(&Utc),
+        Err(_) => NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok()?
+            .and_hms_opt(0, 0, 0)?.and_utc(),
+    };
+    Some(utc.format("%Y-%m-%dT%H:%M:%S+00:00").to_string())
+}
+
+
+/// Running totals of observation insert outcomes.
+#[derive(Debug, Default)]
+pub struct InsertCounters {
+    pub inserted:   AtomicU64,
+    pub duplicate:  AtomicU64,
+    pub failed:     AtomicU64,
+}
+
+/// Implementation for insert counters.
+impl InsertCounters {
+
+    ///  Counts an insert outcome.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the counters
+    ///*'outcome'-the InsertOutcome
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn count(&self, outcome: &InsertOutcome) {
+        let counter = match outcome {
+            InsertOutcome::Inserted => &self.inserted,
+            InsertOutcome::Duplicate => &self.duplicate,
+            InsertOutcome::Failed(_) => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///  Adds batch totals.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the counters
+    ///*'inserted'-rows inserted
+    ///*'duplicate'-rows skipped as duplicates
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn add(&self, inserted: u64, duplicate: u64) {
+        self.inserted.fetch_add(inserted, Ordering::Relaxed);
+        self.duplicate.fetch_add(duplicate, Ordering::Relaxed);
+    }
+
+    ///  Gets the current totals.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the counters
+    ///
+    /// # Return
+    ///
+    /// (inserted, duplicate, failed)
+    pub fn totals(&self) -> (u64, u64, u64) {
+        (self.inserted.load(Ordering::Relaxed),
+         self.duplicate.load(Ordering::Relaxed),
+         self.failed.load(Ordering::Relaxed))
+    }
+}
+
+
+/// A place to keep station and observation records.
+///     Implemented by db::Db for MySQL.
+///     The read methods are optional, a write-only backend keeps the
+///     defaults, which fail with a permanent error.
+#[async_trait]
+pub trait Storage: Send + Sync {
+
+    ///  Creates whatever tables or files the backend needs.
+    async fn create_tables(&self) -> Result<(), StorageError>;
+
+    ///  Adds or replaces a station record.
+    async fn put_station_record(&self, rec: &StationRecord) -> Result<(), StorageError>;
+
+    ///  Adds an observation record, duplicates are reported, not replaced.
+    async fn put_observation_record(&self, rec: &ObservationRecord) -> InsertOutcome;
+
+    ///  Adds many observation records at once, all or nothing.
+    async fn put_observation_batch(&self, recs: &[ObservationRecord])
+                                                -> Result<BatchOutcome, StorageError>;
+
+    ///  Gets all station records, ordered by call id.
+    async fn list_stations(&self) -> Result<Vec<StationRecord>, StorageError> {
+        Err(StorageError::permanent("this storage can't list stations"))
+    }
+
+    ///  Gets the newest observation record of a station, if any.
+    async fn latest_observation(&self, _station_id: &str)
+                                        -> Result<Option<ObservationRecord>, StorageError> {
+        Err(StorageError::permanent("this storage can't read observations"))
+    }
+
+    ///  Gets the observation records selected by a query.
+    async fn observations(&self, _query: &ObservationQuery)
+                                        -> Result<Vec<ObservationRecord>, StorageError> {
+        Err(StorageError::permanent("this storage can't read observations"))
+    }
+
+    ///  Adds a rule firing or clearing.
+    async fn put_rule_event(&self, _event: &RuleEvent) -> Result<(), StorageError> {
+        Err(StorageError::permanent("this storage can't keep rule events"))
+    }
+
+    ///  Gets the newest rule event of each rule and station.
+    async fn latest_rule_events(&self) -> Result<Vec<RuleEvent>, StorageError> {
+        Err(StorageError::permanent("this storage can't read rule events"))
+    }
+
+    ///  Adds or replaces a station's daily summary.
+    async fn put_daily_summary(&self, _summary: &DailySummary) -> Result<(), StorageError> {
+        Err(StorageError::permanent("this storage can't keep summaries"))
+    }
+
+    ///  Adds or replaces a station's monthly summary.
+    async fn put_monthly_summary(&self, _summary: &MonthlySummary) -> Result<(), StorageError> {
+        Err(StorageError::permanent("this storage can't keep summaries"))
+    }
+
+    ///  Gets a station's daily summaries of local dates in [from, to),
+    ///      dates as YYYY-MM-DD, ordered by date.
+    async fn daily_summaries(&self, _station_id: &str, _from: &str, _to: &str)
+                                        -> Result<Vec<DailySummary>, StorageError> {
+        Err(StorageError::permanent("this storage can't read summaries"))
+    }
+
+    ///  Gets a station's monthly summaries of local months in [from, to),
+    ///      months as YYYY-MM, ordered by month.
+    async fn monthly_summaries(&self, _station_id: &str, _from: &str, _to: &str)
+                                        -> Result<Vec<MonthlySummary>, StorageError> {
+        Err(StorageError::permanent("this storage can't read summaries"))
+    }
+
+    ///  Rolls a station's raw observations in [from, to) up into hourly
+    ///      summaries: stores the summaries and deletes the observations,
+    ///      all or nothing, so a failure can be retried without counting
+    ///      observations twice.
+    async fn roll_up(&self, _summaries: &[HourlySummary], _station_id: &str, _from: &str,
+                     _to: &str) -> Result<u64, StorageError> {
+        Err(StorageError::permanent("this storage can't roll up observations"))
+    }
+
+    ///  Gets a station's hourly summaries of hours in [from, to), ordered by hour.
+    async fn hourly_summaries(&self, _station_id: &str, _from: &str, _to: &str)
+                                        -> Result<Vec<HourlySummary>, StorageError> {
+        Err(StorageError::permanent("this storage can't read summaries"))
+    }
+
+    ///  Deletes up to limit hourly summaries of hours before a UTC time.
+    async fn purge_hourly_summaries(&self, _before: &str, _limit: usize)
+                                        -> Result<u64, StorageError> {
+        Err(StorageError::permanent("this storage can't purge summaries"))
+    }
+
+    ///  Deletes up to limit daily summaries of local dates before a date.
+    async fn purge_daily_summaries(&self, _before: &str, _limit: usize)
+                                        -> Result<u64, StorageError> {
+        Err(StorageError::permanent("this storage can't purge summaries"))
+    }
+
+    ///  Gets the running insert totals.
+    fn counters(&self) -> &InsertCounters;
+}
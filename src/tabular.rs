@@ -0,0 +1,153 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::station::MISSING;
+
+/// A record as named columns, in struct field order.
+pub type Row = Map<String, Value>;
+
+///  Converts a record to a row, MISSING values become null.
+///
+/// # Arguments
+///
+///*'rec'-the record, e.g. an ObservationRecord
+///
+/// # Return
+///
+/// Row, empty if the record does not serialize to an object
+pub fn to_row<T: Serialize>(rec: &T) -> Row {
+    match serde_json::to_value(rec) {
+        Ok(Value::Object(map)) => map.into_iter()
+            .map(|(k, v)| match v.as_f64() {
+                Some(f) if f == MISSING => (k, Value::Null),
+                _ => (k, v),
+            })
+            .collect(),
+        _ => Row::new(),
+    }
+}
+
+///  Gets a record type's fields with their default values, MISSING kept
+///      as a number, so numeric fields can be told from the others.
+///
+/// # Arguments
+/// None
+///
+/// # Return
+///
+/// Row, in struct field order
+pub fn fields<T: Serialize + Default>() -> Row {
+    match serde_json::to_value(T::default()) {
+        Ok(Value::Object(map)) => map,
+        _ => Row::new(),
+    }
+}
+
+///  Flattens nested objects into one level, joining names with '_',
+///      e.g. {"temperature_C": {"min": 1}} becomes {"temperature_C_min": 1}.
+///
+/// # Arguments
+///
+///*'row'-the row
+///
+/// # Return
+///
+/// Row
+pub fn flatten(row: Row) -> Row {
+    let mut out = Row::new();
+    for (k, v) in row {
+        match v {
+            Value::Object(inner) => {
+                for (ik, iv) in flatten(inner) {
+                    out.insert(format!("{}_{}", k, ik), iv);
+                }
+            },
+            v => { out.insert(k, v); },
+        }
+    }
+    out
+}
+
+///  Keeps only the named columns, in the order given.
+///
+/// # Arguments
+///
+///*'row'-the row
+///*'fields'-the column names
+///
+/// # Return
+///
+/// Row, columns the row doesn't have are null
+pub fn select(row: &Row, fields: &[String]) -> Row {
+    fields.iter()
+        .map(|f| (f.clone(), row.get(f).cloned().unwrap_or(Value::Null)))
+        .collect()
+}
+
+///  Quotes a CSV field if it holds a comma, quote or line break (RFC 4180).
+///
+/// # Arguments
+///
+///*'field'-the field text
+///
+/// # Return
+///
+/// The field, quoted if needed
+pub fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+///  Builds a CSV header line from column names.
+///
+/// # Arguments
+///
+///*'fields'-the column names
+///
+/// # Return
+///
+/// The line, ending in CRLF
+pub fn csv_header<S: AsRef<str>>(fields: &[S]) -> String {
+    let names: Vec<String> = fields.iter().map(|f| csv_escape(f.as_ref())).collect();
+    format!("{}\r\n", names.join(","))
+}
+
+///  Builds a CSV line from a row's values, nulls are empty fields.
+///
+/// # Arguments
+///
+///*'row'-the row
+///
+/// # Return
+///
+/// The line, ending in CRLF
+pub fn csv_line(row: &Row) -> String {
+    let values: Vec<String> = row.values()
+        .map(|v| match v {
+            Value::Null => String::new(),
+            Value::String(s) => csv_escape(s),
+            v => csv_escape(&v.to_string()),
+        })
+        .collect();
+    format!("{}\r\n", values.join(","))
+}
+
+///  Renders rows as a CSV document with a header line.
+///
+/// # Arguments
+///
+///*'fields'-the column names, the header
+///*'rows'-the rows, with those columns in that order
+///
+/// # Return
+///
+/// The CSV text, just the header if there are no rows
+pub fn to_csv<S: AsRef<str>>(fields: &[S], rows: &[Row]) -> String {
+    let mut out = csv_header(fields);
+    for row in rows {
+        out.push_str(&csv_line(row));
+    }
+    out
+}
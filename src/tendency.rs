@@ -0,0 +1,137 @@
+use chrono::{DateTime, Duration, FixedOffset};
+use crate::station::{ObservationRecord, MISSING};
+
+/// The tendency period, WMO's three hours.
+const PERIOD_MINUTES: i64 = 180;
+
+/// How far an earlier observation may be from the time wanted.
+const TOLERANCE_MINUTES: i64 = 30;
+
+/// Pressure changes smaller than this are steady, Pa (0.1 hPa).
+const STEADY_PA: f64 = 10.0;
+
+///  Gets how far back tendencies look, with the tolerance, so callers
+///      know what history to read.
+///
+/// # Arguments
+///
+/// None
+///
+/// # Return
+///
+/// The Duration
+pub fn lookback() -> Duration {
+    Duration::minutes(PERIOD_MINUTES + TOLERANCE_MINUTES)
+}
+
+///  Gets the WMO pressure tendency characteristic (code table 0200) from
+///      the pressure three hours ago, half way, and now:
+///      0 increasing then decreasing, same or higher;
+///      1 increasing then steady, or increasing more slowly;
+///      2 increasing; 3 decreasing or steady then increasing, or increasing
+///      more rapidly; 4 steady; 5 decreasing then increasing, same or lower;
+///      6 decreasing then steady, or decreasing more slowly; 7 decreasing;
+///      8 steady or increasing then decreasing, or decreasing more rapidly.
+///      Without the half way pressure only 2, 4 or 7 can be told.
+///
+/// # Arguments
+///
+///*'before'-the pressure three hours ago, Pa
+///*'middle'-the pressure half way, Pa, None if unknown
+///*'now'-the pressure now, Pa
+///
+/// # Return
+///
+/// The code, 0 to 8
+pub fn pressure_characteristic(before: f64, middle: Option<f64>, now: f64) -> u8 {
+    let total = now - before;
+    let steady = |d: f64| d.abs() < STEADY_PA;
+    let Some(middle) = middle else {
+        return if steady(total) { 4 } else if total > 0.0 { 2 } else { 7 };
+    };
+    let (first, second) = (middle - before, now - middle);
+
+    if steady(total) {
+        if first >= STEADY_PA && second <= -STEADY_PA {
+            0
+        } else if first <= -STEADY_PA && second >= STEADY_PA {
+            5
+        } else {
+            4
+        }
+    } else if total > 0.0 {
+        if second <= -STEADY_PA {
+            0
+        } else if steady(second) {
+            1
+        } else if first < STEADY_PA || second > first + STEADY_PA {
+            3
+        } else if second < first - STEADY_PA {
+            1
+        } else {
+            2
+        }
+    } else if second >= STEADY_PA {
+        5
+    } else if steady(second) {
+        6
+    } else if first > -STEADY_PA || second < first - STEADY_PA {
+        8
+    } else if second > first + STEADY_PA {
+        6
+    } else {
+        7
+    }
+}
+
+///  Gets a field of the observation closest to a time, within the
+///      tolerance, among those where the field isn't MISSING.
+fn closest(history: &[ObservationRecord], at: DateTime<FixedOffset>,
+           field: fn(&ObservationRecord) -> f64) -> Option<f64> {
+    history.iter()
+        .filter(|r| field(r) != MISSING)
+        .filter_map(|r| DateTime::parse_from_rfc3339(&r.timestamp_UTC).ok()
+            .map(|t| ((t - at).num_seconds().abs(), field(r))))
+        .filter(|(off, _)| *off <= TOLERANCE_MINUTES * 60)
+        .min_by_key(|(off, _)| *off)
+        .map(|(_, v)| v)
+}
+
+///  Fills in an observation's three-hour tendencies from the station's
+///      earlier observations: pres_tendency_3h_pa and pres_tendency_code,
+///      temperature_change_3h_C and dewpoint_change_3h_C. They stay
+///      MISSING without an observation 3 hours (± 30 minutes) earlier.
+///
+/// # Arguments
+///
+///*'rec'-the observation
+///*'history'-the station's earlier observations, e.g. the last lookback()
+///
+/// # Return
+///
+/// None
+pub fn apply(rec: &mut ObservationRecord, history: &[ObservationRecord]) {
+    rec.pres_tendency_3h_pa = MISSING;
+    rec.pres_tendency_code = MISSING;
+    rec.temperature_change_3h_C = MISSING;
+    rec.dewpoint_change_3h_C = MISSING;
+
+    let Ok(now) = DateTime::parse_from_rfc3339(&rec.timestamp_UTC) else { return };
+    let three_hours = now - Duration::minutes(PERIOD_MINUTES);
+    let change = |now: f64, field: fn(&ObservationRecord) -> f64| {
+        match closest(history, three_hours, field) {
+            Some(before) if now != MISSING => now - before,
+            _ => MISSING,
+        }
+    };
+
+    rec.temperature_change_3h_C = change(rec.temperature_C, |r| r.temperature_C);
+    rec.dewpoint_change_3h_C = change(rec.dewpoint_C, |r| r.dewpoint_C);
+    rec.pres_tendency_3h_pa = change(rec.baro_pres_pa, |r| r.baro_pres_pa);
+    if rec.pres_tendency_3h_pa != MISSING {
+        let middle = closest(history, now - Duration::minutes(PERIOD_MINUTES / 2),
+                             |r| r.baro_pres_pa);
+        rec.pres_tendency_code = pressure_characteristic(rec.baro_pres_pa
+            - rec.pres_tendency_3h_pa, middle, rec.baro_pres_pa) as f64;
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::task;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{info, warn, error, debug};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use crate::rules::RuleEvent;
+use crate::sink::ObservationSink;
+use crate::station::ObservationRecord;
+use crate::storage::BoxError;
+use crate::tabular;
+
+/// Header carrying the HMAC-SHA256 of the body, "sha256=<hex>".
+pub const SIGNATURE_HEADER: &str = "X-Weather-Gov-Signature";
+
+/// Header carrying the event name, e.g. "observation".
+pub const EVENT_HEADER: &str = "X-Weather-Gov-Event";
+
+/// Represents a webhook endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookEndpoint {
+    pub name:      String,
+    pub url:       String,
+    /// Signs bodies when set.
+    pub secret:    Option<String>,
+    /// Station ids to send, empty sends every station.
+    pub stations:  Vec<String>,
+}
+
+/// Implementation for WebhookEndpoint.
+impl WebhookEndpoint {
+
+    ///  Checks the endpoint's station filter.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the endpoint
+    ///*'station_id'-the station call id
+    ///
+    /// # Return
+    ///
+    /// true if the endpoint wants the station's events
+    pub fn accepts(&self, station_id: &str) -> bool {
+        self.stations.is_empty() || self.stations.iter().any(|s| s == station_id)
+    }
+}
+
+/// Webhook settings, read from webhook_section.
+///     Endpoints are keys <name>.URL, with optional <name>.SECRET and
+///     <name>.STATIONS (comma separated); the rest apply to every endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookConfig {
+    pub endpoints:    Vec<WebhookEndpoint>,
+    /// Retries after the first attempt.
+    pub retries:      u32,
+    /// Doubled on each retry.
+    pub retry_delay:  Duration,
+    pub timeout:      Duration,
+    /// Bodies that could not be delivered, as JSON lines.
+    pub dead_letter:  PathBuf,
+    /// Deliveries waiting per endpoint before new ones go to the dead letter file.
+    pub queue:        usize,
+}
+
+/// Implementation for WebhookConfig.
+impl WebhookConfig {
+
+    ///  Reads the webhook settings.
+    ///
+    /// # Arguments
+    ///
+    ///*'cfg'-the webhook_section
+    ///
+    /// # Return
+    ///
+    /// WebhookConfig, None if no endpoint has a URL
+    pub fn from_config(cfg: &HashMap<String, String>) -> Option<WebhookConfig> {
+        let get = |key: &str| cfg.get(key).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let number = |key: &str, default: u64| get(key).and_then(|v| v.parse().ok())
+            .unwrap_or(default);
+
+        // Sorted, so endpoints are in a stable order
+        let names: BTreeMap<&str, &str> = cfg.iter()
+            .filter_map(|(k, v)| k.strip_suffix(".URL").map(|n| (n, v.trim())))
+            .filter(|(_, url)| !url.is_empty())
+            .collect();
+        let endpoints: Vec<WebhookEndpoint> = names.into_iter().map(|(name, url)| {
+            WebhookEndpoint {
+                name:      name.to_string(),
+                url:       url.to_string(),
+                secret:    get(&format!("{}.SECRET", name)).map(|s| s.to_string()),
+                stations:  get(&format!("{}.STATIONS", name)).unwrap_or("")
+                               .split(',').map(|s| s.trim().to_string())
+                               .filter(|s| !s.is_empty()).collect(),
+            }
+        }).collect();
+        if endpoints.is_empty() {
+            return None;
+        }
+
+        Some(WebhookConfig {
+            endpoints,
+            retries:      number("RETRIES", 5) as u32,
+            retry_delay:  Duration::from_millis(number("RETRY_MS", 1000)),
+            timeout:      Duration::from_secs(number("TIMEOUT_SECS", 10)),
+            dead_letter:  PathBuf::from(get("DEAD_LETTER_FILE").unwrap_or("./weather_gov.dead_letter")),
+            queue:        number("QUEUE", 1000).max(1) as usize,
+        })
+    }
+}
+
+
+///  Signs a body with HMAC-SHA256.
+///
+/// # Arguments
+///
+///*'secret'-the endpoint secret
+///*'body'-the request body
+///
+/// # Return
+///
+/// "sha256=<lowercase hex>", the SIGNATURE_HEADER value
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC takes a key of any length");
+    mac.update(body.as_bytes());
+    let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+///  Builds the body for a new observation.
+///
+/// # Arguments
+///
+///*'rec'-the ObservationRecord
+///
+/// # Return
+///
+/// {"event": "observation", "station_id", "timestamp_UTC", "observation"},
+/// missing values are null
+pub fn observation_payload(rec: &ObservationRecord) -> Value {
+    json!({
+        "event": "observation",
+        "station_id": rec.station_id,
+        "timestamp_UTC": rec.timestamp_UTC,
+        "observation": tabular::to_row(rec),
+    })
+}
+
+///  Builds the body for a rule firing or clearing.
+///
+/// # Arguments
+///
+///*'event'-the RuleEvent
+///
+/// # Return
+///
+/// {"event": "rule", "rule", "station_id", "timestamp_UTC", "state", "field",
+/// "value", "description"}
+pub fn rule_payload(event: &RuleEvent) -> Value {
+    let mut payload = json!({"event": "rule"});
+    if let (Some(p), Value::Object(fields)) = (payload.as_object_mut(), json!(event)) {
+        p.extend(fields);
+    }
+    payload
+}
+
+
+/// Represents a queued delivery.
+struct Delivery {
+    event:  String,
+    body:   String,
+}
+
+/// Represents the webhook output.
+///     Each endpoint has its own queue and delivery task, so a slow or
+///     failing receiver holds up neither the poll loop nor other endpoints.
+///     Deliveries that fail every retry, or don't fit in the queue, are
+///     appended to the dead letter file.
+pub struct WebhookSink {
+    pub config:  WebhookConfig,
+    queues:      Vec<(WebhookEndpoint, Sender<Delivery>)>,
+    // Serializes dead letter appends from the delivery tasks
+    dead_lock:   Arc<Mutex<()>>,
+}
+
+/// Implementation for the webhook output.
+impl WebhookSink {
+
+    ///  Creates the sink and starts a delivery task per endpoint.
+    ///
+    /// # Arguments
+    ///
+    ///*'config'-the webhook settings
+    ///
+    /// # Return
+    ///
+    /// WebhookSink instance
+    pub fn start(config: WebhookConfig) -> WebhookSink {
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .unwrap_or_default();
+        let dead_lock = Arc::new(Mutex::new(()));
+        let queues = config.endpoints.iter().map(|endpoint| {
+            let (tx, rx) = channel::bounded(config.queue);
+            info!("Webhook {:?} posting to {}", endpoint.name, endpoint.url);
+            task::spawn(deliver_loop(endpoint.clone(), rx, http.clone(), config.clone(),
+                                     dead_lock.clone()));
+            (endpoint.clone(), tx)
+        }).collect();
+        WebhookSink { config, queues, dead_lock }
+    }
+
+    ///  Sends a newly stored observation to the endpoints that want its station.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///*'rec'-the ObservationRecord
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn notify_observation(&self, rec: &ObservationRecord) {
+        self.notify("observation", &rec.station_id, &observation_payload(rec));
+    }
+
+    ///  Sends a rule firing or clearing to the endpoints that want its station.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///*'event'-the RuleEvent
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn notify_rule_event(&self, event: &RuleEvent) {
+        self.notify("rule", &event.station_id, &rule_payload(event));
+    }
+
+    ///  Queues an event for the endpoints that want its station.
+    ///      Does not wait for delivery.
+    ///
+    /// # Arguments
+    ///
+    ///*'self'-the sink
+    ///*'event'-the event name, sent in EVENT_HEADER
+    ///*'station_id'-the station the event is about
+    ///*'payload'-the JSON body
+    ///
+    /// # Return
+    ///
+    /// None
+    pub fn notify(&self, event: &str, station_id: &str, payload: &Value) {
+        let body = payload.to_string();
+        for (endpoint, tx) in self.queues.iter().filter(|(e, _)| e.accepts(station_id)) {
+            let delivery = Delivery { event: event.to_string(), body: body.clone() };
+            match tx.try_send(delivery) {
+                Ok(()) => {},
+                Err(TrySendError::Full(d)) | Err(TrySendError::Closed(d)) => {
+                    warn!("Webhook {:?} queue is full", endpoint.name);
+                    dead_letter(&self.config.dead_letter, &self.dead_lock, endpoint, &d,
+                                0, "queue full");
+                },
+            }
+        }
+    }
+
+} // impl WebhookSink
+
+
+/// Implementation of ObservationSink for the webhook output.
+#[async_trait]
+impl ObservationSink for WebhookSink {
+
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.notify_observation(rec);
+        Ok(())
+    }
+
+    async fn on_rule_event(&self, event: &RuleEvent) -> Result<(), BoxError> {
+        self.notify_rule_event(event);
+        Ok(())
+    }
+
+} // impl ObservationSink for WebhookSink
+
+
+///  Delivers an endpoint's queue, in order, until the sink is dropped.
+async fn deliver_loop(endpoint: WebhookEndpoint, rx: Receiver<Delivery>, http: reqwest::Client,
+                      config: WebhookConfig, dead_lock: Arc<Mutex<()>>) {
+    while let Ok(delivery) = rx.recv().await {
+        let mut attempt: u32 = 0;
+        loop {
+            match post(&http, &endpoint, &delivery).await {
+                Ok(()) => {
+                    debug!("Webhook {:?} delivered {}", endpoint.name, delivery.event);
+                    break;
+                },
+                Err(e) if attempt < config.retries => {
+                    let delay = config.retry_delay * 2u32.saturating_pow(attempt);
+                    attempt += 1;
+                    warn!("Webhook {:?} failed (retry {} of {} in {:?}): {}",
+                          endpoint.name, attempt, config.retries, delay, e);
+                    task::sleep(delay).await;
+                },
+                Err(e) => {
+                    error!("Webhook {:?} failed after {} attempt(s), dead lettered: {}",
+                           endpoint.name, attempt + 1, e);
+                    dead_letter(&config.dead_letter, &dead_lock, &endpoint, &delivery,
+                                attempt + 1, &e);
+                    break;
+                },
+            }
+        }
+    }
+}
+
+///  POSTs one delivery.
+async fn post(http: &reqwest::Client, endpoint: &WebhookEndpoint, delivery: &Delivery)
+                                                                    -> Result<(), String> {
+    let mut req = http.post(&endpoint.url)
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .body(delivery.body.clone());
+    if let Some(secret) = &endpoint.secret {
+        req = req.header(SIGNATURE_HEADER, sign(secret, &delivery.body));
+    }
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("{} answered {}", endpoint.url, resp.status()))
+    }
+}
+
+///  Appends an undeliverable body to the dead letter file.
+fn dead_letter(path: &PathBuf, lock: &Mutex<()>, endpoint: &WebhookEndpoint,
+               delivery: &Delivery, attempts: u32, reason: &str) {
+    let line = json!({
+        "failed_at": Utc::now().to_rfc3339(),
+        "endpoint": endpoint.name,
+        "url": endpoint.url,
+        "event": delivery.event,
+        "attempts": attempts,
+        "error": reason,
+        "body": delivery.body,
+    });
+    let _guard = lock.lock().unwrap();
+    let written = OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut f| writeln!(f, "{}", line));
+    if let Err(e) = written {
+        error!("Could not write webhook dead letter {:?}: {:?}", path, e);
+    }
+}
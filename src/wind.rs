@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use crate::station::{ObservationRecord, MISSING};
+
+/// The 16 compass points, clockwise from north.
+pub const COMPASS_POINTS: [&str; 16] = ["N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE",
+    "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW"];
+
+/// wind_compass of a calm, Beaufort force 0.
+pub const CALM: &str = "CALM";
+
+/// wind_compass of wind without a direction, as METAR's VRB.
+pub const VARIABLE: &str = "VRB";
+
+/// Upper bounds of Beaufort forces 0 to 11, km/h (WMO); 12 is anything above.
+const BEAUFORT_KM_H: [f64; 12] = [1.0, 6.0, 12.0, 20.0, 29.0, 39.0, 50.0, 62.0, 75.0, 89.0,
+    103.0, 118.0];
+
+/// Beaufort force descriptions, by force.
+pub const BEAUFORT_DESCRIPTIONS: [&str; 13] = ["Calm", "Light air", "Light breeze",
+    "Gentle breeze", "Moderate breeze", "Fresh breeze", "Strong breeze", "Near gale", "Gale",
+    "Strong gale", "Storm", "Violent storm", "Hurricane force"];
+
+/// Lower bounds of Saffir-Simpson categories 1 to 5, km/h (NHC).
+const SAFFIR_SIMPSON_KM_H: [f64; 5] = [119.0, 154.0, 178.0, 209.0, 252.0];
+
+/// Default wind rose speed bin edges, km/h. Below the first is calm.
+pub const DEFAULT_SPEED_BINS: [f64; 6] = [1.0, 10.0, 20.0, 30.0, 40.0, 50.0];
+
+///  Gets the 16-point compass direction of a wind direction.
+///
+/// # Arguments
+///
+///*'degrees'-the direction the wind blows from, degrees from north
+///
+/// # Return
+///
+/// The compass point, e.g. "WSW"
+pub fn compass_point(degrees: f64) -> &'static str {
+    let sector = (degrees.rem_euclid(360.0) / 22.5 + 0.5).floor() as usize;
+    COMPASS_POINTS[sector % 16]
+}
+
+///  Gets the Beaufort force of a wind speed.
+///
+/// # Arguments
+///
+///*'km_h'-the wind speed, km/h
+///
+/// # Return
+///
+/// The force, 0 to 12
+pub fn beaufort(km_h: f64) -> u8 {
+    BEAUFORT_KM_H.iter().position(|limit| km_h < *limit).unwrap_or(12) as u8
+}
+
+///  Gets the Saffir-Simpson hurricane category of a sustained wind speed.
+///
+/// # Arguments
+///
+///*'km_h'-the wind speed, km/h
+///
+/// # Return
+///
+/// The category, 1 to 5, None below hurricane force
+pub fn saffir_simpson(km_h: f64) -> Option<u8> {
+    match SAFFIR_SIMPSON_KM_H.iter().filter(|bound| km_h >= **bound).count() {
+        0 => None,
+        category => Some(category as u8),
+    }
+}
+
+///  Gets the wind_compass of a direction and speed: CALM below Beaufort
+///      force 1, VRB when there is wind but no direction, else the compass
+///      point.
+///
+/// # Arguments
+///
+///*'degrees'-the direction, MISSING if none
+///*'km_h'-the speed, MISSING if none
+///
+/// # Return
+///
+/// The compass direction, empty without a speed
+pub fn compass(degrees: f64, km_h: f64) -> &'static str {
+    if km_h == MISSING {
+        ""
+    } else if beaufort(km_h) == 0 {
+        CALM
+    } else if degrees == MISSING {
+        VARIABLE
+    } else {
+        compass_point(degrees)
+    }
+}
+
+///  Fills in an observation's wind classification: wind_compass,
+///      wind_beaufort, wind_beaufort_desc and wind_saffir_simpson.
+///
+/// # Arguments
+///
+///*'rec'-the observation
+///
+/// # Return
+///
+/// None
+pub fn classify(rec: &mut ObservationRecord) {
+    let speed = rec.wind_spd_km_h;
+    rec.wind_compass = compass(rec.wind_dir, speed).to_string();
+    if speed == MISSING {
+        rec.wind_beaufort = MISSING;
+        rec.wind_beaufort_desc = String::new();
+        rec.wind_saffir_simpson = MISSING;
+    } else {
+        let force = beaufort(speed);
+        rec.wind_beaufort = force as f64;
+        rec.wind_beaufort_desc = BEAUFORT_DESCRIPTIONS[force as usize].to_string();
+        rec.wind_saffir_simpson = saffir_simpson(speed).map_or(MISSING, |c| c as f64);
+    }
+}
+
+
+/// Represents a wind rose, how often the wind blew from each compass
+///     point, by speed bin.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WindRose {
+    pub station_id:    String,
+    /// Observations with a wind speed.
+    pub observations:  u32,
+    pub calm:          u32,
+    pub variable:      u32,
+    /// Bin labels, e.g. "10-20" km/h, the last open ended, e.g. "50+".
+    pub speed_bins:    Vec<String>,
+    /// By COMPASS_POINTS order.
+    pub directions:    Vec<WindRoseDirection>,
+}
+
+/// Represents a wind rose petal.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WindRoseDirection {
+    pub direction:  String,
+    /// Observations per speed bin.
+    pub counts:     Vec<u32>,
+    /// Percent of all observations with a wind speed, per speed bin.
+    pub percent:    Vec<f64>,
+}
+
+///  Builds a wind rose of a station's observations. Observations are
+///      classified from wind_dir and wind_spd_km_h, so rows stored before
+///      the wind fields existed count too.
+///
+/// # Arguments
+///
+///*'station_id'-the station
+///*'recs'-the observations
+///*'edges'-ascending speed bin edges, km/h; below the first is calm
+///
+/// # Return
+///
+/// WindRose
+pub fn wind_rose(station_id: &str, recs: &[ObservationRecord], edges: &[f64]) -> WindRose {
+    let bins = edges.len().max(1);
+    let mut counts = vec![vec![0u32; bins]; COMPASS_POINTS.len()];
+    let mut rose = WindRose { station_id: station_id.to_string(), ..Default::default() };
+    for rec in recs.iter().filter(|r| r.wind_spd_km_h != MISSING) {
+        rose.observations += 1;
+        let speed = rec.wind_spd_km_h;
+        if edges.first().is_some_and(|calm| speed < *calm) {
+            rose.calm += 1;
+        } else if rec.wind_dir == MISSING {
+            rose.variable += 1;
+        } else {
+            let bin = edges.iter().rposition(|edge| speed >= *edge).unwrap_or(0);
+            let point = COMPASS_POINTS.iter()
+                .position(|p| *p == compass_point(rec.wind_dir)).unwrap_or(0);
+            counts[point][bin] += 1;
+        }
+    }
+
+    rose.speed_bins = (0..bins).map(|i| match (edges.get(i), edges.get(i + 1)) {
+        (Some(low), Some(high)) => format!("{}-{}", low, high),
+        (Some(low), None) => format!("{}+", low),
+        _ => "all".to_string(),
+    }).collect();
+    let total = rose.observations.max(1) as f64;
+    rose.directions = COMPASS_POINTS.iter().zip(counts).map(|(point, counts)| {
+        WindRoseDirection {
+            direction:  point.to_string(),
+            percent:    counts.iter().map(|c| *c as f64 * 100.0 / total).collect(),
+            counts,
+        }
+    }).collect();
+    rose
+}
@@ -0,0 +1,219 @@
+#![cfg(feature = "http-api")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde_json::Value;
+use common::rec;
+use weather_gov::api::{self, Api, Period};
+use weather_gov::memory::MemoryStore;
+use weather_gov::station::{StationRecord, ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+
+/// An api over a store with KPHX and KTUS, page size 2.
+async fn api() -> Api {
+    let store = MemoryStore::new();
+    for id in ["KPHX", "KTUS"] {
+        store.put_station_record(&StationRecord {
+            call_id: id.to_string(),
+            name:    format!("Station {}", id),
+            ..Default::default()
+        }).await.unwrap();
+    }
+    let recs = vec![
+        rec("KPHX", "2024-04-01T10:51:00+00:00", 20.0),
+        rec("KPHX", "2024-04-01T11:51:00+00:00", MISSING),
+        rec("KPHX", "2024-04-01T12:51:00+00:00", 24.0),
+        rec("KPHX", "2024-04-02T00:51:00+00:00", 18.0),
+        ObservationRecord {
+            description: "Clear, dry".to_string(),
+            ..rec("KTUS", "2024-04-01T12:51:00+00:00", 30.0)
+        },
+    ];
+    store.put_observation_batch(&recs).await.unwrap();
+
+    let mut cfg = HashMap::new();
+    cfg.insert("PAGE_SIZE".to_string(), "2".to_string());
+    Api::new(Arc::new(store), &cfg)
+}
+
+fn json(body: &str) -> Value {
+    serde_json::from_str(body).unwrap()
+}
+
+#[async_std::test]
+async fn lists_stations_and_latest() {
+    let api = api().await;
+
+    let resp = api.handle("GET", "/stations", None).await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.content_type, "application/json");
+    let body = json(&resp.body);
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["stations"][1]["call_id"], "KTUS");
+
+    let resp = api.handle("GET", "/stations/KPHX/latest", None).await;
+    assert_eq!(resp.status, 200);
+    let body = json(&resp.body);
+    assert_eq!(body["timestamp_UTC"], "2024-04-02T00:51:00+00:00");
+    assert_eq!(body["temperature_C"], 18.0);
+    // MISSING is served as null
+    assert_eq!(body["dewpoint_C"], Value::Null);
+
+    let resp = api.handle("GET", "/stations/KXYZ/latest", None).await;
+    assert_eq!(resp.status, 404);
+    assert_eq!(resp.content_type, "application/problem+json");
+    assert_eq!(json(&resp.body)["status"], 404);
+}
+
+#[async_std::test]
+async fn pages_observations_in_a_range() {
+    let api = api().await;
+
+    let resp = api.handle("GET", "/stations/KPHX/observations?from=2024-04-01&to=2024-04-02", None)
+        .await;
+    assert_eq!(resp.status, 200);
+    let body = json(&resp.body);
+    assert_eq!(body["station_id"], "KPHX");
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["observations"][0]["timestamp_UTC"], "2024-04-01T10:51:00+00:00");
+    let next = body["next"].as_str().unwrap().to_string();
+    assert!(next.contains("offset=2"), "{}", next);
+    assert!(resp.headers.iter().any(|(k, v)| k == "Link" && v.contains(&next)));
+
+    let resp = api.handle("GET", &next, None).await;
+    let body = json(&resp.body);
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["observations"][0]["timestamp_UTC"], "2024-04-01T12:51:00+00:00");
+    assert_eq!(body["next"], Value::Null);
+
+    // An offset timestamp is normalized to UTC
+    let resp = api.handle("GET",
+        "/stations/KPHX/observations?from=2024-04-01T05:00:00-07:00&limit=10", None).await;
+    assert_eq!(json(&resp.body)["count"], 2);
+
+    let resp = api.handle("GET", "/stations/KPHX/observations?from=yesterday", None).await;
+    assert_eq!(resp.status, 400);
+    let resp = api.handle("GET", "/stations/KPHX/observations?limit=5000", None).await;
+    assert_eq!(resp.status, 400);
+}
+
+#[async_std::test]
+async fn selects_fields_and_negotiates_csv() {
+    let api = api().await;
+
+    let resp = api.handle("GET", "/stations/KPHX/observations?fields=temperature_C&limit=3",
+                          Some("text/csv")).await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.content_type, "text/csv; charset=utf-8");
+    let lines: Vec<&str> = resp.body.lines().collect();
+    assert_eq!(lines, vec![
+        "station_id,timestamp_UTC,temperature_C",
+        "KPHX,2024-04-01T10:51:00+00:00,20.0",
+        "KPHX,2024-04-01T11:51:00+00:00,",
+        "KPHX,2024-04-01T12:51:00+00:00,24.0",
+    ]);
+
+    // The whole record, in struct field order, with the comma quoted
+    let resp = api.handle("GET", "/stations/KTUS/latest?format=csv", None).await;
+    let lines: Vec<&str> = resp.body.lines().collect();
+    assert!(lines[0].starts_with("station_id,timestamp_UTC,temperature_C,temperature_F,"));
+    assert!(lines[1].contains(",\"Clear, dry\","), "{}", lines[1]);
+
+    let resp = api.handle("GET", "/stations/KPHX/observations?fields=bogus", None).await;
+    assert_eq!(resp.status, 400);
+}
+
+#[async_std::test]
+async fn summarizes_by_day() {
+    let api = api().await;
+
+    let resp = api.handle("GET", "/stations/KPHX/summary?period=day&from=2024-04-01", None).await;
+    assert_eq!(resp.status, 200);
+    let body = json(&resp.body);
+    assert_eq!(body["time_zone"], "UTC");
+    let day = &body["summaries"][0];
+    assert_eq!(day["period"], "2024-04-01");
+    assert_eq!(day["observations"], 3);
+    assert_eq!(day["temperature_C"]["min"], 20.0);
+    assert_eq!(day["temperature_C"]["max"], 24.0);
+    assert_eq!(day["temperature_C"]["mean"], 22.0);
+    assert_eq!(day["temperature_C"]["count"], 2);
+    assert_eq!(day["dewpoint_C"]["count"], 0);
+    assert_eq!(body["summaries"][1]["period"], "2024-04-02");
+
+    let resp = api.handle("GET", "/stations/KPHX/summary?format=csv&from=2024-04-01", None).await;
+    let lines: Vec<&str> = resp.body.lines().collect();
+    assert!(lines[0].starts_with("period,observations,temperature_C_min,temperature_C_max,"));
+    assert!(lines[1].starts_with("2024-04-01,3,20.0,24.0,22.0,2,"), "{}", lines[1]);
+
+    let resp = api.handle("GET", "/stations/KPHX/summary?period=week&from=2024-04-01", None).await;
+    assert_eq!(resp.status, 400);
+}
+
+#[async_std::test]
+async fn summary_ranges_are_bounded() {
+    let resp = api().await.handle("GET", "/stations/KPHX/summary", None).await;
+    assert_eq!(resp.status, 400);
+    assert!(json(&resp.body)["detail"].as_str().unwrap().contains("from is required"));
+
+    // Page size 2, so the range is read in pages until it passes the cap
+    let store = MemoryStore::new();
+    let recs: Vec<ObservationRecord> = (10..15)
+        .map(|h| rec("KPHX", &format!("2024-04-01T{}:51:00+00:00", h), 20.0))
+        .collect();
+    store.put_observation_batch(&recs).await.unwrap();
+    let cfg: HashMap<String, String> = [("PAGE_SIZE", "2"), ("MAX_PAGE_SIZE", "2"),
+        ("MAX_RANGE_ROWS", "4")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let api = Api::new(Arc::new(store), &cfg);
+    let resp = api.handle("GET", "/stations/KPHX/summary?from=2024-04-01", None).await;
+    assert_eq!(resp.status, 400);
+    let resp = api.handle("GET", "/stations/KPHX/summary?from=2024-04-01T11:00:00Z", None).await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(json(&resp.body)["summaries"][0]["observations"], 4);
+}
+
+#[test]
+fn summary_periods_group_by_timestamp_prefix() {
+    let recs = vec![
+        rec("KPHX", "2024-04-01T10:51:00+00:00", 20.0),
+        rec("KPHX", "2024-04-01T10:55:00+00:00", 22.0),
+        rec("KPHX", "2024-05-01T10:51:00+00:00", 10.0),
+    ];
+    let hours = api::summarize(&recs, Period::Hour);
+    assert_eq!(hours.len(), 2);
+    assert_eq!(hours[0]["period"], "2024-04-01T10");
+    assert_eq!(hours[0]["temperature_C"]["mean"], 21.0);
+    let months = api::summarize(&recs, Period::Month);
+    assert_eq!(months[1]["period"], "2024-05");
+
+    assert_eq!(api::parse_timestamp("2024-04-01T12:00:00Z").unwrap(),
+               "2024-04-01T12:00:00+00:00");
+    // '+' arrives as a space from an unescaped query string
+    assert_eq!(api::parse_timestamp("2024-04-01T05:00:00 07:00").unwrap(),
+               "2024-03-31T22:00:00+00:00");
+    assert_eq!(api::parse_timestamp("April 1st"), None);
+}
+
+#[test]
+fn serves_over_http() {
+    let store = Arc::new(MemoryStore::new());
+    async_std::task::block_on(store.put_observation_record(
+        &rec("KPHX", "2024-04-01T10:51:00+00:00", 20.0)));
+    let api = Arc::new(Api::new(store, &HashMap::new()));
+    let addr = api::spawn(api, "127.0.0.1:0").unwrap();
+
+    let client = reqwest::blocking::Client::new();
+    let resp = client.get(format!("http://{}/stations/KPHX/latest", addr)).send().unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(json(&resp.text().unwrap())["temperature_C"], 20.0);
+
+    let resp = client.get(format!("http://{}/stations/KPHX/observations", addr))
+        .header("Accept", "text/csv").send().unwrap();
+    assert_eq!(resp.headers()["content-type"], "text/csv; charset=utf-8");
+    assert_eq!(resp.text().unwrap().lines().count(), 2);
+
+    let resp = client.post(format!("http://{}/stations", addr)).send().unwrap();
+    assert_eq!(resp.status().as_u16(), 405);
+}
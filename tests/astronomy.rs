@@ -0,0 +1,93 @@
+mod common;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use common::fixture;
+use weather_gov::astronomy::*;
+use weather_gov::models::{decode, ObservationFeature, StationFeature};
+use weather_gov::station::{Station, MISSING};
+
+const PHX: (f64, f64) = (33.4278, -111.9552);
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn near(t: Option<DateTime<Utc>>, expected: &str, minutes: i64) -> bool {
+    let expected: DateTime<Utc> = expected.parse().unwrap();
+    t.is_some_and(|t| (t - expected).num_seconds().abs() <= minutes * 60)
+}
+
+#[test]
+fn computes_published_sun_times() {
+    // USNO, Phoenix winter solstice: sunrise 07:28, noon 12:26, sunset 17:24 MST
+    let t = sun_times(PHX.0, PHX.1, 0.0, date(2024, 12, 21));
+    assert!(near(t.sunrise, "2024-12-21T14:28:00Z", 1), "{:?}", t.sunrise);
+    assert!(near(Some(t.solar_noon), "2024-12-21T19:26:00Z", 1), "{:?}", t.solar_noon);
+    assert!(near(t.sunset, "2024-12-22T00:24:00Z", 1), "{:?}", t.sunset);
+    assert!(near(t.civil_dawn, "2024-12-21T14:01:00Z", 1), "{:?}", t.civil_dawn);
+    assert!((t.day_length_secs - (9 * 3600 + 56 * 60)).abs() <= 120, "{}", t.day_length_secs);
+    assert!((t.noon_elevation_deg - 33.1).abs() < 0.2, "{}", t.noon_elevation_deg);
+
+    // Twilights come in order around the day
+    let order = [t.astronomical_dawn, t.nautical_dawn, t.civil_dawn, t.sunrise,
+                 Some(t.solar_noon), t.sunset, t.civil_dusk, t.nautical_dusk,
+                 t.astronomical_dusk];
+    assert!(order.windows(2).all(|w| w[0] < w[1]), "{:?}", order);
+}
+
+#[test]
+fn polar_days_have_no_sunset() {
+    // Longyearbyen at midsummer and midwinter
+    let summer = sun_times(78.22, 15.65, 0.0, date(2024, 6, 21));
+    assert_eq!((summer.sunrise, summer.sunset, summer.civil_dusk), (None, None, None));
+    assert_eq!(summer.day_length_secs, 86_400);
+
+    let winter = sun_times(78.22, 15.65, 0.0, date(2024, 12, 21));
+    assert_eq!((winter.sunrise, winter.sunset), (None, None));
+    assert_eq!(winter.day_length_secs, 0);
+    assert!(winter.noon_elevation_deg < 0.0);
+}
+
+#[test]
+fn computes_solar_position() {
+    // Phoenix mid afternoon, the sun high in the south west
+    let p = solar_position(PHX.0, PHX.1, "2024-04-12T21:51:00Z".parse().unwrap());
+    assert!((p.elevation_deg - 49.2).abs() < 0.3, "{:?}", p);
+    assert!((p.azimuth_deg - 241.7).abs() < 0.5, "{:?}", p);
+
+    // Local midnight, well below the horizon to the north
+    let p = solar_position(PHX.0, PHX.1, "2024-04-12T07:28:00Z".parse().unwrap());
+    assert!(p.elevation_deg < -40.0 && (p.azimuth_deg < 30.0 || p.azimuth_deg > 330.0),
+            "{:?}", p);
+}
+
+#[test]
+fn observations_are_tagged_day_or_night() {
+    let st: StationFeature = decode("test", &fixture("station_kphx.json")).unwrap();
+    let obs: ObservationFeature = decode("test", &fixture("observation_kphx_latest.json")).unwrap();
+    let mut station = Station::new("KPHX".to_string(), "http://localhost/stations/".to_string());
+
+    // Without a position nothing is tagged
+    let rec = station.preprocess_observation(&obs.properties);
+    assert_eq!((rec.sun_elevation_deg, rec.day_night.as_str()), (MISSING, ""));
+
+    station.set_station_data(&st);
+    let rec = station.preprocess_observation(&obs.properties);
+    assert!((rec.sun_elevation_deg - 49.2).abs() < 0.3, "{}", rec.sun_elevation_deg);
+    assert_eq!(rec.day_night, "day");
+
+    let mut night = rec.clone();
+    night.timestamp_UTC = "2024-04-13T05:00:00+00:00".to_string();
+    tag(&mut night, station.latitude, station.longitude);
+    assert_eq!(night.day_night, "night");
+    assert!(night.sun_elevation_deg < 0.0);
+
+    // Day until the top of the sun sets, 17:24 MST at the winter solstice
+    let mut dusk = rec.clone();
+    dusk.timestamp_UTC = "2024-12-22T00:22:00+00:00".to_string();
+    tag(&mut dusk, PHX.0, PHX.1);
+    assert_eq!(dusk.day_night, "day", "{}", dusk.sun_elevation_deg);
+    dusk.timestamp_UTC = "2024-12-22T00:26:00+00:00".to_string();
+    tag(&mut dusk, PHX.0, PHX.1);
+    assert_eq!(dusk.day_night, "night", "{}", dusk.sun_elevation_deg);
+}
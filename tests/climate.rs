@@ -0,0 +1,136 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::NaiveDate;
+use common::{config, fixture, rec, MockServer};
+use weather_gov::climate::{self, Summarizer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+
+/// A Phoenix record with a gust and an hour's precipitation.
+fn kphx(timestamp: &str, temperature: f64, gust: f64, precip: f64) -> ObservationRecord {
+    ObservationRecord {
+        wind_gust_km_h:       gust,
+        precip_last_hour_mm:  precip,
+        ..rec("KPHX", timestamp, temperature)
+    }
+}
+
+fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn local_days_follow_the_time_zone() {
+    let phoenix = climate::time_zone("America/Phoenix");
+    assert_eq!(climate::day_bounds(phoenix, date("2024-04-12")),
+               ("2024-04-12T07:00:00+00:00".to_string(), "2024-04-13T07:00:00+00:00".to_string()));
+    assert_eq!(climate::local_date(phoenix, "2024-04-13T06:51:00+00:00"), Some(date("2024-04-12")));
+
+    // A 23 hour day, and a day starting in a daylight saving gap
+    let new_york = climate::time_zone("America/New_York");
+    assert_eq!(climate::day_bounds(new_york, date("2024-03-10")),
+               ("2024-03-10T05:00:00+00:00".to_string(), "2024-03-11T04:00:00+00:00".to_string()));
+    let havana = climate::time_zone("America/Havana");
+    assert_eq!(climate::day_bounds(havana, date("2024-03-10")).0, "2024-03-10T05:00:00+00:00");
+    assert_eq!(climate::time_zone(""), climate::time_zone("UTC"));
+}
+
+#[test]
+fn summarizes_a_day_and_a_month() {
+    let recs = [
+        kphx("2024-04-12T08:51:00+00:00", 20.0, MISSING, 1.0),
+        kphx("2024-04-12T09:10:00+00:00", 25.0, 40.0, 0.5),
+        kphx("2024-04-12T09:51:00+00:00", MISSING, 30.0, 2.0),
+        kphx("2024-04-12T10:51:00+00:00", 21.0, MISSING, MISSING),
+    ];
+    let day = climate::daily_summary("KPHX", "America/Phoenix", date("2024-04-12"), &recs);
+    assert_eq!((day.date.as_str(), day.observations, day.hours, day.temperature_count),
+               ("2024-04-12", 4, 3, 3));
+    assert_eq!((day.temperature_C_max, day.temperature_C_min, day.temperature_C_mean),
+               (25.0, 20.0, 22.0));
+    assert_eq!((day.wind_gust_km_h_max, day.dewpoint_C_mean), (40.0, MISSING));
+    // The 09 hour's largest report counts once
+    assert_eq!((day.precip_mm, day.precip_hours), (3.0, 2));
+    assert_eq!((day.first_UTC.as_str(), day.last_UTC.as_str()),
+               ("2024-04-12T08:51:00+00:00", "2024-04-12T10:51:00+00:00"));
+
+    let dry = climate::daily_summary("KPHX", "America/Phoenix", date("2024-04-13"),
+                                     &[kphx("2024-04-13T08:51:00+00:00", 30.0, MISSING, MISSING)]);
+    let month = climate::monthly_summary("KPHX", "America/Phoenix", "2024-04", &[day, dry]);
+    assert_eq!((month.days, month.observations, month.precip_mm, month.precip_days),
+               (2, 5, 3.0, 1));
+    assert_eq!((month.temperature_C_max, month.temperature_C_min), (30.0, 20.0));
+    assert_eq!((month.temperature_C_mean, month.temperature_C_mean_max), (26.0, 27.5));
+}
+
+#[async_std::test]
+async fn late_observations_update_their_day() {
+    let store = MemoryStore::new();
+    let zones = HashMap::from([("KPHX".to_string(), "America/Phoenix".to_string())]);
+    let mut job = Summarizer::new(Duration::from_secs(300));
+    let now = Instant::now();
+    assert!(!job.due(now));
+
+    let first = [kphx("2024-04-12T20:51:00+00:00", 30.0, MISSING, 0.0),
+                 kphx("2024-04-13T20:51:00+00:00", 32.0, MISSING, 0.0)];
+    store.put_observation_batch(&first).await.unwrap();
+    for r in &first {
+        job.mark(&r.station_id, &r.timestamp_UTC);
+    }
+    assert!(job.due(now));
+    assert_eq!(job.run(&store, &zones, now).await.unwrap(), (2, 1));
+    assert!(!job.due(now));
+
+    // 2024-04-13T03:51Z is still the 12th in Phoenix
+    let late = kphx("2024-04-13T03:51:00+00:00", 35.0, MISSING, 1.5);
+    store.put_observation_record(&late).await;
+    job.mark(&late.station_id, &late.timestamp_UTC);
+    assert!(!job.due(now));
+    let later = now + Duration::from_secs(300);
+    assert_eq!(job.run(&store, &zones, later).await.unwrap(), (1, 1));
+
+    let days = store.daily_summaries("KPHX", "2024-04-01", "2024-05-01").await.unwrap();
+    assert_eq!(days.len(), 2);
+    assert_eq!((days[0].observations, days[0].temperature_C_max, days[0].precip_mm),
+               (2, 35.0, 1.5));
+    assert_eq!(days[0].time_zone, "America/Phoenix");
+    let months = store.monthly_summaries("KPHX", "2024-04", "2024-05").await.unwrap();
+    assert_eq!((months[0].days, months[0].observations, months[0].temperature_C_max),
+               (2, 3, 35.0));
+}
+
+#[async_std::test]
+async fn collector_summarizes_new_and_drained_observations() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX"], "climate_collector.spool");
+    let store = Arc::new(MemoryStore::new());
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, store.clone(), spool.clone());
+    c.init_stations().await;
+    c.poll_once().await;
+
+    let day = &store.daily_summaries("KPHX", "2024-04-12", "2024-04-13").await.unwrap()[0];
+    assert_eq!((day.time_zone.as_str(), day.observations, day.temperature_C_max),
+               ("America/Phoenix", 1, 31.1));
+    assert_eq!((day.precip_mm, day.precip_hours), (0.0, 1));
+
+    // A spooled observation from the day before, drained by the background task
+    spool.push(&kphx("2024-04-11T21:51:00+00:00", 28.0, MISSING, MISSING)).unwrap();
+    assert_eq!(spool.drain(store.as_ref()).await, 1);
+    c.summaries.interval = Duration::ZERO;
+    c.poll_once().await;
+    let days = store.daily_summaries("KPHX", "2024-04-01", "2024-05-01").await.unwrap();
+    assert_eq!(days.len(), 2);
+    assert_eq!(days[0].temperature_C_max, 28.0);
+    let month = &store.monthly_summaries("KPHX", "2024-04", "2024-05").await.unwrap()[0];
+    assert_eq!((month.days, month.observations), (2, 2));
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use async_trait::async_trait;
+use common::{config, fixture, temp_path, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::metrics;
+use weather_gov::sink::ObservationSink;
+use weather_gov::spool::Spool;
+use weather_gov::station::{StationRecord, ObservationRecord, MISSING};
+use weather_gov::storage::{BoxError, Storage, StorageError, InsertOutcome, BatchOutcome,
+                           InsertCounters};
+
+/// A MemoryStore that can be taken down, like a db restart.
+#[derive(Default)]
+struct FlakyStore {
+    inner:  MemoryStore,
+    down:   AtomicBool,
+}
+
+impl FlakyStore {
+    fn outage() -> StorageError {
+        StorageError::transient(std::io::Error::other("connection refused"))
+    }
+}
+
+#[async_trait]
+impl Storage for FlakyStore {
+    async fn create_tables(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn put_station_record(&self, rec: &StationRecord) -> Result<(), StorageError> {
+        self.inner.put_station_record(rec).await
+    }
+
+    async fn put_observation_record(&self, rec: &ObservationRecord) -> InsertOutcome {
+        if self.down.load(Ordering::SeqCst) {
+            return InsertOutcome::Failed(FlakyStore::outage());
+        }
+        self.inner.put_observation_record(rec).await
+    }
+
+    async fn put_observation_batch(&self, recs: &[ObservationRecord])
+                                                -> Result<BatchOutcome, StorageError> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(FlakyStore::outage());
+        }
+        self.inner.put_observation_batch(recs).await
+    }
+
+    fn counters(&self) -> &InsertCounters {
+        self.inner.counters()
+    }
+}
+
+/// A sink that keeps the observations published to it.
+#[derive(Default)]
+struct Published(Mutex<Vec<String>>);
+
+#[async_trait]
+impl ObservationSink for Published {
+    fn name(&self) -> &str {
+        "published"
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.0.lock().unwrap().push(format!("{} {}", rec.station_id, rec.timestamp_UTC));
+        Ok(())
+    }
+}
+
+fn serve_kphx(server: &MockServer) {
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+}
+
+fn collector(server: &MockServer, stations: &[&str], storage: Arc<dyn Storage>,
+             spool_name: &str) -> Collector {
+    let cfg = config(server, stations, spool_name);
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    Collector::new(&cfg, storage, spool)
+}
+
+#[async_std::test]
+async fn fetch_parse_store() {
+    let server = MockServer::start();
+    serve_kphx(&server);
+    let store = Arc::new(MemoryStore::new());
+    let mut c = collector(&server, &["KPHX"], store.clone(), "fetch_parse_store.spool");
+
+    c.init_stations().await;
+    let stations = store.station_records();
+    assert_eq!(stations.len(), 1);
+    assert_eq!(stations[0].name, "Phoenix, Phoenix Sky Harbor International Airport");
+    assert_eq!(stations[0].latitude_deg, 33.4278);
+
+    let cycle = c.poll_once().await;
+    assert_eq!(cycle.len(), 1);
+    let stored = store.observation_records();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].timestamp_UTC, "2024-04-12T21:51:00+00:00");
+    assert_eq!(stored[0].temperature_C, 31.1);
+
+    // The same latest observation again is a duplicate, not a second row
+    c.poll_once().await;
+    assert_eq!(store.observation_records().len(), 1);
+    assert_eq!(store.counters().totals(), (1, 1, 0));
+}
+
+#[async_std::test]
+async fn nulls_are_stored_as_missing() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200, &fixture("observation_nulls.json"));
+    let store = Arc::new(MemoryStore::new());
+    let mut c = collector(&server, &["KPHX"], store.clone(), "nulls.spool");
+
+    c.init_stations().await;
+    c.poll_once().await;
+    let stored = store.observation_records();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].temperature_C, MISSING);
+    assert_eq!(stored[0].wind_dir, MISSING);
+
+    // Fields a record is built without are missing too, not readings of 0
+    let built = ObservationRecord { temperature_C: 20.0, ..Default::default() };
+    assert_eq!((built.dewpoint_C, built.baro_pres_pa, built.sun_elevation_deg),
+               (MISSING, MISSING, MISSING));
+}
+
+#[async_std::test]
+async fn unknown_station_is_dropped() {
+    let server = MockServer::start();
+    serve_kphx(&server);
+    let store = Arc::new(MemoryStore::new());
+    let mut c = collector(&server, &["KPHX", "KNOPE"], store.clone(), "unknown.spool");
+
+    c.init_stations().await;
+    assert_eq!(c.stations.len(), 1);
+    assert_eq!(c.stations[0].station_identifier, "KPHX");
+    assert_eq!(server.hits("/stations/KNOPE"), 1);
+}
+
+#[async_std::test]
+async fn observation_errors_retry_back_off_or_disable() {
+    let server = MockServer::start();
+    serve_kphx(&server);
+    for id in ["KSLO", "KGON", "KBAD"] {
+        server.route(&format!("/stations/{}", id), 200,
+                     &fixture("station_kphx.json").replace("KPHX", id));
+    }
+    server.route("/stations/KSLO/observations/latest", 500, &fixture("problem_500.json"));
+    server.route_with_headers("/stations/KGON/observations/latest", 429,
+                              &[("Retry-After", "3600")], "");
+    server.route("/stations/KBAD/observations/latest", 200,
+                 &fixture("observation_missing_wind_gust.json"));
+    let store = Arc::new(MemoryStore::new());
+    let mut c = collector(&server, &["KPHX", "KSLO", "KGON", "KBAD"], store.clone(),
+                          "errors.spool");
+
+    c.init_stations().await;
+    assert_eq!(c.stations.len(), 4);
+    c.poll_once().await;
+    c.poll_once().await;
+
+    // 500 and bad json are retried every poll, 429 backs off for the hour
+    assert_eq!(server.hits("/stations/KSLO/observations/latest"), 2);
+    assert_eq!(server.hits("/stations/KBAD/observations/latest"), 2);
+    assert_eq!(server.hits("/stations/KGON/observations/latest"), 1);
+    assert_eq!(store.observation_records().len(), 1);
+    assert_eq!(c.stations.len(), 4);
+
+    // A station without a recent observation backs off but is still polled
+    let server404 = MockServer::start();
+    server404.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server404.route("/stations/KPHX/observations/latest", 404, &fixture("problem_404.json"));
+    let mut c = collector(&server404, &["KPHX"], store.clone(), "errors404.spool");
+    c.init_stations().await;
+    c.poll_once().await;
+    c.poll_once().await;
+    assert_eq!(server404.hits("/stations/KPHX/observations/latest"), 1);
+    assert_eq!(c.stations.len(), 1);
+    assert_eq!(c.metrics.counter(metrics::POLL_FAILURES,
+                                 &[("station", "KPHX"), ("action", "back_off")]), 1);
+}
+
+#[async_std::test]
+async fn outage_spools_then_drains() {
+    let server = MockServer::start();
+    serve_kphx(&server);
+    let store = Arc::new(FlakyStore::default());
+    let mut c = collector(&server, &["KPHX"], store.clone(), "outage.spool");
+    let published = Arc::new(Published::default());
+    c.sinks.add(published.clone());
+    c.init_stations().await;
+
+    store.down.store(true, Ordering::SeqCst);
+    c.poll_once().await;
+    assert_eq!(c.spool.status().unwrap().records, 1);
+    assert!(store.inner.observation_records().is_empty());
+
+    // Still down, nothing is lost, and the repeated latest observation isn't spooled again
+    assert_eq!(c.spool.drain(store.as_ref()).await, 0);
+    c.poll_once().await;
+    assert_eq!(c.spool.status().unwrap().records, 1);
+
+    store.down.store(false, Ordering::SeqCst);
+    assert_eq!(c.spool.drain(store.as_ref()).await, 1);
+    assert_eq!(c.spool.status().unwrap().records, 0);
+    assert_eq!(store.inner.observation_records().len(), 1);
+
+    // Published once stored, by the drain, not before
+    c.sinks.flush().await;
+    assert!(published.0.lock().unwrap().is_empty());
+    c.poll_once().await;
+    assert_eq!(*published.0.lock().unwrap(), ["KPHX 2024-04-12T21:51:00+00:00"]);
+}
+
+#[async_std::test]
+async fn spool_moves_a_cursor_instead_of_rewriting() {
+    let path = temp_path("cursor.spool");
+    let cursor = path.with_extension("spool.offset");
+    let _ = std::fs::remove_file(&cursor);
+    let cfg = [("SPOOL_FILE".to_string(), path.to_string_lossy().to_string())].into();
+    let spool = Spool::new(&cfg);
+    for minute in 0..150 {
+        let rec = ObservationRecord {
+            station_id:     "KPHX".to_string(),
+            timestamp_UTC:  format!("2024-04-12T{:02}:{:02}:00+00:00", minute / 60, minute % 60),
+            ..Default::default()
+        };
+        assert!(spool.push(&rec).unwrap());
+    }
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    // The drained head stays in the file, the cursor skips it
+    spool.pop_front(100).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    assert!(cursor.exists());
+    let status = spool.status().unwrap();
+    assert_eq!(status.records, 50);
+    assert!(status.bytes < size / 2);
+    // Counted from the file after a restart
+    assert_eq!(Spool::new(&cfg).status().unwrap().records, 50);
+    let head = spool.peek(1).unwrap();
+    assert_eq!(head[0].as_ref().unwrap().timestamp_UTC, "2024-04-12T01:40:00+00:00");
+
+    // Drained, the spool and its cursor are removed
+    let store = MemoryStore::new();
+    assert_eq!(spool.drain(&store).await, 50);
+    assert!(!path.exists() && !cursor.exists());
+    assert_eq!(store.observation_records().len(), 50);
+
+    // A new spool starts at its beginning
+    let rec = store.observation_records()[0].clone();
+    assert!(spool.push(&rec).unwrap());
+    assert_eq!(spool.status().unwrap().records, 1);
+
+    // Past a megabyte of drained head the rest is compacted
+    let mut records = 1;
+    while std::fs::metadata(&path).unwrap().len() < 1_100_000 {
+        assert!(spool.push(&rec).unwrap());
+        records += 1;
+    }
+    spool.pop_front(records - 1).unwrap();
+    assert!(!cursor.exists());
+    assert!(std::fs::metadata(&path).unwrap().len() < 10_000);
+    assert_eq!(spool.status().unwrap().records, 1);
+}
@@ -0,0 +1,223 @@
+//! Shared helpers for the offline tests: fixtures, observation records, a
+//! mock api.weather.gov server and a config pointing at it.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use weather_gov::config::Config;
+use weather_gov::station::ObservationRecord;
+
+/// Reads a fixture from tests/fixtures.
+pub fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("fixture {:?}: {}", path, e))
+}
+
+/// An observation of a station at a time with a temperature, °C, everything
+/// else MISSING. Set other fields with struct update syntax, e.g.
+/// `ObservationRecord { baro_pres_pa: 101000.0, ..rec("KPHX", t, 20.0) }`.
+pub fn rec(station: &str, timestamp: &str, temperature: f64) -> ObservationRecord {
+    ObservationRecord {
+        station_id:     station.to_string(),
+        timestamp_UTC:  timestamp.to_string(),
+        temperature_C:  temperature,
+        ..Default::default()
+    }
+}
+
+/// A unique path under the system temp dir, removed if it already exists.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("weather_gov_{}_{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
+
+/// A canned response.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status:   u16,
+    pub headers:  Vec<(String, String)>,
+    pub body:     String,
+}
+
+/// A request the mock server received.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method:   String,
+    pub path:     String,
+    pub headers:  Vec<(String, String)>,
+    pub body:     String,
+}
+
+impl MockRequest {
+    /// A header's value, by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+/// A mock api.weather.gov on 127.0.0.1.
+///     Each path answers with its queued responses in order, repeating the last one.
+///     Unknown paths answer 404 with a problem detail body.
+pub struct MockServer {
+    pub base_url:  String,
+    routes:        Arc<Mutex<HashMap<String, Vec<MockResponse>>>>,
+    requests:      Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+
+    /// Starts the server on an ephemeral port.
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(Mutex::new(HashMap::<String, Vec<MockResponse>>::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let (r, q) = (routes.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(s) => serve(s, &r, &q),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        MockServer { base_url, routes, requests }
+    }
+
+    /// The stations url, as STATIONS_URL.
+    pub fn stations_url(&self) -> String {
+        format!("{}/stations/", self.base_url)
+    }
+
+    /// Queues a response for a path.
+    pub fn route(&self, path: &str, status: u16, body: &str) {
+        self.route_with_headers(path, status, &[], body);
+    }
+
+    /// Queues a response with extra headers for a path.
+    pub fn route_with_headers(&self, path: &str, status: u16, headers: &[(&str, &str)],
+                              body: &str) {
+        let resp = MockResponse {
+            status,
+            headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: body.to_string(),
+        };
+        self.routes.lock().unwrap().entry(path.to_string()).or_default().push(resp);
+    }
+
+    /// The paths requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().iter().map(|r| r.path.clone()).collect()
+    }
+
+    /// The requests received so far, with headers and bodies, in order.
+    pub fn received(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// How many times a path was requested.
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests().iter().filter(|p| p.as_str() == path).count()
+    }
+}
+
+fn serve(mut stream: TcpStream, routes: &Mutex<HashMap<String, Vec<MockResponse>>>,
+         requests: &Mutex<Vec<MockRequest>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) if line == "\r\n" => break,
+            Ok(_) => {
+                if let Some((k, v)) = line.trim_end().split_once(':') {
+                    headers.push((k.trim().to_string(), v.trim().to_string()));
+                }
+            },
+            Err(_) => return,
+        }
+    }
+    let length = headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok()).unwrap_or(0);
+    let mut body = vec![0u8; length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("GET").to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+    requests.lock().unwrap().push(MockRequest {
+        method,
+        path: path.clone(),
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    });
+
+    let resp = {
+        let mut routes = routes.lock().unwrap();
+        match routes.get_mut(&path) {
+            Some(queue) if queue.len() > 1 => queue.remove(0),
+            Some(queue) if queue.len() == 1 => queue[0].clone(),
+            _ => MockResponse {
+                status: 404,
+                headers: vec![("Content-Type".to_string(),
+                               "application/problem+json".to_string())],
+                body: fixture("problem_404.json"),
+            },
+        }
+    };
+
+    let mut out = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                          resp.status, resp.body.len());
+    if !resp.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("content-type")) {
+        out.push_str("Content-Type: application/geo+json\r\n");
+    }
+    for (k, v) in &resp.headers {
+        out.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    out.push_str("\r\n");
+    out.push_str(&resp.body);
+    let _ = stream.write_all(out.as_bytes());
+    let _ = stream.flush();
+}
+
+/// A config for the given stations on the mock server, with a spool under temp.
+pub fn config(server: &MockServer, stations: &[&str], spool_name: &str) -> Config {
+    let mut host = HashMap::new();
+    host.insert("BASE_URL".to_string(), server.base_url.clone());
+    host.insert("STATIONS_URL".to_string(), server.stations_url());
+
+    let mut stations_section = HashMap::new();
+    for id in stations {
+        stations_section.insert(format!("Station {}", id), id.to_string());
+    }
+
+    let mut parameters = HashMap::new();
+    parameters.insert("OBS_INTERVAL_SECS".to_string(), "1".to_string());
+
+    let mut spool = HashMap::new();
+    spool.insert("SPOOL_FILE".to_string(),
+                 temp_path(spool_name).to_string_lossy().to_string());
+
+    Config {
+        host_section:       host,
+        stations_section,
+        parameters_section: parameters,
+        spool_section:      spool,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "parquet")]
+
+mod common;
+
+use std::fs::File;
+use std::path::Path;
+use common::{rec, temp_path};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use weather_gov::export::{self, ColumnKind, ExportOptions, ParquetConfig, ParquetSink};
+use weather_gov::memory::MemoryStore;
+use weather_gov::sink::ObservationSink;
+use weather_gov::station::{StationRecord, ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+
+/// The rows of a Parquet file, as (column, field) pairs.
+fn read(path: &Path) -> Vec<Vec<(String, Field)>> {
+    let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+    reader.get_row_iter(None).unwrap()
+        .map(|row| row.unwrap().get_column_iter().map(|(n, f)| (n.clone(), f.clone())).collect())
+        .collect()
+}
+
+fn field<'a>(row: &'a [(String, Field)], name: &str) -> &'a Field {
+    &row.iter().find(|(n, _)| n == name).unwrap().1
+}
+
+#[test]
+fn schema_follows_the_record() {
+    let columns = export::observation_columns();
+    assert_eq!(columns[0].name, "station_id");
+    assert!(columns[0].required);
+    assert_eq!((columns[1].kind, columns[1].required), (ColumnKind::Timestamp, true));
+    let temp = columns.iter().find(|c| c.name == "temperature_C").unwrap();
+    assert_eq!((temp.kind, temp.required), (ColumnKind::Float, false));
+    let text = export::schema(&columns);
+    assert!(text.contains("REQUIRED INT64 timestamp_UTC (TIMESTAMP(MICROS,true));"));
+    assert!(text.contains("OPTIONAL BYTE_ARRAY description (STRING);"));
+    assert!(parquet::schema::parser::parse_message_type(&text).is_ok());
+}
+
+#[async_std::test]
+async fn exports_partitioned_by_station_and_month() {
+    let store = MemoryStore::new();
+    for id in ["KPHX", "KTUS"] {
+        store.put_station_record(&StationRecord { call_id: id.to_string(), ..Default::default() })
+            .await.unwrap();
+    }
+    store.put_observation_batch(&[
+        rec("KPHX", "2024-03-31T23:51:00+00:00", 20.0),
+        rec("KPHX", "2024-04-01T00:51:00+00:00", 19.5),
+        ObservationRecord {
+            description: "Clear".to_string(),
+            ..rec("KPHX", "2024-04-01T01:51:00+00:00", MISSING)
+        },
+        rec("KPHX", "2024-05-01T00:51:00+00:00", 25.0),
+        rec("KTUS", "2024-04-01T00:53:00+00:00", 18.0),
+    ]).await.unwrap();
+
+    let dir = temp_path("export_parquet");
+    let opts = ExportOptions {
+        dir:   dir.clone(),
+        from:  Some("2024-04-01T00:00:00+00:00".to_string()),
+        to:    Some("2024-05-02T00:00:00+00:00".to_string()),
+        ..Default::default()
+    };
+    let files = export::export(&store, &opts).await.unwrap();
+    let april = dir.join("station=KPHX/month=2024-04/observations.parquet");
+    assert_eq!(files, vec![
+        (april.clone(), 2),
+        (dir.join("station=KPHX/month=2024-05/observations.parquet"), 1),
+        (dir.join("station=KTUS/month=2024-04/observations.parquet"), 1),
+    ]);
+
+    let rows = read(&april);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(field(&rows[0], "station_id"), &Field::Str("KPHX".to_string()));
+    // 2024-04-01T00:51:00Z
+    assert_eq!(field(&rows[0], "timestamp_UTC"), &Field::TimestampMicros(1_711_932_660_000_000));
+    assert_eq!(field(&rows[0], "temperature_C"), &Field::Double(19.5));
+    assert_eq!(field(&rows[1], "temperature_C"), &Field::Null);
+    assert_eq!(field(&rows[1], "description"), &Field::Str("Clear".to_string()));
+
+    // Just one station
+    let opts = ExportOptions { stations: vec!["KTUS".to_string()], ..opts };
+    assert_eq!(export::export(&store, &opts).await.unwrap().len(), 1);
+}
+
+#[async_std::test]
+async fn sink_writes_part_files() {
+    let dir = temp_path("export_sink");
+    let sink = ParquetSink::new(ParquetConfig { dir: dir.clone(), interval: Default::default() });
+    sink.on_observation(&rec("KPHX", "2024-04-12T21:51:00+00:00", 31.1)).await.unwrap();
+    sink.on_observation(&rec("KPHX", "2024-04-12T20:51:00+00:00", 32.0)).await.unwrap();
+    sink.flush().await.unwrap();
+    // Nothing new, nothing written
+    sink.flush().await.unwrap();
+
+    let part = dir.join("station=KPHX/month=2024-04/part-20240412205100.parquet");
+    let rows = read(&part);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(field(&rows[0], "temperature_C"), &Field::Double(32.0));
+    assert_eq!(std::fs::read_dir(part.parent().unwrap()).unwrap().count(), 1);
+}
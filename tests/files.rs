@@ -0,0 +1,146 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use flate2::read::GzDecoder;
+use common::{config, fixture, rec, temp_path, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::files::{FileConfig, FileFormat, FileSink};
+use weather_gov::memory::NullStore;
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+
+fn file_config(dir: &Path, entries: &[(&str, &str)]) -> FileConfig {
+    let mut cfg: HashMap<String, String> = entries.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    cfg.insert("DIR".to_string(), dir.to_string_lossy().to_string());
+    FileConfig::from_config(&cfg).unwrap()
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    names
+}
+
+fn gunzip(path: &Path) -> String {
+    let mut text = String::new();
+    GzDecoder::new(fs::File::open(path).unwrap()).read_to_string(&mut text).unwrap();
+    text
+}
+
+#[test]
+fn reads_settings_and_names_files() {
+    assert_eq!(FileConfig::from_config(&HashMap::new()), None);
+    let dir = temp_path("files_settings");
+    let cfg = file_config(&dir, &[]);
+    assert_eq!((cfg.format, cfg.per_station, cfg.daily, cfg.max_bytes, cfg.gzip),
+               (FileFormat::Csv, true, true, 0, false));
+    let r = rec("KPHX", "2024-04-12T21:51:00+00:00", 31.1);
+    assert_eq!(cfg.path_for(&r), dir.join("observations_KPHX_2024-04-12.csv"));
+
+    let cfg = file_config(&dir, &[("FORMAT", "jsonl"), ("PER_STATION", "false"),
+                                  ("ROTATE", "size"), ("PREFIX", "wx")]);
+    assert_eq!(cfg.path_for(&r), dir.join("wx.jsonl"));
+}
+
+#[test]
+fn writes_csv_per_station_and_rotates_daily() {
+    let dir = temp_path("files_daily");
+    let sink = FileSink::new(file_config(&dir, &[("GZIP", "true")])).unwrap();
+    sink.append(&ObservationRecord {
+        description: "Sunny, \"hot\"".to_string(),
+        ..rec("KPHX", "2024-04-12T22:51:00+00:00", 31.1)
+    });
+    sink.append(&rec("KTUS", "2024-04-12T22:53:00+00:00", MISSING));
+    sink.write_pending().unwrap();
+    sink.append(&rec("KPHX", "2024-04-12T23:51:00+00:00", 30.0));
+    sink.write_pending().unwrap();
+
+    let text = fs::read_to_string(dir.join("observations_KPHX_2024-04-12.csv")).unwrap();
+    let lines: Vec<&str> = text.split("\r\n").collect();
+    assert_eq!(lines[0], sink.fields.join(","));
+    assert!(lines[0].starts_with("station_id,timestamp_UTC,temperature_C,"));
+    assert!(lines[1].starts_with("KPHX,2024-04-12T22:51:00+00:00,31.1,"));
+    assert!(lines[1].contains(",\"Sunny, \"\"hot\"\"\","));
+    assert!(lines[2].starts_with("KPHX,2024-04-12T23:51:00+00:00,30.0,"));
+    assert_eq!(lines.len(), 4);
+    let tus = fs::read_to_string(dir.join("observations_KTUS_2024-04-12.csv")).unwrap();
+    assert!(tus.contains("KTUS,2024-04-12T22:53:00+00:00,,"));
+
+    // A new day closes and compresses KPHX's file, KTUS's stays open
+    sink.append(&rec("KPHX", "2024-04-13T00:51:00+00:00", 29.0));
+    sink.write_pending().unwrap();
+    assert_eq!(names(&dir), vec!["observations_KPHX_2024-04-12.csv.gz",
+                                 "observations_KPHX_2024-04-13.csv",
+                                 "observations_KTUS_2024-04-12.csv"]);
+    assert_eq!(gunzip(&dir.join("observations_KPHX_2024-04-12.csv.gz")), text);
+}
+
+#[test]
+fn rotates_combined_jsonl_by_size() {
+    let dir = temp_path("files_size");
+    let sink = FileSink::new(file_config(&dir, &[("FORMAT", "jsonl"), ("PER_STATION", "false"),
+                                                 ("ROTATE", "size"), ("MAX_BYTES", "500")]))
+        .unwrap();
+    for minute in 0..3 {
+        sink.append(&rec("KPHX", &format!("2024-04-12T22:{:02}:00+00:00", minute), 31.1));
+        sink.append(&rec("KTUS", &format!("2024-04-12T22:{:02}:00+00:00", minute), 28.0));
+        sink.write_pending().unwrap();
+    }
+    assert_eq!(names(&dir), vec!["observations.1.jsonl", "observations.2.jsonl",
+                                 "observations.3.jsonl"]);
+    let first = fs::read_to_string(dir.join("observations.1.jsonl")).unwrap();
+    let lines: Vec<serde_json::Value> = first.lines()
+        .map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["station_id"], "KPHX");
+    assert_eq!(lines[1]["temperature_C"], 28.0);
+    // Not set, so missing rather than a reading of 0
+    assert!(lines[0]["dewpoint_C"].is_null());
+}
+
+#[test]
+fn moves_aside_csv_with_other_columns() {
+    let dir = temp_path("files_columns");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("observations_KPHX_2024-04-12.csv");
+    fs::write(&path, "station_id,timestamp_UTC\r\nKPHX,2024-04-12T21:51:00+00:00\r\n").unwrap();
+
+    let sink = FileSink::new(file_config(&dir, &[])).unwrap();
+    sink.append(&ObservationRecord {
+        description: "Sunny, \"hot\"".to_string(),
+        ..rec("KPHX", "2024-04-12T22:51:00+00:00", 31.1)
+    });
+    sink.write_pending().unwrap();
+    assert_eq!(names(&dir), vec!["observations_KPHX_2024-04-12.1.csv",
+                                 "observations_KPHX_2024-04-12.csv"]);
+    assert!(fs::read_to_string(&path).unwrap().starts_with(&sink.fields.join(",")));
+}
+
+#[async_std::test]
+async fn collector_runs_with_only_files() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let dir = temp_path("files_collector");
+    let mut cfg = config(&server, &["KPHX"], "files_collector.spool");
+    cfg.file_section.insert("DIR".to_string(), dir.to_string_lossy().to_string());
+    cfg.sinks = vec!["file".to_string()];
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, Arc::new(NullStore::new()), spool);
+    assert_eq!(c.sinks.names(), vec!["file"]);
+
+    c.init_stations().await;
+    c.poll_once().await;
+    c.poll_once().await;
+
+    let text = fs::read_to_string(dir.join("observations_KPHX_2024-04-12.csv")).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.contains("KPHX,2024-04-12T21:51:00+00:00,31.1,"));
+}
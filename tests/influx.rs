@@ -0,0 +1,95 @@
+mod common;
+
+use std::collections::HashMap;
+use common::{rec, temp_path, MockServer};
+use weather_gov::influx::{self, InfluxConfig, InfluxSink, InfluxTarget};
+use weather_gov::sink::ObservationSink;
+use weather_gov::station::StationRecord;
+
+fn influx_config(entries: &[(&str, &str)]) -> Option<InfluxConfig> {
+    let cfg: HashMap<String, String> = entries.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    InfluxConfig::from_config(&cfg)
+}
+
+#[test]
+fn reads_settings() {
+    assert_eq!(influx_config(&[]), None);
+    let cfg = influx_config(&[("URL", "http://localhost:8086/"), ("ORG", "home"),
+                              ("BUCKET", "wx"), ("BATCH_SIZE", "100")]).unwrap();
+    assert_eq!(cfg.target, InfluxTarget::Http {
+        url:     "http://localhost:8086/api/v2/write".to_string(),
+        org:     "home".to_string(),
+        bucket:  "wx".to_string(),
+        token:   String::new(),
+    });
+    assert_eq!(cfg.batch_size, 100);
+    assert_eq!(influx_config(&[("FILE", "-")]).unwrap().target, InfluxTarget::Stdout);
+}
+
+#[test]
+fn renders_escaped_line_protocol() {
+    let mut r = rec("KPHX", "2024-04-12T21:51:00+00:00", 31.1);
+    // A calm north wind is a reading, the dewpoint is left missing
+    r.wind_dir = 0.0;
+    r.description = "Sunny, \"hot\"".to_string();
+    let line = influx::line(&r, Some("Phoenix, Sky Harbor=PHX")).unwrap();
+    assert!(line.starts_with(
+        "weather,name=Phoenix\\,\\ Sky\\ Harbor\\=PHX,station_id=KPHX temperature_C=31.1,"));
+    assert!(line.contains(",description=\"Sunny, \\\"hot\\\"\","));
+    assert!(line.contains(",wind_dir=0.0,"));
+    assert!(!line.contains("dewpoint_C="));
+    assert!(line.ends_with(" 1712958660000000000"));
+
+    // No name tag without a name, no line without a time
+    assert!(influx::line(&r, None).unwrap().starts_with("weather,station_id=KPHX "));
+    assert_eq!(influx::line(&rec("KPHX", "yesterday", 31.1), None), None);
+}
+
+#[async_std::test]
+async fn writes_batches_over_http() {
+    let server = MockServer::start();
+    let path = "/api/v2/write?org=home&bucket=wx&precision=ns";
+    server.route(path, 500, "{\"message\":\"busy\"}");
+    server.route(path, 204, "");
+    let cfg = influx_config(&[("URL", server.base_url.as_str()), ("ORG", "home"),
+                              ("BUCKET", "wx"), ("TOKEN", "secret"), ("BATCH_SIZE", "2")])
+        .unwrap();
+    let sink = InfluxSink::new(cfg);
+    sink.on_station(&StationRecord { call_id: "KPHX".to_string(), name: "Phoenix".to_string(),
+                                     ..Default::default() }).await.unwrap();
+
+    // The first batch fails and is kept, then goes out with the next
+    sink.on_observation(&rec("KPHX", "2024-04-12T20:51:00+00:00", 32.0)).await.unwrap();
+    assert!(sink.on_observation(&rec("KPHX", "2024-04-12T21:51:00+00:00", 31.1)).await.is_err());
+    sink.on_observation(&rec("KPHX", "2024-04-12T22:51:00+00:00", 30.0)).await.unwrap();
+    sink.flush().await.unwrap();
+    sink.flush().await.unwrap();
+
+    let received = server.received();
+    assert_eq!(received.len(), 3);
+    let retried = &received[1];
+    assert_eq!((retried.method.as_str(), retried.path.as_str()), ("POST", path));
+    assert_eq!(retried.header("Authorization"), Some("Token secret"));
+    let lines: Vec<&str> = retried.body.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("weather,name=Phoenix,station_id=KPHX temperature_C=32.0,"));
+    assert!(lines[1].ends_with(" 1712958660000000000"));
+    assert!(received[2].body.starts_with(
+        "weather,name=Phoenix,station_id=KPHX temperature_C=30.0,"));
+}
+
+#[async_std::test]
+async fn appends_to_a_file() {
+    let path = temp_path("influx.lp");
+    let sink = InfluxSink::new(influx_config(&[("FILE", path.to_str().unwrap())]).unwrap());
+    sink.on_observation(&rec("KPHX", "2024-04-12T21:51:00+00:00", 31.1)).await.unwrap();
+    sink.flush().await.unwrap();
+    sink.on_observation(&rec("KTUS", "2024-04-12T21:53:00+00:00", 28.0)).await.unwrap();
+    sink.flush().await.unwrap();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("weather,station_id=KTUS temperature_C=28.0,"));
+}
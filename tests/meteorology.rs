@@ -0,0 +1,71 @@
+mod common;
+
+use common::fixture;
+use weather_gov::meteorology::{self, *};
+use weather_gov::models::{decode, ObservationFeature};
+use weather_gov::station::{Station, ObservationRecord, MISSING};
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance
+}
+
+#[test]
+fn computes_published_values() {
+    // NWS heat index chart, 90 °F at 50 % is 95 °F
+    let hi = heat_index_c(32.2222, 50.0).unwrap() * 9.0 / 5.0 + 32.0;
+    assert!(close(hi, 94.6, 0.2), "{}", hi);
+    assert_eq!(heat_index_c(25.0, 50.0), None);
+
+    // NWS wind chill chart, 0 °F in 15 mph is -19 °F
+    let wc = wind_chill_c(-17.7778, 24.14).unwrap() * 9.0 / 5.0 + 32.0;
+    assert!(close(wc, -19.0, 0.5), "{}", wc);
+    assert_eq!(wind_chill_c(12.0, 30.0), None);
+    assert_eq!(wind_chill_c(-5.0, 3.0), None);
+
+    assert!(close(humidex(30.0, 15.0), 33.8, 0.2));
+    assert!(close(wet_bulb_c(20.0, 50.0), 13.7, 0.1));
+    assert!(close(relative_humidity(20.0, 20.0), 100.0, 1e-9));
+    assert!(close(relative_humidity(30.0, 10.0), 28.9, 0.2));
+    assert!(close(apparent_temperature_c(30.0, 50.0, 18.0), 29.5, 0.1));
+    assert!(close(absolute_humidity_g_m3(20.0, 50.0), 8.6, 0.1));
+    assert!(close(mixing_ratio_g_kg(20.0, 50.0, 101325.0), 7.2, 0.1));
+    assert!(close(station_pressure_pa(None, 1000.0), 89875.0, 10.0));
+}
+
+#[test]
+fn derives_missing_humidity_and_marks_it() {
+    let mut rec = ObservationRecord {
+        temperature_C:  -5.0,
+        dewpoint_C:     -10.0,
+        rel_humidity:   MISSING,
+        wind_spd_km_h:  20.0,
+        baro_pres_pa:   MISSING,
+        ..Default::default()
+    };
+    meteorology::derive(&mut rec, 1000.0);
+    assert!(close(rec.rel_humidity, 68.0, 0.5), "{}", rec.rel_humidity);
+    assert_eq!(rec.heat_index_C, MISSING);
+    assert!(close(rec.wind_chill_C, -11.6, 0.1), "{}", rec.wind_chill_C);
+    // The standard atmosphere at the station's elevation
+    assert!(close(rec.mixing_ratio_g_kg, 2.0, 0.05), "{}", rec.mixing_ratio_g_kg);
+    assert_eq!(rec.derived, "rel_humidity,wind_chill_C,apparent_temperature_C,humidex,\
+                             wet_bulb_C,abs_humidity_g_m3,mixing_ratio_g_kg");
+
+    let mut bare = ObservationRecord { temperature_C: MISSING, ..Default::default() };
+    meteorology::derive(&mut bare, 0.0);
+    assert_eq!((bare.humidex, bare.wet_bulb_C, bare.derived.as_str()), (MISSING, MISSING, ""));
+}
+
+#[test]
+fn observations_get_derived_values() {
+    let obs: ObservationFeature = decode("test", &fixture("observation_kphx_latest.json")).unwrap();
+    let station = Station::new("KPHX".to_string(), "http://localhost/stations/".to_string());
+    let rec = station.preprocess_observation(&obs.properties);
+
+    // Observed humidity is kept, 31.1 °C at 8.5 % feels cooler
+    assert_eq!(rec.rel_humidity, 8.4931);
+    assert!(rec.heat_index_C < rec.temperature_C);
+    assert_eq!(rec.wind_chill_C, MISSING);
+    assert!(!rec.derived.split(',').any(|f| f == "rel_humidity"));
+    assert!(rec.derived.contains("heat_index_C"));
+}
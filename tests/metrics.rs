@@ -0,0 +1,104 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use common::{config, fixture, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::metrics::{self, Metrics};
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+
+fn collector(server: &MockServer, stations: &[&str], spool_name: &str) -> Collector {
+    let cfg = config(server, stations, spool_name);
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    Collector::new(&cfg, Arc::new(MemoryStore::new()), spool)
+}
+
+#[async_std::test]
+async fn polls_are_counted_and_the_weather_exported() {
+    let server = MockServer::start();
+    for id in ["KPHX", "KSLO"] {
+        server.route(&format!("/stations/{}", id), 200,
+                     &fixture("station_kphx.json").replace("KPHX", id));
+    }
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    server.route("/stations/KSLO/observations/latest", 500, &fixture("problem_500.json"));
+    let mut c = collector(&server, &["KPHX", "KSLO"], "metrics_polls.spool");
+
+    c.init_stations().await;
+    c.poll_once().await;
+    c.poll_once().await;
+
+    let m = &c.metrics;
+    assert_eq!(m.counter(metrics::POLL_ATTEMPTS, &[("station", "KPHX")]), 2);
+    assert_eq!(m.counter(metrics::POLL_SUCCESSES, &[("station", "KPHX")]), 2);
+    assert_eq!(m.counter(metrics::POLL_FAILURES, &[("station", "KSLO"), ("action", "retry")]), 2);
+
+    let text = m.render();
+    assert!(text.contains("# TYPE weather_gov_poll_attempts_total counter"));
+    assert!(text.contains("weather_gov_poll_failures_total{station=\"KSLO\",action=\"retry\"} 2"));
+    // 2 station + 4 observation requests
+    assert!(text.contains("weather_gov_http_request_duration_seconds_count{status=\"200\"} 4"),
+            "{}", text);
+    assert!(text.contains("weather_gov_http_request_duration_seconds_count{status=\"500\"} 2"));
+    assert!(text.contains("weather_gov_storage_insert_duration_seconds_count{op=\"batch\"} 2"));
+    assert!(text.contains("weather_gov_observations_stored_total{outcome=\"duplicate\"} 1"));
+    assert!(text.contains("weather_gov_spool_records 0"));
+    assert!(text.contains("weather_gov_temperature_celsius{station=\"KPHX\"} 31.1"));
+    assert!(text.contains("weather_gov_last_observation_timestamp_seconds{station=\"KPHX\"} 1712958660"));
+    assert!(text.contains("weather_gov_last_observation_age_seconds{station=\"KPHX\"}"));
+    assert!(!text.contains("weather_gov_temperature_celsius{station=\"KSLO\"}"));
+}
+
+#[test]
+fn histograms_are_cumulative_and_missing_weather_is_skipped() {
+    let m = Metrics::new();
+    m.observe_duration(metrics::INSERT_DURATION, &[("op", "batch")], Duration::from_millis(3));
+    m.observe_duration(metrics::INSERT_DURATION, &[("op", "batch")], Duration::from_millis(30));
+    let text = m.render();
+    let bucket = |le: &str| format!(
+        "weather_gov_storage_insert_duration_seconds_bucket{{op=\"batch\",le=\"{}\"}} ", le);
+    assert!(text.contains(&format!("{}0", bucket("0.001"))));
+    assert!(text.contains(&format!("{}1", bucket("0.005"))));
+    assert!(text.contains(&format!("{}2", bucket("0.05"))));
+    assert!(text.contains(&format!("{}2", bucket("+Inf"))));
+
+    let older = ObservationRecord {
+        station_id:     "KPHX".to_string(),
+        timestamp_UTC:  "2024-04-12T20:51:00+00:00".to_string(),
+        temperature_C:  20.0,
+        dewpoint_C:     0.0,
+        ..Default::default()
+    };
+    let newer = ObservationRecord {
+        timestamp_UTC:  "2024-04-12T21:51:00+00:00".to_string(),
+        temperature_C:  MISSING,
+        ..older.clone()
+    };
+    m.observation(&newer);
+    m.observation(&older);
+    let text = m.render();
+    assert!(text.contains("weather_gov_last_observation_timestamp_seconds{station=\"KPHX\"} 1712958660"));
+    assert!(!text.contains("weather_gov_temperature_celsius{"));
+    assert!(text.contains("weather_gov_dewpoint_celsius{station=\"KPHX\"} 0"));
+}
+
+#[cfg(feature = "http-api")]
+#[async_std::test]
+async fn api_serves_metrics() {
+    use std::collections::HashMap;
+    use weather_gov::api::Api;
+
+    let m = Arc::new(Metrics::new());
+    m.inc(metrics::POLL_ATTEMPTS, &[("station", "KPHX")]);
+    let api = Api::new(Arc::new(MemoryStore::new()), &HashMap::new()).with_metrics(m);
+    let resp = api.handle("GET", "/metrics", None).await;
+    assert_eq!(resp.status, 200);
+    assert!(resp.content_type.starts_with("text/plain; version=0.0.4"));
+    assert!(resp.body.contains("weather_gov_poll_attempts_total{station=\"KPHX\"} 1"));
+
+    let api = Api::new(Arc::new(MemoryStore::new()), &HashMap::new());
+    assert_eq!(api.handle("GET", "/metrics", None).await.status, 404);
+}
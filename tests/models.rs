@@ -0,0 +1,150 @@
+mod common;
+
+use common::fixture;
+use weather_gov::error::{ErrorAction, ProblemDetail, WeatherGovError};
+use weather_gov::models::{decode, StationFeature, ObservationFeature, ObservationCollection,
+                          PointFeature, ForecastFeature, AlertCollection, QuantitativeValue};
+use weather_gov::station::{Station, MISSING};
+
+fn qv(unit: &str, value: f64) -> QuantitativeValue {
+    QuantitativeValue { value: Some(value), unit_code: unit.to_string(), ..Default::default() }
+}
+
+#[test]
+fn decodes_station() {
+    let st: StationFeature = decode("test", &fixture("station_kphx.json")).unwrap();
+    assert_eq!(st.properties.station_identifier, "KPHX");
+    assert_eq!(st.properties.time_zone.as_deref(), Some("America/Phoenix"));
+    assert_eq!(st.geometry.unwrap().point(), Some((-111.9552, 33.4278)));
+    assert_eq!(st.properties.elevation.as_meters(), Some(337.0992));
+}
+
+#[test]
+fn station_without_geometry_defaults_location() {
+    let st: StationFeature = decode("test", &fixture("station_no_geometry.json")).unwrap();
+    assert!(st.geometry.is_none());
+
+    let mut station = Station::new("KXYZ".to_string(), "http://localhost/stations/".to_string());
+    station.set_station_data(&st);
+    assert_eq!(station.station_name, "KXYZ");
+    assert_eq!((station.longitude, station.latitude), (0.0, 0.0));
+    assert!((station.elevation_meters - 304.8).abs() < 1e-9);
+}
+
+#[test]
+fn preprocesses_observation() {
+    let obs: ObservationFeature = decode("test", &fixture("observation_kphx_latest.json")).unwrap();
+    let station = Station::new("KPHX".to_string(), "http://localhost/stations/".to_string());
+    let rec = station.preprocess_observation(&obs.properties);
+
+    assert_eq!(rec.station_id, "KPHX");
+    assert_eq!(rec.timestamp_UTC, "2024-04-12T21:51:00+00:00");
+    assert_eq!(rec.temperature_C, 31.1);
+    assert!((rec.temperature_F - 87.98).abs() < 1e-9);
+    assert_eq!(rec.description, "Mostly Clear");
+    assert_eq!(rec.wind_dir, 250.0);
+    assert!((rec.wind_spd_mi_h - 10.357).abs() < 1e-3);
+    assert_eq!(rec.baro_pres_pa, 101080.0);
+    assert!((rec.baro_pres_inHg - 29.849).abs() < 1e-3);
+    assert_eq!(rec.rel_humidity, 8.4931);
+}
+
+#[test]
+fn null_values_become_missing() {
+    let obs: ObservationFeature = decode("test", &fixture("observation_nulls.json")).unwrap();
+    let station = Station::new("KPHX".to_string(), "http://localhost/stations/".to_string());
+    let rec = station.preprocess_observation(&obs.properties);
+
+    assert_eq!(rec.temperature_C, MISSING);
+    assert_eq!(rec.temperature_F, MISSING);
+    assert_eq!(rec.wind_gust_km_h, MISSING);
+    assert_eq!(rec.baro_pres_inHg, MISSING);
+    assert_eq!(rec.rel_humidity, MISSING);
+    assert_eq!(rec.description, "");
+}
+
+#[test]
+fn odd_units_are_converted() {
+    let obs: ObservationFeature = decode("test", &fixture("observation_odd_units.json")).unwrap();
+    let station = Station::new("KPHX".to_string(), "http://localhost/stations/".to_string());
+    let rec = station.preprocess_observation(&obs.properties);
+
+    assert!((rec.temperature_C - 31.111).abs() < 1e-3);
+    assert!((rec.dewpoint_C - -3.9).abs() < 1e-9);
+    assert!((rec.wind_spd_km_h - 18.0).abs() < 1e-9);
+    assert!((rec.wind_gust_km_h - 27.78).abs() < 1e-9);
+    assert!((rec.baro_pres_pa - 101080.0).abs() < 1e-6);
+    // An unknown unit is not stored as a wrong number, the humidity is
+    //     derived from the temperature and dewpoint instead
+    assert_eq!(rec.rel_humidity,
+               weather_gov::meteorology::relative_humidity(rec.temperature_C, rec.dewpoint_C));
+    assert!(rec.derived.starts_with("rel_humidity,"));
+}
+
+#[test]
+fn quantitative_value_conversions() {
+    assert_eq!(qv("wmoUnit:degF", 212.0).as_celsius(), Some(100.0));
+    assert_eq!(qv("wmoUnit:m_s-1", 10.0).as_km_h(), Some(36.0));
+    assert_eq!(qv("wmoUnit:hPa", 1013.25).as_pa(), Some(101325.0));
+    assert_eq!(qv("wmoUnit:mm", 2.5).as_mm(), Some(2.5));
+    assert_eq!(qv("wmoUnit:degC", 1.0).as_km_h(), None);
+    let null = QuantitativeValue { unit_code: "wmoUnit:degC".to_string(), ..Default::default() };
+    assert_eq!(null.as_celsius(), None);
+}
+
+#[test]
+fn missing_field_is_named() {
+    let err = decode::<ObservationFeature>("test", &fixture("observation_missing_wind_gust.json"))
+        .unwrap_err();
+    match &err {
+        WeatherGovError::Json { path, .. } => assert_eq!(path, "properties"),
+        e => panic!("unexpected error {:?}", e),
+    }
+    assert!(err.to_string().contains("windGust"), "{}", err);
+    assert_eq!(err.action(), ErrorAction::Retry);
+}
+
+#[test]
+fn wrong_type_is_named() {
+    let err = decode::<ObservationFeature>("test", &fixture("observation_bad_type.json"))
+        .unwrap_err();
+    match &err {
+        WeatherGovError::Json { path, .. } => assert_eq!(path, "properties.temperature.value"),
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn decodes_collections_points_forecasts_and_alerts() {
+    let obs: ObservationCollection = decode("test", &fixture("observations_kphx.json")).unwrap();
+    assert_eq!(obs.features.len(), 2);
+    assert!(obs.pagination.is_some());
+
+    let point: PointFeature = decode("test", &fixture("point_phoenix.json")).unwrap();
+    assert_eq!((point.properties.grid_id.as_str(), point.properties.grid_x,
+                point.properties.grid_y), ("PSR", 161, 56));
+
+    let forecast: ForecastFeature = decode("test", &fixture("forecast_psr.json")).unwrap();
+    assert_eq!(forecast.properties.periods.len(), 2);
+    assert_eq!(forecast.properties.periods[1].temperature, Some(62.0));
+    assert!(forecast.geometry.unwrap().point().is_none());
+
+    let alerts: AlertCollection = decode("test", &fixture("alerts_active.json")).unwrap();
+    assert_eq!(alerts.features[0].properties.event, "Wind Advisory");
+}
+
+#[test]
+fn problem_details_and_actions() {
+    let problem: ProblemDetail = serde_json::from_str(&fixture("problem_404.json")).unwrap();
+    let err = WeatherGovError::Status {
+        url: "test".to_string(),
+        status: 404,
+        problem: Some(Box::new(problem)),
+    };
+    assert!(err.to_string().contains("Station KNOPE not found"));
+    assert_eq!(err.action(), ErrorAction::Disable);
+    assert!(matches!(err.observation_action(), ErrorAction::BackOff(_)));
+
+    let err = WeatherGovError::Status { url: "test".to_string(), status: 503, problem: None };
+    assert_eq!(err.action(), ErrorAction::Retry);
+}
@@ -0,0 +1,177 @@
+#![cfg(feature = "mqtt")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use common::{config, fixture, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::mqtt::{self, MqttConfig, MqttPublisher};
+use weather_gov::spool::Spool;
+use weather_gov::station::{StationRecord, ObservationRecord, MISSING};
+
+/// A message received by the broker: topic, payload, qos and retain.
+type Received = (String, String, u8, bool);
+
+/// A minimal MQTT 3.1.1 broker on 127.0.0.1 that records what is published.
+struct Broker {
+    port:      u16,
+    received:  Arc<Mutex<Vec<Received>>>,
+}
+
+impl Broker {
+    fn start() -> Broker {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let r = received.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let r = r.clone();
+                thread::spawn(move || { let _ = serve(stream, &r); });
+            }
+        });
+        Broker { port, received }
+    }
+
+    /// Waits until n messages arrived, or a few seconds pass.
+    fn wait_for(&self, n: usize) -> Vec<Received> {
+        let until = Instant::now() + Duration::from_secs(5);
+        while self.received.lock().unwrap().len() < n && Instant::now() < until {
+            thread::sleep(Duration::from_millis(20));
+        }
+        self.received.lock().unwrap().clone()
+    }
+}
+
+fn serve(mut s: TcpStream, received: &Mutex<Vec<Received>>) -> std::io::Result<()> {
+    loop {
+        let mut first = [0u8; 1];
+        s.read_exact(&mut first)?;
+        let (mut len, mut shift) = (0usize, 0);
+        loop {
+            let mut b = [0u8; 1];
+            s.read_exact(&mut b)?;
+            len |= ((b[0] & 0x7f) as usize) << shift;
+            shift += 7;
+            if b[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0u8; len];
+        s.read_exact(&mut body)?;
+
+        match first[0] >> 4 {
+            1 => s.write_all(&[0x20, 0x02, 0x00, 0x00])?,
+            3 => {
+                let qos = (first[0] >> 1) & 3;
+                let retain = first[0] & 1 == 1;
+                let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let topic = String::from_utf8_lossy(&body[2..2 + topic_len]).to_string();
+                let mut at = 2 + topic_len;
+                if qos > 0 {
+                    let id = [body[at], body[at + 1]];
+                    at += 2;
+                    s.write_all(&[if qos == 1 { 0x40 } else { 0x50 }, 0x02, id[0], id[1]])?;
+                }
+                let payload = String::from_utf8_lossy(&body[at..]).to_string();
+                received.lock().unwrap().push((topic, payload, qos, retain));
+            },
+            6 => s.write_all(&[0x70, 0x02, body[0], body[1]])?,
+            12 => s.write_all(&[0xd0, 0x00])?,
+            14 => return Ok(()),
+            _ => {},
+        }
+    }
+}
+
+fn mqtt_config(port: u16, qos: &str, discovery: &str) -> MqttConfig {
+    let mut cfg = HashMap::new();
+    cfg.insert("HOST".to_string(), "127.0.0.1".to_string());
+    cfg.insert("PORT".to_string(), port.to_string());
+    cfg.insert("QOS".to_string(), qos.to_string());
+    cfg.insert("DISCOVERY".to_string(), discovery.to_string());
+    MqttConfig::from_config(&cfg).unwrap()
+}
+
+#[test]
+fn builds_observation_and_discovery_messages() {
+    assert_eq!(MqttConfig::from_config(&HashMap::new()), None);
+    let cfg = mqtt_config(1883, "1", "true");
+    assert_eq!(cfg.topic_prefix, "weather_gov");
+    assert!(cfg.retain);
+
+    let rec = ObservationRecord {
+        station_id:     "KPHX".to_string(),
+        timestamp_UTC:  "2024-04-12T21:51:00+00:00".to_string(),
+        temperature_C:  31.1,
+        dewpoint_C:     MISSING,
+        ..Default::default()
+    };
+    let messages = mqtt::observation_messages(&cfg, &rec);
+    assert_eq!(messages[0].topic, "weather_gov/KPHX/observation");
+    let json: Value = serde_json::from_str(&messages[0].payload).unwrap();
+    assert_eq!(json["temperature_C"], 31.1);
+    assert_eq!(json["dewpoint_C"], Value::Null);
+    let field = |t: &str| messages.iter().find(|m| m.topic == t).unwrap().payload.clone();
+    assert_eq!(field("weather_gov/KPHX/temperature_C"), "31.1");
+    assert_eq!(field("weather_gov/KPHX/dewpoint_C"), "");
+    assert_eq!(field("weather_gov/KPHX/timestamp_UTC"), "2024-04-12T21:51:00+00:00");
+    assert!(messages.iter().all(|m| m.retain && m.topic != "weather_gov/KPHX/station_id"));
+
+    let station = StationRecord {
+        call_id: "KPHX".to_string(),
+        name:    "Phoenix".to_string(),
+        ..Default::default()
+    };
+    let discovery = mqtt::discovery_messages(&cfg, &station);
+    let temp = discovery.iter()
+        .find(|m| m.topic == "homeassistant/sensor/weather_gov_KPHX_temperature_C/config")
+        .unwrap();
+    let json: Value = serde_json::from_str(&temp.payload).unwrap();
+    assert_eq!(json["state_topic"], "weather_gov/KPHX/temperature_C");
+    assert_eq!(json["device_class"], "temperature");
+    assert_eq!(json["unit_of_measurement"], "°C");
+    assert_eq!(json["device"]["name"], "Phoenix");
+}
+
+#[async_std::test]
+async fn publishes_new_observations_to_a_broker() {
+    let broker = Broker::start();
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX"], "mqtt_publish.spool");
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, Arc::new(MemoryStore::new()), spool);
+    let publisher = Arc::new(MqttPublisher::connect(mqtt_config(broker.port, "1", "true")));
+    c.sinks.add(publisher.clone());
+
+    c.init_stations().await;
+    c.poll_once().await;
+    let discovery = mqtt::discovery_messages(&publisher.config,
+                                             &c.stations[0].get_station_record()).len();
+    let fields = mqtt::observation_messages(&publisher.config,
+                                            &ObservationRecord::default()).len();
+    let received = broker.wait_for(discovery + fields);
+    assert_eq!(received.len(), discovery + fields);
+
+    let (_, payload, qos, retain) = received.iter()
+        .find(|(t, _, _, _)| t == "weather_gov/KPHX/observation").unwrap();
+    assert_eq!((*qos, *retain), (1, true));
+    let json: Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(json["timestamp_UTC"], "2024-04-12T21:51:00+00:00");
+    assert!(received.iter().any(|(t, p, _, _)| t == "weather_gov/KPHX/temperature_C" && p == "31.1"));
+
+    // The same latest observation is a duplicate and is not published again
+    c.poll_once().await;
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(broker.received.lock().unwrap().len(), discovery + fields);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use common::{config, fixture, rec, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::metrics;
+use weather_gov::qc::{self, QcFlag, QualityControl};
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+
+/// A Phoenix record with a wind speed.
+fn kphx(timestamp: &str, temperature: f64, wind: f64) -> ObservationRecord {
+    ObservationRecord { wind_spd_km_h: wind, ..rec("KPHX", timestamp, temperature) }
+}
+
+fn flags(f: &[QcFlag]) -> Vec<String> {
+    f.iter().map(|f| f.to_string()).collect()
+}
+
+#[test]
+fn flags_ranges_and_inconsistencies() {
+    let qc = QualityControl::from_config(&HashMap::new());
+    let mut hot = kphx("2024-04-12T21:51:00+00:00", 60.0, 10.0);
+    hot.dewpoint_C = 61.0;
+    hot.wind_gust_km_h = 5.0;
+    assert_eq!(flags(&qc.apply(&mut hot, &[])),
+               ["dewpoint_C:range", "temperature_C:range", "dewpoint_C:consistency",
+                "wind_gust_km_h:consistency"]);
+    assert_eq!(hot.qc_flags, "dewpoint_C:range,temperature_C:range,dewpoint_C:consistency,\
+                              wind_gust_km_h:consistency");
+
+    // Missing values aren't checked, rounding leaves a little room
+    let mut fine = kphx("2024-04-12T21:51:00+00:00", 20.0, MISSING);
+    fine.dewpoint_C = 20.3;
+    fine.wind_gust_km_h = 30.0;
+    assert!(qc.apply(&mut fine, &[]).is_empty());
+    assert_eq!(fine.qc_flags, "");
+}
+
+#[test]
+fn flags_steps_from_the_previous_observation() {
+    let qc = QualityControl::from_config(&HashMap::new());
+    let history = [kphx("2024-04-12T19:51:00+00:00", 20.0, 10.0),
+                   kphx("2024-04-12T20:51:00+00:00", 21.0, 10.0)];
+    let spike = kphx("2024-04-12T21:51:00+00:00", 35.0, 12.0);
+    assert_eq!(flags(&qc.check(&spike, &history)), ["temperature_C:step"]);
+    assert!(qc.check(&kphx("2024-04-12T21:51:00+00:00", 25.0, 12.0), &history).is_empty());
+
+    // Too long since the previous observation to tell
+    assert!(qc.check(&kphx("2024-04-13T03:00:00+00:00", 35.0, 12.0), &history).is_empty());
+}
+
+#[test]
+fn flags_values_stuck_for_too_long() {
+    let qc = QualityControl::from_config(&HashMap::new());
+    let stuck: Vec<ObservationRecord> = (0..24)
+        .map(|h| kphx(&format!("2024-04-{:02}T{:02}:51:00+00:00", 11 + (21 + h) / 24,
+                              (21 + h) % 24), 20.0 + h as f64 * 0.5, 18.5))
+        .collect();
+    let now = kphx("2024-04-12T21:51:00+00:00", 31.0, 18.5);
+    assert_eq!(flags(&qc.check(&now, &stuck)), ["wind_spd_km_h:persistence"]);
+
+    // Half a day of history isn't enough to say for wind
+    assert!(qc.check(&now, &stuck[12..]).is_empty());
+    // Nor is one different value
+    let mut moved = stuck.clone();
+    moved[10].wind_spd_km_h = 20.0;
+    assert!(qc.check(&now, &moved).is_empty());
+}
+
+#[test]
+fn reads_overrides_from_config() {
+    let cfg: HashMap<String, String> = [
+        ("temperature_C.RANGE", "-40, 45"),
+        ("temperature_C.STEP", ""),
+        ("wind_spd_km_h.PERSISTENCE_HOURS", "48"),
+        ("CONSISTENCY", "false"),
+        ("bogus.RANGE", "0,1"),
+        ("dewpoint_C.RANGE", "hot"),
+    ].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let qc = QualityControl::from_config(&cfg);
+    assert_eq!(qc.ranges.get("temperature_C"), Some(&(-40.0, 45.0)));
+    assert_eq!(qc.ranges.get("dewpoint_C"), Some(&(-100.0, 35.0)));
+    assert!(!qc.ranges.contains_key("bogus"));
+    assert!(!qc.steps.contains_key("temperature_C"));
+    assert_eq!(qc.lookback(), chrono::Duration::hours(48));
+
+    let mut hot = kphx("2024-04-12T21:51:00+00:00", 50.0, 10.0);
+    hot.dewpoint_C = 51.0;
+    assert_eq!(flags(&qc.check(&hot, &[])), ["dewpoint_C:range", "temperature_C:range"]);
+
+    let off = QualityControl::from_config(&[("ENABLED".to_string(), "false".to_string())]
+                                          .into_iter().collect());
+    assert!(off.check(&hot, &[]).is_empty());
+}
+
+#[async_std::test]
+async fn collector_stores_and_counts_flags() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX"], "qc_collector.spool");
+    let store = Arc::new(MemoryStore::new());
+    // 31.1 °C now, after 20 °C an hour and a half earlier
+    store.put_observation_batch(&[kphx("2024-04-12T20:21:00+00:00", 20.0, MISSING)])
+        .await.unwrap();
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, store.clone(), spool);
+    c.init_stations().await;
+    c.poll_once().await;
+
+    let latest = store.latest_observation("KPHX").await.unwrap().unwrap();
+    assert_eq!(latest.timestamp_UTC, "2024-04-12T21:51:00+00:00");
+    assert_eq!(latest.qc_flags, "temperature_C:step");
+    assert_eq!(c.metrics.counter(metrics::QC_FLAGS, &[("station", "KPHX"),
+               ("field", "temperature_C"), ("check", qc::STEP)]), 1);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::sync::Arc;
+use common::{config, fixture, temp_path, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::replay::{Recorder, Replayer};
+use weather_gov::spool::Spool;
+
+#[async_std::test]
+async fn record_then_replay() {
+    let dir = temp_path("recording");
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_odd_units.json"));
+    server.route_with_headers("/stations/KPHX/observations/latest", 429,
+                              &[("Retry-After", "600")], "");
+
+    // Record three polls
+    let cfg = config(&server, &["KPHX"], "record.spool");
+    let live = Arc::new(MemoryStore::new());
+    let mut c = Collector::new(&cfg, live.clone(), Arc::new(Spool::new(&cfg.spool_section)));
+    c.client = c.client.clone().with_recorder(Recorder::new(&dir).unwrap());
+    c.init_stations().await;
+    for _ in 0..3 {
+        c.poll_once().await;
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+    let served = server.requests().len();
+
+    // Replay against the same urls: no network, no waiting, same records
+    let replayed = Arc::new(MemoryStore::new());
+    let mut c = Collector::new(&cfg, replayed.clone(),
+                               Arc::new(Spool::new(&cfg.spool_section)));
+    c.interval = std::time::Duration::from_secs(3600);
+    c.client = c.client.clone().with_replayer(Replayer::load(&dir).unwrap());
+    c.init_stations().await;
+    let started = std::time::Instant::now();
+    c.run().await;
+
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(server.requests().len(), served);
+    assert_eq!(c.client.replay_remaining(), Some(0));
+    let live_ts: Vec<String> = live.observation_records().into_iter()
+        .map(|r| r.timestamp_UTC).collect();
+    let replayed_ts: Vec<String> = replayed.observation_records().into_iter()
+        .map(|r| r.timestamp_UTC).collect();
+    assert_eq!(live_ts.len(), 2);
+    assert_eq!(live_ts, replayed_ts);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use common::rec;
+use weather_gov::climate::{DailySummary, HourlySummary};
+use weather_gov::memory::MemoryStore;
+use weather_gov::retention::{self, RetentionPolicy, RetentionReport};
+use weather_gov::station::{StationRecord, MISSING};
+use weather_gov::storage::{Storage, ObservationQuery};
+
+fn policy(entries: &[(&str, &str)]) -> Option<RetentionPolicy> {
+    let cfg: HashMap<String, String> = entries.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    RetentionPolicy::from_config(&cfg)
+}
+
+fn time(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+}
+
+#[test]
+fn reads_the_policy() {
+    assert_eq!(policy(&[]), None);
+    assert_eq!(policy(&[("RAW_DAYS", "0"), ("DAILY_DAYS", "0")]), None);
+    let p = policy(&[("RAW_DAYS", "90"), ("HOURLY_DAYS", "1825"), ("BATCH_PAUSE_MS", "0")])
+        .unwrap();
+    assert_eq!((p.raw_days, p.hourly_days, p.daily_days, p.batch), (90, 1825, 0, 1000));
+    assert_eq!((p.pause, p.interval), (Duration::ZERO, Duration::from_secs(3600)));
+    assert_eq!(p.raw_cutoff(time("2024-07-12T12:30:00Z")).as_deref(),
+               Some("2024-04-13T12:00:00+00:00"));
+}
+
+#[async_std::test]
+async fn rolls_old_observations_up_by_the_hour() {
+    let store = MemoryStore::new();
+    store.put_station_record(&StationRecord { call_id: "KPHX".to_string(),
+                                              time_zone: "America/Phoenix".to_string(),
+                                              ..Default::default() }).await.unwrap();
+    store.put_observation_batch(&[
+        rec("KPHX", "2024-04-12T20:10:00+00:00", 30.0),
+        rec("KPHX", "2024-04-12T20:30:00+00:00", 31.0),
+        rec("KPHX", "2024-04-12T20:50:00+00:00", 32.0),
+        rec("KPHX", "2024-04-12T21:10:00+00:00", 33.0),
+        rec("KPHX", "2024-04-12T21:51:00+00:00", 34.0),
+        rec("KPHX", "2024-04-13T11:51:00+00:00", 20.0),
+        rec("KPHX", "2024-04-13T12:10:00+00:00", 21.0),
+    ]).await.unwrap();
+
+    // Batches of 2 split hours, which are then read whole
+    let p = policy(&[("RAW_DAYS", "90"), ("BATCH_SIZE", "2"), ("BATCH_PAUSE_MS", "0")]).unwrap();
+    let now = time("2024-07-12T12:30:00Z");
+    assert_eq!(retention::run_once(&p, &store, now).await.unwrap(),
+               RetentionReport { hours: 3, raw_deleted: 6, ..Default::default() });
+
+    let kept = store.observations(&ObservationQuery {
+        station_id: "KPHX".to_string(), limit: 100, ..Default::default()
+    }).await.unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].timestamp_UTC, "2024-04-13T12:10:00+00:00");
+
+    let hours = store.hourly_summaries("KPHX", "2024-04-12", "2024-04-14").await.unwrap();
+    let found: Vec<(&str, u32)> = hours.iter()
+        .map(|h| (h.hour_UTC.as_str(), h.observations)).collect();
+    assert_eq!(found, [("2024-04-12T20:00:00+00:00", 3), ("2024-04-12T21:00:00+00:00", 2),
+                       ("2024-04-13T11:00:00+00:00", 1)]);
+    assert_eq!((hours[0].temperature_C_min, hours[0].temperature_C_max,
+                hours[0].temperature_C_mean), (30.0, 32.0, 31.0));
+    assert_eq!(hours[0].dewpoint_C_mean, MISSING);
+
+    // Each local day was summarized while its observations were all there
+    let days = store.daily_summaries("KPHX", "2024-04-01", "2024-05-01").await.unwrap();
+    let found: Vec<(&str, u32)> = days.iter()
+        .map(|d| (d.date.as_str(), d.observations)).collect();
+    assert_eq!(found, [("2024-04-12", 5), ("2024-04-13", 2)]);
+    let month = &store.monthly_summaries("KPHX", "2024-04", "2024-05").await.unwrap()[0];
+    assert_eq!((month.days, month.observations), (2, 7));
+
+    // A late observation of a rolled up hour is merged into it
+    store.put_observation_record(&rec("KPHX", "2024-04-12T20:40:00+00:00", 35.0)).await;
+    assert_eq!(retention::run_once(&p, &store, now).await.unwrap(),
+               RetentionReport { hours: 1, raw_deleted: 1, ..Default::default() });
+    let hour = &store.hourly_summaries("KPHX", "2024-04-12T20", "2024-04-12T21").await
+        .unwrap()[0];
+    assert_eq!((hour.observations, hour.temperature_C_max, hour.temperature_C_mean),
+               (4, 35.0, 32.0));
+    assert_eq!((hour.first_UTC.as_str(), hour.last_UTC.as_str()),
+               ("2024-04-12T20:10:00+00:00", "2024-04-12T20:50:00+00:00"));
+}
+
+#[async_std::test]
+async fn purges_old_summaries_in_batches() {
+    let store = MemoryStore::new();
+    let hours: Vec<HourlySummary> = (0..5).map(|h| HourlySummary {
+        station_id:    "KPHX".to_string(),
+        hour_UTC:      format!("2019-04-12T0{}:00:00+00:00", h),
+        observations:  1,
+        ..Default::default()
+    }).collect();
+    store.roll_up(&hours, "KPHX", "2019-04-12", "2019-04-13").await.unwrap();
+    for date in ["2019-04-12", "2024-04-12"] {
+        store.put_daily_summary(&DailySummary { station_id: "KPHX".to_string(),
+                                                date: date.to_string(),
+                                                ..Default::default() }).await.unwrap();
+    }
+
+    let p = policy(&[("HOURLY_DAYS", "1825"), ("DAILY_DAYS", "1000"), ("BATCH_SIZE", "2"),
+                     ("BATCH_PAUSE_MS", "0")]).unwrap();
+    assert_eq!(retention::run_once(&p, &store, time("2024-07-12T12:30:00Z")).await.unwrap(),
+               RetentionReport { hourly_deleted: 5, daily_deleted: 1, ..Default::default() });
+    assert!(store.hourly_summaries("KPHX", "2019", "2020").await.unwrap().is_empty());
+    let days = store.daily_summaries("KPHX", "2019-01-01", "2025-01-01").await.unwrap();
+    assert_eq!(days.len(), 1);
+    assert_eq!(days[0].date, "2024-04-12");
+}
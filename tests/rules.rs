@@ -0,0 +1,164 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use common::{config, fixture, rec, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::rules::{Measure, Op, Qualifier, Rule, RulesEngine, RuleState};
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+
+fn engine(rules: &[(&str, &str)]) -> RulesEngine {
+    RulesEngine::new(rules.iter().map(|(n, t)| Rule::parse(n, t).unwrap()).collect())
+}
+
+/// An observation with one field set, at 2024-04-12 <hh>:<mm> UTC.
+fn obs(station: &str, hh: u32, mm: u32, field: &str, value: f64) -> ObservationRecord {
+    let timestamp = format!("2024-04-12T{:02}:{:02}:00+00:00", hh, mm);
+    match field {
+        "temperature_C" => rec(station, &timestamp, value),
+        "baro_pres_pa" => ObservationRecord { baro_pres_pa: value, ..rec(station, &timestamp, MISSING) },
+        _ => panic!("no test field {}", field),
+    }
+}
+
+/// The states of the events each observation produced, in order.
+fn run(engine: &mut RulesEngine, recs: &[ObservationRecord]) -> Vec<Vec<RuleState>> {
+    recs.iter().map(|r| engine.evaluate(r).iter().map(|e| e.state).collect()).collect()
+}
+
+#[test]
+fn parses_rules() {
+    let rule = Rule::parse("heat", "temperature_C >= 43 for 2 observations at KPHX,KTUS clear < 41")
+        .unwrap();
+    assert_eq!((rule.op, rule.threshold, rule.measure), (Op::Ge, 43.0, Measure::Value));
+    assert_eq!(rule.qualifier, Qualifier::Consecutive(2));
+    assert_eq!(rule.stations, vec!["KPHX", "KTUS"]);
+    assert_eq!(rule.clear, Some((Op::Lt, 41.0)));
+    assert!(rule.applies_to("KTUS") && !rule.applies_to("KSLO"));
+
+    let rule = Rule::parse("drop", "baro_pres_pa drops 300 in 3h for 30m").unwrap();
+    assert_eq!(rule.measure, Measure::Drop(Duration::from_secs(3 * 3600)));
+    assert_eq!(rule.qualifier, Qualifier::Lasting(Duration::from_secs(1800)));
+    assert!(rule.applies_to("KSLO"));
+
+    for bad in ["temperature_K > 1", "description > 1", "temperature_C ~ 1", "temperature_C >",
+                "baro_pres_pa drops 300 in 3y", "temperature_C > 1 for 0 obs",
+                "temperature_C > 1 soon"] {
+        assert!(Rule::parse("bad", bad).is_err(), "{}", bad);
+    }
+
+    let cfg: HashMap<String, String> = [("good", "temperature_C > 1"), ("bad", "nonsense")]
+        .iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let engine = RulesEngine::from_config(&cfg);
+    assert_eq!(engine.rules.len(), 1);
+    assert_eq!(engine.rules[0].name, "good");
+}
+
+#[test]
+fn consecutive_with_hysteresis() {
+    let mut e = engine(&[("heat", "temperature_C >= 43 for 2 obs clear < 41")]);
+    let t = |mm, v| obs("KPHX", 12, mm, "temperature_C", v);
+    let states = run(&mut e, &[
+        t(0, 43.5),     // 1 of 2
+        t(5, 42.0),     // resets
+        t(10, 43.0),
+        t(15, 44.0),    // fires
+        t(20, 42.0),    // below the condition, but not the clear value
+        t(25, MISSING), // no change
+        t(30, 40.5),    // clears
+        t(30, 45.0),    // not newer, ignored
+    ]);
+    assert_eq!(states, vec![vec![], vec![], vec![], vec![RuleState::Fired], vec![], vec![],
+                            vec![RuleState::Cleared], vec![]]);
+}
+
+#[test]
+fn rate_of_change_and_duration() {
+    let mut e = engine(&[("drop", "baro_pres_pa drops 300 in 3h")]);
+    let p = |hh, v| obs("KPHX", hh, 0, "baro_pres_pa", v);
+    let states = run(&mut e, &[
+        p(0, 101500.0),
+        p(1, 101400.0),
+        p(2, 101250.0),
+        p(3, 101190.0),     // 310 below 00:00, fires
+        p(4, 101150.0),     // 250 below 01:00, clears
+    ]);
+    assert_eq!(states, vec![vec![], vec![], vec![], vec![RuleState::Fired],
+                            vec![RuleState::Cleared]]);
+    let event = &e.evaluate(&p(5, 100800.0))[0];
+    assert_eq!((event.state, event.value), (RuleState::Fired, 450.0));
+
+    let mut e = engine(&[("hot", "temperature_C > 40 for 1h at KPHX")]);
+    let states = run(&mut e, &[
+        obs("KPHX", 12, 0, "temperature_C", 41.0),
+        obs("KTUS", 13, 0, "temperature_C", 45.0),  // not this rule's station
+        obs("KPHX", 12, 30, "temperature_C", 41.0),
+        obs("KPHX", 13, 0, "temperature_C", 41.0),  // an hour, fires
+    ]);
+    assert_eq!(states, vec![vec![], vec![], vec![], vec![RuleState::Fired]]);
+}
+
+#[test]
+fn restarts_where_it_left_off() {
+    let rules = [("drop", "baro_pres_pa drops 300 in 3h"), ("hot", "temperature_C > 40"),
+                 ("long", "temperature_C > 45 for 2h")];
+    let p = |hh, v| obs("KPHX", hh, 0, "baro_pres_pa", v);
+    let mut e = engine(&rules);
+    assert_eq!(e.lookback(), Duration::from_secs(3 * 3600));
+
+    // Replayed history fills the window without events, the same observations
+    // aren't evaluated twice, and a drop across the restart fires
+    e.replay(&[p(0, 101500.0), p(1, 101400.0)]);
+    assert_eq!(run(&mut e, &[p(1, 101400.0), p(2, 101190.0)]),
+               vec![vec![], vec![RuleState::Fired]]);
+
+    // A rule fired before the restart doesn't fire again, and can clear
+    let mut e = engine(&rules);
+    let fired = e.evaluate(&obs("KPHX", 12, 0, "temperature_C", 41.0));
+    let mut restarted = engine(&rules);
+    restarted.restore(&fired);
+    assert_eq!(run(&mut restarted, &[obs("KPHX", 12, 30, "temperature_C", 42.0),
+                                     obs("KPHX", 13, 0, "temperature_C", 35.0)]),
+               vec![vec![], vec![RuleState::Cleared]]);
+}
+
+#[async_std::test]
+async fn collector_stores_rule_events() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let mut cfg = config(&server, &["KPHX"], "rules_collector.spool");
+    cfg.rules_section.insert("warm".to_string(), "temperature_C > 30".to_string());
+    let storage = Arc::new(MemoryStore::new());
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, storage.clone(), spool);
+
+    c.init_stations().await;
+    c.poll_once().await;
+    // The same observation again doesn't fire again
+    c.poll_once().await;
+
+    let events = storage.rule_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].rule, "warm");
+    assert_eq!(events[0].station_id, "KPHX");
+    assert_eq!(events[0].state, RuleState::Fired);
+    assert_eq!(events[0].value, 31.1);
+
+    // Fired before a restart, from an observation no longer in the window,
+    // the rule stays fired and isn't stored or sent again
+    let restarted = Arc::new(MemoryStore::new());
+    let mut earlier = events[0].clone();
+    earlier.timestamp_UTC = "2024-04-11T21:51:00+00:00".to_string();
+    restarted.put_rule_event(&earlier).await.unwrap();
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, restarted.clone(), spool);
+    c.init_stations().await;
+    c.poll_once().await;
+    assert_eq!(restarted.rule_events(), vec![earlier]);
+}
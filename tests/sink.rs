@@ -0,0 +1,145 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use common::{config, fixture, rec, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::metrics::{self, Metrics};
+use weather_gov::sink::{ObservationSink, SinkRegistry};
+use weather_gov::spool::Spool;
+use weather_gov::station::{StationRecord, ObservationRecord, MISSING};
+use weather_gov::storage::BoxError;
+
+/// A sink that records its calls, and can fail or be slow.
+#[derive(Default)]
+struct TestSink {
+    name:   String,
+    calls:  Mutex<Vec<String>>,
+    fail:   bool,
+    delay:  Duration,
+}
+
+impl TestSink {
+    fn new(name: &str) -> TestSink {
+        TestSink { name: name.to_string(), ..Default::default() }
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    async fn call(&self, what: String) -> Result<(), BoxError> {
+        async_std::task::sleep(self.delay).await;
+        self.calls.lock().unwrap().push(what);
+        if self.fail { Err("sink down".into()) } else { Ok(()) }
+    }
+}
+
+#[async_trait]
+impl ObservationSink for TestSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn on_station(&self, station: &StationRecord) -> Result<(), BoxError> {
+        self.call(format!("station {}", station.call_id)).await
+    }
+
+    async fn on_observation(&self, rec: &ObservationRecord) -> Result<(), BoxError> {
+        self.call(format!("observation {} {}", rec.station_id, rec.timestamp_UTC)).await
+    }
+
+    async fn flush(&self) -> Result<(), BoxError> {
+        self.call("flush".to_string()).await
+    }
+}
+
+#[async_std::test]
+async fn sinks_are_isolated_and_buffered() {
+    let metrics = Arc::new(Metrics::new());
+    let mut registry = SinkRegistry::new().with_metrics(metrics.clone());
+    registry.buffer = 2;
+    registry.flush_wait = Duration::from_millis(300);
+    let good = Arc::new(TestSink::new("good"));
+    let failing = Arc::new(TestSink { fail: true, ..TestSink::new("failing") });
+    let slow = Arc::new(TestSink { delay: Duration::from_millis(100), ..TestSink::new("slow") });
+    registry.add(good.clone());
+    registry.add(failing.clone());
+    registry.add(slow.clone());
+    assert_eq!(registry.names(), vec!["good", "failing", "slow"]);
+
+    for minute in 0..4 {
+        let timestamp = format!("2024-04-12T21:{:02}:00+00:00", minute);
+        registry.on_observation(&rec("KPHX", &timestamp, MISSING));
+        async_std::task::sleep(Duration::from_millis(10)).await;
+    }
+    registry.flush().await;
+
+    // Everything reached the good and failing sinks, in order
+    let all: Vec<String> = (0..4).map(|m| format!("observation KPHX 2024-04-12T21:{:02}:00+00:00", m))
+        .chain(std::iter::once("flush".to_string())).collect();
+    assert_eq!(good.calls(), all);
+    assert_eq!(failing.calls(), all);
+    assert_eq!(metrics.counter(metrics::SINK_ERRORS, &[("sink", "failing")]), 5);
+    assert_eq!(metrics.counter(metrics::SINK_ERRORS, &[("sink", "good")]), 0);
+
+    // The slow sink fell behind, had calls dropped, and did not hold up the flush
+    assert!(metrics.counter(metrics::SINK_DROPPED, &[("sink", "slow")]) >= 1);
+    assert!(slow.calls().len() < all.len());
+}
+
+#[async_std::test]
+async fn slow_sinks_flush_together() {
+    let mut registry = SinkRegistry::new();
+    registry.flush_wait = Duration::from_millis(300);
+    let slow: Vec<Arc<TestSink>> = (0..3).map(|i| Arc::new(TestSink {
+        delay: Duration::from_millis(500), ..TestSink::new(&format!("slow{}", i)) })).collect();
+    for sink in &slow {
+        registry.add(sink.clone());
+    }
+
+    // One flush_wait for all of them, not one each
+    let started = std::time::Instant::now();
+    registry.flush().await;
+    assert!(started.elapsed() < Duration::from_millis(600), "{:?}", started.elapsed());
+    async_std::task::sleep(Duration::from_millis(300)).await;
+    assert!(slow.iter().all(|s| s.calls() == ["flush"]));
+}
+
+#[async_std::test]
+async fn collector_sends_new_observations_to_sinks() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX"], "sink_collector.spool");
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let store = Arc::new(MemoryStore::new());
+    let mut c = Collector::new(&cfg, store.clone(), spool.clone());
+    // Nothing else is configured
+    assert_eq!(c.sinks.names(), vec!["metrics"]);
+    let sink = Arc::new(TestSink::new("test"));
+    c.sinks.add(sink.clone());
+
+    c.init_stations().await;
+    c.poll_once().await;
+    c.poll_once().await;
+
+    assert_eq!(sink.calls(), vec!["station KPHX",
+                                  "observation KPHX 2024-04-12T21:51:00+00:00",
+                                  "flush", "flush"]);
+    assert!(c.metrics.render().contains("weather_gov_temperature_celsius{station=\"KPHX\"} 31.1"));
+
+    // After a restart that couldn't read the stored observations, what the
+    // db already has isn't published again, only what the batch inserted
+    server.route("/stations/KTST/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX", "KTST"], "sink_collector.spool");
+    let mut restarted = Collector::new(&cfg, store, spool);
+    let sink = Arc::new(TestSink::new("test"));
+    restarted.sinks.add(sink.clone());
+    restarted.poll_once().await;
+    assert_eq!(sink.calls(), vec!["observation KTST 2024-04-12T21:51:00+00:00", "flush"]);
+}
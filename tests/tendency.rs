@@ -0,0 +1,82 @@
+mod common;
+
+use std::sync::Arc;
+use common::{config, fixture, rec, MockServer};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::spool::Spool;
+use weather_gov::station::{ObservationRecord, MISSING};
+use weather_gov::storage::Storage;
+use weather_gov::tendency;
+
+/// A Phoenix record with a station pressure.
+fn kphx(timestamp: &str, temperature: f64, pressure: f64) -> ObservationRecord {
+    ObservationRecord { baro_pres_pa: pressure, ..rec("KPHX", timestamp, temperature) }
+}
+
+#[test]
+fn classifies_pressure_characteristics() {
+    let code = |before, middle, now| tendency::pressure_characteristic(before, middle, now);
+    assert_eq!(code(100000.0, Some(100100.0), 100200.0), 2);
+    assert_eq!(code(100000.0, Some(100150.0), 100100.0), 0);
+    assert_eq!(code(100000.0, Some(100100.0), 100105.0), 1);
+    assert_eq!(code(100000.0, Some(99950.0), 100100.0), 3);
+    assert_eq!(code(100000.0, Some(100003.0), 100005.0), 4);
+    assert_eq!(code(100000.0, Some(99900.0), 99990.0), 5);
+    assert_eq!(code(100000.0, Some(99900.0), 99895.0), 6);
+    assert_eq!(code(100000.0, Some(99900.0), 99800.0), 7);
+    assert_eq!(code(100000.0, Some(100020.0), 99800.0), 8);
+    // Without a middle value only the direction is known
+    assert_eq!((code(100000.0, None, 100200.0), code(100000.0, None, 99800.0)), (2, 7));
+    assert_eq!((code(100000.0, None, 100015.0), code(100000.0, None, 100005.0)), (2, 4));
+    assert_eq!(code(100000.0, Some(100150.0), 99995.0), 0);
+}
+
+#[test]
+fn applies_changes_from_three_hours_earlier() {
+    let history = [kphx("2024-04-12T18:20:00+00:00", 25.0, 100900.0),
+                   kphx("2024-04-12T18:51:00+00:00", 26.0, 101000.0),
+                   kphx("2024-04-12T20:21:00+00:00", 29.0, 100980.0)];
+    let mut now = kphx("2024-04-12T21:51:00+00:00", 31.1, 100800.0);
+    tendency::apply(&mut now, &history);
+    assert_eq!(now.pres_tendency_3h_pa, -200.0);
+    assert_eq!(now.pres_tendency_code, 8.0);
+    assert!((now.temperature_change_3h_C - 5.1).abs() < 1e-9);
+    assert_eq!(now.dewpoint_change_3h_C, MISSING);
+
+    // Nothing close enough to three hours earlier
+    let mut now = kphx("2024-04-13T00:00:00+00:00", 31.1, 100800.0);
+    tendency::apply(&mut now, &history);
+    assert_eq!((now.pres_tendency_3h_pa, now.pres_tendency_code), (MISSING, MISSING));
+
+    // The closest observation without a pressure is passed over for one that has it
+    let gap = [kphx("2024-04-12T18:30:00+00:00", 25.0, 100900.0),
+               kphx("2024-04-12T18:51:00+00:00", 26.0, MISSING),
+               kphx("2024-04-12T20:21:00+00:00", 29.0, 100980.0)];
+    let mut now = kphx("2024-04-12T21:51:00+00:00", 31.1, 100800.0);
+    tendency::apply(&mut now, &gap);
+    assert_eq!(now.pres_tendency_3h_pa, -100.0);
+    assert!((now.temperature_change_3h_C - 5.1).abs() < 1e-9);
+}
+
+#[async_std::test]
+async fn collector_adds_tendencies_from_stored_history() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    let cfg = config(&server, &["KPHX"], "tendency_collector.spool");
+    let store = Arc::new(MemoryStore::new());
+    store.put_observation_batch(&[kphx("2024-04-12T18:51:00+00:00", 28.0, 100980.0),
+                                  kphx("2024-04-12T20:21:00+00:00", 30.0, 101000.0)])
+        .await.unwrap();
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, store.clone(), spool);
+    c.init_stations().await;
+    c.poll_once().await;
+
+    let latest = store.latest_observation("KPHX").await.unwrap().unwrap();
+    assert_eq!(latest.timestamp_UTC, "2024-04-12T21:51:00+00:00");
+    assert_eq!((latest.pres_tendency_3h_pa, latest.pres_tendency_code), (100.0, 3.0));
+    assert!((latest.temperature_change_3h_C - 3.1).abs() < 1e-9);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use common::{config, fixture, temp_path, MockServer, MockRequest};
+use weather_gov::collector::Collector;
+use weather_gov::memory::MemoryStore;
+use weather_gov::spool::Spool;
+use weather_gov::webhook::{self, WebhookConfig, WebhookSink};
+
+/// Waits until the receiver got n POSTs, or a few seconds pass.
+async fn wait_for_posts(server: &MockServer, n: usize) -> Vec<MockRequest> {
+    let until = Instant::now() + Duration::from_secs(5);
+    loop {
+        let posts: Vec<MockRequest> = server.received().into_iter()
+            .filter(|r| r.method == "POST").collect();
+        if posts.len() >= n || Instant::now() > until {
+            return posts;
+        }
+        async_std::task::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+fn section(entries: &[(&str, &str)]) -> HashMap<String, String> {
+    entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn reads_endpoints_and_signs() {
+    assert_eq!(WebhookConfig::from_config(&section(&[("RETRIES", "3")])), None);
+    let cfg = WebhookConfig::from_config(&section(&[
+        ("ops.URL", "http://a/hook"),
+        ("ops.STATIONS", "KPHX, KTUS"),
+        ("audit.URL", "http://b/hook"),
+        ("audit.SECRET", "s3cret"),
+        ("RETRIES", "3"),
+    ])).unwrap();
+    assert_eq!(cfg.retries, 3);
+    assert_eq!(cfg.endpoints.len(), 2);
+    assert_eq!(cfg.endpoints[0].name, "audit");
+    assert_eq!(cfg.endpoints[0].secret.as_deref(), Some("s3cret"));
+    assert!(cfg.endpoints[0].accepts("KXYZ"));
+    assert_eq!(cfg.endpoints[1].stations, vec!["KPHX", "KTUS"]);
+    assert!(!cfg.endpoints[1].accepts("KXYZ"));
+
+    // RFC 4231 test case 2
+    assert_eq!(webhook::sign("Jefe", "what do ya want for nothing?"),
+               "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+}
+
+#[async_std::test]
+async fn posts_signed_new_observations_to_matching_endpoints() {
+    let server = MockServer::start();
+    server.route("/stations/KPHX", 200, &fixture("station_kphx.json"));
+    server.route("/stations/KPHX/observations/latest", 200,
+                 &fixture("observation_kphx_latest.json"));
+    server.route("/hooks/phx", 204, "");
+    server.route("/hooks/tus", 204, "");
+
+    let hooks = WebhookConfig::from_config(&section(&[
+        ("phx.URL", &format!("{}/hooks/phx", server.base_url)),
+        ("phx.SECRET", "s3cret"),
+        ("phx.STATIONS", "KPHX"),
+        ("tus.URL", &format!("{}/hooks/tus", server.base_url)),
+        ("tus.STATIONS", "KTUS"),
+    ])).unwrap();
+    let cfg = config(&server, &["KPHX"], "webhook_posts.spool");
+    let spool = Arc::new(Spool::new(&cfg.spool_section));
+    let mut c = Collector::new(&cfg, Arc::new(MemoryStore::new()), spool);
+    c.sinks.add(Arc::new(WebhookSink::start(hooks)));
+
+    c.init_stations().await;
+    c.poll_once().await;
+    // A duplicate is not sent again
+    c.poll_once().await;
+
+    let posts = wait_for_posts(&server, 1).await;
+    async_std::task::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.received().iter().filter(|r| r.method == "POST").count(), 1);
+    let post = &posts[0];
+    assert_eq!(post.path, "/hooks/phx");
+    assert_eq!(post.header(webhook::EVENT_HEADER), Some("observation"));
+    assert_eq!(post.header(webhook::SIGNATURE_HEADER),
+               Some(webhook::sign("s3cret", &post.body).as_str()));
+    let body: Value = serde_json::from_str(&post.body).unwrap();
+    assert_eq!(body["event"], "observation");
+    assert_eq!(body["station_id"], "KPHX");
+    assert_eq!(body["observation"]["temperature_C"], 31.1);
+}
+
+#[async_std::test]
+async fn retries_then_dead_letters() {
+    let server = MockServer::start();
+    server.route("/hooks/flaky", 500, "");
+    server.route("/hooks/flaky", 200, "");
+    server.route("/hooks/down", 503, "");
+    let dead = temp_path("webhook.dead_letter");
+
+    let hooks = WebhookConfig::from_config(&section(&[
+        ("flaky.URL", &format!("{}/hooks/flaky", server.base_url)),
+        ("down.URL", &format!("{}/hooks/down", server.base_url)),
+        ("RETRIES", "2"),
+        ("RETRY_MS", "10"),
+        ("DEAD_LETTER_FILE", dead.to_str().unwrap()),
+    ])).unwrap();
+    let sink = WebhookSink::start(hooks);
+    sink.notify("alert", "KPHX", &serde_json::json!({"event": "alert", "headline": "Heat"}));
+
+    // flaky: 500 then 200; down: 503 three times
+    let posts = wait_for_posts(&server, 5).await;
+    assert_eq!(posts.iter().filter(|p| p.path == "/hooks/flaky").count(), 2);
+    assert_eq!(posts.iter().filter(|p| p.path == "/hooks/down").count(), 3);
+
+    let until = Instant::now() + Duration::from_secs(5);
+    while !dead.exists() && Instant::now() < until {
+        async_std::task::sleep(Duration::from_millis(20)).await;
+    }
+    let lines = std::fs::read_to_string(&dead).unwrap();
+    let lines: Vec<Value> = lines.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["endpoint"], "down");
+    assert_eq!(lines[0]["attempts"], 3);
+    assert_eq!(lines[0]["event"], "alert");
+    let body: Value = serde_json::from_str(lines[0]["body"].as_str().unwrap()).unwrap();
+    assert_eq!(body["headline"], "Heat");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::rec;
+use weather_gov::station::{ObservationRecord, MISSING};
+use weather_gov::wind::{self, CALM, VARIABLE};
+
+/// A Phoenix record with only a wind.
+fn kphx(timestamp: &str, dir: f64, speed: f64) -> ObservationRecord {
+    ObservationRecord { wind_dir: dir, wind_spd_km_h: speed, ..rec("KPHX", timestamp, MISSING) }
+}
+
+#[test]
+fn classifies_direction_and_force() {
+    assert_eq!(wind::compass_point(0.0), "N");
+    assert_eq!(wind::compass_point(11.24), "N");
+    assert_eq!(wind::compass_point(11.25), "NNE");
+    assert_eq!(wind::compass_point(250.0), "WSW");
+    assert_eq!(wind::compass_point(270.0), "W");
+    assert_eq!(wind::compass_point(355.0), "N");
+    assert_eq!(wind::compass_point(-90.0), "W");
+
+    assert_eq!((wind::beaufort(0.5), wind::beaufort(1.0), wind::beaufort(19.9)), (0, 1, 3));
+    assert_eq!((wind::beaufort(20.0), wind::beaufort(117.9), wind::beaufort(150.0)), (4, 11, 12));
+    assert_eq!((wind::saffir_simpson(118.0), wind::saffir_simpson(119.0)), (None, Some(1)));
+    assert_eq!(wind::saffir_simpson(260.0), Some(5));
+
+    assert_eq!(wind::compass(250.0, 0.0), CALM);
+    assert_eq!(wind::compass(MISSING, 9.0), VARIABLE);
+    assert_eq!(wind::compass(250.0, MISSING), "");
+}
+
+#[test]
+fn classifies_observations() {
+    let mut r = kphx("2024-04-12T21:51:00+00:00", 250.0, 16.668);
+    wind::classify(&mut r);
+    assert_eq!((r.wind_compass.as_str(), r.wind_beaufort, r.wind_beaufort_desc.as_str()),
+               ("WSW", 3.0, "Gentle breeze"));
+    assert_eq!(r.wind_saffir_simpson, MISSING);
+
+    let mut r = kphx("2024-04-12T21:51:00+00:00", MISSING, MISSING);
+    wind::classify(&mut r);
+    assert_eq!((r.wind_compass.as_str(), r.wind_beaufort, r.wind_beaufort_desc.as_str()),
+               ("", MISSING, ""));
+}
+
+#[test]
+fn builds_a_wind_rose() {
+    let recs = [
+        kphx("2024-04-12T18:51:00+00:00", 250.0, 16.0),
+        kphx("2024-04-12T19:51:00+00:00", 260.0, 25.0),
+        kphx("2024-04-12T20:51:00+00:00", 90.0, 55.0),
+        kphx("2024-04-12T21:51:00+00:00", 0.0, 0.0),
+        kphx("2024-04-12T22:51:00+00:00", MISSING, 7.0),
+        kphx("2024-04-12T23:51:00+00:00", MISSING, MISSING),
+    ];
+    let rose = wind::wind_rose("KPHX", &recs, &wind::DEFAULT_SPEED_BINS);
+    assert_eq!((rose.observations, rose.calm, rose.variable), (5, 1, 1));
+    assert_eq!(rose.speed_bins, ["1-10", "10-20", "20-30", "30-40", "40-50", "50+"]);
+    assert_eq!(rose.directions.len(), 16);
+
+    let wsw = &rose.directions[11];
+    assert_eq!(wsw.direction, "WSW");
+    assert_eq!(wsw.counts, [0, 1, 0, 0, 0, 0]);
+    assert_eq!(wsw.percent[1], 20.0);
+    // 260 degrees is W
+    assert_eq!(rose.directions[12].counts, [0, 0, 1, 0, 0, 0]);
+    assert_eq!(rose.directions[4].counts[5], 1);
+}
+
+#[cfg(feature = "http-api")]
+#[async_std::test]
+async fn serves_a_wind_rose() {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use weather_gov::api::Api;
+    use weather_gov::memory::MemoryStore;
+    use weather_gov::storage::Storage;
+
+    let store = MemoryStore::new();
+    store.put_observation_batch(&[kphx("2024-04-12T18:51:00+00:00", 250.0, 16.0),
+                                  kphx("2024-04-12T19:51:00+00:00", 260.0, 25.0),
+                                  kphx("2024-04-13T19:51:00+00:00", 260.0, 25.0)]).await.unwrap();
+    let api = Api::new(Arc::new(store), &HashMap::new());
+
+    let resp = api.handle("GET", "/stations/KPHX/windrose?from=2024-04-12&to=2024-04-13&bins=1,20", None).await;
+    assert_eq!(resp.status, 200);
+    let body: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+    assert_eq!(body["observations"], 2);
+    assert_eq!(body["speed_bins"], serde_json::json!(["1-20", "20+"]));
+    assert_eq!(body["directions"][12]["counts"], serde_json::json!([0, 1]));
+
+    let resp = api.handle("GET", "/stations/KPHX/windrose?format=csv&from=2024-04-12&bins=1,20", None).await;
+    let lines: Vec<&str> = resp.body.lines().collect();
+    assert_eq!(lines[0], "direction,1-20,20+,total");
+    assert_eq!(lines[12], "WSW,1,0,1");
+    assert_eq!(lines[13], "W,0,2,2");
+    assert_eq!(lines[17..], ["CALM,,,0", "VRB,,,0"]);
+
+    let resp = api.handle("GET", "/stations/KPHX/windrose?from=2024-04-12&bins=20,10", None).await;
+    assert_eq!(resp.status, 400);
+
+    // The whole history isn't read without a from
+    let resp = api.handle("GET", "/stations/KPHX/windrose?to=2024-04-13", None).await;
+    assert_eq!(resp.status, 400);
+}